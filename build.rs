@@ -0,0 +1,58 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// the package which embeds the QuickJS sources, the bytecode format depends on its version
+const SYS_PACKAGE: &str = "hirofa-quickjs-sys";
+
+fn main() {
+    // the Cargo.lock of the build is in the root of the workspace, which is an ancestor of the target dir
+    // (or of this package when it is built in its own repository)
+    let lock_file = [env::var("OUT_DIR"), env::var("CARGO_MANIFEST_DIR")]
+        .into_iter()
+        .flatten()
+        .flat_map(|dir| {
+            PathBuf::from(dir)
+                .ancestors()
+                .map(|dir| dir.join("Cargo.lock"))
+                .collect::<Vec<_>>()
+        })
+        .find(|path| path.is_file());
+
+    let version = match &lock_file {
+        Some(lock_file) => {
+            println!("cargo:rerun-if-changed={}", lock_file.display());
+            find_locked_version(lock_file)
+        }
+        None => None,
+    };
+    // without a version bytecode is only read by the same version of quickjs_runtime
+    let version = version.unwrap_or_else(|| {
+        format!(
+            "unknown (quickjs_runtime {})",
+            env::var("CARGO_PKG_VERSION").unwrap_or_default()
+        )
+    });
+    println!("cargo:rustc-env=QUICKJS_SYS_VERSION={version}");
+    println!("cargo:rerun-if-changed=build.rs");
+}
+
+/// the version of the sys package in a Cargo.lock, None if it is not locked or locked in more than one version
+fn find_locked_version(lock_file: &Path) -> Option<String> {
+    let lock = fs::read_to_string(lock_file).ok()?;
+    let mut versions = vec![];
+    let mut lines = lock.lines();
+    while let Some(line) = lines.next() {
+        if line.trim() == format!("name = \"{SYS_PACKAGE}\"") {
+            let version = lines
+                .next()
+                .and_then(|line| line.trim().strip_prefix("version = "))
+                .map(|version| version.trim_matches('"').to_string());
+            versions.extend(version);
+        }
+    }
+    match versions.as_slice() {
+        [version] => Some(version.clone()),
+        _ => None,
+    }
+}
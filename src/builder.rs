@@ -3,9 +3,18 @@
 use crate::facades::QuickJsRuntimeFacade;
 use crate::quickjsrealmadapter::QuickJsRealmAdapter;
 use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use crate::values::JsValueFacade;
 
-use crate::jsutils::modules::{CompiledModuleLoader, NativeModuleLoader, ScriptModuleLoader};
+use crate::jsutils::bytecodecache::BytecodeStore;
+use crate::jsutils::ifdef::{Defines, IfDefPreProcessor};
+use crate::jsutils::importmaps::ImportMap;
+use crate::jsutils::modules::{
+    AsyncModuleLoader, CompiledModuleLoader, NativeModuleLoader, ScriptModuleLoader,
+};
 use crate::jsutils::{JsError, ScriptPreProcessor};
+#[cfg(feature = "typescript")]
+use crate::typescript::TypeScriptOptions;
 use std::time::Duration;
 
 pub type EsRuntimeInitHooks =
@@ -22,6 +31,7 @@ pub type EsRuntimeInitHooks =
 /// ```
 pub struct QuickJsRuntimeBuilder {
     pub(crate) script_module_loaders: Vec<Box<dyn ScriptModuleLoader + Send>>,
+    pub(crate) async_module_loaders: Vec<Box<dyn AsyncModuleLoader + Send>>,
     pub(crate) native_module_loaders: Vec<Box<dyn NativeModuleLoader + Send>>,
     pub(crate) compiled_module_loaders: Vec<Box<dyn CompiledModuleLoader + Send>>,
     pub(crate) opt_memory_limit_bytes: Option<u64>,
@@ -32,6 +42,21 @@ pub struct QuickJsRuntimeBuilder {
     pub(crate) script_pre_processors: Vec<Box<dyn ScriptPreProcessor + Send>>,
     #[allow(clippy::type_complexity)]
     pub(crate) interrupt_handler: Option<Box<dyn Fn(&QuickJsRuntimeAdapter) -> bool + Send>>,
+    #[allow(clippy::type_complexity)]
+    pub(crate) panic_handler:
+        Option<Box<dyn Fn(&QuickJsRuntimeAdapter, &QuickJsRealmAdapter, &str, &str) + Send>>,
+    #[allow(clippy::type_complexity)]
+    pub(crate) unhandled_rejection_handler:
+        Option<Box<dyn Fn(&QuickJsRealmAdapter, JsValueFacade, &QuickJsValueAdapter) + Send>>,
+    #[allow(clippy::type_complexity)]
+    pub(crate) rejection_handled_handler:
+        Option<Box<dyn Fn(&QuickJsRealmAdapter, &QuickJsValueAdapter) + Send>>,
+    pub(crate) strict_unhandled_rejections: bool,
+    pub(crate) import_map: Option<ImportMap>,
+    pub(crate) bytecode_store: Option<Box<dyn BytecodeStore + Send>>,
+    pub(crate) code_frame_context_lines: Option<u32>,
+    #[cfg(feature = "typescript")]
+    pub(crate) typescript_options: Option<TypeScriptOptions>,
 }
 
 impl QuickJsRuntimeBuilder {
@@ -45,6 +70,7 @@ impl QuickJsRuntimeBuilder {
     pub fn new() -> Self {
         Self {
             script_module_loaders: vec![],
+            async_module_loaders: vec![],
             native_module_loaders: vec![],
             compiled_module_loaders: vec![],
             opt_memory_limit_bytes: None,
@@ -54,6 +80,15 @@ impl QuickJsRuntimeBuilder {
             runtime_init_hooks: vec![],
             script_pre_processors: vec![],
             interrupt_handler: None,
+            panic_handler: None,
+            unhandled_rejection_handler: None,
+            rejection_handled_handler: None,
+            strict_unhandled_rejections: false,
+            import_map: None,
+            bytecode_store: None,
+            code_frame_context_lines: None,
+            #[cfg(feature = "typescript")]
+            typescript_options: None,
         }
     }
 
@@ -88,6 +123,39 @@ impl QuickJsRuntimeBuilder {
         self
     }
 
+    /// add an async module loader, its modules are loaded in a helper thread so the event loop keeps running while they load
+    /// async loaders are used after the other loaders
+    /// # Example
+    /// ```rust
+    /// use futures::executor::block_on;
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// use quickjs_runtime::jsutils::modules::{AsyncModuleLoader, ModuleSource, ModuleSourceFuture};
+    /// use quickjs_runtime::quickjsrealmadapter::QuickJsRealmAdapter;
+    /// use quickjs_runtime::jsutils::Script;
+    /// struct MyModuleLoader {}
+    /// impl AsyncModuleLoader for MyModuleLoader {
+    ///     fn normalize_path(&self, realm: &QuickJsRealmAdapter ,ref_path: &str,path: &str) -> Option<String> {
+    ///         Some(path.to_string())
+    ///     }
+    ///
+    ///     fn load_module(&self, realm: &QuickJsRealmAdapter, absolute_path: &str) -> ModuleSourceFuture {
+    ///         Box::pin(async move {
+    ///             // e.g. query a database here
+    ///             Ok(ModuleSource::Script("export const foo = 12;".to_string()))
+    ///         })
+    ///     }
+    /// }
+    ///
+    /// let rt = QuickJsRuntimeBuilder::new()
+    ///     .async_module_loader(MyModuleLoader{})
+    ///     .build();
+    /// block_on(rt.eval_module(None, Script::new("test_module.es", "import {foo} from 'some_module.mes';\nconsole.log('foo = %s', foo);"))).ok().unwrap();
+    /// ```
+    pub fn async_module_loader<M: AsyncModuleLoader + Send + 'static>(mut self, loader: M) -> Self {
+        self.async_module_loaders.push(Box::new(loader));
+        self
+    }
+
     /// add a ScriptPreProcessor which will be called for all scripts which are evaluated and compiled
     pub fn script_pre_processor<S: ScriptPreProcessor + Send + 'static>(
         mut self,
@@ -97,6 +165,82 @@ impl QuickJsRuntimeBuilder {
         self
     }
 
+    /// add an IfDefPreProcessor with flags and constants for conditional compilation (//#ifdef, //#define)
+    /// a realm may add to or override these with QuickJsRealmAdapter::set_defines
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// use quickjs_runtime::jsutils::ifdef::Defines;
+    /// let rt = QuickJsRuntimeBuilder::new()
+    ///     .defines(Defines::new().flag("DEBUG").define("VERSION", "'1.2.0'"))
+    ///     .build();
+    /// ```
+    pub fn defines(self, defines: Defines) -> Self {
+        self.script_pre_processor(IfDefPreProcessor::new(defines))
+    }
+
+    /// set an import map which is used to resolve module specifiers before they are passed to the module loaders
+    /// a realm may override this with QuickJsRealmAdapter::set_import_map
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// use quickjs_runtime::jsutils::importmaps::ImportMap;
+    /// let mut import_map = ImportMap::new();
+    /// import_map.add_import("lodash", "/libs/lodash@4.17.21/lodash.js");
+    /// let rt = QuickJsRuntimeBuilder::new().import_map(import_map).build();
+    /// ```
+    pub fn import_map(mut self, import_map: ImportMap) -> Self {
+        self.import_map = Some(import_map);
+        self
+    }
+
+    /// cache the bytecode of evaluated scripts and modules (and of modules loaded by ScriptModuleLoaders)
+    /// so they don't need to be pre-processed and parsed again
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// use quickjs_runtime::jsutils::bytecodecache::MemoryBytecodeStore;
+    /// let rt = QuickJsRuntimeBuilder::new().bytecode_cache(MemoryBytecodeStore::new()).build();
+    /// ```
+    pub fn bytecode_cache<S: BytecodeStore + Send + 'static>(mut self, store: S) -> Self {
+        self.bytecode_store = Some(Box::new(store));
+        self
+    }
+
+    /// remember the source of evaluated scripts and modules so a JsError gets a code frame
+    /// showing the offending line (with context_lines lines before and after it) and a caret at the column
+    /// for TypeScript the original source is shown
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// use quickjs_runtime::jsutils::Script;
+    /// let rt = QuickJsRuntimeBuilder::new().code_frames(2).build();
+    /// let err = rt
+    ///     .eval_sync(None, Script::new("frames.js", "let a = 1;\nthrow new Error('oops');"))
+    ///     .expect_err("script should fail");
+    /// assert!(err.get_code_frame().expect("no code frame").contains("> 2 | throw new Error('oops');"));
+    /// ```
+    pub fn code_frames(mut self, context_lines: u32) -> Self {
+        self.code_frame_context_lines = Some(context_lines);
+        self
+    }
+
+    /// set the options used for transpiling TypeScript
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// use quickjs_runtime::typescript::{JsxRuntime, TypeScriptOptions};
+    /// let options = TypeScriptOptions::new()
+    ///     .jsx_runtime(JsxRuntime::Automatic)
+    ///     .jsx_import_source("preact");
+    /// let rt = QuickJsRuntimeBuilder::new().typescript_options(options).build();
+    /// ```
+    #[cfg(feature = "typescript")]
+    pub fn typescript_options(mut self, options: TypeScriptOptions) -> Self {
+        self.typescript_options = Some(options);
+        self
+    }
+
     /// add a module loader which can load native functions and proxy classes
     /// # Example
     /// ```rust
@@ -189,6 +333,80 @@ impl QuickJsRuntimeBuilder {
         self.interrupt_handler = Some(Box::new(interrupt_handler));
         self
     }
+
+    /// add a panic handler, this will be called when a rust callback (e.g. a function, a Proxy method or a module loader) panics
+    /// the panic is always converted to an InternalError in the script, the handler may be used to report it
+    /// the handler receives the name of the native function and the panic message
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// let rt = QuickJsRuntimeBuilder::new()
+    ///     .set_panic_handler(|_rt, realm, native_name, msg| {
+    ///         log::error!("[{}] {} panicked: {}", realm.get_realm_id(), native_name, msg);
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn set_panic_handler<
+        H: Fn(&QuickJsRuntimeAdapter, &QuickJsRealmAdapter, &str, &str) + Send + 'static,
+    >(
+        mut self,
+        panic_handler: H,
+    ) -> Self {
+        self.panic_handler = Some(Box::new(panic_handler));
+        self
+    }
+
+    /// set a handler which is called when a promise is rejected and no rejection handler was added to it
+    /// by default unhandled rejections are logged as error
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// let rt = QuickJsRuntimeBuilder::new()
+    ///     .on_unhandled_rejection(|realm, reason, _promise| {
+    ///         log::error!("[{}] unhandled rejection: {}", realm.get_realm_id(), reason.stringify());
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn on_unhandled_rejection<
+        H: Fn(&QuickJsRealmAdapter, JsValueFacade, &QuickJsValueAdapter) + Send + 'static,
+    >(
+        mut self,
+        handler: H,
+    ) -> Self {
+        self.unhandled_rejection_handler = Some(Box::new(handler));
+        self
+    }
+
+    /// set a handler which is called when a rejection handler is added to a promise after it was reported as unhandled
+    pub fn on_rejection_handled<
+        H: Fn(&QuickJsRealmAdapter, &QuickJsValueAdapter) + Send + 'static,
+    >(
+        mut self,
+        handler: H,
+    ) -> Self {
+        self.rejection_handled_handler = Some(Box::new(handler));
+        self
+    }
+
+    /// when strict, a promise rejection which is still unhandled when an eval or eval_module of the QuickJsRuntimeFacade
+    /// (and its pending jobs) is done fails that eval with the reason of the rejection
+    ///
+    /// to see those rejections the facade runs the pending jobs (promise reactions) of such an eval before it returns,
+    /// the evals of a QuickJsRealmAdapter don't do this, their unhandled rejections are reported to the handler
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// use quickjs_runtime::jsutils::Script;
+    /// let rt = QuickJsRuntimeBuilder::new()
+    ///     .strict_unhandled_rejections(true)
+    ///     .build();
+    /// let res = rt.eval_sync(None, Script::new("forgot_await.js", "Promise.reject(new Error('poof'));"));
+    /// assert!(res.is_err());
+    /// ```
+    pub fn strict_unhandled_rejections(mut self, strict: bool) -> Self {
+        self.strict_unhandled_rejections = strict;
+        self
+    }
 }
 
 impl Default for QuickJsRuntimeBuilder {
@@ -249,6 +467,141 @@ pub mod tests {
     use crate::jsutils::modules::ScriptModuleLoader;
     use crate::jsutils::Script;
     use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+    use crate::values::JsValueFacade;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_panic_handler() {
+        crate::facades::tests::init_logging();
+
+        let reported = Arc::new(Mutex::new(vec![]));
+        let reported2 = reported.clone();
+
+        let rt = QuickJsRuntimeBuilder::new()
+            .set_panic_handler(move |_rt, _realm, native_name, msg| {
+                reported2
+                    .lock()
+                    .unwrap()
+                    .push(format!("{native_name}: {msg}"));
+            })
+            .build();
+
+        rt.set_function(&[], "panicky", |_realm, _args| panic!("poof"))
+            .expect("could not set function");
+
+        let err = rt
+            .eval_sync(None, Script::new("test_panic_handler.js", "panicky();"))
+            .expect_err("script should have failed");
+        assert_eq!(err.get_name(), "InternalError");
+        assert!(err.get_message().contains("poof"));
+
+        let reported = &*reported.lock().unwrap();
+        assert_eq!(reported.len(), 1);
+        assert_eq!(reported[0], "panicky: poof");
+    }
+
+    #[test]
+    fn test_unhandled_rejection_handler() {
+        crate::facades::tests::init_logging();
+
+        let reported = Arc::new(Mutex::new(vec![]));
+        let reported2 = reported.clone();
+        let reported3 = reported.clone();
+
+        let rt = QuickJsRuntimeBuilder::new()
+            .on_unhandled_rejection(move |_realm, reason, _promise| {
+                let msg = match reason {
+                    JsValueFacade::JsError { val } => val.get_message().to_string(),
+                    other => other.stringify(),
+                };
+                reported2.lock().unwrap().push(format!("unhandled: {msg}"));
+            })
+            .on_rejection_handled(move |_realm, _promise| {
+                reported3.lock().unwrap().push("handled".to_string());
+            })
+            .build();
+
+        rt.eval_sync(
+            None,
+            Script::new(
+                "test_unhandled_rejection_handler.js",
+                "let p = Promise.reject(new Error('late')); p.catch(() => {}); true;",
+            ),
+        )
+        .expect("script failed");
+
+        let reported = &*reported.lock().unwrap();
+        assert_eq!(reported.len(), 2);
+        assert_eq!(reported[0], "unhandled: late");
+        assert_eq!(reported[1], "handled");
+    }
+
+    #[test]
+    fn test_unhandled_rejection_handler_panic() {
+        crate::facades::tests::init_logging();
+
+        let reported = Arc::new(Mutex::new(vec![]));
+        let reported2 = reported.clone();
+
+        let rt = QuickJsRuntimeBuilder::new()
+            .on_unhandled_rejection(|_realm, _reason, _promise| panic!("poof"))
+            .set_panic_handler(move |_rt, _realm, native_name, msg| {
+                reported2
+                    .lock()
+                    .unwrap()
+                    .push(format!("{native_name}: {msg}"));
+            })
+            .build();
+
+        // the panic does not unwind into the engine and the script is not affected
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test_unhandled_rejection_handler_panic.js",
+                    "Promise.reject(new Error('late')); 1 + 2;",
+                ),
+            )
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 3);
+
+        let reported = &*reported.lock().unwrap();
+        assert_eq!(reported.len(), 1);
+        assert_eq!(reported[0], "promise rejection tracker: poof");
+    }
+
+    #[test]
+    fn test_strict_unhandled_rejections() {
+        crate::facades::tests::init_logging();
+
+        let rt = QuickJsRuntimeBuilder::new()
+            .strict_unhandled_rejections(true)
+            .build();
+
+        let err = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test_strict_unhandled_rejections.js",
+                    "async function job() { throw new TypeError('forgot to await'); }; job(); true;",
+                ),
+            )
+            .expect_err("script should have failed");
+        assert_eq!(err.get_name(), "TypeError");
+        assert_eq!(err.get_message(), "forgot to await");
+
+        // handled in the same eval is fine
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test_strict_unhandled_rejections2.js",
+                    "Promise.reject(new Error('poof')).catch(() => {}); true;",
+                ),
+            )
+            .expect("script failed");
+        assert!(res.get_bool());
+    }
 
     #[test]
     fn test_module_loader() {
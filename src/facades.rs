@@ -5,8 +5,8 @@ use crate::jsutils::{JsError, Script};
 use crate::quickjs_utils::{functions, objects};
 use crate::quickjsrealmadapter::QuickJsRealmAdapter;
 use crate::quickjsruntimeadapter::{
-    CompiledModuleLoaderAdapter, MemoryUsage, NativeModuleLoaderAdapter, QuickJsRuntimeAdapter,
-    ScriptModuleLoaderAdapter, QJS_RT,
+    AsyncModuleLoaderAdapter, CompiledModuleLoaderAdapter, MemoryUsage, NativeModuleLoaderAdapter,
+    QuickJsRuntimeAdapter, ScriptModuleLoaderAdapter, QJS_RT,
 };
 use crate::quickjsvalueadapter::QuickJsValueAdapter;
 use crate::reflection;
@@ -198,7 +198,8 @@ impl QuickJsRuntimeFacade {
             feature = "settimeout",
             feature = "setinterval",
             feature = "console",
-            feature = "setimmediate"
+            feature = "setimmediate",
+            feature = "commonjs"
         ))]
         {
             let res = crate::features::init(&ret);
@@ -241,6 +242,11 @@ impl QuickJsRuntimeFacade {
                         script_module_loader,
                     ));
                 }
+                for async_module_loader in builder.async_module_loaders {
+                    q_js_rt.add_async_module_loader(AsyncModuleLoaderAdapter::new(
+                        async_module_loader,
+                    ));
+                }
                 for compiled_module_loader in builder.compiled_module_loaders {
                     q_js_rt.add_compiled_module_loader(CompiledModuleLoaderAdapter::new(
                         compiled_module_loader,
@@ -266,6 +272,25 @@ impl QuickJsRuntimeFacade {
                 if let Some(interrupt_handler) = builder.interrupt_handler {
                     q_js_rt.set_interrupt_handler(interrupt_handler);
                 }
+                if let Some(panic_handler) = builder.panic_handler {
+                    q_js_rt.set_panic_handler(panic_handler);
+                }
+                if let Some(handler) = builder.unhandled_rejection_handler {
+                    q_js_rt.set_unhandled_rejection_handler(handler);
+                }
+                if let Some(handler) = builder.rejection_handled_handler {
+                    q_js_rt.set_rejection_handled_handler(handler);
+                }
+                q_js_rt.set_strict_unhandled_rejections(builder.strict_unhandled_rejections);
+                q_js_rt.import_map = builder.import_map;
+                q_js_rt.bytecode_store = builder.bytecode_store;
+                if let Some(context_lines) = builder.code_frame_context_lines {
+                    crate::quickjs_utils::errors::enable_code_frames(context_lines);
+                }
+                #[cfg(feature = "typescript")]
+                if let Some(options) = builder.typescript_options {
+                    crate::typescript::set_serverside_options(options);
+                }
             })
         });
 
@@ -514,6 +539,20 @@ impl QuickJsRuntimeFacade {
     }
 }
 
+/// await a promise which was converted to a JsValueFacade, a rejection becomes an Err
+async fn await_promise_facade(promise: JsValueFacade) -> Result<JsValueFacade, JsError> {
+    match promise {
+        JsValueFacade::JsPromise { cached_promise } => {
+            match cached_promise.get_promise_result().await? {
+                Ok(res) => Ok(res),
+                Err(JsValueFacade::JsError { val }) => Err(val),
+                Err(reason) => Err(JsError::new_string(reason.stringify())),
+            }
+        }
+        res => Ok(res),
+    }
+}
+
 fn loop_realm_func<
     R: Send + 'static,
     C: FnOnce(&QuickJsRuntimeAdapter, &QuickJsRealmAdapter) -> R + Send + 'static,
@@ -681,7 +720,7 @@ impl QuickJsRuntimeFacade {
         script: Script,
     ) -> Pin<Box<dyn Future<Output = Result<JsValueFacade, JsError>>>> {
         self.loop_realm(realm_name, |_rt, realm| {
-            let res = realm.eval(script);
+            let res = realm.track_unhandled_rejections(|| realm.eval(script));
             match res {
                 Ok(jsvr) => realm.to_js_value_facade(&jsvr),
                 Err(e) => Err(e),
@@ -706,7 +745,7 @@ impl QuickJsRuntimeFacade {
         script: Script,
     ) -> Result<JsValueFacade, JsError> {
         self.loop_realm_sync(realm_name, |_rt, realm| {
-            let res = realm.eval(script);
+            let res = realm.track_unhandled_rejections(|| realm.eval(script));
             match res {
                 Ok(jsvr) => realm.to_js_value_facade(&jsvr),
                 Err(e) => Err(e),
@@ -748,15 +787,115 @@ impl QuickJsRuntimeFacade {
     /// // in real life you would .await this
     /// let _res = block_on(rt.eval_module(None, script));
     /// ```
+    /// when the runtime has async module loaders the modules they provide are loaded before the module is evaluated
+    ///
+    /// the result is the settled result of the module, a module with top level await (or a module of an engine
+    /// which evaluates modules to a promise) is awaited, a rejection is an Err
     pub fn eval_module(
         &self,
         realm_name: Option<&str>,
         script: Script,
     ) -> Pin<Box<dyn Future<Output = Result<JsValueFacade, JsError>>>> {
-        self.loop_realm(realm_name, |_rt, realm| {
-            let res = realm.eval_module(script)?;
+        let res_fut = self.loop_realm(realm_name, |rt, realm| -> Result<JsValueFacade, JsError> {
+            let res = if rt.has_async_module_loaders() {
+                realm.track_unhandled_rejections(|| realm.eval_module_async(script))?
+            } else {
+                realm.track_unhandled_rejections(|| realm.eval_module(script))?
+            };
             realm.to_js_value_facade(&res)
-        })
+        });
+        Box::pin(async move { await_promise_facade(res_fut.await?).await })
+    }
+
+    /// evaluate a module and get its namespace, the result is an object with the exports of the module
+    /// the namespace is returned when the module (including top level await) was evaluated
+    /// # example
+    /// ```rust
+    /// use futures::executor::block_on;
+    /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+    /// use quickjs_runtime::jsutils::Script;
+    /// use quickjs_runtime::values::JsValueConvertable;
+    /// let rt = QuickJsRuntimeBuilder::new().build();
+    /// let script = Script::new("/opt/files/my_module.js", "export function mul(a, b) {return a * b;}");
+    /// let namespace = block_on(rt.eval_module_namespace(None, script)).expect("module failed");
+    /// assert!(namespace.is_js_object());
+    /// let res = block_on(rt.invoke_module_export(None, "/opt/files/my_module.js", "mul", vec![6.to_js_value_facade(), 7.to_js_value_facade()])).expect("mul failed");
+    /// assert_eq!(res.get_i32(), 42);
+    /// ```
+    pub fn eval_module_namespace(
+        &self,
+        realm_name: Option<&str>,
+        script: Script,
+    ) -> Pin<Box<dyn Future<Output = Result<JsValueFacade, JsError>>>> {
+        let res_fut = self.loop_realm(realm_name, |rt, realm| -> Result<JsValueFacade, JsError> {
+            let promise = realm.track_unhandled_rejections(|| {
+                if rt.has_async_module_loaders() {
+                    realm.eval_module_namespace_async(script)
+                } else {
+                    realm.eval_module_namespace(script)
+                }
+            })?;
+            realm.to_js_value_facade(&promise)
+        });
+        Box::pin(async move { await_promise_facade(res_fut.await?).await })
+    }
+
+    /// get the namespace of a module, the module is imported (by the module loaders) if it was not loaded in the realm yet
+    pub fn get_module_namespace(
+        &self,
+        realm_name: Option<&str>,
+        path: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<JsValueFacade, JsError>>>> {
+        let path = path.to_string();
+        let res_fut = self.loop_realm(
+            realm_name,
+            move |_rt, realm| -> Result<JsValueFacade, JsError> {
+                let promise = realm.import_module(path.as_str())?;
+                realm.to_js_value_facade(&promise)
+            },
+        );
+        Box::pin(async move { await_promise_facade(res_fut.await?).await })
+    }
+
+    /// invoke a function which is exported by a module, the module is imported if it was not loaded in the realm yet
+    /// when the function returns a promise the result is the resolved value of that promise
+    pub fn invoke_module_export(
+        &self,
+        realm_name: Option<&str>,
+        path: &str,
+        name: &str,
+        args: Vec<JsValueFacade>,
+    ) -> Pin<Box<dyn Future<Output = Result<JsValueFacade, JsError>>>> {
+        let path = path.to_string();
+        let name = name.to_string();
+        let res_fut = self.loop_realm(
+            realm_name,
+            move |_rt, realm| -> Result<JsValueFacade, JsError> {
+                let args = args
+                    .into_iter()
+                    .map(|arg| realm.from_js_value_facade(arg))
+                    .collect::<Result<Vec<QuickJsValueAdapter>, JsError>>()?;
+                let promise = realm.import_module(path.as_str())?;
+                let invoke = realm.create_function(
+                    "invoke_module_export",
+                    move |realm, _this, call_args| {
+                        let namespace = &call_args[0];
+                        let func = objects::get_property_q(realm, namespace, name.as_str())?;
+                        if !functions::is_function_q(realm, &func) {
+                            return Err(JsError::new_string(format!(
+                                "module {path} has no exported function {name}"
+                            )));
+                        }
+                        functions::call_function_q(realm, &func, &args, None)
+                    },
+                    1,
+                )?;
+                let promise =
+                    functions::invoke_member_function_q(realm, &promise, "then", &[invoke])?;
+                realm.to_js_value_facade(&promise)
+            },
+        );
+        Box::pin(async move { await_promise_facade(res_fut.await?).await })
     }
 
     /// evaluate a module synchronously, you need this if you want to compile a script that contains static imports
@@ -797,11 +936,37 @@ impl QuickJsRuntimeFacade {
         script: Script,
     ) -> Result<JsValueFacade, JsError> {
         self.loop_realm_sync(realm_name, |_rt, realm| {
-            let res = realm.eval_module(script)?;
+            let res = realm.track_unhandled_rejections(|| realm.eval_module(script))?;
             realm.to_js_value_facade(&res)
         })
     }
 
+    /// invalidate a module and all modules which import it so the next import loads fresh code
+    /// see [QuickJsRealmAdapter::invalidate_module](crate::quickjsrealmadapter::QuickJsRealmAdapter::invalidate_module)
+    pub fn invalidate_module(
+        &self,
+        realm_name: Option<&str>,
+        path: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<String>, JsError>>>> {
+        let path = path.to_string();
+        self.loop_realm(realm_name, move |_rt, realm| {
+            realm.invalidate_module(path.as_str())
+        })
+    }
+
+    /// invalidate a module and all modules which import it so the next import loads fresh code
+    /// see [QuickJsRealmAdapter::invalidate_module](crate::quickjsrealmadapter::QuickJsRealmAdapter::invalidate_module)
+    pub fn invalidate_module_sync(
+        &self,
+        realm_name: Option<&str>,
+        path: &str,
+    ) -> Result<Vec<String>, JsError> {
+        let path = path.to_string();
+        self.loop_realm_sync(realm_name, move |_rt, realm| {
+            realm.invalidate_module(path.as_str())
+        })
+    }
+
     /// invoke a function in the engine and get the result synchronously
     /// # example
     /// ```rust
@@ -966,8 +1131,8 @@ pub mod tests {
     use std::panic;
     use std::time::Duration;
 
-    struct TestNativeModuleLoader {}
-    struct TestScriptModuleLoader {}
+    pub(crate) struct TestNativeModuleLoader {}
+    pub(crate) struct TestScriptModuleLoader {}
 
     impl NativeModuleLoader for TestNativeModuleLoader {
         fn has_module(&self, _q_ctx: &QuickJsRealmAdapter, module_name: &str) -> bool {
@@ -1062,6 +1227,61 @@ pub mod tests {
         }
     }
 
+    #[test]
+    fn test_eval_module_settled() {
+        use crate::jsutils::modules::{AsyncModuleLoader, ModuleSourceFuture};
+
+        struct NoAsyncModules {}
+        impl AsyncModuleLoader for NoAsyncModules {
+            fn normalize_path(
+                &self,
+                _realm: &QuickJsRealmAdapter,
+                _ref_path: &str,
+                _path: &str,
+            ) -> Option<String> {
+                None
+            }
+
+            fn load_module(
+                &self,
+                _realm: &QuickJsRealmAdapter,
+                absolute_path: &str,
+            ) -> ModuleSourceFuture {
+                let err = JsError::new_string(format!("no such module {absolute_path}"));
+                Box::pin(async move { Err(err) })
+            }
+        }
+
+        // eval_module has the same result with or without async module loaders
+        for rt in [
+            QuickJsRuntimeFacade::builder().build(),
+            QuickJsRuntimeFacade::builder()
+                .async_module_loader(NoAsyncModules {})
+                .build(),
+        ] {
+            let res = block_on(rt.eval_module(
+                None,
+                Script::new(
+                    "settled_ok.mes",
+                    "await null;\nglobalThis.settled = 'done';",
+                ),
+            ))
+            .expect("module failed");
+            assert!(!matches!(res, JsValueFacade::JsPromise { .. }));
+            let res = rt
+                .eval_sync(None, Script::new("settled.js", "settled"))
+                .expect("script failed");
+            assert_eq!(res.get_str(), "done");
+
+            let res = block_on(rt.eval_module(
+                None,
+                Script::new("settled_err.mes", "await null;\nthrow Error('later');"),
+            ));
+            let err = res.expect_err("module should fail");
+            assert_eq!(err.get_message(), "later");
+        }
+    }
+
     pub fn init_logging() {
         {
             let i_lock = &mut *crate::facades::INITTED.lock().unwrap();
@@ -0,0 +1,385 @@
+//! CommonJS support, this adds a global require() function
+//!
+//! modules are resolved and loaded by the ScriptModuleLoaders of the runtime (after the import map was applied),
+//! the code of a module is run in a function with `exports`, `require`, `module`, `__filename` and `__dirname` like Node does
+//!
+//! modules are cached per realm by their normalized path, a module which requires a module that is still loading (a cycle)
+//! gets the exports of that module as they are at that moment, again like Node does
+//!
+//! ES modules may import a CommonJS module (a .cjs file or a module which a ScriptModuleLoader loads as ModuleSource::CommonJs),
+//! module.exports is the default export of that module, please note that the CommonJS module is run when the ES module is linked
+//!
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::modules::ScriptModuleLoader;
+//! use quickjs_runtime::jsutils::Script;
+//! use quickjs_runtime::quickjsrealmadapter::QuickJsRealmAdapter;
+//!
+//! struct CjsLoader {}
+//! impl ScriptModuleLoader for CjsLoader {
+//!     fn normalize_path(&self, _realm: &QuickJsRealmAdapter, _ref_path: &str, path: &str) -> Option<String> {
+//!         Some(path.to_string())
+//!     }
+//!     fn load_module(&self, _realm: &QuickJsRealmAdapter, _absolute_path: &str) -> String {
+//!         "exports.mul = function(a, b) { return a * b; };".to_string()
+//!     }
+//! }
+//!
+//! let rt = QuickJsRuntimeBuilder::new().script_module_loader(CjsLoader{}).build();
+//! let res = rt.eval_sync(None, Script::new("main.js", "require('util.js').mul(6, 7);")).expect("script failed");
+//! assert_eq!(res.get_i32(), 42);
+//! ```
+
+use crate::jsutils::modules::ModuleSource;
+use crate::jsutils::{JsError, Script};
+use crate::quickjs_utils::{compile, functions, get_global_q, json, modules, typedarrays};
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use std::path::Path;
+
+pub fn init(q_js_rt: &QuickJsRuntimeAdapter) -> Result<(), JsError> {
+    log::trace!("commonjs::init");
+
+    q_js_rt.add_context_init_hook(|_q_js_rt, realm| {
+        // scripts resolve relative to the root of the loaders
+        let require = create_require(realm, "")?;
+        realm.set_object_property(&get_global_q(realm), "require", &require)
+    })
+}
+
+/// create the require function for a module
+fn create_require(
+    realm: &QuickJsRealmAdapter,
+    referrer: &str,
+) -> Result<QuickJsValueAdapter, JsError> {
+    let require_referrer = referrer.to_string();
+    let require = realm.create_function(
+        "require",
+        move |realm, _this, args| {
+            let specifier = get_specifier(args, "require")?;
+            let module = require_module(realm, require_referrer.as_str(), specifier.as_str())?;
+            realm.get_object_property(&module, "exports")
+        },
+        1,
+    )?;
+
+    let resolve_referrer = referrer.to_string();
+    let resolve = realm.create_function(
+        "resolve",
+        move |realm, _this, args| {
+            let specifier = get_specifier(args, "require.resolve")?;
+            let path = resolve_path(realm, resolve_referrer.as_str(), specifier.as_str())?;
+            realm.create_string(path.as_str())
+        },
+        1,
+    )?;
+    realm.set_object_property(&require, "resolve", &resolve)?;
+    Ok(require)
+}
+
+fn get_specifier(args: &[QuickJsValueAdapter], function_name: &str) -> Result<String, JsError> {
+    match args.first() {
+        Some(arg) if arg.is_string() => arg.to_string(),
+        _ => Err(JsError::new_string(format!(
+            "{function_name} expects a string as first argument"
+        ))),
+    }
+}
+
+fn resolve_path(
+    realm: &QuickJsRealmAdapter,
+    referrer: &str,
+    specifier: &str,
+) -> Result<String, JsError> {
+    let mapped_name = realm.resolve_import_map(specifier, referrer);
+    let name = mapped_name.as_deref().unwrap_or(specifier);
+    QuickJsRuntimeAdapter::do_with(|rt| {
+        rt.with_all_module_loaders(|loader| loader.normalize_path(realm, referrer, name))
+    })
+    .ok_or_else(|| JsError::new_string(format!("Cannot find module '{specifier}'")))
+}
+
+/// a module which was resolved by require
+enum Resolved {
+    Cached(QuickJsValueAdapter),
+    Source(String, Result<ModuleSource, JsError>),
+}
+
+/// require a module, returns the module object
+fn require_module(
+    realm: &QuickJsRealmAdapter,
+    referrer: &str,
+    specifier: &str,
+) -> Result<QuickJsValueAdapter, JsError> {
+    let mapped_name = realm.resolve_import_map(specifier, referrer);
+    let name = mapped_name.as_deref().unwrap_or(specifier);
+    let resolved = QuickJsRuntimeAdapter::do_with(|rt| {
+        rt.with_all_module_loaders(|loader| {
+            let path = loader.normalize_path(realm, referrer, name)?;
+            if let Some(module) = realm
+                .module_registry
+                .borrow()
+                .commonjs_modules
+                .get(path.as_str())
+            {
+                return Some(Resolved::Cached(module.clone()));
+            }
+            // only loaders which provide source (ScriptModuleLoaders) return Some
+            loader
+                .get_module_source(realm, path.as_str())
+                .map(|source| Resolved::Source(path, source))
+        })
+    })
+    .ok_or_else(|| JsError::new_string(format!("Cannot find module '{specifier}'")))?;
+
+    match resolved {
+        Resolved::Cached(module) => Ok(module),
+        Resolved::Source(path, source) => load_module(realm, path.as_str(), source?),
+    }
+}
+
+/// get the exports of a CommonJS module for an ES module which imports it
+pub(crate) fn get_module_exports(
+    realm: &QuickJsRealmAdapter,
+    path: &str,
+    code: String,
+) -> Result<QuickJsValueAdapter, JsError> {
+    let cached = realm
+        .module_registry
+        .borrow()
+        .commonjs_modules
+        .get(path)
+        .cloned();
+    let module = match cached {
+        Some(module) => module,
+        None => load_module(realm, path, ModuleSource::Script(code))?,
+    };
+    realm.get_object_property(&module, "exports")
+}
+
+/// see if a script module is a CommonJS module, other CommonJS modules are loaded as ModuleSource::CommonJs
+pub(crate) fn is_commonjs(path: &str) -> bool {
+    path.ends_with(".cjs")
+}
+
+/// load a module and add it to the cache, returns the module object
+fn load_module(
+    realm: &QuickJsRealmAdapter,
+    path: &str,
+    source: ModuleSource,
+) -> Result<QuickJsValueAdapter, JsError> {
+    let module = realm.create_object()?;
+    realm.set_object_property(&module, "id", &realm.create_string(path)?)?;
+    realm.set_object_property(&module, "filename", &realm.create_string(path)?)?;
+    realm.set_object_property(&module, "exports", &realm.create_object()?)?;
+    realm.set_object_property(&module, "loaded", &realm.create_boolean(false)?)?;
+
+    // cache the module before it runs so cycles get the exports as they are so far
+    realm
+        .module_registry
+        .borrow_mut()
+        .commonjs_modules
+        .insert(path.to_string(), module.clone());
+
+    let res = match source {
+        ModuleSource::Script(code) if path.ends_with(".json") => {
+            set_exports(realm, &module, json::parse_q(realm, code.as_str()))
+        }
+        ModuleSource::Script(code) | ModuleSource::CommonJs(code) => {
+            run_module(realm, path, code.as_str(), &module)
+        }
+        ModuleSource::Json(code) => {
+            set_exports(realm, &module, json::parse_q(realm, code.as_str()))
+        }
+        ModuleSource::Text(text) => set_exports(realm, &module, realm.create_string(text.as_str())),
+        ModuleSource::Bytes(bytes) => {
+            set_exports(realm, &module, typedarrays::new_uint8_array_q(realm, bytes))
+        }
+        ModuleSource::Bytecode(_) => Err(JsError::new_string(format!(
+            "require() of compiled module {path} is not supported"
+        ))),
+    };
+    if let Err(e) = res {
+        realm
+            .module_registry
+            .borrow_mut()
+            .commonjs_modules
+            .remove(path);
+        return Err(e);
+    }
+
+    realm.set_object_property(&module, "loaded", &realm.create_boolean(true)?)?;
+    Ok(module)
+}
+
+fn set_exports(
+    realm: &QuickJsRealmAdapter,
+    module: &QuickJsValueAdapter,
+    exports: Result<QuickJsValueAdapter, JsError>,
+) -> Result<(), JsError> {
+    realm.set_object_property(module, "exports", &exports?)
+}
+
+/// run the code of a module in a function like Node does
+fn run_module(
+    realm: &QuickJsRealmAdapter,
+    path: &str,
+    code: &str,
+    module: &QuickJsValueAdapter,
+) -> Result<(), JsError> {
+    if modules::detect_module(code) {
+        return Err(JsError::new_string(format!(
+            "require() of ES module {path} is not supported, use import instead"
+        )));
+    }
+
+    let compiled = unsafe {
+        compile::compile_cached(realm.context, Script::new(path, code), false, |script| {
+            let script = QuickJsRuntimeAdapter::pre_process(realm, script)?;
+            // the wrapper is on the first line so line numbers don't change
+            let code = format!(
+                "(function (exports, require, module, __filename, __dirname) {{{}\n}})",
+                script.get_runnable_code()
+            );
+            Ok(Script::new(script.get_path(), code.as_str()))
+        })?
+    };
+    let func = unsafe { compile::run_compiled_function(realm.context, &compiled)? };
+
+    let dirname = Path::new(path)
+        .parent()
+        .map(|dir| dir.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let exports = realm.get_object_property(module, "exports")?;
+    let args = [
+        exports.clone(),
+        create_require(realm, path)?,
+        module.clone(),
+        realm.create_string(path)?,
+        realm.create_string(dirname.as_str())?,
+    ];
+    functions::call_function_q(realm, &func, &args, Some(&exports))?;
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::modules::{ModuleSource, ScriptModuleLoader};
+    use crate::jsutils::{JsError, Script};
+    use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+
+    struct CjsLoader {}
+    impl ScriptModuleLoader for CjsLoader {
+        fn normalize_path(
+            &self,
+            _realm: &QuickJsRealmAdapter,
+            _ref_path: &str,
+            path: &str,
+        ) -> Option<String> {
+            let path = path.trim_start_matches("./");
+            let path = if path.contains('.') {
+                path.to_string()
+            } else {
+                format!("lib/{path}.js")
+            };
+            Some(path)
+        }
+
+        fn load_module(&self, _realm: &QuickJsRealmAdapter, absolute_path: &str) -> String {
+            match absolute_path {
+                "lib/a.js" => "exports.loaded = false;\nconst b = require('b');\nexports.fromB = b.fromA;\nexports.loaded = true;".to_string(),
+                "lib/b.js" => "const a = require('a');\nexports.fromA = a.loaded;\nexports.dir = __dirname;\nexports.file = __filename;".to_string(),
+                "lib/counter.js" => "globalThis.counterRuns = (globalThis.counterRuns || 0) + 1;\nmodule.exports = function() { return 42; };".to_string(),
+                "data.json" => "{\"answer\": 42}".to_string(),
+                "esm.mjs" => "export const a = 1;".to_string(),
+                "main.mjs" => "import counter from 'counter';\nimport data from 'data.json' with { type: 'json' };\nglobalThis.esmAnswer = counter() === data.answer;".to_string(),
+                "lib/util.cjs" => "exports.answer = 42;".to_string(),
+                "main2.mjs" => "import util from 'lib/util.cjs';\nimport * as sniffed from 'lib/sniffed.js';\nglobalThis.esmAnswer2 = util.answer === 42 && sniffed.answer === 42;".to_string(),
+                // looks like CommonJS but it is loaded as a Script so it is an ES module
+                "lib/sniffed.js" => "const s = 'exports.a = require(\\'x\\')';\nexport const answer = 42;".to_string(),
+                _ => "throw Error('unexpected module');".to_string(),
+            }
+        }
+
+        fn load_module_source(
+            &self,
+            realm: &QuickJsRealmAdapter,
+            absolute_path: &str,
+        ) -> Result<ModuleSource, JsError> {
+            let code = self.load_module(realm, absolute_path);
+            if absolute_path == "lib/counter.js" {
+                Ok(ModuleSource::CommonJs(code))
+            } else {
+                Ok(ModuleSource::Script(code))
+            }
+        }
+    }
+
+    #[test]
+    fn test_require() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_module_loader(CjsLoader {})
+            .build();
+
+        // cycle, a gets the exports of b as they were when b required a
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test_require.js",
+                    "const a = require('a'); const b = require('./b'); `${a.loaded}-${a.fromB}-${b.dir}-${b.file}`;",
+                ),
+            )
+            .expect("script failed");
+        assert_eq!(res.get_str(), "true-false-lib-lib/b.js");
+
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test_require2.js",
+                    "require('counter')(); require('counter')() + require('data.json').answer + counterRuns;",
+                ),
+            )
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 85);
+
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new("test_require3.js", "require.resolve('./counter');"),
+            )
+            .expect("script failed");
+        assert_eq!(res.get_str(), "lib/counter.js");
+
+        let err = rt
+            .eval_sync(None, Script::new("test_require4.js", "require('esm.mjs');"))
+            .expect_err("esm was required");
+        assert!(err.get_message().contains("ES module"));
+
+        // esm importing commonjs, the module was cached so it does not run again
+        rt.eval_module_sync(None, Script::new("test_require.mjs", "import 'main.mjs';"))
+            .expect("module failed");
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new("test_require5.js", "esmAnswer && counterRuns === 1;"),
+            )
+            .expect("script failed");
+        assert!(res.get_bool());
+
+        // a .cjs module is CommonJS, a Script which is not a .cjs module is an ES module
+        rt.eval_module_sync(
+            None,
+            Script::new("test_require2.mjs", "import 'main2.mjs';"),
+        )
+        .expect("module failed");
+        let res = rt
+            .eval_sync(None, Script::new("test_require6.js", "esmAnswer2;"))
+            .expect("script failed");
+        assert!(res.get_bool());
+    }
+}
@@ -2,6 +2,8 @@
 
 use crate::facades::QuickJsRuntimeFacade;
 use crate::jsutils::JsError;
+#[cfg(feature = "commonjs")]
+pub mod commonjs;
 #[cfg(feature = "console")]
 pub mod console;
 #[cfg(any(feature = "settimeout", feature = "setinterval"))]
@@ -13,7 +15,8 @@ pub mod setimmediate;
     feature = "settimeout",
     feature = "setinterval",
     feature = "console",
-    feature = "setimmediate"
+    feature = "setimmediate",
+    feature = "commonjs"
 ))]
 pub fn init(es_rt: &QuickJsRuntimeFacade) -> Result<(), JsError> {
     log::trace!("features::init");
@@ -26,6 +29,8 @@ pub fn init(es_rt: &QuickJsRuntimeFacade) -> Result<(), JsError> {
 
         #[cfg(any(feature = "settimeout", feature = "setinterval"))]
         set_timeout::init(q_js_rt)?;
+        #[cfg(feature = "commonjs")]
+        commonjs::init(q_js_rt)?;
         Ok(())
     })
 }
@@ -0,0 +1,237 @@
+//! a cache for compiled scripts and modules so they don't have to be pre-processed and parsed again
+//!
+//! entries are keyed by a hash of the path and code of the script, the engine (bellard or quickjs-ng),
+//! the version of this crate and the cache keys of the ScriptPreProcessors
+//!
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::bytecodecache::DirectoryBytecodeStore;
+//! let store = DirectoryBytecodeStore::new(std::env::temp_dir().join("my_app_bytecode")).expect("could not create cache dir");
+//! let rt = QuickJsRuntimeBuilder::new().bytecode_cache(store).build();
+//! ```
+
+use crate::jsutils::JsError;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// a store for cached bytecode
+pub trait BytecodeStore {
+    fn get(&self, key: &str) -> Option<Vec<u8>>;
+    fn put(&self, key: &str, bytecode: &[u8]);
+}
+
+/// a BytecodeStore which keeps the bytecode in memory, handy for runtimes which evaluate the same scripts in many realms
+#[derive(Default)]
+pub struct MemoryBytecodeStore {
+    entries: RefCell<HashMap<String, Vec<u8>>>,
+}
+
+impl MemoryBytecodeStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.borrow().is_empty()
+    }
+}
+
+impl BytecodeStore for MemoryBytecodeStore {
+    fn get(&self, key: &str) -> Option<Vec<u8>> {
+        self.entries.borrow().get(key).cloned()
+    }
+
+    fn put(&self, key: &str, bytecode: &[u8]) {
+        self.entries
+            .borrow_mut()
+            .insert(key.to_string(), bytecode.to_vec());
+    }
+}
+
+/// a BytecodeStore which persists the bytecode as files in a directory
+pub struct DirectoryBytecodeStore {
+    dir: PathBuf,
+}
+
+impl DirectoryBytecodeStore {
+    /// create a new store, the directory is created if it does not exist
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Result<Self, JsError> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir).map_err(|e| {
+            JsError::new_string(format!(
+                "could not create bytecode cache dir {}: {e}",
+                dir.display()
+            ))
+        })?;
+        Ok(Self { dir })
+    }
+
+    fn get_file(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.qjsc"))
+    }
+}
+
+impl BytecodeStore for DirectoryBytecodeStore {
+    fn get(&self, key: &str) -> Option<Vec<u8>> {
+        std::fs::read(self.get_file(key)).ok()
+    }
+
+    fn put(&self, key: &str, bytecode: &[u8]) {
+        // write to a temp file first so a concurrent reader never sees half a file
+        let file = self.get_file(key);
+        let tmp_file = self.dir.join(format!("{key}.{}.tmp", std::process::id()));
+        let res =
+            std::fs::write(&tmp_file, bytecode).and_then(|_| std::fs::rename(&tmp_file, &file));
+        if let Err(e) = res {
+            log::error!("could not write bytecode cache {}: {e}", file.display());
+            let _ = std::fs::remove_file(&tmp_file);
+        }
+    }
+}
+
+/// the engine the bytecode was compiled with, bytecode of one engine can not be read by the other
+pub(crate) const ENGINE: &str = if cfg!(feature = "quickjs-ng") {
+    "quickjs-ng"
+} else {
+    "bellard"
+};
+
+/// create the key for a cache entry, this is a hex string of 128 bits
+pub(crate) fn cache_key(kind: &str, path: &str, code: &str, pre_processors: &str) -> String {
+    let mut hasher = StableHasher::new();
+    for field in [
+        ENGINE,
+        env!("CARGO_PKG_VERSION"),
+        kind,
+        path,
+        code,
+        pre_processors,
+    ] {
+        hasher.write_str(field);
+    }
+    format!("{:032x}", hasher.finish())
+}
+
+/// a 128 bit FNV-1a hash, unlike DefaultHasher it does not change between Rust releases so keys of persisted
+/// entries stay valid
+pub(crate) struct StableHasher(u128);
+
+impl StableHasher {
+    pub(crate) fn new() -> Self {
+        Self(0x6c62272e07bb014262b821756295c58d)
+    }
+
+    pub(crate) fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 = (self.0 ^ *b as u128).wrapping_mul(0x0000000001000000000000000000013b);
+        }
+    }
+
+    /// write a string prefixed with its length so ("ab", "c") and ("a", "bc") don't have the same hash
+    pub(crate) fn write_str(&mut self, s: &str) {
+        self.write(&(s.len() as u64).to_le_bytes());
+        self.write(s.as_bytes());
+    }
+
+    pub(crate) fn finish(&self) -> u128 {
+        self.0
+    }
+}
+
+/// an entry is the length of the source map (u32 le), the source map and the bytecode
+pub(crate) fn encode_entry(map: Option<&str>, bytecode: &[u8]) -> Vec<u8> {
+    let map = map.unwrap_or_default().as_bytes();
+    let mut entry = Vec::with_capacity(4 + map.len() + bytecode.len());
+    entry.extend_from_slice(&(map.len() as u32).to_le_bytes());
+    entry.extend_from_slice(map);
+    entry.extend_from_slice(bytecode);
+    entry
+}
+
+/// decode an entry into the source map and the bytecode
+pub(crate) fn decode_entry(entry: &[u8]) -> Option<(Option<&str>, &[u8])> {
+    let map_len = u32::from_le_bytes(entry.get(0..4)?.try_into().ok()?) as usize;
+    let map = std::str::from_utf8(entry.get(4..4 + map_len)?).ok()?;
+    let bytecode = entry.get(4 + map_len..)?;
+    if bytecode.is_empty() {
+        return None;
+    }
+    Some(((!map.is_empty()).then_some(map), bytecode))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::bytecodecache::{cache_key, MemoryBytecodeStore, StableHasher};
+    use crate::jsutils::{Script, ScriptPreProcessor};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    struct CountingPreProcessor {
+        count: Arc<AtomicUsize>,
+    }
+    impl ScriptPreProcessor for CountingPreProcessor {
+        fn process(&self, _script: &mut Script) -> Result<(), crate::jsutils::JsError> {
+            self.count.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_bytecode_cache() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_pre_processor(CountingPreProcessor {
+                count: count.clone(),
+            })
+            .bytecode_cache(MemoryBytecodeStore::new())
+            .build();
+
+        for _ in 0..3 {
+            let res = rt
+                .eval_sync(None, Script::new("cached.js", "7 * 6;"))
+                .expect("script failed");
+            assert_eq!(res.get_i32(), 42);
+        }
+        // compiled once, then loaded from the cache
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        // changed code is a new entry
+        let res = rt
+            .eval_sync(None, Script::new("cached.js", "7 * 7;"))
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 49);
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+
+        rt.eval_module_sync(
+            None,
+            Script::new("cached_module.mes", "globalThis.cached_module = 1;"),
+        )
+        .expect("module failed");
+        rt.eval_module_sync(
+            None,
+            Script::new("cached_module.mes", "globalThis.cached_module += 1;"),
+        )
+        .expect("module failed");
+        assert_eq!(count.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn test_cache_key() {
+        // the FNV-1a test vector, keys must not change between builds
+        let mut hasher = StableHasher::new();
+        hasher.write(b"a");
+        assert_eq!(hasher.finish(), 0xd228cb696f1a8caf78912b704e4a8964);
+
+        let key = cache_key("script", "a.js", "1;", "");
+        assert_eq!(key.len(), 32);
+        assert_eq!(key, cache_key("script", "a.js", "1;", ""));
+        assert_ne!(key, cache_key("script", "a.j", "s1;", ""));
+    }
+}
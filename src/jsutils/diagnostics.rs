@@ -0,0 +1,143 @@
+//! structured diagnostics (e.g. syntax errors and warnings of the TypeScript transpiler)
+
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DiagnosticSeverity {
+    Error,
+    Warning,
+    Note,
+    Help,
+}
+
+impl Display for DiagnosticSeverity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiagnosticSeverity::Error => f.write_str("error"),
+            DiagnosticSeverity::Warning => f.write_str("warning"),
+            DiagnosticSeverity::Note => f.write_str("note"),
+            DiagnosticSeverity::Help => f.write_str("help"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub severity: DiagnosticSeverity,
+    pub message: String,
+    /// an identifier of the kind of diagnostic, if the producer has one
+    pub code: Option<String>,
+    pub file_name: Option<String>,
+    /// 1-based
+    pub line_number: Option<u32>,
+    /// 1-based
+    pub column_number: Option<u32>,
+    /// the source line(s) with a marker at the column, see code_frame
+    pub code_frame: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: DiagnosticSeverity, message: &str) -> Self {
+        Self {
+            severity,
+            message: message.to_string(),
+            code: None,
+            file_name: None,
+            line_number: None,
+            column_number: None,
+            code_frame: None,
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.severity)?;
+        if let Some(code) = &self.code {
+            write!(f, "[{code}]")?;
+        }
+        write!(f, ": {}", self.message)?;
+        if let Some(file_name) = &self.file_name {
+            write!(f, "\n  --> {file_name}")?;
+            if let Some(line_number) = self.line_number {
+                write!(f, ":{line_number}")?;
+                if let Some(column_number) = self.column_number {
+                    write!(f, ":{column_number}")?;
+                }
+            }
+        }
+        if let Some(code_frame) = &self.code_frame {
+            write!(f, "\n{code_frame}")?;
+        }
+        Ok(())
+    }
+}
+
+/// render a line of source with a marker under the (1-based) column
+/// # Example
+/// ```rust
+/// use quickjs_runtime::jsutils::diagnostics::code_frame;
+/// assert_eq!(code_frame("let a = ;", 3, 9, 1), "3 | let a = ;\n  |         ^");
+/// ```
+pub fn code_frame(line: &str, line_number: u32, column_number: u32, width: usize) -> String {
+    let gutter = line_number.to_string();
+    let line = line.trim_end_matches(['\r', '\n']);
+    // keep tabs so the marker lines up with the source
+    let indent: String = line
+        .chars()
+        .take(column_number.saturating_sub(1) as usize)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    format!(
+        "{gutter} | {line}\n{} | {indent}{}",
+        " ".repeat(gutter.len()),
+        "^".repeat(width.max(1))
+    )
+}
+
+/// render a line of source with context_lines lines before and after it, the line is marked with a '>'
+/// and if the (1-based) column is known a marker is placed under it, None if the source has no such line
+/// # Example
+/// ```rust
+/// use quickjs_runtime::jsutils::diagnostics::code_frame_with_context;
+/// let source = "let a = 1;\nthrow new Error(a);\na++;\n";
+/// assert_eq!(
+///     code_frame_with_context(source, 2, Some(7), 1).unwrap(),
+///     "  1 | let a = 1;\n> 2 | throw new Error(a);\n    |       ^\n  3 | a++;"
+/// );
+/// ```
+pub fn code_frame_with_context(
+    source: &str,
+    line_number: u32,
+    column_number: Option<u32>,
+    context_lines: u32,
+) -> Option<String> {
+    let lines: Vec<&str> = source.lines().collect();
+    if line_number == 0 || line_number as usize > lines.len() {
+        return None;
+    }
+    let first = line_number.saturating_sub(context_lines).max(1);
+    let last = line_number
+        .saturating_add(context_lines)
+        .min(lines.len() as u32);
+    let gutter_width = last.to_string().len();
+    let mut frame: Vec<String> = vec![];
+    for current in first..=last {
+        let line = lines[current as usize - 1].trim_end_matches('\r');
+        let marker = if current == line_number { '>' } else { ' ' };
+        frame.push(format!("{marker} {current:>gutter_width$} | {line}"));
+        if current == line_number {
+            if let Some(column_number) = column_number {
+                // keep tabs so the marker lines up with the source
+                let indent: String = line
+                    .chars()
+                    .take(column_number.saturating_sub(1) as usize)
+                    .map(|c| if c == '\t' { '\t' } else { ' ' })
+                    .collect();
+                frame.push(format!("  {} | {indent}^", " ".repeat(gutter_width)));
+            }
+        }
+    }
+    Some(frame.join("\n"))
+}
@@ -0,0 +1,611 @@
+//! a ScriptModuleLoader which loads modules from the filesystem
+//!
+//! modules are resolved like node does it:
+//! * relative (./ and ../) and absolute (/) paths, the latter are relative to the root of the loader
+//! * extension probing (.js, .mjs, .ts) and index files for directories
+//! * bare specifiers are looked up in node_modules directories from the importing module up to the root
+//! * package.json exports (conditions in the order of the exports object, the longest matching subpath pattern), module and main fields
+//!
+//! .json files are loaded as json modules (the parsed json is the default export)
+//!
+//! paths which resolve outside the root of the loader are refused
+//!
+//! .ts files are transpiled by the typescript pre processor when the typescript feature is enabled
+//!
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::fsmoduleloader::FileSystemModuleLoader;
+//! let root = std::env::temp_dir();
+//! let loader = FileSystemModuleLoader::new(root).expect("invalid root");
+//! let rt = QuickJsRuntimeBuilder::new().script_module_loader(loader).build();
+//! ```
+
+use crate::jsutils::modules::{ModuleSource, ScriptModuleLoader};
+use crate::jsutils::JsError;
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use serde::de::{MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+use std::fmt::Formatter;
+use std::path::{Component, Path, PathBuf};
+
+const DEFAULT_EXTENSIONS: [&str; 3] = ["js", "mjs", "ts"];
+const EXPORT_CONDITIONS: [&str; 3] = ["import", "module", "default"];
+
+/// the exports field of a package.json, unlike a serde_json::Value this keeps the order of the keys because the first
+/// matching condition wins
+#[derive(Debug)]
+enum Exports {
+    Target(String),
+    Map(Vec<(String, Exports)>),
+    Alternatives(Vec<Exports>),
+    /// null or another value which does not export anything
+    None,
+}
+
+impl<'de> Deserialize<'de> for Exports {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ExportsVisitor;
+        impl<'de> Visitor<'de> for ExportsVisitor {
+            type Value = Exports;
+
+            fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+                f.write_str("a package.json exports value")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Exports, E> {
+                Ok(Exports::Target(v.to_string()))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Exports, A::Error> {
+                let mut entries = vec![];
+                while let Some(entry) = map.next_entry::<String, Exports>()? {
+                    entries.push(entry);
+                }
+                Ok(Exports::Map(entries))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Exports, A::Error> {
+                let mut alternatives = vec![];
+                while let Some(alternative) = seq.next_element::<Exports>()? {
+                    alternatives.push(alternative);
+                }
+                Ok(Exports::Alternatives(alternatives))
+            }
+
+            fn visit_unit<E: serde::de::Error>(self) -> Result<Exports, E> {
+                Ok(Exports::None)
+            }
+
+            fn visit_bool<E: serde::de::Error>(self, _v: bool) -> Result<Exports, E> {
+                Ok(Exports::None)
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, _v: i64) -> Result<Exports, E> {
+                Ok(Exports::None)
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, _v: u64) -> Result<Exports, E> {
+                Ok(Exports::None)
+            }
+
+            fn visit_f64<E: serde::de::Error>(self, _v: f64) -> Result<Exports, E> {
+                Ok(Exports::None)
+            }
+        }
+        deserializer.deserialize_any(ExportsVisitor)
+    }
+}
+
+#[derive(Deserialize)]
+struct PackageExports {
+    exports: Option<Exports>,
+}
+
+pub struct FileSystemModuleLoader {
+    root: PathBuf,
+    extensions: Vec<String>,
+}
+
+impl FileSystemModuleLoader {
+    /// create a new loader which only loads files in root (or its subdirectories)
+    pub fn new<P: AsRef<Path>>(root: P) -> Result<Self, JsError> {
+        let root = root.as_ref().canonicalize().map_err(|e| {
+            JsError::new_string(format!(
+                "invalid module root {}: {e}",
+                root.as_ref().display()
+            ))
+        })?;
+        Ok(Self {
+            root,
+            extensions: DEFAULT_EXTENSIONS.iter().map(|e| e.to_string()).collect(),
+        })
+    }
+
+    /// set the extensions which are probed (in order) when a path has no extension, defaults to js, mjs and ts
+    pub fn extensions(mut self, extensions: &[&str]) -> Self {
+        self.extensions = extensions.iter().map(|e| e.to_string()).collect();
+        self
+    }
+
+    /// the (canonicalized) root of this loader
+    pub fn get_root(&self) -> &Path {
+        self.root.as_path()
+    }
+
+    /// the directory which relative imports are resolved against, modules outside the root (like an eval'ed script) resolve against the root
+    fn get_base_dir(&self, ref_path: &str) -> PathBuf {
+        let ref_path = Path::new(ref_path);
+        if ref_path.starts_with(&self.root) {
+            if ref_path.is_dir() {
+                return ref_path.to_path_buf();
+            }
+            if let Some(parent) = ref_path.parent() {
+                return parent.to_path_buf();
+            }
+        }
+        self.root.clone()
+    }
+
+    /// resolve . and .. without touching the filesystem, None if the path would leave the root
+    fn normalize_lexically(&self, path: &Path) -> Option<PathBuf> {
+        let mut normalized = PathBuf::new();
+        for component in path.components() {
+            match component {
+                Component::ParentDir => {
+                    if !normalized.pop() {
+                        return None;
+                    }
+                }
+                Component::CurDir => {}
+                c => normalized.push(c.as_os_str()),
+            }
+        }
+        if normalized.starts_with(&self.root) {
+            Some(normalized)
+        } else {
+            None
+        }
+    }
+
+    /// check the path is still in the root after resolving symlinks
+    fn confine(&self, path: PathBuf) -> Option<PathBuf> {
+        match path.canonicalize() {
+            Ok(canonical) if canonical.starts_with(&self.root) => Some(canonical),
+            _ => {
+                log::warn!(
+                    "FileSystemModuleLoader refused {} which is outside of {}",
+                    path.display(),
+                    self.root.display()
+                );
+                None
+            }
+        }
+    }
+
+    fn resolve_file(&self, path: &Path) -> Option<PathBuf> {
+        if path.is_file() {
+            return Some(path.to_path_buf());
+        }
+        let file_name = path.file_name()?.to_str()?;
+        self.extensions
+            .iter()
+            .map(|ext| path.with_file_name(format!("{file_name}.{ext}")))
+            .find(|candidate| candidate.is_file())
+    }
+
+    fn resolve_index(&self, dir: &Path) -> Option<PathBuf> {
+        self.extensions
+            .iter()
+            .map(|ext| dir.join(format!("index.{ext}")))
+            .find(|candidate| candidate.is_file())
+    }
+
+    fn resolve_file_or_dir(&self, path: &Path) -> Option<PathBuf> {
+        if let Some(file) = self.resolve_file(path) {
+            return Some(file);
+        }
+        if path.is_dir() {
+            if let Some(entry) = self.resolve_package_entry(path, ".") {
+                return Some(entry);
+            }
+            return self.resolve_index(path);
+        }
+        None
+    }
+
+    fn read_package_json(dir: &Path) -> Option<Value> {
+        let json = std::fs::read_to_string(dir.join("package.json")).ok()?;
+        match serde_json::from_str(json.as_str()) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                log::warn!("invalid package.json in {}: {e}", dir.display());
+                None
+            }
+        }
+    }
+
+    /// the exports field of the package.json in dir, None if there is no package.json or it has no exports
+    fn read_package_exports(dir: &Path) -> Option<Exports> {
+        let json = std::fs::read_to_string(dir.join("package.json")).ok()?;
+        serde_json::from_str::<PackageExports>(json.as_str())
+            .ok()?
+            .exports
+    }
+
+    /// resolve a conditional export target, e.g. {"import": "./esm/index.js", "require": "./cjs/index.js"},
+    /// like node the first condition (in the order of the object) which we support is used
+    fn resolve_export_target(target: &Exports) -> Option<&str> {
+        match target {
+            Exports::Target(s) => Some(s.as_str()),
+            Exports::Map(conditions) => conditions
+                .iter()
+                .filter(|(condition, _)| EXPORT_CONDITIONS.contains(&condition.as_str()))
+                .find_map(|(_, target)| Self::resolve_export_target(target)),
+            Exports::Alternatives(alternatives) => {
+                alternatives.iter().find_map(Self::resolve_export_target)
+            }
+            Exports::None => None,
+        }
+    }
+
+    /// resolve the exports field of a package.json for a subpath like "." or "./utils"
+    fn resolve_exports(exports: &Exports, subpath: &str) -> Option<String> {
+        let map = match exports {
+            Exports::Map(map) if map.iter().all(|(key, _)| key.starts_with('.')) => map,
+            _ => {
+                return if subpath == "." {
+                    Self::resolve_export_target(exports).map(|t| t.to_string())
+                } else {
+                    None
+                };
+            }
+        };
+
+        if let Some((_, target)) = map.iter().find(|(key, _)| key == subpath) {
+            return Self::resolve_export_target(target).map(|t| t.to_string());
+        }
+        // subpath patterns like "./features/*": "./src/features/*.js", the pattern with the longest prefix wins
+        map.iter()
+            .filter_map(|(key, target)| {
+                let (prefix, suffix) = key.split_once('*')?;
+                if subpath.len() >= prefix.len() + suffix.len()
+                    && subpath.starts_with(prefix)
+                    && subpath.ends_with(suffix)
+                {
+                    Some((prefix.len(), key.len(), prefix, suffix, target))
+                } else {
+                    None
+                }
+            })
+            .max_by_key(|(prefix_len, key_len, ..)| (*prefix_len, *key_len))
+            .and_then(|(_, _, prefix, suffix, target)| {
+                let matched = &subpath[prefix.len()..subpath.len() - suffix.len()];
+                Self::resolve_export_target(target).map(|t| t.replace('*', matched))
+            })
+    }
+
+    /// resolve a subpath of a package dir by its package.json, subpath "." is the main entry
+    fn resolve_package_entry(&self, package_dir: &Path, subpath: &str) -> Option<PathBuf> {
+        let package_json = Self::read_package_json(package_dir)?;
+        if package_json.get("exports").is_some() {
+            // when a package has exports only the exported paths may be imported
+            let exports = Self::read_package_exports(package_dir)?;
+            let target = Self::resolve_exports(&exports, subpath)?;
+            return self.resolve_file(&self.normalize_lexically(&package_dir.join(target))?);
+        }
+        if subpath != "." {
+            return None;
+        }
+        for field in ["module", "main"] {
+            if let Some(main) = package_json.get(field).and_then(|m| m.as_str()) {
+                let main_path = self.normalize_lexically(&package_dir.join(main))?;
+                if let Some(resolved) = self.resolve_file_or_dir(&main_path) {
+                    return Some(resolved);
+                }
+            }
+        }
+        None
+    }
+
+    /// resolve a bare specifier like "lodash" or "@scope/pkg/utils.js" in the node_modules dirs from base_dir up to the root
+    fn resolve_bare(&self, base_dir: &Path, specifier: &str) -> Option<PathBuf> {
+        let mut parts = specifier.splitn(if specifier.starts_with('@') { 3 } else { 2 }, '/');
+        let package_name = if specifier.starts_with('@') {
+            format!("{}/{}", parts.next()?, parts.next()?)
+        } else {
+            parts.next()?.to_string()
+        };
+        let subpath = parts.next();
+
+        let mut dir = Some(base_dir);
+        while let Some(current) = dir {
+            if !current.starts_with(&self.root) {
+                break;
+            }
+            let package_dir = current.join("node_modules").join(package_name.as_str());
+            if package_dir.is_dir() {
+                return match subpath {
+                    None => self
+                        .resolve_package_entry(&package_dir, ".")
+                        .or_else(|| self.resolve_index(&package_dir)),
+                    Some(subpath) => {
+                        let has_exports = Self::read_package_json(&package_dir)
+                            .map(|p| p.get("exports").is_some())
+                            .unwrap_or(false);
+                        if has_exports {
+                            self.resolve_package_entry(&package_dir, &format!("./{subpath}"))
+                        } else {
+                            self.resolve_file_or_dir(
+                                &self.normalize_lexically(&package_dir.join(subpath))?,
+                            )
+                        }
+                    }
+                };
+            }
+            dir = current.parent();
+        }
+        None
+    }
+
+    fn read_module(absolute_path: &str) -> Result<String, JsError> {
+        std::fs::read_to_string(absolute_path)
+            .map_err(|e| JsError::new_string(format!("could not read module {absolute_path}: {e}")))
+    }
+
+    /// resolve a specifier imported by the module at ref_path to the absolute path of a file in the root
+    pub fn resolve(&self, ref_path: &str, path: &str) -> Option<PathBuf> {
+        let base_dir = self.get_base_dir(ref_path);
+        let resolved = if path.starts_with("./") || path.starts_with("../") {
+            self.resolve_file_or_dir(&self.normalize_lexically(&base_dir.join(path))?)
+        } else if Path::new(path).starts_with(&self.root) {
+            // an already normalized path
+            self.resolve_file_or_dir(&self.normalize_lexically(Path::new(path))?)
+        } else if let Some(root_relative) = path.strip_prefix('/') {
+            self.resolve_file_or_dir(&self.normalize_lexically(&self.root.join(root_relative))?)
+        } else if path.contains("://") {
+            None
+        } else {
+            self.resolve_bare(&base_dir, path)
+        };
+        resolved.and_then(|p| self.confine(p))
+    }
+}
+
+impl ScriptModuleLoader for FileSystemModuleLoader {
+    fn normalize_path(
+        &self,
+        _realm: &QuickJsRealmAdapter,
+        ref_path: &str,
+        path: &str,
+    ) -> Option<String> {
+        self.resolve(ref_path, path)
+            .and_then(|p| p.to_str().map(|s| s.to_string()))
+    }
+
+    /// modules are loaded with load_module_source, this returns an empty module when the file can not be read
+    fn load_module(&self, _realm: &QuickJsRealmAdapter, absolute_path: &str) -> String {
+        Self::read_module(absolute_path).unwrap_or_else(|e| {
+            log::error!("{e}");
+            String::new()
+        })
+    }
+
+    fn load_module_source(
+        &self,
+        _realm: &QuickJsRealmAdapter,
+        absolute_path: &str,
+    ) -> Result<ModuleSource, JsError> {
+        let code = Self::read_module(absolute_path)?;
+        if absolute_path.ends_with(".json") {
+            Ok(ModuleSource::Json(code))
+        } else {
+            Ok(ModuleSource::Script(code))
+        }
+    }
+
+    fn get_name(&self) -> &str {
+        "FileSystemModuleLoader"
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::fsmoduleloader::FileSystemModuleLoader;
+    use crate::jsutils::modules::ScriptModuleLoader;
+    use crate::jsutils::Script;
+    use std::ops::Deref;
+    use std::path::{Path, PathBuf};
+
+    /// the root dir of a test, the temp dir is removed when it is dropped
+    struct TestRoot {
+        dir: PathBuf,
+        root: PathBuf,
+    }
+
+    impl Deref for TestRoot {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            self.root.as_path()
+        }
+    }
+
+    impl Drop for TestRoot {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn write(root: &Path, path: &str, code: &str) {
+        let file = root.join(path);
+        std::fs::create_dir_all(file.parent().unwrap()).expect("could not create dir");
+        std::fs::write(file, code).expect("could not write file");
+    }
+
+    fn init_test_root(name: &str) -> TestRoot {
+        let dir = std::env::temp_dir().join(format!("{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let root = dir.join("root");
+        write(&root, "lib/math.js", "export const twice = (a) => a * 2;");
+        write(
+            &root,
+            "lib/strings/index.mjs",
+            "export const shout = (s) => s.toUpperCase();",
+        );
+        write(
+            &root,
+            "node_modules/pkg/package.json",
+            r#"{"name": "pkg", "exports": {".": {"require": "./cjs.js", "import": "./esm.js"}, "./extra": "./extra.js", "./features/*": "./features/*.js", "./features/internal/*": "./internal/*.js"}}"#,
+        );
+        write(
+            &root,
+            "node_modules/pkg/features/a.js",
+            "export default 'a';",
+        );
+        write(
+            &root,
+            "node_modules/pkg/internal/b.js",
+            "export default 'b';",
+        );
+        // default comes first so import is never used
+        write(
+            &root,
+            "node_modules/ordered/package.json",
+            r#"{"name": "ordered", "exports": {"default": "./default.js", "import": "./esm.js"}}"#,
+        );
+        write(
+            &root,
+            "node_modules/ordered/default.js",
+            "export default 'default';",
+        );
+        write(
+            &root,
+            "node_modules/ordered/esm.js",
+            "export default 'esm';",
+        );
+        write(&root, "node_modules/pkg/esm.js", "export default 'esm';");
+        write(
+            &root,
+            "node_modules/pkg/extra.js",
+            "export default 'extra';",
+        );
+        write(
+            &root,
+            "node_modules/@scope/main_pkg/package.json",
+            r#"{"name": "@scope/main_pkg", "main": "dist/main"}"#,
+        );
+        write(
+            &root,
+            "node_modules/@scope/main_pkg/dist/main.js",
+            "export default 'main';",
+        );
+        write(&dir, "secret.js", "export default 'secret';");
+        TestRoot { dir, root }
+    }
+
+    #[test]
+    fn test_resolve() {
+        let test_root = init_test_root("fsmoduleloader_resolve");
+        let loader = FileSystemModuleLoader::new(&*test_root).expect("invalid root");
+        let root = loader.get_root().to_path_buf();
+        let main = root.join("main.js");
+        let main = main.to_str().unwrap();
+
+        assert_eq!(
+            loader.resolve(main, "./lib/math"),
+            Some(root.join("lib/math.js"))
+        );
+        assert_eq!(
+            loader.resolve(main, "./lib/strings"),
+            Some(root.join("lib/strings/index.mjs"))
+        );
+        assert_eq!(
+            loader.resolve("eval.js", "/lib/math.js"),
+            Some(root.join("lib/math.js"))
+        );
+        assert_eq!(
+            loader.resolve(main, "pkg"),
+            Some(root.join("node_modules/pkg/esm.js"))
+        );
+        assert_eq!(
+            loader.resolve(main, "pkg/extra"),
+            Some(root.join("node_modules/pkg/extra.js"))
+        );
+        // not in exports
+        assert_eq!(loader.resolve(main, "pkg/esm.js"), None);
+        // the longest subpath pattern
+        assert_eq!(
+            loader.resolve(main, "pkg/features/a"),
+            Some(root.join("node_modules/pkg/features/a.js"))
+        );
+        assert_eq!(
+            loader.resolve(main, "pkg/features/internal/b"),
+            Some(root.join("node_modules/pkg/internal/b.js"))
+        );
+        // conditions in the order of the exports object
+        assert_eq!(
+            loader.resolve(main, "ordered"),
+            Some(root.join("node_modules/ordered/default.js"))
+        );
+        assert_eq!(
+            loader.resolve(
+                root.join("lib/math.js").to_str().unwrap(),
+                "@scope/main_pkg"
+            ),
+            Some(root.join("node_modules/@scope/main_pkg/dist/main.js"))
+        );
+        // traversal outside of the root
+        assert_eq!(loader.resolve(main, "../secret.js"), None);
+        assert_eq!(loader.resolve(main, "./lib/../../secret"), None);
+        assert_eq!(loader.resolve(main, "/../secret.js"), None);
+        assert_eq!(loader.resolve(main, "./notfound.js"), None);
+    }
+
+    #[test]
+    fn test_load() {
+        let root = init_test_root("fsmoduleloader_load");
+        let loader = FileSystemModuleLoader::new(&*root).expect("invalid root");
+        let main = loader.get_root().join("main.js");
+        let missing = loader.get_root().join("missing.js");
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_module_loader(loader)
+            .build();
+
+        rt.eval_module_sync(
+            None,
+            Script::new(
+                main.to_str().unwrap(),
+                "import {twice} from './lib/math';\n\
+                 import {shout} from './lib/strings';\n\
+                 import pkg from 'pkg';\n\
+                 import main from '@scope/main_pkg';\n\
+                 globalThis.fs_result = shout(pkg + main + twice(21));",
+            ),
+        )
+        .expect("module failed");
+        let res = rt
+            .eval_sync(None, Script::new("res.js", "fs_result"))
+            .expect("script failed");
+        assert_eq!(res.get_str(), "ESMMAIN42");
+
+        let res = rt.eval_module_sync(
+            None,
+            Script::new(main.to_str().unwrap(), "import secret from '../secret.js';"),
+        );
+        assert!(res.is_err());
+
+        // a file which can not be read is an Err instead of a module
+        let err = rt
+            .loop_realm_sync(None, move |_rt, realm| {
+                let loader = FileSystemModuleLoader::new(root.deref()).expect("invalid root");
+                loader
+                    .load_module_source(realm, missing.to_str().unwrap())
+                    .map(|_| ())
+            })
+            .expect_err("missing file was loaded");
+        assert!(err.get_message().contains("could not read module"));
+    }
+}
@@ -0,0 +1,467 @@
+//! conditional compilation of scripts
+//!
+//! the IfDefPreProcessor removes the code between directives whose flag is not defined and substitutes constants
+//!
+//! ```javascript
+//! //#ifdef DEBUG
+//! console.log("debugging");
+//! //#else
+//! console.log("not debugging");
+//! //#endif
+//! //#ifndef TENANT
+//! //#define TENANT "default"
+//! //#endif
+//! let url = API_URL + "/" + TENANT;
+//! ```
+//!
+//! supported directives are `//#ifdef`, `//#ifndef`, `//#else`, `//#endif`, `//#define NAME [value]` and `//#undef NAME`,
+//! a directive must be the only thing on its line, removed lines (and directives) are replaced by empty lines so line numbers don't change
+//!
+//! constants are substituted as they are (so a string value should be quoted) where they are read, so a constant is not
+//! substituted where it is declared (let NAME = 1), assigned to, or used as a property name (obj.NAME, {NAME: 1}),
+//! a shorthand property ({NAME}) becomes NAME: value, a constant also counts as a defined flag
+//!
+//! the code is parsed with swc to find where the constants are read so constants need the typescript feature
+//!
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::ifdef::Defines;
+//! use quickjs_runtime::jsutils::Script;
+//! let rt = QuickJsRuntimeBuilder::new()
+//!     .defines(Defines::new().flag("DEBUG").define("API_URL", "'https://api.example.com'"))
+//!     .build();
+//! let res = rt.eval_sync(None, Script::new("ifdef.js", "//#ifdef DEBUG\nlet a = API_URL + '/debug';\n//#else\nlet a = API_URL;\n//#endif\na;")).expect("script failed");
+//! assert_eq!(res.get_str(), "https://api.example.com/debug");
+//! ```
+
+use crate::jsutils::{JsError, Script, ScriptPreProcessor};
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// the flags and constants for conditional compilation
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Defines {
+    flags: BTreeSet<String>,
+    constants: BTreeMap<String, String>,
+}
+
+impl Defines {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// define a flag for //#ifdef
+    pub fn flag(mut self, name: &str) -> Self {
+        self.flags.insert(name.to_string());
+        self
+    }
+
+    /// define a constant, the value is the code which is substituted (e.g. "42" or "'production'"),
+    /// without the typescript feature a script fails to pre-process if a constant is defined
+    pub fn define(mut self, name: &str, value: &str) -> Self {
+        self.constants.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    pub fn is_defined(&self, name: &str) -> bool {
+        self.flags.contains(name) || self.constants.contains_key(name)
+    }
+
+    pub fn get_constant(&self, name: &str) -> Option<&str> {
+        self.constants.get(name).map(|v| v.as_str())
+    }
+
+    /// these defines with the flags and constants of other added, constants of other override those of self
+    pub fn merge(&self, other: &Defines) -> Defines {
+        let mut merged = self.clone();
+        merged.flags.extend(other.flags.iter().cloned());
+        merged.constants.extend(
+            other
+                .constants
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone())),
+        );
+        merged
+    }
+
+    fn undefine(&mut self, name: &str) {
+        self.flags.remove(name);
+        self.constants.remove(name);
+    }
+
+    fn get_cache_key(&self) -> String {
+        format!("{:?}{:?}", self.flags, self.constants)
+    }
+}
+
+/// a ScriptPreProcessor for //#ifdef blocks and //#define constants, see the module docs
+pub struct IfDefPreProcessor {
+    defines: Defines,
+}
+
+impl IfDefPreProcessor {
+    pub fn new(defines: Defines) -> Self {
+        Self { defines }
+    }
+
+    /// process a script with the given defines instead of those of this pre-processor
+    pub fn process_with(defines: &Defines, script: &mut Script) -> Result<(), JsError> {
+        if !script.get_code().contains("//#") && defines.constants.is_empty() {
+            return Ok(());
+        }
+        let code = apply_defines(defines, script.get_path(), script.get_code())?;
+        script.set_code(code);
+        Ok(())
+    }
+}
+
+impl ScriptPreProcessor for IfDefPreProcessor {
+    fn process(&self, script: &mut Script) -> Result<(), JsError> {
+        Self::process_with(&self.defines, script)
+    }
+
+    fn get_cache_key(&self) -> String {
+        format!("IfDefPreProcessor:{}", self.defines.get_cache_key())
+    }
+
+    fn process_for_realm(
+        &self,
+        realm: &QuickJsRealmAdapter,
+        script: &mut Script,
+    ) -> Result<(), JsError> {
+        match &*realm.defines.borrow() {
+            Some(realm_defines) => Self::process_with(&self.defines.merge(realm_defines), script),
+            None => self.process(script),
+        }
+    }
+
+    fn get_realm_cache_key(&self, realm: &QuickJsRealmAdapter) -> String {
+        match &*realm.defines.borrow() {
+            Some(realm_defines) => format!(":{}", realm_defines.get_cache_key()),
+            None => "".to_string(),
+        }
+    }
+}
+
+enum Directive<'a> {
+    IfDef(&'a str),
+    IfNDef(&'a str),
+    Else,
+    EndIf,
+    Define(&'a str, Option<&'a str>),
+    Undef(&'a str),
+}
+
+/// an //#ifdef or //#ifndef block
+struct Block {
+    line_number: usize,
+    condition: bool,
+    parent_active: bool,
+    in_else: bool,
+}
+
+impl Block {
+    fn is_active(&self) -> bool {
+        self.parent_active && (self.condition != self.in_else)
+    }
+}
+
+fn parse_directive(line: &str) -> Option<Directive<'_>> {
+    let directive = line.trim().strip_prefix("//#")?;
+    let mut parts = directive.splitn(2, char::is_whitespace);
+    let keyword = parts.next()?;
+    let rest = parts.next().map(|r| r.trim()).unwrap_or("");
+    let mut args = rest.splitn(2, char::is_whitespace);
+    let name = args.next().unwrap_or("");
+    match keyword {
+        "ifdef" => Some(Directive::IfDef(name)),
+        "ifndef" => Some(Directive::IfNDef(name)),
+        "else" => Some(Directive::Else),
+        "endif" => Some(Directive::EndIf),
+        "define" => Some(Directive::Define(
+            name,
+            args.next().map(|v| v.trim()).filter(|v| !v.is_empty()),
+        )),
+        "undef" => Some(Directive::Undef(name)),
+        // e.g. //# sourceMappingURL=
+        _ => None,
+    }
+}
+
+fn directive_error(path: &str, line_number: usize, message: &str) -> JsError {
+    JsError::new(
+        "SyntaxError".to_string(),
+        message.to_string(),
+        format!("    at {path}:{line_number}\n"),
+    )
+}
+
+/// apply the directives and substitute the constants, lines which are removed are replaced by empty lines
+pub fn apply_defines(defines: &Defines, path: &str, code: &str) -> Result<String, JsError> {
+    let mut defines = defines.clone();
+    let mut blocks: Vec<Block> = vec![];
+    let mut scanner = Scanner::Code;
+    let mut out = String::with_capacity(code.len());
+    // the constants from a byte offset in out, //#define and //#undef change them halfway
+    let mut constants = vec![(0, defines.constants.clone())];
+
+    for (index, line) in code.split_inclusive('\n').enumerate() {
+        let line_number = index + 1;
+        let content = line.trim_end_matches(['\r', '\n']);
+        let eol = &line[content.len()..];
+        let active = blocks.last().map(|b| b.is_active()).unwrap_or(true);
+
+        let directive = match scanner {
+            Scanner::Code => parse_directive(content),
+            // the line is part of a comment or template literal
+            _ => None,
+        };
+        match directive {
+            Some(directive) => match directive {
+                Directive::IfDef(name) | Directive::IfNDef(name) if name.is_empty() => {
+                    return Err(directive_error(path, line_number, "missing flag name"));
+                }
+                Directive::IfDef(name) => blocks.push(Block {
+                    line_number,
+                    condition: defines.is_defined(name),
+                    parent_active: active,
+                    in_else: false,
+                }),
+                Directive::IfNDef(name) => blocks.push(Block {
+                    line_number,
+                    condition: !defines.is_defined(name),
+                    parent_active: active,
+                    in_else: false,
+                }),
+                Directive::Else => match blocks.last_mut() {
+                    Some(block) if !block.in_else => block.in_else = true,
+                    Some(_) => return Err(directive_error(path, line_number, "duplicate //#else")),
+                    None => {
+                        return Err(directive_error(
+                            path,
+                            line_number,
+                            "//#else without //#ifdef",
+                        ))
+                    }
+                },
+                Directive::EndIf => {
+                    if blocks.pop().is_none() {
+                        return Err(directive_error(
+                            path,
+                            line_number,
+                            "//#endif without //#ifdef",
+                        ));
+                    }
+                }
+                Directive::Define(name, _) | Directive::Undef(name) if name.is_empty() => {
+                    return Err(directive_error(path, line_number, "missing define name"));
+                }
+                Directive::Define(name, value) if active => {
+                    defines = match value {
+                        Some(value) => defines.define(name, value),
+                        None => defines.flag(name),
+                    };
+                    constants.push((out.len(), defines.constants.clone()));
+                }
+                Directive::Undef(name) if active => {
+                    defines.undefine(name);
+                    constants.push((out.len(), defines.constants.clone()));
+                }
+                Directive::Define(..) | Directive::Undef(_) => {}
+            },
+            None if active => scan_line(content, &mut scanner, &mut out),
+            None => {}
+        }
+        out.push_str(eol);
+    }
+
+    if let Some(block) = blocks.last() {
+        return Err(directive_error(
+            path,
+            block.line_number,
+            "//#ifdef without //#endif",
+        ));
+    }
+    if constants.iter().all(|(_, c)| c.is_empty()) {
+        return Ok(out);
+    }
+    substitute(path, out, &constants)
+}
+
+/// substitute the constants where they are read in the code
+#[cfg(feature = "typescript")]
+fn substitute(
+    path: &str,
+    code: String,
+    constants: &[(usize, BTreeMap<String, String>)],
+) -> Result<String, JsError> {
+    let used = constants
+        .iter()
+        .any(|(_, c)| c.keys().any(|name| code.contains(name.as_str())));
+    if !used {
+        return Ok(code);
+    }
+    let mut out = String::with_capacity(code.len());
+    let mut last = 0;
+    for reference in crate::typescript::find_identifier_references(path, code.as_str())? {
+        let index = constants.partition_point(|(offset, _)| *offset <= reference.start);
+        let value = match constants[index - 1].1.get(reference.name.as_str()) {
+            Some(value) => value,
+            None => continue,
+        };
+        out.push_str(&code[last..reference.start]);
+        if reference.shorthand {
+            out.push_str(reference.name.as_str());
+            out.push_str(": ");
+        }
+        out.push_str(value);
+        last = reference.end;
+    }
+    out.push_str(&code[last..]);
+    Ok(out)
+}
+
+#[cfg(not(feature = "typescript"))]
+fn substitute(
+    path: &str,
+    _code: String,
+    _constants: &[(usize, BTreeMap<String, String>)],
+) -> Result<String, JsError> {
+    Err(JsError::new_string(format!(
+        "could not substitute the constants in {path}, constants need the typescript feature"
+    )))
+}
+
+/// what the scanner is in at the end of a line
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Scanner {
+    Code,
+    BlockComment,
+    Template,
+}
+
+/// copy a line to out and keep track of comments and template literals so directives in those are ignored
+fn scan_line(line: &str, scanner: &mut Scanner, out: &mut String) {
+    out.push_str(line);
+    let chars: Vec<char> = line.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match *scanner {
+            Scanner::BlockComment => {
+                if c == '*' && chars.get(i + 1) == Some(&'/') {
+                    i += 1;
+                    *scanner = Scanner::Code;
+                }
+            }
+            Scanner::Template => {
+                if c == '\\' {
+                    i += 1;
+                } else if c == '`' {
+                    *scanner = Scanner::Code;
+                }
+            }
+            Scanner::Code => {
+                if c == '/' && chars.get(i + 1) == Some(&'/') {
+                    // the rest of the line is a comment
+                    return;
+                } else if c == '/' && chars.get(i + 1) == Some(&'*') {
+                    i += 1;
+                    *scanner = Scanner::BlockComment;
+                } else if c == '`' {
+                    *scanner = Scanner::Template;
+                } else if c == '\'' || c == '"' {
+                    // strings end at the end of the line
+                    i += 1;
+                    while i < chars.len() && chars[i] != c {
+                        if chars[i] == '\\' {
+                            i += 1;
+                        }
+                        i += 1;
+                    }
+                }
+            }
+        }
+        i += 1;
+    }
+}
+
+// substituting constants needs the typescript feature
+#[cfg(all(test, feature = "typescript"))]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::ifdef::{apply_defines, Defines};
+    use crate::jsutils::Script;
+
+    #[test]
+    fn test_apply_defines() {
+        let defines = Defines::new().flag("A").define("MAX", "10");
+        let code = "//#ifdef A\nlet a = MAX;\n//#ifndef B\nlet b = 'MAX' + obj.MAX; // MAX\n//#else\nlet b = 2;\n//#endif\n//#else\nlet a = 0;\n//#endif\n/* MAX\nMAX */ `MAX ${1}`;\n";
+        let res = apply_defines(&defines, "test.js", code).expect("apply failed");
+        assert_eq!(
+            res,
+            "\nlet a = 10;\n\nlet b = 'MAX' + obj.MAX; // MAX\n\n\n\n\n\n\n/* MAX\nMAX */ `MAX ${1}`;\n"
+        );
+
+        let res = apply_defines(
+            &Defines::new(),
+            "test.js",
+            "//#define LIMIT 5\n//#ifdef LIMIT\nLIMIT;\n//#endif\n",
+        )
+        .expect("apply failed");
+        assert_eq!(res, "\n\n5;\n\n");
+
+        // only identifiers which are read are substituted
+        let res = apply_defines(
+            &defines,
+            "positions.ts",
+            "let MAX: number = 1;\nlet o = {MAX, MAX: 2, [MAX]: 3};\nlet t = `max ${MAX}`;\nMAX = 2;\n//#undef MAX\nMAX;\n",
+        )
+        .expect("apply failed");
+        assert_eq!(
+            res,
+            "let MAX: number = 1;\nlet o = {MAX: 10, MAX: 2, [10]: 3};\nlet t = `max ${10}`;\nMAX = 2;\n\nMAX;\n"
+        );
+
+        let err = apply_defines(&defines, "unbalanced.js", "let a = 1;\n//#ifdef A\n")
+            .expect_err("apply passed");
+        assert_eq!(err.get_line_number(), Some(2));
+        assert!(apply_defines(&defines, "unbalanced.js", "//#endif\n").is_err());
+    }
+
+    #[test]
+    fn test_ifdef_realm() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .defines(Defines::new().define("MODE", "'runtime'"))
+            .build();
+        rt.create_context("ifdef_realm").expect("create failed");
+
+        let script = "//#ifdef PRO\nlet tier = 'pro';\n//#else\nlet tier = 'free';\n//#endif\ntier + ':' + MODE;";
+        let res = rt
+            .eval_sync(None, Script::new("tier.js", script))
+            .expect("script failed");
+        assert_eq!(res.get_str(), "free:runtime");
+
+        let res = rt
+            .loop_realm_sync(Some("ifdef_realm"), |_rt, realm| {
+                realm.set_defines(Some(Defines::new().flag("PRO").define("MODE", "'realm'")));
+                let val = realm.eval(Script::new("tier.js", script))?;
+                val.to_string()
+            })
+            .expect("script failed");
+        assert_eq!(res, "pro:realm");
+
+        // removed lines are kept as empty lines
+        let err = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "lines.js",
+                    "//#ifdef PRO\nlet a = 1;\n//#endif\nthrow new Error('boom');",
+                ),
+            )
+            .expect_err("script passed");
+        assert_eq!(err.get_line_number(), Some(4));
+    }
+}
@@ -0,0 +1,298 @@
+//! [import maps](https://github.com/WICG/import-maps) for resolving module specifiers before they are passed to the module loaders
+//!
+//! like in a browser the keys, targets and scopes which are urls or paths (starting with /, ./ or ../) are resolved against the
+//! base url of the import map when it is parsed and relative specifiers are resolved against the importing module before they are
+//! matched, other keys and targets (e.g. "lodash" or "some_module.mes") are used as written because module paths of the
+//! module loaders don't have to be urls
+//!
+//! # Example
+//! ```rust
+//! use quickjs_runtime::jsutils::importmaps::ImportMap;
+//! let import_map = ImportMap::from_json(r#"{
+//!     "imports": {"lodash": "/libs/lodash@4.17.21/lodash.js", "utils/": "/libs/utils/"},
+//!     "scopes": {"/tenants/a/": {"lodash": "/libs/lodash@3.10.1/lodash.js"}}
+//! }"#).expect("invalid import map");
+//! assert_eq!(import_map.resolve("lodash", "/main.js").unwrap(), "/libs/lodash@4.17.21/lodash.js");
+//! assert_eq!(import_map.resolve("lodash", "/tenants/a/main.js").unwrap(), "/libs/lodash@3.10.1/lodash.js");
+//! assert_eq!(import_map.resolve("utils/strings.js", "/main.js").unwrap(), "/libs/utils/strings.js");
+//! assert!(import_map.resolve("./local.js", "/main.js").is_none());
+//!
+//! // relative keys and targets are resolved against the base of the import map
+//! let import_map = ImportMap::from_json_with_base(r#"{
+//!     "imports": {"./config.js": "./config.prod.js"}
+//! }"#, "/srv/app/importmap.json").expect("invalid import map");
+//! assert_eq!(import_map.resolve("../config.js", "/srv/app/lib/util.js").unwrap(), "/srv/app/config.prod.js");
+//! ```
+
+use crate::jsutils::JsError;
+
+/// a specifier map, entries are sorted with the longest key first so the most specific match wins
+#[derive(Clone, Debug, Default)]
+struct SpecifierMap {
+    entries: Vec<(String, String)>,
+}
+
+impl SpecifierMap {
+    fn insert(&mut self, specifier: &str, target: &str) {
+        self.entries.retain(|(key, _)| key != specifier);
+        self.entries
+            .push((specifier.to_string(), target.to_string()));
+        self.entries
+            .sort_by_key(|(key, _)| std::cmp::Reverse(key.len()));
+    }
+
+    fn resolve(&self, specifier: &str) -> Option<String> {
+        for (key, target) in &self.entries {
+            if key == specifier {
+                return Some(target.clone());
+            }
+            // keys ending with a / map all specifiers starting with that key (packages)
+            if key.ends_with('/') && target.ends_with('/') && specifier.starts_with(key.as_str()) {
+                return Some(format!("{}{}", target, &specifier[key.len()..]));
+            }
+        }
+        None
+    }
+}
+
+/// an import map with top level imports and scoped imports
+/// specifiers are matched as written in the import statement, the resolved target is passed to the module loaders
+#[derive(Clone, Debug, Default)]
+pub struct ImportMap {
+    imports: SpecifierMap,
+    /// scope prefix and its specifier map, longest scope first
+    scopes: Vec<(String, SpecifierMap)>,
+}
+
+impl ImportMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// parse an import map from its json form, urls and paths are resolved against the root (/)
+    pub fn from_json(json: &str) -> Result<Self, JsError> {
+        Self::from_json_with_base(json, "/")
+    }
+
+    /// parse an import map from its json form, urls and paths in it are resolved against base_url,
+    /// the url or path of the import map (e.g. "/srv/app/importmap.json" or "https://example.com/app/importmap.json")
+    pub fn from_json_with_base(json: &str, base_url: &str) -> Result<Self, JsError> {
+        let value: serde_json::Value = serde_json::from_str(json)
+            .map_err(|e| JsError::new_string(format!("invalid import map: {e}")))?;
+        let mut import_map = Self::new();
+
+        if let Some(imports) = value.get("imports") {
+            for (specifier, target) in Self::parse_specifier_map(imports, "imports")? {
+                import_map.add_import(
+                    normalize(base_url, specifier.as_str()).as_str(),
+                    normalize(base_url, target.as_str()).as_str(),
+                );
+            }
+        }
+        if let Some(scopes) = value.get("scopes") {
+            let scopes = scopes.as_object().ok_or_else(|| {
+                JsError::new_str("invalid import map: scopes should be an object")
+            })?;
+            for (scope, imports) in scopes {
+                let normalized_scope = normalize(base_url, scope);
+                for (specifier, target) in Self::parse_specifier_map(imports, scope)? {
+                    import_map.add_scoped_import(
+                        normalized_scope.as_str(),
+                        normalize(base_url, specifier.as_str()).as_str(),
+                        normalize(base_url, target.as_str()).as_str(),
+                    );
+                }
+            }
+        }
+        Ok(import_map)
+    }
+
+    fn parse_specifier_map(
+        value: &serde_json::Value,
+        name: &str,
+    ) -> Result<Vec<(String, String)>, JsError> {
+        let map = value.as_object().ok_or_else(|| {
+            JsError::new_string(format!("invalid import map: {name} should be an object"))
+        })?;
+        map.iter()
+            .map(|(specifier, target)| match target.as_str() {
+                Some(target) => Ok((specifier.clone(), target.to_string())),
+                None => Err(JsError::new_string(format!(
+                    "invalid import map: target of {specifier} in {name} should be a string"
+                ))),
+            })
+            .collect()
+    }
+
+    /// add a top level import, a specifier ending with / maps all specifiers starting with it
+    ///
+    /// unlike the entries of from_json the specifier and target are not resolved against a base
+    pub fn add_import(&mut self, specifier: &str, target: &str) -> &mut Self {
+        self.imports.insert(specifier, target);
+        self
+    }
+
+    /// add an import which only applies to modules of which the path starts with scope
+    pub fn add_scoped_import(&mut self, scope: &str, specifier: &str, target: &str) -> &mut Self {
+        match self.scopes.iter_mut().find(|(s, _)| s == scope) {
+            Some((_, map)) => map.insert(specifier, target),
+            None => {
+                let mut map = SpecifierMap::default();
+                map.insert(specifier, target);
+                self.scopes.push((scope.to_string(), map));
+                self.scopes
+                    .sort_by_key(|(scope, _)| std::cmp::Reverse(scope.len()));
+            }
+        }
+        self
+    }
+
+    /// resolve a specifier imported by the module at referrer_path, returns None if the import map has no match
+    ///
+    /// a relative specifier (./ or ../) is resolved against referrer_path before it is matched
+    pub fn resolve(&self, specifier: &str, referrer_path: &str) -> Option<String> {
+        let normalized = normalize(referrer_path, specifier);
+        let specifier = normalized.as_str();
+        for (scope, map) in &self.scopes {
+            let in_scope = if scope.ends_with('/') {
+                referrer_path.starts_with(scope.as_str())
+            } else {
+                referrer_path == scope
+            };
+            if in_scope {
+                if let Some(resolved) = map.resolve(specifier) {
+                    return Some(resolved);
+                }
+            }
+        }
+        self.imports.resolve(specifier)
+    }
+}
+
+/// true for specifiers which are resolved like urls, other specifiers are bare specifiers
+fn is_url_like(specifier: &str) -> bool {
+    specifier.starts_with('/')
+        || specifier.starts_with("./")
+        || specifier.starts_with("../")
+        || specifier.contains("://")
+}
+
+/// resolve a url like specifier against a base url or path, bare specifiers are returned as they are
+fn normalize(base: &str, specifier: &str) -> String {
+    if !is_url_like(specifier) {
+        return specifier.to_string();
+    }
+    if specifier.contains("://") {
+        return url::Url::parse(specifier)
+            .map(|url| url.to_string())
+            .unwrap_or_else(|_| specifier.to_string());
+    }
+    if base.contains("://") {
+        if let Some(url) = url::Url::parse(base)
+            .ok()
+            .and_then(|base| base.join(specifier).ok())
+        {
+            return url.to_string();
+        }
+    }
+    let joined = if specifier.starts_with('/') {
+        specifier.to_string()
+    } else {
+        let base_dir = &base[..base.rfind('/').map(|i| i + 1).unwrap_or(0)];
+        format!("{base_dir}{specifier}")
+    };
+    remove_dot_segments(joined.as_str())
+}
+
+/// resolve the . and .. segments of a path, a trailing / is kept
+fn remove_dot_segments(path: &str) -> String {
+    let mut segments: Vec<&str> = vec![];
+    let parts: Vec<&str> = path.split('/').collect();
+    for (index, part) in parts.iter().enumerate() {
+        let last = index == parts.len() - 1;
+        match *part {
+            "." => {
+                if last {
+                    segments.push("");
+                }
+            }
+            ".." => {
+                if segments.len() > 1 || segments.first().map(|s| !s.is_empty()).unwrap_or(false) {
+                    segments.pop();
+                }
+                if last {
+                    segments.push("");
+                }
+            }
+            part => segments.push(part),
+        }
+    }
+    segments.join("/")
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::jsutils::importmaps::{remove_dot_segments, ImportMap};
+
+    #[test]
+    fn test_base_url() {
+        assert_eq!(remove_dot_segments("/a/./b/../c.js"), "/a/c.js");
+        assert_eq!(remove_dot_segments("/a/b/../../../c/"), "/c/");
+        assert_eq!(remove_dot_segments("a/../b.js"), "b.js");
+
+        let import_map = ImportMap::from_json_with_base(
+            r#"{
+                "imports": {"./config.js": "./config.prod.js", "lib/": "./vendor/lib/", "https://cdn.example.com/x.js": "/x.js"},
+                "scopes": {"./tenants/a/": {"lib/": "../shared/lib/"}}
+            }"#,
+            "/srv/app/importmap.json",
+        )
+        .expect("invalid import map");
+        assert_eq!(
+            import_map
+                .resolve("./config.js", "/srv/app/main.js")
+                .as_deref(),
+            Some("/srv/app/config.prod.js")
+        );
+        assert_eq!(
+            import_map
+                .resolve("/srv/app/config.js", "/other/main.js")
+                .as_deref(),
+            Some("/srv/app/config.prod.js")
+        );
+        assert_eq!(
+            import_map
+                .resolve("lib/x.js", "/srv/app/main.js")
+                .as_deref(),
+            Some("/srv/app/vendor/lib/x.js")
+        );
+        assert_eq!(
+            import_map
+                .resolve("lib/x.js", "/srv/app/tenants/a/main.js")
+                .as_deref(),
+            Some("/srv/shared/lib/x.js")
+        );
+        assert_eq!(
+            import_map
+                .resolve("https://CDN.example.com/x.js", "/srv/app/main.js")
+                .as_deref(),
+            Some("/x.js")
+        );
+        assert!(import_map
+            .resolve("./other.js", "/srv/app/main.js")
+            .is_none());
+
+        // an url base
+        let import_map = ImportMap::from_json_with_base(
+            r#"{"imports": {"app": "./app/main.js"}}"#,
+            "https://example.com/site/importmap.json",
+        )
+        .expect("invalid import map");
+        assert_eq!(
+            import_map
+                .resolve("app", "https://example.com/index.js")
+                .as_deref(),
+            Some("https://example.com/site/app/main.js")
+        );
+    }
+}
@@ -3,15 +3,41 @@
 //! The facade classes are for use outside the worker thread, they are Send
 //!
 
+use crate::jsutils::diagnostics::Diagnostic;
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::values::JsValueFacade;
 use std::fmt::{Debug, Display, Error, Formatter};
 
+pub mod bytecodecache;
+pub mod diagnostics;
+pub mod fsmoduleloader;
 pub mod helper_tasks;
+pub mod ifdef;
+pub mod importmaps;
 pub mod jsproxies;
 pub mod modules;
 pub mod promises;
 
 pub trait ScriptPreProcessor {
     fn process(&self, script: &mut Script) -> Result<(), JsError>;
+    /// identifies this pre-processor and its configuration in the key of the bytecode cache
+    /// defaults to the type name, override this if the output depends on configuration
+    fn get_cache_key(&self) -> String {
+        std::any::type_name::<Self>().to_string()
+    }
+    /// process a script which is evaluated or compiled in a realm, defaults to process
+    /// override this (and get_realm_cache_key) if the output depends on the realm
+    fn process_for_realm(
+        &self,
+        _realm: &QuickJsRealmAdapter,
+        script: &mut Script,
+    ) -> Result<(), JsError> {
+        self.process(script)
+    }
+    /// identifies the configuration of this pre-processor for a realm in the key of the bytecode cache
+    fn get_realm_cache_key(&self, _realm: &QuickJsRealmAdapter) -> String {
+        "".to_string()
+    }
 }
 
 /// the JsValueType represents the type of value for a JSValue
@@ -52,11 +78,62 @@ impl Display for JsValueType {
     }
 }
 
+/// the kind of a JsError, derived from the name (and for some engine errors the message) of the Error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JsErrorKind {
+    Error,
+    EvalError,
+    RangeError,
+    ReferenceError,
+    SyntaxError,
+    TypeError,
+    URIError,
+    AggregateError,
+    InternalError,
+    /// the script was interrupted by the interrupt handler
+    Interrupted,
+    OutOfMemory,
+    StackOverflow,
+    /// an Error with a custom name (e.g. a subclass of Error)
+    Custom(String),
+}
+
+impl JsErrorKind {
+    pub fn from_name(name: &str, message: &str) -> Self {
+        match (name, message) {
+            ("InternalError", "interrupted") => JsErrorKind::Interrupted,
+            ("InternalError", "out of memory") => JsErrorKind::OutOfMemory,
+            ("InternalError", "stack overflow") => JsErrorKind::StackOverflow,
+            ("RangeError", "Maximum call stack size exceeded") => JsErrorKind::StackOverflow,
+            ("Error", _) => JsErrorKind::Error,
+            ("EvalError", _) => JsErrorKind::EvalError,
+            ("RangeError", _) => JsErrorKind::RangeError,
+            ("ReferenceError", _) => JsErrorKind::ReferenceError,
+            ("SyntaxError", _) => JsErrorKind::SyntaxError,
+            ("TypeError", _) => JsErrorKind::TypeError,
+            ("URIError", _) => JsErrorKind::URIError,
+            ("AggregateError", _) => JsErrorKind::AggregateError,
+            ("InternalError", _) => JsErrorKind::InternalError,
+            (custom, _) => JsErrorKind::Custom(custom.to_string()),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct JsError {
     name: String,
     message: String,
     stack: String,
+    details: Option<Box<JsErrorDetails>>,
+}
+
+/// the optional structured data of a JsError, boxed so a Result<_, JsError> stays small
+#[derive(Debug, Default)]
+struct JsErrorDetails {
+    cause: Option<JsError>,
+    properties: Option<JsValueFacade>,
+    diagnostics: Vec<Diagnostic>,
+    code_frame: Option<String>,
 }
 
 impl JsError {
@@ -65,17 +142,37 @@ impl JsError {
             name,
             message,
             stack,
+            details: None,
         }
     }
     pub fn new_str(err: &str) -> Self {
         Self::new_string(err.to_string())
     }
     pub fn new_string(err: String) -> Self {
-        JsError {
-            name: "Error".to_string(),
-            message: err,
-            stack: "".to_string(),
-        }
+        Self::new("Error".to_string(), err, "".to_string())
+    }
+    fn details_mut(&mut self) -> &mut JsErrorDetails {
+        self.details.get_or_insert_with(Default::default)
+    }
+    /// set the cause of this error, this is passed to script as Error.cause
+    pub fn with_cause(mut self, cause: JsError) -> Self {
+        self.details_mut().cause = Some(cause);
+        self
+    }
+    /// set custom properties of this error, when passed to script all properties of the object are set on the Error
+    pub fn with_properties(mut self, properties: JsValueFacade) -> Self {
+        self.details_mut().properties = Some(properties);
+        self
+    }
+    /// add diagnostics, e.g. the syntax errors of a failed transpile
+    pub fn with_diagnostics(mut self, diagnostics: Vec<Diagnostic>) -> Self {
+        self.details_mut().diagnostics.extend(diagnostics);
+        self
+    }
+    /// set the code frame, the source around the position where the error was thrown
+    pub fn with_code_frame(mut self, code_frame: String) -> Self {
+        self.details_mut().code_frame = Some(code_frame);
+        self
     }
     pub fn get_message(&self) -> &str {
         self.message.as_str()
@@ -86,18 +183,99 @@ impl JsError {
     pub fn get_name(&self) -> &str {
         self.name.as_str()
     }
+    /// the kind of this error, derived from the name and message
+    pub fn get_kind(&self) -> JsErrorKind {
+        JsErrorKind::from_name(self.name.as_str(), self.message.as_str())
+    }
+    /// the file where the error was thrown (parsed from the first frame of the stack)
+    pub fn get_file_name(&self) -> Option<&str> {
+        parse_stack_position(self.stack.as_str()).map(|(file_name, _, _)| file_name)
+    }
+    pub fn get_line_number(&self) -> Option<u32> {
+        parse_stack_position(self.stack.as_str()).map(|(_, line_number, _)| line_number)
+    }
+    pub fn get_column_number(&self) -> Option<u32> {
+        parse_stack_position(self.stack.as_str()).and_then(|(_, _, column_number)| column_number)
+    }
+    pub fn get_diagnostics(&self) -> &[Diagnostic] {
+        match &self.details {
+            Some(details) => details.diagnostics.as_slice(),
+            None => &[],
+        }
+    }
+    /// the source around the position where the error was thrown (see QuickJsRuntimeBuilder::code_frames)
+    /// ```text
+    ///   1 | let a = 1;
+    /// > 2 | throw new Error(a);
+    ///     |       ^
+    ///   3 | a++;
+    /// ```
+    pub fn get_code_frame(&self) -> Option<&str> {
+        self.details.as_ref()?.code_frame.as_deref()
+    }
+    pub fn get_cause(&self) -> Option<&JsError> {
+        self.details.as_ref()?.cause.as_ref()
+    }
+    /// the custom enumerable properties of the Error (e.g. err.code = 'E_FAIL')
+    pub fn get_properties(&self) -> Option<&JsValueFacade> {
+        self.details.as_ref()?.properties.as_ref()
+    }
+    pub(crate) fn take_cause(&mut self) -> Option<JsError> {
+        self.details.as_mut()?.cause.take()
+    }
+    pub(crate) fn take_properties(&mut self) -> Option<JsValueFacade> {
+        self.details.as_mut()?.properties.take()
+    }
+}
+
+/// get the file, line and column of the first frame in a stack which has a position
+/// frames look like "    at func (file.js:3:7)" or "    at file.js:3"
+fn parse_stack_position(stack: &str) -> Option<(&str, u32, Option<u32>)> {
+    stack.lines().find_map(|line| {
+        let frame = line.trim().strip_prefix("at ")?;
+        let location = match (frame.rfind('('), frame.strip_suffix(')')) {
+            (Some(start), Some(frame)) => &frame[start + 1..],
+            _ => frame,
+        };
+        let (rest, last) = location.rsplit_once(':')?;
+        let last: u32 = last.parse().ok()?;
+        match rest.rsplit(':').next()?.parse::<u32>() {
+            Ok(line_number) => {
+                let (file_name, _) = rest.rsplit_once(':')?;
+                if file_name.is_empty() {
+                    None
+                } else {
+                    Some((file_name, line_number, Some(last)))
+                }
+            }
+            // no column, rest is the file name
+            Err(_) if rest.is_empty() => None,
+            Err(_) => Some((rest, last, None)),
+        }
+    })
 }
 
 impl std::error::Error for JsError {
     fn description(&self) -> &str {
         self.get_message()
     }
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.get_cause()
+            .map(|c| c as &(dyn std::error::Error + 'static))
+    }
 }
 
 impl std::fmt::Display for JsError {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
         let e = format!("{}: {}\n{}", self.name, self.message, self.stack);
-        f.write_str(e.as_str())
+        f.write_str(e.as_str())?;
+        if let Some(code_frame) = self.get_code_frame() {
+            f.write_str(format!("\n{code_frame}").as_str())?;
+        }
+        if let Some(cause) = self.get_cause() {
+            f.write_str(format!("\nCaused by: {cause}").as_str())?;
+        }
+        Ok(())
     }
 }
 
@@ -112,6 +290,8 @@ pub struct Script {
     code: String,
     transpiled_code: Option<String>,
     map: Option<String>,
+    #[cfg(feature = "typescript")]
+    code_maps: Vec<String>,
 }
 
 impl Debug for Script {
@@ -127,6 +307,8 @@ impl Script {
             code: script_code.to_string(),
             transpiled_code: None,
             map: None,
+            #[cfg(feature = "typescript")]
+            code_maps: vec![],
         }
     }
     pub fn get_path(&self) -> &str {
@@ -149,9 +331,29 @@ impl Script {
         self.transpiled_code = Some(transpiled_code);
         self.map = map;
     }
+    /// set the code and a source map which maps the new code to the code before this call
+    /// a ScriptPreProcessor which moves code to other lines should use this instead of set_code,
+    /// the runtime composes the maps of all pre-processors (and the TypeScript transpiler) so stack traces point to the original code
+    ///
+    /// the maps are composed by the typescript feature, which is why this is only available with it
+    #[cfg(feature = "typescript")]
+    pub fn set_code_with_map(&mut self, code: String, map: String) {
+        self.code = code;
+        self.code_maps.push(map);
+    }
+    /// the maps set with set_code_with_map, in the order they were set
+    #[cfg(feature = "typescript")]
+    pub fn get_code_maps(&self) -> &[String] {
+        self.code_maps.as_slice()
+    }
+    /// the source map of the runnable code
     pub fn get_map(&self) -> Option<&str> {
         self.map.as_deref()
     }
+    #[cfg(feature = "typescript")]
+    pub(crate) fn set_map(&mut self, map: Option<String>) {
+        self.map = map;
+    }
 }
 
 impl Clone for Script {
@@ -161,6 +363,8 @@ impl Clone for Script {
             code: self.code.clone(),
             transpiled_code: self.transpiled_code.clone(),
             map: self.map.clone(),
+            #[cfg(feature = "typescript")]
+            code_maps: self.code_maps.clone(),
         }
     }
 }
@@ -1,7 +1,42 @@
+use crate::jsutils::JsError;
 use crate::quickjsrealmadapter::QuickJsRealmAdapter;
 use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::Arc;
 
+/// the source of a module as returned by [ScriptModuleLoader::load_module_source]
+pub enum ModuleSource {
+    /// javascript source, compiled as module
+    Script(String),
+    /// json, the default export of the module is the parsed value
+    Json(String),
+    /// the default export of the module is the string
+    Text(String),
+    /// the default export of the module is an Uint8Array with the bytes
+    Bytes(Vec<u8>),
+    /// a module compiled to bytecode, like the result of a CompiledModuleLoader
+    Bytecode(Arc<Vec<u8>>),
+    /// javascript source of a CommonJS module (needs the commonjs feature), module.exports is the default export of the module
+    ///
+    /// a Script with a path ending in .cjs is also a CommonJS module
+    CommonJs(String),
+}
+
+impl ModuleSource {
+    /// the type of the module as used in the type import attribute
+    pub fn get_type(&self) -> &str {
+        match self {
+            ModuleSource::Script(_) | ModuleSource::Bytecode(_) | ModuleSource::CommonJs(_) => {
+                "javascript"
+            }
+            ModuleSource::Json(_) => "json",
+            ModuleSource::Text(_) => "text",
+            ModuleSource::Bytes(_) => "bytes",
+        }
+    }
+}
+
 pub trait ScriptModuleLoader {
     fn normalize_path(
         &self,
@@ -10,6 +45,34 @@ pub trait ScriptModuleLoader {
         path: &str,
     ) -> Option<String>;
     fn load_module(&self, realm: &QuickJsRealmAdapter, absolute_path: &str) -> String;
+    /// load a module which may be something else than javascript, defaults to a Script loaded with load_module
+    ///
+    /// when a module is imported with a type attribute (`import data from './data.json' with { type: 'json' }`)
+    /// a Script source is interpreted as that type, for other sources the type attribute should match
+    ///
+    /// an Err fails the import with that error
+    fn load_module_source(
+        &self,
+        realm: &QuickJsRealmAdapter,
+        absolute_path: &str,
+    ) -> Result<ModuleSource, JsError> {
+        Ok(ModuleSource::Script(self.load_module(realm, absolute_path)))
+    }
+    /// the name of this loader as reported in the module registry of a realm, defaults to the type name
+    fn get_name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+    /// add properties to import.meta of a module which was loaded by this loader (e.g. a tenant id or environment)
+    ///
+    /// import.meta.url and import.meta.resolve() are already set when this is called
+    fn init_import_meta(
+        &self,
+        _realm: &QuickJsRealmAdapter,
+        _absolute_path: &str,
+        _import_meta: &QuickJsValueAdapter,
+    ) -> Result<(), JsError> {
+        Ok(())
+    }
 }
 
 pub trait CompiledModuleLoader {
@@ -19,7 +82,27 @@ pub trait CompiledModuleLoader {
         ref_path: &str,
         path: &str,
     ) -> Option<String>;
-    fn load_module(&self, realm: &QuickJsRealmAdapter, absolute_path: &str) -> Arc<Vec<u8>>;
+    /// load the bytecode of a module (as written by quickjs_utils::compile::to_bytecode), an Err fails the import with that error
+    fn load_module(
+        &self,
+        realm: &QuickJsRealmAdapter,
+        absolute_path: &str,
+    ) -> Result<Arc<Vec<u8>>, JsError>;
+    /// the name of this loader as reported in the module registry of a realm, defaults to the type name
+    fn get_name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+    /// add properties to import.meta of a module which was loaded by this loader (e.g. a tenant id or environment)
+    ///
+    /// import.meta.url and import.meta.resolve() are already set when this is called
+    fn init_import_meta(
+        &self,
+        _realm: &QuickJsRealmAdapter,
+        _absolute_path: &str,
+        _import_meta: &QuickJsValueAdapter,
+    ) -> Result<(), JsError> {
+        Ok(())
+    }
 }
 
 pub trait NativeModuleLoader {
@@ -30,4 +113,47 @@ pub trait NativeModuleLoader {
         realm: &QuickJsRealmAdapter,
         module_name: &str,
     ) -> Vec<(&str, QuickJsValueAdapter)>;
+    /// the name of this loader as reported in the module registry of a realm, defaults to the type name
+    fn get_name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+}
+
+/// the future which is returned by [AsyncModuleLoader::load_module]
+pub type ModuleSourceFuture = Pin<Box<dyn Future<Output = Result<ModuleSource, JsError>> + Send>>;
+
+/// a module loader which loads modules asynchronously, e.g. from a database or over the network
+///
+/// the futures run on a helper thread so the event loop keeps running other tasks while modules load,
+/// all modules of an async loader which are (transitively) statically imported are loaded before a module is linked
+///
+/// modules of an async loader can be imported by modules evaluated with QuickJsRuntimeFacade::eval_module or
+/// QuickJsRealmAdapter::eval_module_async and by dynamic import()
+///
+/// the imports are found in the code of a module after it was pre-processed and transpiled, import() calls are found by the
+/// parser of the typescript feature or else by a lexer (then columns after an import() on the same line shift in stack traces)
+pub trait AsyncModuleLoader {
+    fn normalize_path(
+        &self,
+        realm: &QuickJsRealmAdapter,
+        ref_path: &str,
+        path: &str,
+    ) -> Option<String>;
+    /// load a module, this is called in the worker thread but the future is run in a helper thread
+    fn load_module(&self, realm: &QuickJsRealmAdapter, absolute_path: &str) -> ModuleSourceFuture;
+    /// the name of this loader as reported in the module registry of a realm, defaults to the type name
+    fn get_name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+    /// add properties to import.meta of a module which was loaded by this loader (e.g. a tenant id or environment)
+    ///
+    /// import.meta.url and import.meta.resolve() are already set when this is called
+    fn init_import_meta(
+        &self,
+        _realm: &QuickJsRealmAdapter,
+        _absolute_path: &str,
+        _import_meta: &QuickJsValueAdapter,
+    ) -> Result<(), JsError> {
+        Ok(())
+    }
 }
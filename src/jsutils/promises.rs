@@ -62,11 +62,7 @@ where
                                     }
                                     Err(err) => {
                                         let err_ref = realm
-                                            .create_error(
-                                                err.get_name(),
-                                                err.get_message(),
-                                                err.get_stack(),
-                                            )
+                                            .create_error_from_js_error(err)
                                             .expect("could not create error");
                                         if let Err(e) = prom_ref.js_promise_reject(realm, &err_ref)
                                         {
@@ -80,13 +76,8 @@ where
                                 }
                             }
                             Err(err) => {
-                                // todo use error:new_error(err)
                                 let err_ref = realm
-                                    .create_error(
-                                        err.get_name(),
-                                        err.get_message(),
-                                        err.get_stack(),
-                                    )
+                                    .create_error_from_js_error(err)
                                     .expect("could not create error");
                                 if let Err(e) = prom_ref.js_promise_reject(realm, &err_ref) {
                                     log::error!(
@@ -173,11 +164,7 @@ where
                                     }
                                     Err(err) => {
                                         let err_ref = realm
-                                            .create_error(
-                                                err.get_name(),
-                                                err.get_message(),
-                                                err.get_stack(),
-                                            )
+                                            .create_error_from_js_error(err)
                                             .expect("could not create err");
                                         if let Err(e) = prom_ref.js_promise_reject(realm, &err_ref)
                                         {
@@ -191,13 +178,8 @@ where
                                 }
                             }
                             Err(err) => {
-                                // todo use error:new_error(err)
                                 let err_ref = realm
-                                    .create_error(
-                                        err.get_name(),
-                                        err.get_message(),
-                                        err.get_stack(),
-                                    )
+                                    .create_error_from_js_error(err)
                                     .expect("could not create str");
                                 if let Err(e) = prom_ref.js_promise_reject(realm, &err_ref) {
                                     log::error!(
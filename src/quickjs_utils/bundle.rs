@@ -0,0 +1,504 @@
+//! compile a module and all the modules it imports to a single bytecode bundle
+//!
+//! [compile_bundle] loads an entry module and every module it (transitively) imports statically with the module loaders of the runtime
+//! and compiles them to bytecode, [BytecodeBundle::to_bytes] writes the bundle as a single archive with an index
+//! and a [BytecodeBundleModuleLoader] serves the modules of a bundle, no source is included in the bundle
+//!
+//! modules without a source (native modules and modules of a CompiledModuleLoader) are not included in a bundle,
+//! they should be provided by the module loaders of the runtime which loads the bundle
+//!
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::modules::ScriptModuleLoader;
+//! use quickjs_runtime::jsutils::Script;
+//! use quickjs_runtime::quickjs_utils::bundle::{compile_bundle, BytecodeBundle, BytecodeBundleModuleLoader};
+//! use quickjs_runtime::quickjsrealmadapter::QuickJsRealmAdapter;
+//!
+//! struct SourceLoader {}
+//! impl ScriptModuleLoader for SourceLoader {
+//!     fn normalize_path(&self, _realm: &QuickJsRealmAdapter, _ref_path: &str, path: &str) -> Option<String> {
+//!         Some(path.to_string())
+//!     }
+//!     fn load_module(&self, _realm: &QuickJsRealmAdapter, absolute_path: &str) -> String {
+//!         match absolute_path {
+//!             "main.mjs" => "import {b} from 'b.mjs'; export const a = b * 2;".to_string(),
+//!             _ => "export const b = 21;".to_string(),
+//!         }
+//!     }
+//! }
+//!
+//! // compile the bundle
+//! let rt = QuickJsRuntimeBuilder::new().script_module_loader(SourceLoader{}).build();
+//! let bytes = rt.loop_realm_sync(None, |_rt, realm| {
+//!     compile_bundle(realm, "main.mjs").expect("could not compile bundle").to_bytes()
+//! });
+//!
+//! // and run it somewhere else
+//! let bundle = BytecodeBundle::from_bytes(&bytes).expect("invalid bundle");
+//! let rt = QuickJsRuntimeBuilder::new().compiled_module_loader(BytecodeBundleModuleLoader::new(bundle)).build();
+//! rt.eval_module_sync(None, Script::new("run.mjs", "import {a} from 'main.mjs'; globalThis.a = a;")).expect("module failed");
+//! let a = rt.eval_sync(None, Script::new("a.js", "a;")).expect("script failed");
+//! assert_eq!(a.get_i32(), 42);
+//! ```
+
+use crate::jsutils::modules::{CompiledModuleLoader, ModuleSource};
+use crate::jsutils::{JsError, Script};
+use crate::quickjs_utils::{compile, modules};
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::{
+    apply_declared_type, QuickJsRuntimeAdapter, ScriptModuleLoaderAdapter,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+const MAGIC: &[u8; 8] = b"QJSBUNDL";
+const FORMAT_VERSION: u32 = 1;
+
+/// a set of modules compiled to bytecode, created by [compile_bundle]
+pub struct BytecodeBundle {
+    entry: String,
+    modules: Vec<(String, Arc<Vec<u8>>)>,
+    /// (referrer, specifier, path) of the imports
+    resolutions: Vec<(String, String, String)>,
+}
+
+impl BytecodeBundle {
+    /// the path of the entry module
+    pub fn get_entry(&self) -> &str {
+        self.entry.as_str()
+    }
+
+    /// the paths of all modules in the bundle
+    pub fn get_module_paths(&self) -> Vec<&str> {
+        self.modules.iter().map(|(path, _)| path.as_str()).collect()
+    }
+
+    /// get the bytecode of a module
+    pub fn get_module(&self, path: &str) -> Option<Arc<Vec<u8>>> {
+        self.modules
+            .iter()
+            .find(|(module_path, _)| module_path == path)
+            .map(|(_, bytecode)| bytecode.clone())
+    }
+
+    /// write the bundle as a single archive
+    ///
+    /// the archive starts with an index of the modules and the resolved imports, followed by the bytecode of the modules
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        write_u32(&mut bytes, FORMAT_VERSION);
+        write_str(&mut bytes, self.entry.as_str());
+        write_u32(&mut bytes, self.modules.len() as u32);
+        for (path, bytecode) in &self.modules {
+            write_str(&mut bytes, path.as_str());
+            write_u32(&mut bytes, bytecode.len() as u32);
+        }
+        write_u32(&mut bytes, self.resolutions.len() as u32);
+        for (referrer, specifier, path) in &self.resolutions {
+            write_str(&mut bytes, referrer.as_str());
+            write_str(&mut bytes, specifier.as_str());
+            write_str(&mut bytes, path.as_str());
+        }
+        for (_, bytecode) in &self.modules {
+            bytes.extend_from_slice(bytecode);
+        }
+        bytes
+    }
+
+    /// read a bundle which was written with to_bytes
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, JsError> {
+        let mut reader = Reader { bytes, pos: 0 };
+        if reader.read(MAGIC.len())? != MAGIC {
+            return Err(JsError::new_str("not a bytecode bundle"));
+        }
+        let format_version = reader.read_u32()?;
+        if format_version != FORMAT_VERSION {
+            return Err(JsError::new_string(format!(
+                "unsupported bytecode bundle format version {format_version}"
+            )));
+        }
+        let entry = reader.read_str()?;
+        let mut index = vec![];
+        for _ in 0..reader.read_u32()? {
+            index.push((reader.read_str()?, reader.read_u32()? as usize));
+        }
+        let mut resolutions = vec![];
+        for _ in 0..reader.read_u32()? {
+            resolutions.push((reader.read_str()?, reader.read_str()?, reader.read_str()?));
+        }
+        let mut modules = vec![];
+        for (path, len) in index {
+            modules.push((path, Arc::new(reader.read(len)?.to_vec())));
+        }
+        Ok(Self {
+            entry,
+            modules,
+            resolutions,
+        })
+    }
+}
+
+fn write_u32(bytes: &mut Vec<u8>, val: u32) {
+    bytes.extend_from_slice(&val.to_le_bytes());
+}
+
+fn write_str(bytes: &mut Vec<u8>, val: &str) {
+    write_u32(bytes, val.len() as u32);
+    bytes.extend_from_slice(val.as_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn read(&mut self, len: usize) -> Result<&'a [u8], JsError> {
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| JsError::new_str("bytecode bundle is truncated"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn read_u32(&mut self) -> Result<u32, JsError> {
+        let bytes = self.read(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn read_str(&mut self) -> Result<String, JsError> {
+        let len = self.read_u32()? as usize;
+        String::from_utf8(self.read(len)?.to_vec())
+            .map_err(|_| JsError::new_str("invalid string in bytecode bundle"))
+    }
+}
+
+/// compile a module and all the modules it imports statically to a BytecodeBundle
+///
+/// the modules are loaded and resolved with the module loaders and the import map of the runtime and realm,
+/// modules of async module loaders can not be bundled
+///
+/// please note that the compiled modules stay in the realm (unevaluated), use a realm or runtime which is dedicated to bundling
+pub fn compile_bundle(realm: &QuickJsRealmAdapter, entry: &str) -> Result<BytecodeBundle, JsError> {
+    let entry_path = normalize_import(realm, entry, entry)
+        .map(|(_, path)| path)
+        .ok_or_else(|| JsError::new_string(format!("module {entry} was not found")))?;
+
+    let mut bundle = BytecodeBundle {
+        entry: entry_path.clone(),
+        modules: vec![],
+        resolutions: vec![],
+    };
+    // normalized path -> type attribute it was imported with, a bundle has one module per path
+    let mut module_types: HashMap<String, Option<String>> = HashMap::new();
+    let mut todo = vec![(entry_path.clone(), None)];
+    while let Some((path, module_type)) = todo.pop() {
+        match module_types.get(&path) {
+            Some(seen_type) if *seen_type == module_type => continue,
+            Some(_) => {
+                return Err(JsError::new_string(format!(
+                    "module {path} is imported with different types and can not be bundled"
+                )));
+            }
+            None => {
+                module_types.insert(path.clone(), module_type.clone());
+            }
+        }
+        let source = get_module_source(realm, path.as_str())?;
+        let source = match source {
+            Some(source) => apply_declared_type(path.as_str(), module_type.as_deref(), source)?,
+            None if path == entry_path => {
+                return Err(JsError::new_string(format!(
+                    "module {path} has no source and can not be bundled"
+                )));
+            }
+            None => {
+                log::debug!("compile_bundle: {path} has no source and is not bundled");
+                continue;
+            }
+        };
+
+        let script = match source {
+            ModuleSource::Script(code) => {
+                let mut script = Script::new(path.as_str(), code.as_str());
+                modules::strip_import_attributes(&mut script);
+                let script =
+                    ScriptModuleLoaderAdapter::process_script_module(realm, path.as_str(), script)?;
+                for specifier in
+                    modules::find_static_imports(path.as_str(), script.get_runnable_code())
+                {
+                    // the type of an import was added to its specifier when the attributes were stripped
+                    let (specifier, import_type) = modules::split_module_type(specifier.as_str());
+                    let (mapped_name, import_path) =
+                        normalize_import(realm, path.as_str(), specifier).ok_or_else(|| {
+                            JsError::new_string(format!(
+                                "module {specifier} imported by {path} was not found"
+                            ))
+                        })?;
+                    // the runtime which loads the bundle may apply the import map before the loaders
+                    if let Some(mapped_name) = mapped_name {
+                        bundle
+                            .resolutions
+                            .push((path.clone(), mapped_name, import_path.clone()));
+                    }
+                    bundle.resolutions.push((
+                        path.clone(),
+                        specifier.to_string(),
+                        import_path.clone(),
+                    ));
+                    todo.push((import_path, import_type.map(|t| t.to_string())));
+                }
+                script
+            }
+            ModuleSource::Json(json) => {
+                serde_json::from_str::<serde_json::Value>(json.as_str()).map_err(|e| {
+                    JsError::new_string(format!("module {path} is not valid json: {e}"))
+                })?;
+                let code = format!("export default JSON.parse({});", to_js_string(&json));
+                Script::new(path.as_str(), code.as_str())
+            }
+            ModuleSource::Text(text) => {
+                let code = format!("export default {};", to_js_string(&text));
+                Script::new(path.as_str(), code.as_str())
+            }
+            ModuleSource::Bytes(bytes) => {
+                let code = format!(
+                    "export default new Uint8Array({});",
+                    serde_json::to_string(&bytes).unwrap_or_default()
+                );
+                Script::new(path.as_str(), code.as_str())
+            }
+            ModuleSource::Bytecode(bytecode) => {
+                bundle.modules.push((path, bytecode));
+                continue;
+            }
+            ModuleSource::CommonJs(_) => {
+                return Err(JsError::new_string(format!(
+                    "module {path} is a CommonJS module and can not be bundled"
+                )));
+            }
+        };
+
+        let bytecode = unsafe {
+            let compiled_module = modules::compile_module(realm.context, script)?;
+            compile::to_bytecode(realm.context, &compiled_module)
+        };
+        bundle.modules.push((path, Arc::new(bytecode)));
+    }
+    Ok(bundle)
+}
+
+fn to_js_string(val: &str) -> String {
+    serde_json::to_string(val).unwrap_or_default()
+}
+
+/// resolve an import with the import map and the module loaders, returns the mapped name (if any) and the path
+pub(crate) fn normalize_import(
+    realm: &QuickJsRealmAdapter,
+    referrer: &str,
+    specifier: &str,
+) -> Option<(Option<String>, String)> {
+    let mapped_name = realm.resolve_import_map(specifier, referrer);
+    let name = mapped_name.as_deref().unwrap_or(specifier);
+    let path = QuickJsRuntimeAdapter::do_with(|rt| {
+        rt.with_all_module_loaders(|loader| loader.normalize_path(realm, referrer, name))
+    })?;
+    Some((mapped_name, path))
+}
+
+/// get the source of a module from the loader which would load it
+pub(crate) fn get_module_source(
+    realm: &QuickJsRealmAdapter,
+    path: &str,
+) -> Result<Option<ModuleSource>, JsError> {
+    QuickJsRuntimeAdapter::do_with(|rt| {
+        rt.with_all_module_loaders(|loader| {
+            if !loader.has_module(realm, path) {
+                return None;
+            }
+            if loader.is_async() {
+                return Some(Err(JsError::new_string(format!(
+                    "module {path} of async module loader {} can not be bundled",
+                    loader.get_name()
+                ))));
+            }
+            Some(loader.get_module_source(realm, path).transpose())
+        })
+    })
+    .unwrap_or(Ok(None))
+}
+
+/// a CompiledModuleLoader which loads the modules of a BytecodeBundle
+///
+/// imports are resolved as they were resolved when the bundle was compiled, modules which are not in the bundle are left to the other module loaders
+pub struct BytecodeBundleModuleLoader {
+    modules: HashMap<String, Arc<Vec<u8>>>,
+    resolutions: HashMap<(String, String), String>,
+}
+
+impl BytecodeBundleModuleLoader {
+    pub fn new(bundle: BytecodeBundle) -> Self {
+        Self {
+            modules: bundle.modules.into_iter().collect(),
+            resolutions: bundle
+                .resolutions
+                .into_iter()
+                .map(|(referrer, specifier, path)| ((referrer, specifier), path))
+                .collect(),
+        }
+    }
+}
+
+impl CompiledModuleLoader for BytecodeBundleModuleLoader {
+    fn normalize_path(
+        &self,
+        _realm: &QuickJsRealmAdapter,
+        ref_path: &str,
+        path: &str,
+    ) -> Option<String> {
+        let resolved = self
+            .resolutions
+            .get(&(ref_path.to_string(), path.to_string()))
+            .map(|path| path.as_str())
+            .unwrap_or(path);
+        if self.modules.contains_key(resolved) {
+            Some(resolved.to_string())
+        } else {
+            None
+        }
+    }
+
+    fn load_module(
+        &self,
+        _realm: &QuickJsRealmAdapter,
+        absolute_path: &str,
+    ) -> Result<Arc<Vec<u8>>, JsError> {
+        self.modules.get(absolute_path).cloned().ok_or_else(|| {
+            JsError::new_string(format!("module {absolute_path} is not in the bundle"))
+        })
+    }
+
+    fn get_name(&self) -> &str {
+        "BytecodeBundleModuleLoader"
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::modules::ScriptModuleLoader;
+    use crate::jsutils::{JsError, Script};
+    use crate::quickjs_utils::bundle::{
+        compile_bundle, BytecodeBundle, BytecodeBundleModuleLoader,
+    };
+    use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+
+    struct SourceLoader {}
+    impl ScriptModuleLoader for SourceLoader {
+        fn normalize_path(
+            &self,
+            _realm: &QuickJsRealmAdapter,
+            ref_path: &str,
+            path: &str,
+        ) -> Option<String> {
+            match path {
+                "./util.mjs" if ref_path == "app/main.mjs" => Some("app/util.mjs".to_string()),
+                "app/main.mjs" | "app/util.mjs" | "app/data.json" => Some(path.to_string()),
+                "./data.json" => Some("app/data.json".to_string()),
+                _ => None,
+            }
+        }
+
+        fn load_module(&self, _realm: &QuickJsRealmAdapter, absolute_path: &str) -> String {
+            match absolute_path {
+                "app/main.mjs" => "import {mul} from './util.mjs';\nimport data from './data.json' with { type: 'json' };\nexport const answer = mul(data.a, data.b);".to_string(),
+                "app/util.mjs" => "export function mul(a, b) { return a * b; }".to_string(),
+                "app/data.json" => "{\"a\": 6, \"b\": 7}".to_string(),
+                _ => panic!("unexpected module {absolute_path}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_bundle() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_module_loader(SourceLoader {})
+            .build();
+        let bytes = rt.loop_realm_sync(None, |_rt, realm| {
+            let bundle = compile_bundle(realm, "app/main.mjs").expect("bundle failed");
+            assert_eq!(bundle.get_entry(), "app/main.mjs");
+            let mut paths = bundle.get_module_paths();
+            paths.sort();
+            assert_eq!(paths, vec!["app/data.json", "app/main.mjs", "app/util.mjs"]);
+            bundle.to_bytes()
+        });
+        drop(rt);
+
+        let bundle = BytecodeBundle::from_bytes(&bytes).expect("could not read bundle");
+        assert!(BytecodeBundle::from_bytes(&bytes[0..bytes.len() - 1]).is_err());
+
+        // no source loaders here
+        let rt = QuickJsRuntimeBuilder::new()
+            .compiled_module_loader(BytecodeBundleModuleLoader::new(bundle))
+            .build();
+        rt.eval_module_sync(
+            None,
+            Script::new(
+                "run.mjs",
+                "import {answer} from 'app/main.mjs'; globalThis.answer = answer;",
+            ),
+        )
+        .expect("module failed");
+        let res = rt
+            .eval_sync(None, Script::new("answer.js", "answer;"))
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 42);
+    }
+
+    #[test]
+    fn test_bundle_async_loader() {
+        use crate::jsutils::modules::{AsyncModuleLoader, ModuleSourceFuture};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        struct RemoteLoader {
+            loads: Arc<AtomicUsize>,
+        }
+        impl AsyncModuleLoader for RemoteLoader {
+            fn normalize_path(
+                &self,
+                _realm: &QuickJsRealmAdapter,
+                _ref_path: &str,
+                path: &str,
+            ) -> Option<String> {
+                Some(path.to_string())
+            }
+
+            fn load_module(
+                &self,
+                _realm: &QuickJsRealmAdapter,
+                absolute_path: &str,
+            ) -> ModuleSourceFuture {
+                self.loads.fetch_add(1, Ordering::SeqCst);
+                let path = absolute_path.to_string();
+                Box::pin(async move { Err(JsError::new_string(format!("no such module {path}"))) })
+            }
+        }
+
+        let loads = Arc::new(AtomicUsize::new(0));
+        let rt = QuickJsRuntimeBuilder::new()
+            .async_module_loader(RemoteLoader {
+                loads: loads.clone(),
+            })
+            .build();
+        let res = rt.loop_realm_sync(None, |_rt, realm| {
+            compile_bundle(realm, "remote/main.mjs").map(|_| ())
+        });
+        let err = res.expect_err("bundle passed");
+        assert!(err.get_message().contains("can not be bundled"));
+        // checking the loader does not start a load
+        assert_eq!(loads.load(Ordering::SeqCst), 0);
+    }
+}
@@ -1,9 +1,11 @@
 //! Utils to compile script to bytecode and run script from bytecode
 
+use crate::jsutils::bytecodecache;
 use crate::jsutils::JsError;
 use crate::jsutils::Script;
+use crate::quickjs_utils::{errors, modules};
 use crate::quickjsrealmadapter::QuickJsRealmAdapter;
-use crate::quickjsruntimeadapter::make_cstring;
+use crate::quickjsruntimeadapter::{make_cstring, QuickJsRuntimeAdapter};
 use crate::quickjsvalueadapter::QuickJsValueAdapter;
 use libquickjs_sys as q;
 use std::os::raw::c_void;
@@ -74,6 +76,83 @@ pub unsafe fn compile(
     }
 }
 
+/// compile a script or module with the bytecode cache of the runtime
+/// process (e.g. QuickJsRuntimeAdapter::pre_process) is only called when the script is not in the cache
+/// without a bytecode cache this just processes and compiles the script
+/// # Safety
+/// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
+pub(crate) unsafe fn compile_cached<P>(
+    context: *mut q::JSContext,
+    script: Script,
+    is_module: bool,
+    process: P,
+) -> Result<QuickJsValueAdapter, JsError>
+where
+    P: FnOnce(Script) -> Result<Script, JsError>,
+{
+    let compile_script = |script: Script| {
+        if is_module {
+            modules::compile_module(context, script)
+        } else {
+            compile(context, script)
+        }
+    };
+
+    QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+        let store = match &q_js_rt.bytecode_store {
+            Some(store) => store,
+            None => return compile_script(process(script)?),
+        };
+
+        // a script from the cache is not pre-processed so remember its source here
+        errors::remember_source(script.get_path(), script.get_code());
+
+        let key = bytecodecache::cache_key(
+            if is_module { "module" } else { "script" },
+            script.get_path(),
+            script.get_code(),
+            q_js_rt
+                .get_pre_processors_cache_key(
+                    q_js_rt.get_context(QuickJsRealmAdapter::get_id(context)),
+                )
+                .as_str(),
+        );
+        if let Some(entry) = store.get(key.as_str()) {
+            match bytecodecache::decode_entry(&entry) {
+                Some((_map, bytecode)) => match from_bytecode(context, bytecode) {
+                    Ok(compiled) => {
+                        log::trace!("compile_cached: {} from cache", script.get_path());
+                        #[cfg(feature = "typescript")]
+                        if let Some(map) = _map {
+                            crate::typescript::register_source_map(
+                                modules::strip_module_version(script.get_path()),
+                                map,
+                            );
+                        }
+                        return Ok(compiled);
+                    }
+                    Err(e) => log::warn!(
+                        "could not read cached bytecode of {}: {}",
+                        script.get_path(),
+                        e
+                    ),
+                },
+                None => log::warn!("invalid bytecode cache entry for {}", script.get_path()),
+            }
+        }
+
+        let script = process(script)?;
+        let map = script.get_map().map(|m| m.to_string());
+        let compiled = compile_script(script)?;
+        let bytecode = to_bytecode(context, &compiled);
+        store.put(
+            key.as_str(),
+            &bytecodecache::encode_entry(map.as_deref(), &bytecode),
+        );
+        Ok(compiled)
+    })
+}
+
 /// run a compiled function, see compile for an example
 /// # Safety
 /// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
@@ -81,7 +160,13 @@ pub unsafe fn run_compiled_function(
     context: *mut q::JSContext,
     compiled_func: &QuickJsValueAdapter,
 ) -> Result<QuickJsValueAdapter, JsError> {
-    assert!(compiled_func.is_compiled_function());
+    assert!(compiled_func.is_compiled_function() || compiled_func.is_module());
+    // the imports of a module are loaded before it is evaluated
+    if compiled_func.is_module() && q::JS_ResolveModule(context, *compiled_func.borrow_value()) < 0
+    {
+        return Err(QuickJsRealmAdapter::get_exception(context)
+            .unwrap_or_else(|| JsError::new_str("could not resolve the imports of the module")));
+    }
     let val = q::JS_EvalFunction(context, compiled_func.clone_value_incr_rc());
     let val_ref =
         QuickJsValueAdapter::new(context, val, false, true, "run_compiled_function result");
@@ -100,6 +185,8 @@ pub unsafe fn run_compiled_function(
 }
 
 /// write a function to bytecode
+///
+/// the bytecode starts with a header (engine, engine version, crate version, flags and a checksum) which is validated by from_bytecode
 /// # Example
 /// ```rust
 /// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
@@ -143,20 +230,189 @@ pub unsafe fn to_bytecode(
     );
 
     let slice = std::slice::from_raw_parts(slice_u8, len as _);
-    // it's a shame to copy the vec here but the alternative is to create a wrapping struct which free's the ptr on drop
-    let ret = slice.to_vec();
+    let flags = if compiled_func.is_module() {
+        BYTECODE_FLAG_MODULE
+    } else {
+        0
+    } | current_endian_flag();
+    let ret = write_bytecode_header(flags, slice);
     q::js_free(context, slice_u8 as *mut c_void);
     ret
 }
 
+const BYTECODE_MAGIC: &[u8; 4] = b"QJSB";
+const BYTECODE_HEADER_VERSION: u8 = 1;
+/// the version of the hirofa-quickjs-sys crate which embeds the QuickJS sources, read from the Cargo.lock of the build
+/// by build.rs
+const SYS_CRATE_VERSION: &str = env!("QUICKJS_SYS_VERSION");
+/// the bytecode is a module
+pub const BYTECODE_FLAG_MODULE: u16 = 1;
+/// the bytecode was written on a big endian machine
+pub const BYTECODE_FLAG_BIG_ENDIAN: u16 = 2;
+
+/// the header which is written before the bytecode by to_bytecode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BytecodeHeader {
+    /// bellard or quickjs-ng
+    pub engine: String,
+    pub engine_version: String,
+    /// the version of quickjs_runtime which compiled the bytecode, this is informational, bytecode of another version
+    /// of quickjs_runtime is read if the engine and engine_version are the same
+    pub crate_version: String,
+    pub flags: u16,
+    /// FNV-1a hash of the bytecode
+    pub checksum: u32,
+}
+
+impl BytecodeHeader {
+    /// the header for bytecode compiled by this runtime
+    fn current(flags: u16, bytecode: &[u8]) -> Self {
+        Self {
+            engine: bytecodecache::ENGINE.to_string(),
+            engine_version: engine_version(),
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            flags,
+            checksum: checksum(bytecode),
+        }
+    }
+
+    pub fn is_module(&self) -> bool {
+        self.flags & BYTECODE_FLAG_MODULE != 0
+    }
+}
+
+/// the version of the sys crate, quickjs-ng also reports its own version
+fn engine_version() -> String {
+    #[cfg(feature = "quickjs-ng")]
+    let engine_version = format!(
+        "hirofa-quickjs-sys {SYS_CRATE_VERSION} quickjs-ng {}.{}.{}",
+        q::QJS_VERSION_MAJOR,
+        q::QJS_VERSION_MINOR,
+        q::QJS_VERSION_PATCH
+    );
+    #[cfg(not(feature = "quickjs-ng"))]
+    let engine_version = format!("hirofa-quickjs-sys {SYS_CRATE_VERSION}");
+    engine_version
+}
+
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c9dc5_u32, |hash, b| {
+        (hash ^ *b as u32).wrapping_mul(0x01000193)
+    })
+}
+
+fn write_bytecode_header(flags: u16, bytecode: &[u8]) -> Vec<u8> {
+    let header = BytecodeHeader::current(flags, bytecode);
+    let mut ret = Vec::with_capacity(bytecode.len() + 64);
+    ret.extend_from_slice(BYTECODE_MAGIC);
+    ret.push(BYTECODE_HEADER_VERSION);
+    for field in [
+        &header.engine,
+        &header.engine_version,
+        &header.crate_version,
+    ] {
+        ret.push(field.len() as u8);
+        ret.extend_from_slice(field.as_bytes());
+    }
+    ret.extend_from_slice(&header.flags.to_le_bytes());
+    ret.extend_from_slice(&header.checksum.to_le_bytes());
+    ret.extend_from_slice(&(bytecode.len() as u32).to_le_bytes());
+    ret.extend_from_slice(bytecode);
+    ret
+}
+
+/// read the header of bytecode written by to_bytecode, returns the header and the actual bytecode
+pub fn read_bytecode_header(bytecode: &[u8]) -> Result<(BytecodeHeader, &[u8]), JsError> {
+    let invalid = || JsError::new_str("invalid bytecode: the header is incomplete");
+    if bytecode.get(0..BYTECODE_MAGIC.len()) != Some(BYTECODE_MAGIC) {
+        return Err(JsError::new_str(
+            "invalid bytecode: no bytecode header found, bytecode should be created with compile::to_bytecode",
+        ));
+    }
+    let mut pos = BYTECODE_MAGIC.len();
+    let mut read = |len: usize| {
+        let slice = bytecode.get(pos..pos + len).ok_or_else(invalid)?;
+        pos += len;
+        Ok::<&[u8], JsError>(slice)
+    };
+    let header_version = read(1)?[0];
+    if header_version != BYTECODE_HEADER_VERSION {
+        return Err(JsError::new_string(format!(
+            "invalid bytecode: unsupported header version {header_version}"
+        )));
+    }
+    let mut fields = vec![];
+    for _ in 0..3 {
+        let len = read(1)?[0] as usize;
+        let field = std::str::from_utf8(read(len)?).map_err(|_| invalid())?;
+        fields.push(field.to_string());
+    }
+    let flags = u16::from_le_bytes(read(2)?.try_into().map_err(|_| invalid())?);
+    let checksum = u32::from_le_bytes(read(4)?.try_into().map_err(|_| invalid())?);
+    let len = u32::from_le_bytes(read(4)?.try_into().map_err(|_| invalid())?) as usize;
+    let code = read(len)?;
+    let crate_version = fields.pop().unwrap_or_default();
+    let engine_version = fields.pop().unwrap_or_default();
+    let engine = fields.pop().unwrap_or_default();
+    Ok((
+        BytecodeHeader {
+            engine,
+            engine_version,
+            crate_version,
+            flags,
+            checksum,
+        },
+        code,
+    ))
+}
+
+/// check that bytecode can be read by this runtime, returns the actual bytecode
+fn validate_bytecode(bytecode: &[u8]) -> Result<&[u8], JsError> {
+    let (header, code) = read_bytecode_header(bytecode)?;
+    let current = BytecodeHeader::current(header.flags, code);
+    if header.engine != current.engine {
+        return Err(JsError::new_string(format!(
+            "bytecode was compiled with the {} engine but this runtime uses {}",
+            header.engine, current.engine
+        )));
+    }
+    if header.engine_version != current.engine_version {
+        return Err(JsError::new_string(format!(
+            "bytecode was compiled with engine version {} but this runtime uses {}",
+            header.engine_version, current.engine_version
+        )));
+    }
+    if header.flags & BYTECODE_FLAG_BIG_ENDIAN != current_endian_flag() {
+        return Err(JsError::new_str(
+            "bytecode was compiled on a machine with a different byte order",
+        ));
+    }
+    if header.checksum != current.checksum {
+        return Err(JsError::new_str(
+            "bytecode is corrupt: the checksum does not match",
+        ));
+    }
+    Ok(code)
+}
+
+fn current_endian_flag() -> u16 {
+    if cfg!(target_endian = "big") {
+        BYTECODE_FLAG_BIG_ENDIAN
+    } else {
+        0
+    }
+}
+
 /// read a compiled function from bytecode, see to_bytecode for an example
+///
+/// the header written by to_bytecode is validated first, bytecode of another engine or version results in an Err
 /// # Safety
 /// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
 pub unsafe fn from_bytecode(
     context: *mut q::JSContext,
     bytecode: &[u8],
 ) -> Result<QuickJsValueAdapter, JsError> {
-    assert!(!bytecode.is_empty());
+    let bytecode = validate_bytecode(bytecode)?;
     {
         let len = bytecode.len();
 
@@ -184,9 +440,10 @@ pub mod tests {
     use crate::builder::QuickJsRuntimeBuilder;
     use crate::facades::tests::init_test_rt;
     use crate::jsutils::modules::CompiledModuleLoader;
-    use crate::jsutils::Script;
+    use crate::jsutils::{JsError, Script};
     use crate::quickjs_utils::compile::{
-        compile, from_bytecode, run_compiled_function, to_bytecode,
+        compile, from_bytecode, read_bytecode_header, run_compiled_function, to_bytecode,
+        SYS_CRATE_VERSION,
     };
     use crate::quickjs_utils::modules::compile_module;
     use crate::quickjs_utils::primitives;
@@ -265,6 +522,46 @@ pub mod tests {
         });
     }
 
+    #[test]
+    fn test_bytecode_header() {
+        let rt = init_test_rt();
+        rt.exe_rt_task_in_event_loop(|q_js_rt| unsafe {
+            let q_ctx = q_js_rt.get_main_realm();
+            let func = compile(q_ctx.context, Script::new("test_header.es", "1 + 2;"))
+                .expect("func compile failed");
+            let bytecode = to_bytecode(q_ctx.context, &func);
+
+            let (header, _) = read_bytecode_header(&bytecode).expect("no header");
+            assert_eq!(header.crate_version, env!("CARGO_PKG_VERSION"));
+            assert!(header.engine_version.starts_with("hirofa-quickjs-sys "));
+            assert!(!header.is_module());
+
+            // other engine
+            let mut other = bytecode.clone();
+            let engine_len = other[5] as usize;
+            other[6..6 + engine_len].fill(b'x');
+            let err = from_bytecode(q_ctx.context, &other).expect_err("other engine was read");
+            assert!(err.get_message().contains("engine"));
+
+            // corrupt bytecode
+            let mut corrupt = bytecode.clone();
+            let last = corrupt.len() - 1;
+            corrupt[last] ^= 0xff;
+            let err =
+                from_bytecode(q_ctx.context, &corrupt).expect_err("corrupt bytecode was read");
+            assert!(err.get_message().contains("checksum"));
+
+            // truncated or missing header
+            from_bytecode(q_ctx.context, &bytecode[0..bytecode.len() - 1])
+                .expect_err("truncated bytecode was read");
+            from_bytecode(q_ctx.context, &[]).expect_err("empty bytecode was read");
+
+            let func2 = from_bytecode(q_ctx.context, &bytecode).expect("could not read bytecode");
+            let res = run_compiled_function(q_ctx.context, &func2).expect("run failed");
+            assert_eq!(primitives::to_i32(&res).expect("not an i32"), 3);
+        });
+    }
+
     #[test]
     fn test_bytecode_bad_compile() {
         let rt = QuickJsRuntimeBuilder::new().build();
@@ -354,8 +651,12 @@ pub mod tests {
             Some(path.to_string())
         }
 
-        fn load_module(&self, _q_ctx: &QuickJsRealmAdapter, _absolute_path: &str) -> Arc<Vec<u8>> {
-            COMPILED_BYTES.clone()
+        fn load_module(
+            &self,
+            _q_ctx: &QuickJsRealmAdapter,
+            _absolute_path: &str,
+        ) -> Result<Arc<Vec<u8>>, JsError> {
+            Ok(COMPILED_BYTES.clone())
         }
     }
 
@@ -395,4 +696,58 @@ pub mod tests {
             panic!("did not get a prom");
         }
     }
+
+    struct FailingCml {}
+    impl CompiledModuleLoader for FailingCml {
+        fn normalize_path(
+            &self,
+            _q_ctx: &QuickJsRealmAdapter,
+            _ref_path: &str,
+            path: &str,
+        ) -> Option<String> {
+            Some(path.to_string())
+        }
+
+        fn load_module(
+            &self,
+            _q_ctx: &QuickJsRealmAdapter,
+            absolute_path: &str,
+        ) -> Result<Arc<Vec<u8>>, JsError> {
+            Err(JsError::new_string(format!(
+                "no bytecode for {absolute_path}"
+            )))
+        }
+    }
+
+    #[test]
+    fn test_bytecode_module_load_error() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .compiled_module_loader(FailingCml {})
+            .build();
+
+        let test_script = Script::new(
+            "test_bytecode_module_load_error.js",
+            "import('missingmodule').then(() => 'loaded', (ex) => '' + ex)",
+        );
+        let res_fut = rt.eval(None, test_script);
+        let res_prom = block_on(res_fut).expect("script failed");
+        if let JsValueFacade::JsPromise { cached_promise } = res_prom {
+            let prom_res = block_on(cached_promise.get_promise_result())
+                .expect("prom failed")
+                .expect("prom was rejected");
+            let res = prom_res.get_str().to_string();
+            assert!(res.contains("no bytecode for missingmodule"), "{res}");
+        } else {
+            panic!("did not get a prom");
+        }
+    }
+
+    #[test]
+    fn test_sys_crate_version() {
+        // the version was found in Cargo.lock
+        assert!(
+            !SYS_CRATE_VERSION.starts_with("unknown"),
+            "{SYS_CRATE_VERSION}"
+        );
+    }
 }
@@ -1,10 +1,20 @@
 //! utils for getting and reporting exceptions
 
+use crate::jsutils::diagnostics::code_frame_with_context;
 use crate::jsutils::JsError;
-use crate::quickjs_utils::{objects, primitives};
+use crate::quickjs_utils::modules::strip_module_version;
+use crate::quickjs_utils::{json, objects, primitives};
 use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::{make_cstring, QuickJsRuntimeAdapter};
 use crate::quickjsvalueadapter::{QuickJsValueAdapter, TAG_EXCEPTION};
+use crate::values::JsValueFacade;
 use libquickjs_sys as q;
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::os::raw::c_char;
+use std::panic;
+use std::panic::AssertUnwindSafe;
 
 /// Get the last exception from the runtime, and if present, convert it to an JsError.
 /// # Safety
@@ -30,12 +40,102 @@ pub unsafe fn get_exception(context: *mut q::JSContext) -> Option<JsError> {
     }
 }
 
+/// the max number of sources which are kept for code frames, the least recently evaluated sources are dropped first
+const MAX_CODE_FRAME_SOURCES: usize = 256;
+
+struct CodeFrameSources {
+    context_lines: u32,
+    /// path -> (source, tick of the last eval)
+    sources: HashMap<String, (String, u64)>,
+    tick: u64,
+}
+
+thread_local! {
+    // the sources of evaluated scripts by path, only kept in the worker thread when code frames are enabled
+    static CODE_FRAME_SOURCES: RefCell<Option<CodeFrameSources>> = const { RefCell::new(None) };
+}
+
+/// keep the sources of scripts so errors get a code frame, see QuickJsRuntimeBuilder::code_frames
+pub(crate) fn enable_code_frames(context_lines: u32) {
+    CODE_FRAME_SOURCES.with(|rc| {
+        rc.replace(Some(CodeFrameSources {
+            context_lines,
+            sources: HashMap::new(),
+            tick: 0,
+        }));
+    })
+}
+
+/// remember the (original) source of a script before it is pre-processed, if code frames are enabled
+pub(crate) fn remember_source(path: &str, code: &str) {
+    CODE_FRAME_SOURCES.with(|rc| {
+        if let Some(code_frame_sources) = &mut *rc.borrow_mut() {
+            let path = strip_module_version(path);
+            code_frame_sources.tick += 1;
+            let tick = code_frame_sources.tick;
+            let sources = &mut code_frame_sources.sources;
+            match sources.get_mut(path) {
+                Some((source, last_used)) => {
+                    *last_used = tick;
+                    if source != code {
+                        *source = code.to_string();
+                    }
+                }
+                None => {
+                    if sources.len() >= MAX_CODE_FRAME_SOURCES {
+                        // drop the least recently evaluated source
+                        let lru = sources
+                            .iter()
+                            .min_by_key(|(_, (_, last_used))| *last_used)
+                            .map(|(k, _)| k.clone());
+                        if let Some(lru) = lru {
+                            sources.remove(&lru);
+                        }
+                    }
+                    sources.insert(path.to_string(), (code.to_string(), tick));
+                }
+            }
+        }
+    })
+}
+
+/// the code frame for a position in a script which was remembered
+fn get_code_frame(file_name: &str, line_number: u32, column_number: Option<u32>) -> Option<String> {
+    CODE_FRAME_SOURCES.with(|rc| {
+        let code_frame_sources = rc.borrow();
+        let code_frame_sources = code_frame_sources.as_ref()?;
+        let (source, _) = code_frame_sources
+            .sources
+            .get(strip_module_version(file_name))?;
+        code_frame_with_context(
+            source,
+            line_number,
+            column_number,
+            code_frame_sources.context_lines,
+        )
+    })
+}
+
+/// max depth of Error.cause chains which are converted to JsError
+const MAX_CAUSE_DEPTH: usize = 16;
+
+/// names of properties which are part of the JsError itself and are thus not added as custom properties
+const ERROR_PROPERTY_NAMES: [&str; 5] = ["name", "message", "stack", "stack2", "cause"];
+
 /// convert an instance of Error to JsError
 /// # Safety
 /// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
 pub unsafe fn error_to_js_error(
     context: *mut q::JSContext,
     exception_ref: &QuickJsValueAdapter,
+) -> JsError {
+    error_to_js_error_depth(context, exception_ref, 0)
+}
+
+unsafe fn error_to_js_error_depth(
+    context: *mut q::JSContext,
+    exception_ref: &QuickJsValueAdapter,
+    depth: usize,
 ) -> JsError {
     log::trace!("error_to_js_error");
     let name_ref = objects::get_property(context, exception_ref, "name")
@@ -71,7 +171,71 @@ pub unsafe fn error_to_js_error(
         stack_string.push_str(stack_str.as_str());
     }
 
-    JsError::new(name_string, message_string, stack_string)
+    let mut js_error = JsError::new(name_string, message_string, stack_string);
+
+    let code_frame = match (js_error.get_file_name(), js_error.get_line_number()) {
+        (Some(file_name), Some(line_number)) => {
+            get_code_frame(file_name, line_number, js_error.get_column_number())
+        }
+        _ => None,
+    };
+    if let Some(code_frame) = code_frame {
+        js_error = js_error.with_code_frame(code_frame);
+    }
+
+    if depth < MAX_CAUSE_DEPTH {
+        if let Ok(cause_ref) = objects::get_property(context, exception_ref, "cause") {
+            if is_error(context, &cause_ref) {
+                js_error =
+                    js_error.with_cause(error_to_js_error_depth(context, &cause_ref, depth + 1));
+            } else if !cause_ref.is_null_or_undefined() {
+                if let Ok(cause_str) = primitives::to_string(context, &cause_ref) {
+                    js_error = js_error.with_cause(JsError::new_string(cause_str));
+                }
+            }
+        }
+    }
+
+    match get_custom_properties(context, exception_ref) {
+        Ok(props) => {
+            if !props.is_empty() {
+                js_error = js_error.with_properties(JsValueFacade::SerdeValue {
+                    value: serde_json::Value::Object(props),
+                });
+            }
+        }
+        Err(e) => {
+            log::debug!("could not get custom properties of error: {}", e);
+        }
+    }
+
+    js_error
+}
+
+/// get the custom enumerable properties of an Error as json values
+/// properties which can not be represented as json (like functions) are skipped
+unsafe fn get_custom_properties(
+    context: *mut q::JSContext,
+    error_ref: &QuickJsValueAdapter,
+) -> Result<serde_json::Map<String, serde_json::Value>, JsError> {
+    let entries = objects::traverse_properties(context, error_ref, |name, value| {
+        if ERROR_PROPERTY_NAMES.contains(&name) {
+            return Ok(None);
+        }
+        // stringify per property so cyclic values fail for that property only
+        let json_ref = match json::stringify(context, value, None) {
+            Ok(json_ref) => json_ref,
+            Err(_) => return Ok(None),
+        };
+        if !json_ref.is_string() {
+            return Ok(None);
+        }
+        let json_str = primitives::to_string(context, &json_ref)?;
+        Ok(serde_json::from_str::<serde_json::Value>(json_str.as_str())
+            .ok()
+            .map(|v| (name.to_string(), v)))
+    })?;
+    Ok(entries.into_iter().flatten().collect())
 }
 
 /// Create a new Error object
@@ -112,6 +276,48 @@ pub unsafe fn new_error(
     Ok(obj_ref)
 }
 
+/// Create a new Error object from a JsError, including its cause chain and custom properties
+/// # Safety
+/// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
+pub unsafe fn js_error_to_error(
+    context: *mut q::JSContext,
+    mut js_error: JsError,
+) -> Result<QuickJsValueAdapter, JsError> {
+    let err_ref = new_error(
+        context,
+        js_error.get_name(),
+        js_error.get_message(),
+        js_error.get_stack(),
+    )?;
+    add_error_details(context, &err_ref, &mut js_error)?;
+    Ok(err_ref)
+}
+
+/// set the cause and the custom properties of a JsError on an Error object
+/// # Safety
+/// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
+pub unsafe fn add_error_details(
+    context: *mut q::JSContext,
+    error_ref: &QuickJsValueAdapter,
+    js_error: &mut JsError,
+) -> Result<(), JsError> {
+    if let Some(cause) = js_error.take_cause() {
+        let cause_ref = js_error_to_error(context, cause)?;
+        objects::set_property(context, error_ref, "cause", &cause_ref)?;
+    }
+    if let Some(properties) = js_error.take_properties() {
+        let props_ref = QuickJsRealmAdapter::with_context(context, |realm| {
+            realm.from_js_value_facade(properties)
+        })?;
+        if props_ref.is_object() {
+            objects::traverse_properties(context, &props_ref, |name, value| {
+                objects::set_property(context, error_ref, name, value)
+            })?;
+        }
+    }
+    Ok(())
+}
+
 /// See if a JSValueRef is an Error object
 pub fn is_error_q(q_ctx: &QuickJsRealmAdapter, obj_ref: &QuickJsValueAdapter) -> bool {
     unsafe { is_error(q_ctx.context, obj_ref) }
@@ -146,10 +352,69 @@ pub unsafe fn throw(context: *mut q::JSContext, error: QuickJsValueAdapter) -> q
     }
 }
 
+/// Throw an InternalError with a message and get an Exception JSValue to return from native methods
+/// unlike report_ex the message is not used as a format string so it may safely contain '%'
+/// # Safety
+/// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
+pub unsafe fn throw_internal_error(context: *mut q::JSContext, message: &str) -> q::JSValue {
+    let fmt = "%s\0";
+    let c_msg = make_cstring(message.replace('\0', "").as_str()).expect("invalid state");
+    q::JS_ThrowInternalError(context, fmt.as_ptr() as *const c_char, c_msg.as_ptr());
+    q::JSValue {
+        u: q::JSValueUnion { int32: 0 },
+        tag: TAG_EXCEPTION,
+    }
+}
+
+/// get a readable message from the payload of a caught panic
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+/// run a native callback and catch a panic if it unwinds out of that callback
+/// a panic is reported to the runtime's panic handler (if one was set) and thrown in the context as an InternalError
+/// in that case the Err contains the Exception JSValue to return from the native method
+/// # Safety
+/// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
+pub unsafe fn catch_native_panic<R, F>(
+    context: *mut q::JSContext,
+    native_name: &str,
+    func: F,
+) -> Result<R, q::JSValue>
+where
+    F: FnOnce() -> R,
+{
+    match panic::catch_unwind(AssertUnwindSafe(func)) {
+        Ok(res) => Ok(res),
+        Err(payload) => {
+            let msg = panic_message(payload.as_ref());
+            log::error!("native function [{}] panicked: {}", native_name, msg);
+
+            QuickJsRuntimeAdapter::do_with(|rt| {
+                if let Some(panic_handler) = &rt.panic_handler {
+                    let realm = rt.get_quickjs_context(context);
+                    panic_handler(rt, realm, native_name, msg.as_str());
+                }
+            });
+
+            Err(throw_internal_error(
+                context,
+                format!("native function [{native_name}] panicked: {msg}").as_str(),
+            ))
+        }
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
     use crate::facades::tests::init_test_rt;
-    use crate::jsutils::{JsError, Script};
+    use crate::jsutils::{JsError, JsErrorKind, Script};
     use crate::quickjs_utils::functions;
     use crate::values::{JsValueConvertable, JsValueFacade};
     use std::thread;
@@ -424,4 +689,144 @@ async function a(){
 
         std::thread::sleep(Duration::from_secs(1));
     }
+
+    #[test]
+    fn test_structured_error() {
+        let rt = init_test_rt();
+        let res = rt.eval_sync(
+            None,
+            Script::new(
+                "structured.js",
+                r#"
+                let cause = new TypeError('inner');
+                let err = new RangeError('outer', {cause});
+                err.code = 'E_OUTER';
+                err.retries = 3;
+                throw err;
+            "#,
+            ),
+        );
+        let ex = res.expect_err("script should have failed");
+        assert_eq!(ex.get_kind(), JsErrorKind::RangeError);
+        assert_eq!(ex.get_message(), "outer");
+        assert_eq!(ex.get_file_name(), Some("structured.js"));
+        assert_eq!(ex.get_line_number(), Some(3));
+
+        let cause = ex.get_cause().expect("no cause");
+        assert_eq!(cause.get_kind(), JsErrorKind::TypeError);
+        assert_eq!(cause.get_message(), "inner");
+        assert!(std::error::Error::source(&ex).is_some());
+
+        match ex.get_properties() {
+            Some(JsValueFacade::SerdeValue { value }) => {
+                assert_eq!(value["code"], "E_OUTER");
+                assert_eq!(value["retries"], 3);
+            }
+            _ => panic!("no properties"),
+        }
+    }
+
+    #[test]
+    fn test_structured_error_to_script() {
+        let rt = init_test_rt();
+        rt.set_function(&[], "failWithCause", move |_realm, _args| {
+            let mut props = std::collections::HashMap::new();
+            props.insert("code".to_string(), "E_NATIVE".to_js_value_facade());
+            Err(JsError::new_str("outer")
+                .with_cause(JsError::new_str("inner"))
+                .with_properties(JsValueFacade::Object { val: props }))
+        })
+        .expect("could not set function");
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "structured_to_script.js",
+                    r#"
+                try {
+                    failWithCause();
+                    'no error';
+                } catch(ex) {
+                    `${ex.message}/${ex.cause.message}/${ex.code}`;
+                }
+            "#,
+                ),
+            )
+            .expect("script failed");
+        assert_eq!(res.get_str(), "outer/inner/E_NATIVE");
+    }
+
+    #[test]
+    fn test_error_kind() {
+        assert_eq!(
+            JsErrorKind::from_name("InternalError", "interrupted"),
+            JsErrorKind::Interrupted
+        );
+        assert_eq!(
+            JsErrorKind::from_name("InternalError", "out of memory"),
+            JsErrorKind::OutOfMemory
+        );
+        assert_eq!(
+            JsErrorKind::from_name("MyError", "poof"),
+            JsErrorKind::Custom("MyError".to_string())
+        );
+
+        let err = JsError::new(
+            "Error".to_string(),
+            "poof".to_string(),
+            "   at native_function [test]\n    at a (file.js:12:5)\n".to_string(),
+        );
+        assert_eq!(err.get_file_name(), Some("file.js"));
+        assert_eq!(err.get_line_number(), Some(12));
+        assert_eq!(err.get_column_number(), Some(5));
+
+        let err = JsError::new(
+            "SyntaxError".to_string(),
+            "poof".to_string(),
+            "    at file.js:7\n".to_string(),
+        );
+        assert_eq!(err.get_file_name(), Some("file.js"));
+        assert_eq!(err.get_line_number(), Some(7));
+        assert_eq!(err.get_column_number(), None);
+    }
+
+    #[cfg(feature = "typescript")]
+    #[test]
+    fn test_code_frame() {
+        use crate::builder::QuickJsRuntimeBuilder;
+
+        let rt = QuickJsRuntimeBuilder::new().code_frames(1).build();
+        let err = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "code_frame.ts",
+                    "let a: number = 1;\nlet b: string = 'b';\nthrow new Error(`fail ${a} ${b}`);\nlet c: number = 3;\nlet d: number = 4;",
+                ),
+            )
+            .expect_err("script should have failed");
+        assert_eq!(err.get_line_number(), Some(3));
+        // bellard does not report columns so there is no caret
+        #[cfg(feature = "bellard")]
+        assert_eq!(
+            err.get_code_frame(),
+            Some("  2 | let b: string = 'b';\n> 3 | throw new Error(`fail ${a} ${b}`);\n  4 | let c: number = 3;")
+        );
+        #[cfg(feature = "quickjs-ng")]
+        assert_eq!(
+            err.get_code_frame(),
+            Some("  2 | let b: string = 'b';\n> 3 | throw new Error(`fail ${a} ${b}`);\n    |           ^\n  4 | let c: number = 3;")
+        );
+        assert!(format!("{err}").contains("> 3 | throw"));
+
+        // without code_frames there is no code frame
+        let rt = QuickJsRuntimeBuilder::new().build();
+        let err = rt
+            .eval_sync(
+                None,
+                Script::new("no_code_frame.js", "throw new Error('fail');"),
+            )
+            .expect_err("script should have failed");
+        assert!(err.get_code_frame().is_none());
+    }
 }
@@ -933,6 +933,42 @@ pub mod tests {
         assert!(err.contains("[testMe]"));
         assert!(err.contains("test_927.es"));
     }
+
+    #[test]
+    fn test_panic() {
+        let rt = init_test_rt();
+
+        let res = rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_main_realm();
+
+            q_ctx
+                .install_closure(
+                    &["test_panic"],
+                    "panicMe",
+                    |_rt, _q_ctx, _this_ref, _args| panic!("oh no, a panic"),
+                    0,
+                )
+                .expect("could not install func");
+
+            let res = q_ctx
+                .eval(Script::new(
+                    "test_panic.es",
+                    "let res; try {test_panic.panicMe(); res = 'no ex';} catch(ex) {res = (ex instanceof InternalError) + ': ' + ex.message;} res;",
+                ))
+                .expect("script failed");
+            res.to_string().expect("could not get string")
+        });
+
+        assert!(res.starts_with("true: "));
+        assert!(res.contains("[panicMe]"));
+        assert!(res.contains("oh no, a panic"));
+
+        // the runtime should still be usable
+        let i = rt
+            .eval_sync(None, Script::new("test_panic2.es", "(1 + 2);"))
+            .expect("script failed");
+        assert_eq!(i.get_i32(), 3);
+    }
 }
 
 unsafe extern "C" fn callback_finalizer(_rt: *mut q::JSRuntime, val: q::JSValue) {
@@ -986,17 +1022,22 @@ unsafe extern "C" fn callback_function(
         let this_ref =
             QuickJsValueAdapter::new(ctx, this_val, true, true, "callback_function this_val");
 
-        let callback_res: Result<QuickJsValueAdapter, JsError> =
-            callback(ctx, &this_ref, args_vec.as_slice());
+        // a panic in the callback may not unwind through the quickjs frames, it is converted to an InternalError instead
+        let callback_res: Result<Result<QuickJsValueAdapter, JsError>, q::JSValue> =
+            errors::catch_native_panic(ctx, name.as_str(), || {
+                callback(ctx, &this_ref, args_vec.as_slice())
+            });
 
         match callback_res {
-            Ok(res) => res.clone_value_incr_rc(),
-            Err(e) => {
+            Ok(Ok(res)) => res.clone_value_incr_rc(),
+            Ok(Err(mut e)) => {
                 let nat_stack = format!("   at native_function [{}]\n{}", name, e.get_stack());
                 let err = errors::new_error(ctx, e.get_name(), e.get_message(), nat_stack.as_str())
                     .expect("could not create err");
+                errors::add_error_details(ctx, &err, &mut e).expect("could not create err");
                 errors::throw(ctx, err)
             }
+            Err(ex) => ex,
         }
     } else {
         panic!("callback not found");
@@ -1014,6 +1055,9 @@ pub mod tests2 {
         let rt = QuickJsRuntimeBuilder::new().build();
         rt.exe_rt_task_in_event_loop(|q_js_rt| {
             let q_ctx = q_js_rt.get_main_realm();
+            // features like commonjs may have registered callbacks of their own
+            let before1 = CALLBACK_REGISTRY.with(|rc| rc.borrow().len());
+            let before2 = CALLBACK_IDS.with(|rc| rc.borrow().len());
             let func = new_function_q(
                 q_ctx,
                 "test_func",
@@ -1024,14 +1068,14 @@ pub mod tests2 {
             .unwrap();
             let ct1 = CALLBACK_REGISTRY.with(|rc| rc.borrow().len());
             let ct2 = CALLBACK_IDS.with(|rc| rc.borrow().len());
-            assert_eq!(1, ct1);
-            assert_eq!(1, ct2);
+            assert_eq!(before1 + 1, ct1);
+            assert_eq!(before2 + 1, ct2);
             drop(func);
 
             let ct1 = CALLBACK_REGISTRY.with(|rc| rc.borrow().len());
             let ct2 = CALLBACK_IDS.with(|rc| rc.borrow().len());
-            assert_eq!(0, ct1);
-            assert_eq!(0, ct2);
+            assert_eq!(before1, ct1);
+            assert_eq!(before2, ct2);
         });
     }
 }
@@ -5,6 +5,7 @@ use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
 pub mod arrays;
 pub mod atoms;
 pub mod bigints;
+pub mod bundle;
 pub mod compile;
 pub mod dates;
 pub mod errors;
@@ -1,14 +1,17 @@
 //! utils for working with ES6 Modules
 
+use crate::jsutils::modules::ModuleSource;
 use crate::jsutils::{JsError, Script};
-use crate::quickjs_utils::atoms;
 use crate::quickjs_utils::atoms::JSAtomRef;
+use crate::quickjs_utils::promises::PromiseState;
+use crate::quickjs_utils::{atoms, errors, functions, objects, promises};
 use crate::quickjsrealmadapter::QuickJsRealmAdapter;
 use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
 use crate::quickjsvalueadapter::QuickJsValueAdapter;
 use core::ptr;
 
 use libquickjs_sys as q;
+use std::collections::{HashMap, HashSet};
 use std::ffi::{CStr, CString};
 
 /// compile a module, used for module loading
@@ -147,6 +150,1241 @@ pub unsafe fn get_module_name(
     atoms::to_string(ctx, &atom_ref)
 }
 
+/// separator of the version which is added to the name of an invalidated module
+/// QuickJS never forgets a loaded module so an invalidated module is loaded again under a new (versioned) name
+const MODULE_VERSION_SEPARATOR: &str = "?hmr=";
+
+/// separator of the type attribute which is added to the name of a module which was imported with a type
+/// QuickJS keys modules by name so a module imported as json and as javascript are two modules
+const MODULE_TYPE_SEPARATOR: &str = "#type=";
+
+/// strip the version which is added to the name of a module after it was invalidated and the type it was imported with
+/// e.g. "file.js?hmr=2" becomes "file.js" and "data.json#type=json" becomes "data.json"
+pub fn strip_module_version(module_name: &str) -> &str {
+    split_module_type(strip_version(module_name)).0
+}
+
+/// strip the version of a module name, the type is kept
+pub(crate) fn strip_version(module_name: &str) -> &str {
+    match module_name.rfind(MODULE_VERSION_SEPARATOR) {
+        Some(idx)
+            if module_name[idx + MODULE_VERSION_SEPARATOR.len()..]
+                .chars()
+                .all(|c| c.is_ascii_digit()) =>
+        {
+            &module_name[..idx]
+        }
+        _ => module_name,
+    }
+}
+
+/// split a module name without version in the path and the type it was imported with
+pub(crate) fn split_module_type(module_name: &str) -> (&str, Option<&str>) {
+    match module_name.rfind(MODULE_TYPE_SEPARATOR) {
+        Some(idx)
+            if module_name[idx + MODULE_TYPE_SEPARATOR.len()..]
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-') =>
+        {
+            (
+                &module_name[..idx],
+                Some(&module_name[idx + MODULE_TYPE_SEPARATOR.len()..]),
+            )
+        }
+        _ => (module_name, None),
+    }
+}
+
+/// the type attribute of the import which loaded a module, by the name of the module
+pub(crate) fn get_module_type(module_name: &str) -> Option<&str> {
+    split_module_type(strip_version(module_name)).1
+}
+
+/// the name of a module without version, this is the path plus the type the module was imported with
+fn typed_module_name(path: &str, module_type: Option<&str>) -> String {
+    match module_type {
+        Some(module_type) => format!("{path}{MODULE_TYPE_SEPARATOR}{module_type}"),
+        None => path.to_string(),
+    }
+}
+
+/// get the namespace object of a module, the exports of a module which was not evaluated yet can not be read
+///
+/// only quickjs-ng can get the namespace of a module def, with bellard use import_module_namespace
+/// # Safety
+/// please ensure the module ptr is valid and belongs to the context
+#[cfg(feature = "quickjs-ng")]
+pub unsafe fn get_module_namespace(
+    context: *mut q::JSContext,
+    module: *mut q::JSModuleDef,
+) -> Result<QuickJsValueAdapter, JsError> {
+    let namespace = QuickJsValueAdapter::new(
+        context,
+        q::JS_GetModuleNamespace(context, module),
+        false,
+        true,
+        "modules::get_module_namespace",
+    );
+    if namespace.is_exception() {
+        Err(QuickJsRealmAdapter::get_exception(context)
+            .unwrap_or_else(|| JsError::new_str("could not get module namespace")))
+    } else {
+        Ok(namespace)
+    }
+}
+
+/// the import.meta.hot state of a module
+#[derive(Default)]
+pub(crate) struct HotModuleState {
+    /// the module called import.meta.hot.accept()
+    accepted: bool,
+    accept_callbacks: Vec<QuickJsValueAdapter>,
+    dispose_callbacks: Vec<QuickJsValueAdapter>,
+    /// the data object which is passed from the dispose callbacks to the next version as import.meta.hot.data
+    data: Option<QuickJsValueAdapter>,
+}
+
+/// the load status of a module
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModuleStatus {
+    /// the module was loaded but did not (yet) finish evaluating
+    Pending,
+    Evaluated,
+    /// loading, compiling or evaluating the module failed
+    Errored,
+}
+
+/// info about a module which was loaded in a realm
+#[derive(Debug, Clone)]
+pub struct ModuleInfo {
+    /// the name of the module in QuickJS, this is the normalized path plus a version if the module was invalidated
+    pub name: String,
+    /// the normalized path of the module
+    pub path: String,
+    /// the name of the loader which loaded the module, None for modules evaluated by eval_module
+    pub loader: Option<String>,
+    /// the normalized paths of the modules this module imports
+    pub dependencies: Vec<String>,
+    pub status: ModuleStatus,
+}
+
+struct LoadedModule {
+    path: String,
+    loader: Option<String>,
+    status: ModuleStatus,
+}
+
+/// keeps track of the modules loaded in a realm
+#[derive(Default)]
+pub struct ModuleRegistry {
+    /// module name -> module
+    modules: HashMap<String, LoadedModule>,
+    /// normalized path -> normalized paths of the modules it imports
+    dependencies: HashMap<String, Vec<String>>,
+    /// normalized path -> normalized paths of the modules which import it
+    importers: HashMap<String, HashSet<String>>,
+    /// normalized path -> version, bumped every time the module is invalidated
+    versions: HashMap<String, u32>,
+    /// path and evaluation promise of modules evaluated by eval_module, used to settle the status of their imports
+    evaluations: Vec<(String, QuickJsValueAdapter)>,
+    pub(crate) hot_modules: HashMap<String, HotModuleState>,
+    /// module name -> default export of a json/text/bytes module which was loaded but not yet initialized
+    pub(crate) synthetic_exports: HashMap<String, QuickJsValueAdapter>,
+    /// module name without version -> source loaded by an async module loader which was not yet imported
+    pub(crate) preloaded: HashMap<String, ModuleSource>,
+    /// module names without version which are being loaded by an async module loader
+    preloading: HashSet<String>,
+    /// normalized path -> (code, pre-processed script) of a module which was pre-processed before it was compiled
+    processed: HashMap<String, (String, Script)>,
+    /// module name -> module, QuickJS keeps modules until the realm is dropped
+    pub(crate) module_defs: HashMap<String, *mut q::JSModuleDef>,
+    /// normalized path -> module object of a CommonJS module
+    #[cfg(feature = "commonjs")]
+    pub(crate) commonjs_modules: HashMap<String, QuickJsValueAdapter>,
+}
+
+impl ModuleRegistry {
+    pub(crate) fn add_dependency(&mut self, importer: &str, dependency: &str) {
+        let dependencies = self.dependencies.entry(importer.to_string()).or_default();
+        if !dependencies.iter().any(|d| d == dependency) {
+            dependencies.push(dependency.to_string());
+        }
+        self.importers
+            .entry(dependency.to_string())
+            .or_default()
+            .insert(importer.to_string());
+    }
+
+    pub(crate) fn register_module(
+        &mut self,
+        module_name: &str,
+        loader: Option<&str>,
+        status: ModuleStatus,
+    ) {
+        self.modules.insert(
+            module_name.to_string(),
+            LoadedModule {
+                path: strip_module_version(module_name).to_string(),
+                loader: loader.map(|l| l.to_string()),
+                status,
+            },
+        );
+    }
+
+    pub(crate) fn set_status(&mut self, module_name: &str, status: ModuleStatus) {
+        if let Some(module) = self.modules.get_mut(module_name) {
+            module.status = status;
+        }
+    }
+
+    /// set the status of all pending modules in the graph of an entry module
+    fn settle_graph(&mut self, entry_path: &str, status: ModuleStatus) {
+        let mut paths = vec![entry_path.to_string()];
+        let mut index = 0;
+        while index < paths.len() {
+            if let Some(dependencies) = self.dependencies.get(paths[index].as_str()) {
+                for dependency in dependencies {
+                    if !paths.contains(dependency) {
+                        paths.push(dependency.clone());
+                    }
+                }
+            }
+            index += 1;
+        }
+        for module in self.modules.values_mut() {
+            if module.status == ModuleStatus::Pending && paths.contains(&module.path) {
+                module.status = status;
+            }
+        }
+    }
+
+    /// settle the status of module graphs of which the evaluation promise was settled
+    fn settle_evaluations(&mut self, realm: &QuickJsRealmAdapter) {
+        let evaluations = std::mem::take(&mut self.evaluations);
+        for (path, promise) in evaluations {
+            match promises::get_promise_state_q(realm, &promise) {
+                PromiseState::Pending => self.evaluations.push((path, promise)),
+                PromiseState::Fulfilled => {
+                    self.settle_graph(path.as_str(), ModuleStatus::Evaluated)
+                }
+                PromiseState::Rejected => self.settle_graph(path.as_str(), ModuleStatus::Errored),
+            }
+        }
+    }
+
+    fn get_module_info(&self, module_name: &str) -> Option<ModuleInfo> {
+        self.modules.get(module_name).map(|module| ModuleInfo {
+            name: module_name.to_string(),
+            path: module.path.clone(),
+            loader: module.loader.clone(),
+            dependencies: self
+                .dependencies
+                .get(module.path.as_str())
+                .cloned()
+                .unwrap_or_default(),
+            status: module.status,
+        })
+    }
+
+    /// get the name under which QuickJS should load a module (a path, optionally with the type it is imported with),
+    /// this is the name plus a version if the module was invalidated
+    pub(crate) fn get_versioned_name(&self, name: &str) -> String {
+        match self.versions.get(strip_module_version(name)) {
+            Some(version) => format!("{name}{MODULE_VERSION_SEPARATOR}{version}"),
+            None => name.to_string(),
+        }
+    }
+
+    /// get a module and all modules which (transitively) import it
+    pub(crate) fn get_dependents(&self, path: &str) -> Vec<String> {
+        let mut result = vec![path.to_string()];
+        let mut index = 0;
+        while index < result.len() {
+            if let Some(importers) = self.importers.get(result[index].as_str()) {
+                for importer in importers {
+                    if !result.contains(importer) {
+                        result.push(importer.clone());
+                    }
+                }
+            }
+            index += 1;
+        }
+        result
+    }
+
+    pub(crate) fn bump_version(&mut self, path: &str) {
+        *self.versions.entry(path.to_string()).or_insert(0) += 1;
+    }
+
+    /// drop all script values, this needs to happen before the context is freed
+    pub(crate) fn clear_values(&mut self) {
+        self.hot_modules.clear();
+        self.evaluations.clear();
+        self.synthetic_exports.clear();
+        #[cfg(feature = "commonjs")]
+        self.commonjs_modules.clear();
+    }
+}
+
+/// name of the (non enumerable) global function which replaces import() calls when the runtime has async module loaders
+const IMPORT_ASYNC_FUNCTION: &str = "__importAsync";
+
+/// strip the import attributes (`with { type: 'json' }` or the older `assert { type: 'json' }`) from the static imports of
+/// a module before it is pre-processed, the type is added to the specifier of the import and applied when the module is
+/// loaded, the rest is replaced by whitespace so positions in stack traces don't change
+pub(crate) fn strip_import_attributes(script: &mut Script) {
+    let code = script.get_code();
+    if !code.contains("with") && !code.contains("assert") {
+        return;
+    }
+    if let Some(code) = find_imports(script.get_path(), code).code {
+        script.set_code(code);
+    }
+}
+
+/// get the specifiers of the static imports and exports of the (pre-processed) code of a module
+#[allow(unused_variables)]
+pub(crate) fn find_static_imports(path: &str, code: &str) -> Vec<String> {
+    #[cfg(feature = "typescript")]
+    if let Some(imports) = crate::typescript::find_static_imports(path, code) {
+        return imports;
+    }
+    // without the parser of the typescript feature, or when the code could not be parsed
+    scan_imports(code)
+        .imports
+        .into_iter()
+        .map(|(specifier, _)| specifier)
+        .collect()
+}
+
+/// when the runtime has async module loaders the import() calls of pre-processed code are replaced by a function which
+/// first loads the modules of those loaders
+///
+/// with the typescript feature the calls are found by the parser and the source map of the script is updated, without
+/// it they are found by scan_imports and the columns after an import() on the same line shift in stack traces
+pub(crate) fn replace_dynamic_imports(
+    realm: &QuickJsRealmAdapter,
+    script: &mut Script,
+) -> Result<(), JsError> {
+    if !script.get_runnable_code().contains("import") {
+        return Ok(());
+    }
+    init_import_async(realm)?;
+    let importer = strip_module_version(script.get_path());
+    let callee = format!(
+        "{IMPORT_ASYNC_FUNCTION}({}, ",
+        serde_json::to_string(importer).unwrap_or_default()
+    );
+    #[cfg(feature = "typescript")]
+    crate::typescript::replace_dynamic_imports(script, callee.as_str())?;
+    #[cfg(not(feature = "typescript"))]
+    {
+        let code = script.get_runnable_code();
+        let calls = scan_imports(code).dynamic_imports;
+        if !calls.is_empty() {
+            let code = replace_import_calls(code, calls.as_slice(), callee.as_str());
+            let map = script.get_map().map(|map| map.to_string());
+            script.set_transpiled_code(code, map);
+        }
+    }
+    Ok(())
+}
+
+/// run the pre-processors for the code of a module as it is compiled,
+/// a module which was already pre-processed by [find_module_imports] is not pre-processed again
+pub(crate) fn pre_process_module(
+    realm: &QuickJsRealmAdapter,
+    script: Script,
+) -> Result<Script, JsError> {
+    let processed = realm
+        .module_registry
+        .borrow_mut()
+        .processed
+        .remove(script.get_path());
+    match processed {
+        Some((code, processed)) if code == script.get_code() => Ok(processed),
+        _ => QuickJsRuntimeAdapter::pre_process(realm, script),
+    }
+}
+
+/// pre-process the code of a module to find its static imports before it is compiled, e.g. to preload the modules of
+/// async module loaders, the pre-processed code is kept until the module is compiled
+pub(crate) fn find_module_imports(
+    realm: &QuickJsRealmAdapter,
+    script: Script,
+) -> Result<Vec<String>, JsError> {
+    let path = script.get_path().to_string();
+    let code = script.get_code().to_string();
+    let processed = QuickJsRuntimeAdapter::pre_process(realm, script)?;
+    let imports = find_static_imports(path.as_str(), processed.get_runnable_code());
+    realm
+        .module_registry
+        .borrow_mut()
+        .processed
+        .insert(path, (code, processed));
+    Ok(imports)
+}
+
+/// forget the pre-processed code of a module once it was compiled, with a bytecode cache it may not have been used
+pub(crate) fn forget_pre_processed(realm: &QuickJsRealmAdapter, path: &str) {
+    realm.module_registry.borrow_mut().processed.remove(path);
+}
+
+/// the result of scan_imports
+pub(crate) struct ImportScan {
+    /// the code without import attributes, None if nothing changed
+    pub(crate) code: Option<String>,
+    /// the specifiers of the static imports and exports with their type attribute
+    pub(crate) imports: Vec<(String, Option<String>)>,
+    /// the (byte offset of the import keyword, byte offset of the first argument) of the import() calls,
+    /// only used without the parser of the typescript feature
+    #[cfg_attr(feature = "typescript", allow(dead_code))]
+    pub(crate) dynamic_imports: Vec<(usize, usize)>,
+}
+
+/// the previous token of scan_imports, used to tell a regular expression from a division
+#[derive(Clone, Copy, PartialEq)]
+enum ScanToken<'a> {
+    Start,
+    Punctuator(u8),
+    Word(&'a str),
+    /// a string, template, regex or number
+    Literal,
+}
+
+impl ScanToken<'_> {
+    /// a `/` after this token starts a regular expression
+    fn allows_regex(&self) -> bool {
+        match self {
+            ScanToken::Start => true,
+            // a } mostly ends a block
+            ScanToken::Punctuator(c) => !matches!(c, b')' | b']'),
+            ScanToken::Word(word) => matches!(
+                *word,
+                "return"
+                    | "typeof"
+                    | "instanceof"
+                    | "in"
+                    | "of"
+                    | "new"
+                    | "delete"
+                    | "void"
+                    | "throw"
+                    | "case"
+                    | "do"
+                    | "else"
+                    | "yield"
+                    | "await"
+            ),
+            ScanToken::Literal => false,
+        }
+    }
+}
+
+/// find the static imports of code which was not pre-processed and strip their attributes,
+/// with the lexer of swc of the typescript feature or else with scan_imports
+#[allow(unused_variables)]
+pub(crate) fn find_imports(path: &str, code: &str) -> ImportScan {
+    #[cfg(feature = "typescript")]
+    if let Some(scan) = crate::typescript::scan_imports(path, code) {
+        return scan;
+    }
+    scan_imports(code)
+}
+
+/// scan code for static imports and import() calls and strip the attributes of the static imports
+///
+/// this is a lexer which skips comments, strings, templates (with their substitutions) and regular expressions,
+/// static imports are only recognized outside of blocks
+pub(crate) fn scan_imports(code: &str) -> ImportScan {
+    let bytes = code.as_bytes();
+    let mut out = String::new();
+    // code[..copied] was copied to out
+    let mut copied = 0;
+    let mut changed = false;
+    let mut imports = vec![];
+    let mut dynamic_imports = vec![];
+    let mut prev = ScanToken::Start;
+    // the open braces, true for the ${ of a template substitution
+    let mut braces: Vec<bool> = vec![];
+    let mut i = 0;
+
+    let skip_whitespace = |mut idx: usize| {
+        while idx < bytes.len() && bytes[idx].is_ascii_whitespace() {
+            idx += 1;
+        }
+        idx
+    };
+    let read_word = |mut idx: usize| {
+        while idx < bytes.len()
+            && (bytes[idx].is_ascii_alphanumeric()
+                || bytes[idx] == b'_'
+                || bytes[idx] == b'$'
+                || bytes[idx] >= 0x80)
+        {
+            idx += 1;
+        }
+        idx
+    };
+    // skip the rest of a template, returns the index after the closing ` or after the ${ of a substitution
+    let skip_template = |mut idx: usize, braces: &mut Vec<bool>| {
+        while idx < bytes.len() {
+            match bytes[idx] {
+                b'\\' => idx += 2,
+                b'`' => return idx + 1,
+                b'$' if bytes.get(idx + 1) == Some(&b'{') => {
+                    braces.push(true);
+                    return idx + 2;
+                }
+                _ => idx += 1,
+            }
+        }
+        bytes.len()
+    };
+
+    while i < bytes.len() {
+        let c = bytes[i];
+        if c.is_ascii_whitespace() {
+            i += 1;
+        } else if c == b'/' && bytes.get(i + 1) == Some(&b'/') {
+            i = code[i..]
+                .find('\n')
+                .map(|idx| i + idx)
+                .unwrap_or(bytes.len());
+        } else if c == b'/' && bytes.get(i + 1) == Some(&b'*') {
+            i = code[i + 2..]
+                .find("*/")
+                .map(|idx| i + idx + 4)
+                .unwrap_or(bytes.len());
+        } else if c == b'/' && prev.allows_regex() {
+            let mut end = i + 1;
+            let mut in_class = false;
+            while end < bytes.len() && bytes[end] != b'\n' {
+                match bytes[end] {
+                    b'\\' => end += 1,
+                    b'[' => in_class = true,
+                    b']' => in_class = false,
+                    b'/' if !in_class => break,
+                    _ => {}
+                }
+                end += 1;
+            }
+            // the flags
+            i = read_word((end + 1).min(bytes.len()));
+            prev = ScanToken::Literal;
+        } else if c == b'\'' || c == b'"' {
+            let mut end = i + 1;
+            while end < bytes.len() && bytes[end] != c {
+                if bytes[end] == b'\\' {
+                    end += 1;
+                }
+                end += 1;
+            }
+            end = (end + 1).min(bytes.len());
+
+            if braces.is_empty()
+                && matches!(prev, ScanToken::Word("from") | ScanToken::Word("import"))
+            {
+                let specifier = code.get(i + 1..end - 1).unwrap_or_default();
+                let mut module_type = None;
+                let word_start = skip_whitespace(end);
+                let word_end = read_word(word_start);
+                let word = &code[word_start..word_end];
+                let brace = skip_whitespace(word_end);
+                if (word == "with" || word == "assert") && bytes.get(brace) == Some(&b'{') {
+                    if let Some(close) = code[brace..].find('}').map(|idx| brace + idx) {
+                        module_type = parse_type_attribute(&code[brace + 1..close]);
+                        out.push_str(&code[copied..end - 1]);
+                        out.push_str(
+                            strip_attributes_clause(&code[end - 1..=close], module_type.as_deref())
+                                .as_str(),
+                        );
+                        copied = close + 1;
+                        changed = true;
+                        end = close + 1;
+                    }
+                }
+                imports.push((specifier.to_string(), module_type));
+            }
+            prev = ScanToken::Literal;
+            i = end;
+        } else if c == b'`' {
+            i = skip_template(i + 1, &mut braces);
+            prev = ScanToken::Literal;
+        } else if c == b'{' {
+            braces.push(false);
+            prev = ScanToken::Punctuator(c);
+            i += 1;
+        } else if c == b'}' {
+            if braces.pop() == Some(true) {
+                // the end of a template substitution
+                i = skip_template(i + 1, &mut braces);
+                prev = ScanToken::Literal;
+            } else {
+                prev = ScanToken::Punctuator(c);
+                i += 1;
+            }
+        } else if c.is_ascii_digit() {
+            i = read_word(i);
+            prev = ScanToken::Literal;
+        } else if c.is_ascii_alphabetic() || c == b'_' || c == b'$' || c >= 0x80 {
+            let end = read_word(i);
+            let word = &code[i..end];
+            if word == "import" && prev != ScanToken::Punctuator(b'.') {
+                let paren = skip_whitespace(end);
+                if bytes.get(paren) == Some(&b'(') {
+                    dynamic_imports.push((i, skip_whitespace(paren + 1)));
+                }
+            }
+            prev = ScanToken::Word(word);
+            i = end;
+        } else {
+            prev = ScanToken::Punctuator(c);
+            i += 1;
+        }
+    }
+
+    let code = if changed {
+        out.push_str(&code[copied..]);
+        Some(out)
+    } else {
+        None
+    };
+    ImportScan {
+        code,
+        imports,
+        dynamic_imports,
+    }
+}
+
+/// replace the import() calls found by scan_imports by calls to another function, line breaks are kept
+#[cfg(not(feature = "typescript"))]
+fn replace_import_calls(code: &str, calls: &[(usize, usize)], callee: &str) -> String {
+    let mut out = String::with_capacity(code.len() + calls.len() * callee.len());
+    let mut copied = 0;
+    for (start, arg_start) in calls {
+        out.push_str(&code[copied..*start]);
+        out.push_str(callee);
+        out.extend(code[*start..*arg_start].chars().filter(|c| *c == '\n'));
+        copied = *arg_start;
+    }
+    out.push_str(&code[copied..]);
+    out
+}
+
+/// strip the attributes clause of a static import, quote_and_clause is the closing quote of the specifier followed by
+/// the clause, the type is added to the specifier (e.g. './data.json#type=json') so the module is loaded per type,
+/// the rest is replaced by whitespace so the positions after the import don't change
+pub(crate) fn strip_attributes_clause(quote_and_clause: &str, module_type: Option<&str>) -> String {
+    let mut chars = quote_and_clause.chars();
+    let quote = chars.next().unwrap_or('\'');
+    let mut out = match module_type {
+        Some(module_type) => format!("{MODULE_TYPE_SEPARATOR}{module_type}{quote}"),
+        None => quote.to_string(),
+    };
+    // the clause is always longer than the type separator and the type
+    let mut padding = chars
+        .clone()
+        .filter(|c| *c != '\n')
+        .count()
+        .saturating_sub(out.len() - 1);
+    for c in chars {
+        if c == '\n' {
+            out.push('\n');
+        } else if padding > 0 {
+            out.push(' ');
+            padding -= 1;
+        }
+    }
+    out
+}
+
+/// get the type from the body of an attributes clause like `type: 'json'`
+fn parse_type_attribute(clause: &str) -> Option<String> {
+    let unquote = |s: &str| s.trim().trim_matches(|c| c == '\'' || c == '"').to_string();
+    clause.split(',').find_map(|entry| {
+        let (key, value) = entry.split_once(':')?;
+        if unquote(key) == "type" {
+            Some(unquote(value))
+        } else {
+            None
+        }
+    })
+}
+
+/// install the function which replaces import() when there are async module loaders
+fn init_import_async(realm: &QuickJsRealmAdapter) -> Result<(), JsError> {
+    let global = realm.get_global()?;
+    if !objects::get_property_q(realm, &global, IMPORT_ASYNC_FUNCTION)?.is_undefined() {
+        return Ok(());
+    }
+
+    // preload(referrer, specifier) returns a promise which resolves when the modules of async loaders are loaded
+    let preload = realm.create_function(
+        "preload",
+        |realm, _this, args| {
+            let referrer = args[0].to_string()?;
+            let specifier = args[1].to_string()?;
+            preload_imports(realm, vec![(referrer, specifier)])
+        },
+        2,
+    )?;
+    // resolve(referrer, specifier) returns the name under which the module is loaded
+    let resolve = realm.create_function(
+        "resolve",
+        |realm, _this, args| {
+            let referrer = args[0].to_string()?;
+            let specifier = args[1].to_string()?;
+            let name = resolve_module_name(realm, referrer.as_str(), specifier.as_str())
+                .unwrap_or(specifier);
+            realm.create_string(name.as_str())
+        },
+        2,
+    )?;
+
+    // track(name, promise) registers the promise of the import so the status of the module can be settled
+    let track = realm.create_function(
+        "track",
+        |realm, _this, args| {
+            let name = args[0].to_string()?;
+            track_import(realm, name.as_str(), &args[1]);
+            Ok(args[1].clone())
+        },
+        2,
+    )?;
+
+    // not realm.eval, that would pre-process this and rewrite the import() in here
+    let factory = unsafe {
+        QuickJsRealmAdapter::eval_internal_ctx(
+            realm.context,
+            &Script::new(
+                IMPORT_ASYNC_SCRIPT,
+                "(function(preload, resolve, track){return function(referrer, specifier){specifier = String(specifier); return preload(referrer, specifier).then(() => {const name = resolve(referrer, specifier); return track(name, import(name));});};})",
+            ),
+            None,
+        )
+    }?;
+    let import_async =
+        functions::call_function_q(realm, &factory, &[preload, resolve, track], None)?;
+    objects::set_property2_q(
+        realm,
+        &global,
+        IMPORT_ASYNC_FUNCTION,
+        &import_async,
+        q::JS_PROP_CONFIGURABLE as i32,
+    )
+}
+
+/// get the name under which a module is loaded by QuickJS, the import map is applied before the loaders
+fn resolve_module_name(
+    realm: &QuickJsRealmAdapter,
+    referrer: &str,
+    specifier: &str,
+) -> Option<String> {
+    let referrer = strip_module_version(referrer);
+    // the runtime itself may import a module which was loaded (e.g. by eval_module) by its name
+    if is_internal_referrer(referrer) && get_loaded_module(realm, specifier).is_some() {
+        return Some(specifier.to_string());
+    }
+    // the type of a static import was added to its specifier when the attributes were stripped
+    let (specifier, module_type) = split_module_type(specifier);
+    normalize_module_path(realm, referrer, specifier).map(|path| {
+        let registry = &mut *realm.module_registry.borrow_mut();
+        if !is_internal_referrer(referrer) {
+            registry.add_dependency(referrer, path.as_str());
+        }
+        registry.get_versioned_name(typed_module_name(path.as_str(), module_type).as_str())
+    })
+}
+
+/// resolve a specifier to the normalized path of a module, the import map is applied before the module loaders
+pub(crate) fn normalize_module_path(
+    realm: &QuickJsRealmAdapter,
+    referrer: &str,
+    specifier: &str,
+) -> Option<String> {
+    let referrer = strip_module_version(referrer);
+    let mapped_name = realm.resolve_import_map(specifier, referrer);
+    let name = mapped_name.as_deref().unwrap_or(specifier);
+    QuickJsRuntimeAdapter::do_with(|rt| {
+        rt.with_all_module_loaders(|loader| loader.normalize_path(realm, referrer, name))
+    })
+}
+
+/// the url of a module as used for import.meta.url, absolute paths become file urls (with percent-encoded path segments)
+pub fn module_url(path: &str) -> String {
+    let path = strip_module_version(path);
+    let bytes = path.as_bytes();
+    if path.contains("://") {
+        path.to_string()
+    } else if path.starts_with('/') {
+        file_url(path.split('/').skip(1))
+    } else if bytes.len() > 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+        // windows path
+        file_url(path.split(['/', '\\']))
+    } else {
+        path.to_string()
+    }
+}
+
+fn file_url<'a, I: Iterator<Item = &'a str>>(segments: I) -> String {
+    let mut url = url::Url::parse("file:///").expect("invalid file url");
+    url.path_segments_mut()
+        .expect("file urls have a path")
+        .clear()
+        .extend(segments);
+    url.to_string()
+}
+
+/// load the modules of async module loaders which are (transitively) imported so they can be linked synchronously later
+/// imports is a list of (referrer, specifier) pairs
+///
+/// returns a promise which resolves when all modules are loaded
+pub(crate) fn preload_imports(
+    realm: &QuickJsRealmAdapter,
+    imports: Vec<(String, String)>,
+) -> Result<QuickJsValueAdapter, JsError> {
+    let mut loads = vec![];
+    QuickJsRuntimeAdapter::do_with(|rt| {
+        for (referrer, specifier) in &imports {
+            let referrer = strip_module_version(referrer);
+            let (specifier, module_type) = split_module_type(specifier);
+            let mapped_name = realm.resolve_import_map(specifier, referrer);
+            let name = mapped_name.as_deref().unwrap_or(specifier);
+            // modules of the other loaders are loaded when they are imported
+            let load = rt.with_all_module_loaders(|loader| {
+                let path = loader.normalize_path(realm, referrer, name)?;
+                let module_name = {
+                    let registry = &*realm.module_registry.borrow();
+                    let module_name = typed_module_name(path.as_str(), module_type);
+                    let loaded = registry
+                        .modules
+                        .contains_key(registry.get_versioned_name(module_name.as_str()).as_str());
+                    if loaded
+                        || registry.preloaded.contains_key(module_name.as_str())
+                        || registry.preloading.contains(module_name.as_str())
+                    {
+                        return Some(None);
+                    }
+                    module_name
+                };
+                match loader.load_module_async(realm, path.as_str()) {
+                    Some(future) => {
+                        realm
+                            .module_registry
+                            .borrow_mut()
+                            .preloading
+                            .insert(module_name.clone());
+                        Some(Some((module_name, future)))
+                    }
+                    // not an async loader
+                    None => Some(None),
+                }
+            });
+            if let Some(Some(load)) = load {
+                loads.push(load);
+            }
+        }
+    });
+
+    if loads.is_empty() {
+        let promise = realm.create_promise()?;
+        promise.js_promise_resolve(realm, &realm.create_undefined()?)?;
+        return Ok(promise.js_promise_get_value(realm));
+    }
+
+    realm.create_resolving_promise_async(
+        async move {
+            let results = futures::future::join_all(
+                loads
+                    .into_iter()
+                    .map(|(module_name, future)| async move { (module_name, future.await) }),
+            )
+            .await;
+            Ok(results)
+        },
+        |realm, results| {
+            let mut scripts = vec![];
+            let mut error = None;
+            {
+                let registry = &mut *realm.module_registry.borrow_mut();
+                for (module_name, result) in results {
+                    registry.preloading.remove(module_name.as_str());
+                    match result {
+                        Ok(source) => {
+                            // a script imported with a type attribute is not a javascript module
+                            if let ModuleSource::Script(code) = &source {
+                                if get_module_type(module_name.as_str()).is_none() {
+                                    scripts.push(Script::new(module_name.as_str(), code.as_str()));
+                                }
+                            }
+                            registry.preloaded.insert(module_name, source);
+                        }
+                        Err(e) => {
+                            error.get_or_insert(e);
+                        }
+                    }
+                }
+            }
+            if let Some(e) = error {
+                return Err(e);
+            }
+            let mut nested_imports = vec![];
+            for mut script in scripts {
+                #[cfg(feature = "commonjs")]
+                if crate::features::commonjs::is_commonjs(script.get_path()) {
+                    continue;
+                }
+                let path = script.get_path().to_string();
+                strip_import_attributes(&mut script);
+                for specifier in find_module_imports(realm, script)? {
+                    nested_imports.push((path.clone(), specifier));
+                }
+            }
+            preload_imports(realm, nested_imports)
+        },
+    )
+}
+
+/// register the start of an eval_module so the status of the entry module and its imports can be settled
+pub(crate) fn track_module_evaluation(
+    realm: &QuickJsRealmAdapter,
+    path: &str,
+    res: &Result<QuickJsValueAdapter, JsError>,
+) {
+    let registry = &mut *realm.module_registry.borrow_mut();
+    match res {
+        Ok(value) if promises::is_promise_q(realm, value) => {
+            // top level await, settled when the module info is requested
+            registry.evaluations.push((path.to_string(), value.clone()));
+        }
+        Ok(_) => registry.settle_graph(path, ModuleStatus::Evaluated),
+        Err(_) => registry.settle_graph(path, ModuleStatus::Errored),
+    }
+}
+
+/// register the promise of an import() by the runtime so the status of the imported module and its imports can be settled
+fn track_import(realm: &QuickJsRealmAdapter, name: &str, promise: &QuickJsValueAdapter) {
+    realm
+        .module_registry
+        .borrow_mut()
+        .evaluations
+        .push((strip_module_version(name).to_string(), promise.clone()));
+}
+
+/// get info about all modules loaded in a realm, see [QuickJsRealmAdapter::get_loaded_modules]
+pub(crate) fn get_loaded_modules(realm: &QuickJsRealmAdapter) -> Vec<ModuleInfo> {
+    let registry = &mut *realm.module_registry.borrow_mut();
+    registry.settle_evaluations(realm);
+    let mut names: Vec<&String> = registry.modules.keys().collect();
+    names.sort();
+    names
+        .into_iter()
+        .filter_map(|name| registry.get_module_info(name))
+        .collect()
+}
+
+/// get info about a loaded module by name or path, see [QuickJsRealmAdapter::get_module_info]
+pub(crate) fn get_module_info(realm: &QuickJsRealmAdapter, name: &str) -> Option<ModuleInfo> {
+    let registry = &mut *realm.module_registry.borrow_mut();
+    registry.settle_evaluations(realm);
+    if registry.modules.contains_key(name) {
+        registry.get_module_info(name)
+    } else {
+        // latest version of a path
+        let versioned_name = registry.get_versioned_name(name);
+        registry.get_module_info(versioned_name.as_str())
+    }
+}
+
+/// get the name and module def of a loaded module by its name or normalized path
+pub(crate) fn get_loaded_module(
+    realm: &QuickJsRealmAdapter,
+    name: &str,
+) -> Option<(String, *mut q::JSModuleDef)> {
+    let registry = &*realm.module_registry.borrow();
+    // a module which was invalidated is only loaded when its current version is
+    let versioned_name = registry.get_versioned_name(name);
+    registry
+        .module_defs
+        .get(versioned_name.as_str())
+        .map(|module| (versioned_name, *module))
+}
+
+/// get the namespace of a loaded module by its name or normalized path
+#[cfg(feature = "quickjs-ng")]
+pub(crate) fn get_loaded_module_namespace(
+    realm: &QuickJsRealmAdapter,
+    name: &str,
+) -> Result<Option<QuickJsValueAdapter>, JsError> {
+    match get_loaded_module(realm, name) {
+        Some((_name, module)) => unsafe { get_module_namespace(realm.context, module).map(Some) },
+        None => Ok(None),
+    }
+}
+
+/// get the namespace of a module which was loaded in the realm under module_name
+///
+/// returns a promise which resolves with the namespace, with bellard the module is imported by name to get it
+#[allow(unused_variables)]
+pub(crate) fn import_module_namespace(
+    realm: &QuickJsRealmAdapter,
+    module_name: &str,
+    module: *mut q::JSModuleDef,
+) -> Result<QuickJsValueAdapter, JsError> {
+    #[cfg(feature = "quickjs-ng")]
+    {
+        let namespace = unsafe { get_module_namespace(realm.context, module)? };
+        let promise = realm.create_promise()?;
+        promise.js_promise_resolve(realm, &namespace)?;
+        Ok(promise.js_promise_get_value(realm))
+    }
+    #[cfg(not(feature = "quickjs-ng"))]
+    {
+        import_internal(realm, module_name)
+    }
+}
+
+/// name of the script of the import() calls of the runtime itself, e.g. QuickJsRealmAdapter::import_module
+/// imports of this script don't add to the module graph and may import a loaded module by its name
+pub(crate) const INTERNAL_IMPORT_SCRIPT: &str = "quickjs_runtime_import.js";
+/// name of the script which defines the function which replaces import() when there are async module loaders
+const IMPORT_ASYNC_SCRIPT: &str = "quickjs_runtime_import_async.js";
+
+/// import() calls from the scripts of the runtime itself
+fn is_internal_referrer(referrer: &str) -> bool {
+    referrer == INTERNAL_IMPORT_SCRIPT || referrer == IMPORT_ASYNC_SCRIPT
+}
+
+/// import a module by its normalized path or the name of a loaded module, like import() in a script of the runtime
+///
+/// returns a promise which resolves with the namespace of the module
+pub(crate) fn import_internal(
+    realm: &QuickJsRealmAdapter,
+    name: &str,
+) -> Result<QuickJsValueAdapter, JsError> {
+    let name_ref = realm.create_string(name)?;
+    if QuickJsRuntimeAdapter::do_with(|rt| rt.has_async_module_loaders()) {
+        // load the modules of the async loaders first
+        init_import_async(realm)?;
+        let import_async =
+            objects::get_property_q(realm, &realm.get_global()?, IMPORT_ASYNC_FUNCTION)?;
+        let referrer = realm.create_string(INTERNAL_IMPORT_SCRIPT)?;
+        return functions::call_function_q(realm, &import_async, &[referrer, name_ref], None);
+    }
+    let import = unsafe {
+        QuickJsRealmAdapter::eval_internal_ctx(
+            realm.context,
+            &Script::new(
+                INTERNAL_IMPORT_SCRIPT,
+                "(function(name){return import(name);})",
+            ),
+            None,
+        )
+    }?;
+    let promise = functions::call_function_q(realm, &import, &[name_ref], None)?;
+    track_import(realm, name, &promise);
+    Ok(promise)
+}
+
+/// get the import.meta object of a module
+/// # Safety
+/// please ensure the module ptr is valid and belongs to the realm
+pub(crate) unsafe fn get_import_meta(
+    realm: &QuickJsRealmAdapter,
+    module: *mut q::JSModuleDef,
+) -> Result<QuickJsValueAdapter, JsError> {
+    let meta = QuickJsValueAdapter::new(
+        realm.context,
+        q::JS_GetImportMeta(realm.context, module),
+        false,
+        true,
+        "modules::get_import_meta import.meta",
+    );
+    if meta.is_exception() {
+        return Err(QuickJsRealmAdapter::get_exception(realm.context)
+            .unwrap_or_else(|| JsError::new_str("could not get import.meta")));
+    }
+    Ok(meta)
+}
+
+/// init import.meta of a compiled script module, this adds import.meta.url, import.meta.resolve and import.meta.hot
+/// # Safety
+/// please ensure the module ptr is valid and belongs to the realm
+pub(crate) unsafe fn init_import_meta(
+    realm: &QuickJsRealmAdapter,
+    module: *mut q::JSModuleDef,
+    module_name: &str,
+) -> Result<(), JsError> {
+    let path = strip_module_version(module_name);
+    let meta = get_import_meta(realm, module)?;
+
+    realm.set_object_property(
+        &meta,
+        "url",
+        &realm.create_string(module_url(path).as_str())?,
+    )?;
+    // resolve a specifier relative to this module, like an import of this module would
+    let resolve_path = path.to_string();
+    let resolve = realm.create_function(
+        "resolve",
+        move |realm, _this, args| {
+            let specifier = match args.first() {
+                Some(arg) if arg.is_string() => arg.to_string()?,
+                _ => return Err(JsError::new_str("import.meta.resolve expects a string")),
+            };
+            match normalize_module_path(realm, resolve_path.as_str(), specifier.as_str()) {
+                Some(resolved) => realm.create_string(module_url(resolved.as_str()).as_str()),
+                None => Err(JsError::new_string(format!(
+                    "Cannot resolve {specifier} from {resolve_path}"
+                ))),
+            }
+        },
+        1,
+    )?;
+    realm.set_object_property(&meta, "resolve", &resolve)?;
+
+    // data from the dispose callbacks of the previous version
+    let previous_data = realm
+        .module_registry
+        .borrow_mut()
+        .hot_modules
+        .remove(path)
+        .and_then(|state| state.data);
+    let data = match previous_data {
+        Some(data) => data,
+        None => realm.create_object()?,
+    };
+
+    let hot = realm.create_object()?;
+    realm.set_object_property(&hot, "data", &data)?;
+
+    let accept_path = path.to_string();
+    let accept = realm.create_function(
+        "accept",
+        move |realm, _this, args| {
+            let registry = &mut *realm.module_registry.borrow_mut();
+            let state = registry.hot_modules.entry(accept_path.clone()).or_default();
+            state.accepted = true;
+            if let Some(callback) = args.first() {
+                if functions::is_function_q(realm, callback) {
+                    state.accept_callbacks.push(callback.clone());
+                }
+            }
+            realm.create_undefined()
+        },
+        1,
+    )?;
+    realm.set_object_property(&hot, "accept", &accept)?;
+
+    let dispose_path = path.to_string();
+    let dispose = realm.create_function(
+        "dispose",
+        move |realm, _this, args| {
+            if let Some(callback) = args.first() {
+                if !functions::is_function_q(realm, callback) {
+                    return Err(JsError::new_str("dispose callback should be a function"));
+                }
+                let registry = &mut *realm.module_registry.borrow_mut();
+                registry
+                    .hot_modules
+                    .entry(dispose_path.clone())
+                    .or_default()
+                    .dispose_callbacks
+                    .push(callback.clone());
+            }
+            realm.create_undefined()
+        },
+        1,
+    )?;
+    realm.set_object_property(&hot, "dispose", &dispose)?;
+
+    realm.set_object_property(&meta, "hot", &hot)
+}
+
+/// invalidate a module and its dependents, see [QuickJsRealmAdapter::invalidate_module]
+pub(crate) fn invalidate_module(
+    realm: &QuickJsRealmAdapter,
+    path: &str,
+) -> Result<Vec<String>, JsError> {
+    let invalidated = realm.module_registry.borrow().get_dependents(path);
+
+    // a failing dispose callback does not stop the invalidation, the first error is returned when it is done
+    let mut errors = vec![];
+    let mut accepted = vec![];
+    for module_path in &invalidated {
+        // take the state out of the registry so callbacks may alter the registry
+        let state_opt = realm
+            .module_registry
+            .borrow_mut()
+            .hot_modules
+            .remove(module_path.as_str());
+        let data = realm.create_object()?;
+        if let Some(state) = state_opt {
+            for callback in &state.dispose_callbacks {
+                if let Err(e) = realm.invoke_function(None, callback, &[&data]) {
+                    errors.push(e);
+                }
+            }
+            if state.accepted {
+                accepted.push((module_path.clone(), state.accept_callbacks));
+            }
+        }
+        let registry = &mut *realm.module_registry.borrow_mut();
+        registry.hot_modules.insert(
+            module_path.clone(),
+            HotModuleState {
+                data: Some(data),
+                ..Default::default()
+            },
+        );
+        registry.bump_version(module_path.as_str());
+    }
+
+    // re-import the modules which accept updates
+    for (module_path, accept_callbacks) in accepted {
+        log::debug!("re-importing hot module {}", module_path);
+        let promise = import_internal(realm, module_path.as_str())?;
+
+        let then = realm.create_function(
+            "hotAccept",
+            move |realm, _this, args| {
+                if let Some(namespace) = args.first() {
+                    for callback in &accept_callbacks {
+                        realm.invoke_function(None, callback, &[namespace])?;
+                    }
+                }
+                realm.create_undefined()
+            },
+            1,
+        )?;
+        let catch_path = module_path.clone();
+        let catch = realm.create_function(
+            "hotAcceptFailed",
+            move |realm, _this, args| {
+                let reason = match args.first() {
+                    Some(reason) => reason.to_string().unwrap_or_default(),
+                    None => "".to_string(),
+                };
+                log::error!(
+                    "[{}] hot reload of {} failed: {}",
+                    realm.get_realm_id(),
+                    catch_path,
+                    reason
+                );
+                realm.create_undefined()
+            },
+            1,
+        )?;
+        realm.add_promise_reactions(&promise, Some(then), Some(catch), None)?;
+    }
+
+    match errors.into_iter().next() {
+        Some(e) => Err(e),
+        None => Ok(invalidated),
+    }
+}
+
 unsafe extern "C" fn js_module_normalize(
     ctx: *mut q::JSContext,
     module_base_name: *const ::std::os::raw::c_char,
@@ -170,23 +1408,22 @@ unsafe extern "C" fn js_module_normalize(
         name_str
     );
 
-    QuickJsRuntimeAdapter::do_with(|q_js_rt| {
-        let q_ctx = q_js_rt.get_quickjs_context(ctx);
+    // a module which was invalidated imports relative to its path without the version
+    let base_str = strip_module_version(base_str);
+
+    errors::catch_native_panic(ctx, "module normalize", || {
+        QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+            let q_ctx = q_js_rt.get_quickjs_context(ctx);
 
-        if let Some(res) = q_js_rt.with_all_module_loaders(|loader| {
-            if let Some(normalized_path) = loader.normalize_path(q_ctx, base_str, name_str) {
-                let c_absolute_path = CString::new(normalized_path.as_str()).expect("fail");
-                Some(c_absolute_path.into_raw())
+            if let Some(name) = resolve_module_name(q_ctx, base_str, name_str) {
+                CString::new(name.as_str()).expect("fail").into_raw()
             } else {
-                None
+                q_ctx.report_ex(format!("Module {name_str} was not found").as_str());
+                ptr::null_mut()
             }
-        }) {
-            res
-        } else {
-            q_ctx.report_ex(format!("Module {name_str} was not found").as_str());
-            ptr::null_mut()
-        }
+        })
     })
+    .unwrap_or(ptr::null_mut())
 }
 
 unsafe extern "C" fn js_module_loader(
@@ -201,30 +1438,54 @@ unsafe extern "C" fn js_module_loader(
 
     log::trace!("js_module_loader called: {}", module_name);
 
-    QuickJsRuntimeAdapter::do_with(|q_js_rt| {
-        QuickJsRealmAdapter::with_context(ctx, |q_ctx| {
-            if let Some(res) = q_js_rt.with_all_module_loaders(|module_loader| {
-                if module_loader.has_module(q_ctx, module_name) {
-                    let mod_val_res = module_loader.load_module(q_ctx, module_name);
-                    return match mod_val_res {
-                        Ok(mod_val) => Some(mod_val),
-                        Err(e) => {
-                            let err =
-                                format!("Module load failed for {module_name} because of: {e}");
-                            log::error!("{}", err);
-                            q_ctx.report_ex(err.as_str());
-                            Some(std::ptr::null_mut())
-                        }
-                    };
+    errors::catch_native_panic(ctx, "module loader", || {
+        QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+            QuickJsRealmAdapter::with_context(ctx, |q_ctx| {
+                if let Some(res) = q_js_rt.with_all_module_loaders(|module_loader| {
+                    if module_loader.has_module(q_ctx, strip_module_version(module_name)) {
+                        let mod_val_res = module_loader.load_module(q_ctx, module_name).and_then(
+                            |module| unsafe {
+                                module_loader.init_import_meta(q_ctx, module, module_name)?;
+                                Ok(module)
+                            },
+                        );
+                        let status = match mod_val_res {
+                            Ok(_) => ModuleStatus::Pending,
+                            Err(_) => ModuleStatus::Errored,
+                        };
+                        q_ctx.module_registry.borrow_mut().register_module(
+                            module_name,
+                            Some(module_loader.get_name()),
+                            status,
+                        );
+                        return match mod_val_res {
+                            Ok(mod_val) => {
+                                q_ctx
+                                    .module_registry
+                                    .borrow_mut()
+                                    .module_defs
+                                    .insert(module_name.to_string(), mod_val);
+                                Some(mod_val)
+                            }
+                            Err(e) => {
+                                let err =
+                                    format!("Module load failed for {module_name} because of: {e}");
+                                log::error!("{}", err);
+                                q_ctx.report_ex(err.as_str());
+                                Some(std::ptr::null_mut())
+                            }
+                        };
+                    }
+                    None
+                }) {
+                    res
+                } else {
+                    std::ptr::null_mut()
                 }
-                None
-            }) {
-                res
-            } else {
-                std::ptr::null_mut()
-            }
+            })
         })
     })
+    .unwrap_or(ptr::null_mut())
 }
 
 #[cfg(test)]
@@ -232,7 +1493,8 @@ pub mod tests {
     use crate::facades::tests::init_test_rt;
     use crate::jsutils::Script;
     use crate::quickjs_utils::modules::detect_module;
-    use crate::values::JsValueFacade;
+    use crate::values::{JsValueConvertable, JsValueFacade};
+    use futures::executor::block_on;
     use std::time::Duration;
 
     #[test]
@@ -250,26 +1512,109 @@ pub mod tests {
         let res_prom = rt.eval_sync(None, Script::new("test_mod_nat_async.es", "(import('greco://someMod').then((module) => {return {a: module.a, b: module.b, c: module.c};}));")).ok().unwrap();
         assert!(res_prom.is_js_promise());
 
-        match res_prom {
-            JsValueFacade::JsPromise { cached_promise } => {
-                let res = cached_promise
-                    .get_promise_result_sync()
-                    .expect("prom timed out");
-                let obj = res.expect("prom failed");
-                assert!(obj.is_js_object());
-                match obj {
-                    JsValueFacade::JsObject { cached_object } => {
-                        let map = cached_object.get_object_sync().expect("esvf to map failed");
-                        let a = map.get("a").expect("obj did not have a");
-                        assert_eq!(a.get_i32(), 1234);
-                        let b = map.get("b").expect("obj did not have b");
-                        assert_eq!(b.get_i32(), 64834);
-                    }
-                    _ => {}
-                }
+        if let JsValueFacade::JsPromise { cached_promise } = res_prom {
+            let res = cached_promise
+                .get_promise_result_sync()
+                .expect("prom timed out");
+            let obj = res.expect("prom failed");
+            assert!(obj.is_js_object());
+            if let JsValueFacade::JsObject { cached_object } = obj {
+                let map = cached_object.get_object_sync().expect("esvf to map failed");
+                let a = map.get("a").expect("obj did not have a");
+                assert_eq!(a.get_i32(), 1234);
+                let b = map.get("b").expect("obj did not have b");
+                assert_eq!(b.get_i32(), 64834);
+            }
+        }
+    }
+
+    #[test]
+    fn test_module_namespace() {
+        let rt = init_test_rt();
+        let namespace = block_on(rt.eval_module_namespace(
+            None,
+            Script::new(
+                "test_module_namespace.mes",
+                "import {mltpl} from 'some_module.mes';\nawait new Promise((resolve) => setTimeout(resolve, 10));\nexport const answer = mltpl(6, 7);\nexport async function twice(a) { return a * 2; }",
+            ),
+        ))
+        .expect("module failed");
+        match namespace {
+            JsValueFacade::JsObject { cached_object } => {
+                let map = cached_object
+                    .get_object_sync()
+                    .expect("could not get object");
+                assert_eq!(map.get("answer").expect("no answer").get_i32(), 42);
             }
-            _ => {}
+            _ => panic!("namespace is not an object"),
         }
+
+        // the exports of the evaluated module, an async function resolves
+        let res = block_on(rt.invoke_module_export(
+            None,
+            "test_module_namespace.mes",
+            "twice",
+            vec![21.to_js_value_facade()],
+        ))
+        .expect("twice failed");
+        assert_eq!(res.get_i32(), 42);
+
+        // a module which was imported
+        let res = block_on(rt.invoke_module_export(
+            None,
+            "some_module.mes",
+            "mltpl",
+            vec![3.to_js_value_facade(), 5.to_js_value_facade()],
+        ))
+        .expect("mltpl failed");
+        assert_eq!(res.get_i32(), 15);
+
+        let err =
+            block_on(rt.invoke_module_export(None, "test_module_namespace.mes", "answer", vec![]))
+                .expect_err("answer is not a function");
+        assert!(err.get_message().contains("no exported function answer"));
+
+        // a module which was not loaded yet is imported
+        let namespace = block_on(rt.get_module_namespace(None, "not_loaded_yet.mes"))
+            .expect("could not import module");
+        assert!(namespace.is_js_object());
+    }
+
+    #[test]
+    fn test_import_module() {
+        // a module which was evaluated (not loaded by a loader) is imported by its name, also with bellard
+        let rt = init_test_rt();
+        rt.eval_module_sync(
+            None,
+            Script::new(
+                "test_import_module.mes",
+                "import {mltpl} from 'some_module.mes';\nexport const answer = mltpl(6, 7);",
+            ),
+        )
+        .expect("module failed");
+
+        let namespace = block_on(rt.get_module_namespace(None, "test_import_module.mes"))
+            .expect("could not import module");
+        match namespace {
+            JsValueFacade::JsObject { cached_object } => {
+                let map = cached_object
+                    .get_object_sync()
+                    .expect("could not get object");
+                assert_eq!(map.get("answer").expect("no answer").get_i32(), 42);
+            }
+            _ => panic!("namespace is not an object"),
+        }
+
+        // the imports of the runtime itself are not part of the module graph
+        rt.loop_realm_sync(None, |_rt, realm| {
+            let info = realm
+                .get_module_info("test_import_module.mes")
+                .expect("module not found");
+            assert_eq!(info.dependencies, vec!["some_module.mes".to_string()]);
+            assert!(!realm.get_loaded_modules().iter().any(|m| m
+                .dependencies
+                .contains(&"test_import_module.mes".to_string())));
+        });
     }
 
     #[test]
@@ -292,10 +1637,9 @@ pub mod tests {
                 "export const name = 'foobar';\nconsole.log('evalling module');",
             ));
 
-            if res.is_err() {
-                panic!("parse module failed: {}", res.err().unwrap())
+            if let Err(e) = res {
+                panic!("parse module failed: {}", e)
             }
-            res.ok().expect("parse module failed");
         });
 
         rt.exe_rt_task_in_event_loop(|q_js_rt| {
@@ -305,11 +1649,9 @@ pub mod tests {
                 "import {name} from 'test1.mes';\n\nconsole.log('imported name: ' + name);",
             ));
 
-            if res.is_err() {
-                panic!("parse module2 failed: {}", res.err().unwrap())
+            if let Err(e) = res {
+                panic!("parse module2 failed: {}", e)
             }
-
-            res.ok().expect("parse module2 failed");
         });
 
         rt.exe_rt_task_in_event_loop(|q_js_rt| {
@@ -349,15 +1691,625 @@ pub mod tests {
                 "import {name} from 'test1.mes';\n\nconsole.log('imported name: ' + name);",
             ));
 
-            if res.is_err() {
-                panic!("parse module2 failed: {}", res.err().unwrap())
+            if let Err(e) = res {
+                panic!("parse module2 failed: {}", e)
             }
-
-            res.ok().expect("parse module2 failed");
         });
 
         std::thread::sleep(Duration::from_secs(1));
 
         log::info!("< test_module_sandbox");
     }
+
+    #[test]
+    fn test_invalidate_module() {
+        use crate::builder::QuickJsRuntimeBuilder;
+        use crate::jsutils::modules::ScriptModuleLoader;
+        use crate::quickjs_utils::modules::MODULE_VERSION_SEPARATOR;
+        use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+        use std::collections::HashMap;
+        use std::sync::{Arc, Mutex};
+
+        struct HotLoader {
+            sources: Arc<Mutex<HashMap<String, String>>>,
+        }
+        impl ScriptModuleLoader for HotLoader {
+            fn normalize_path(
+                &self,
+                _realm: &QuickJsRealmAdapter,
+                _ref_path: &str,
+                path: &str,
+            ) -> Option<String> {
+                if self.sources.lock().unwrap().contains_key(path) {
+                    Some(path.to_string())
+                } else {
+                    None
+                }
+            }
+
+            fn load_module(&self, _realm: &QuickJsRealmAdapter, absolute_path: &str) -> String {
+                self.sources.lock().unwrap()[absolute_path].clone()
+            }
+        }
+
+        let sources = Arc::new(Mutex::new(HashMap::new()));
+        {
+            let sources = &mut *sources.lock().unwrap();
+            sources.insert(
+                "hot_dep.mes".to_string(),
+                "export const val = 1;".to_string(),
+            );
+            sources.insert(
+                "hot_main.mes".to_string(),
+                r#"
+                import {val} from 'hot_dep.mes';
+                export const seen = val;
+                globalThis.prevSeen = import.meta.hot.data.seen;
+                import.meta.hot.dispose((data) => {data.seen = seen;});
+                import.meta.hot.accept((mod) => {globalThis.accepted = mod.seen;});
+                "#
+                .to_string(),
+            );
+            sources.insert(
+                "hot_fail.mes".to_string(),
+                r#"
+                import {val} from 'hot_dep.mes';
+                import.meta.hot.dispose(() => {throw Error('dispose failed');});
+                "#
+                .to_string(),
+            );
+        }
+
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_module_loader(HotLoader {
+                sources: sources.clone(),
+            })
+            .build();
+
+        rt.eval_module_sync(
+            None,
+            Script::new(
+                "hot_entry.mes",
+                "import 'hot_main.mes';\nimport 'hot_fail.mes';",
+            ),
+        )
+        .expect("module failed");
+
+        sources.lock().unwrap().insert(
+            "hot_dep.mes".to_string(),
+            "export const val = 2;".to_string(),
+        );
+
+        // the error of the dispose callback is returned after all modules were invalidated
+        let err = rt
+            .invalidate_module_sync(None, "hot_dep.mes")
+            .expect_err("dispose did not fail");
+        assert!(err.get_message().contains("dispose failed"));
+        rt.loop_realm_sync(None, |_rt, realm| {
+            let registry = realm.module_registry.borrow();
+            for path in [
+                "hot_dep.mes",
+                "hot_main.mes",
+                "hot_fail.mes",
+                "hot_entry.mes",
+            ] {
+                assert_eq!(
+                    registry.get_versioned_name(path),
+                    format!("{path}{MODULE_VERSION_SEPARATOR}1")
+                );
+            }
+        });
+        let info = rt
+            .loop_realm_sync(None, |_rt, realm| realm.get_module_info("hot_main.mes"))
+            .expect("hot_main.mes not found");
+        assert_eq!(info.dependencies, vec!["hot_dep.mes".to_string()]);
+
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test_invalidate_module.js",
+                    "`${globalThis.prevSeen}/${globalThis.accepted}`",
+                ),
+            )
+            .expect("script failed");
+        assert_eq!(res.get_str(), "1/2");
+    }
+
+    #[test]
+    fn test_module_registry() {
+        use crate::quickjs_utils::modules::ModuleStatus;
+
+        let rt = init_test_rt();
+        rt.eval_module_sync(
+            None,
+            Script::new(
+                "registry_entry.mes",
+                "import {foo} from 'registry_dep.mes';\nimport {a} from 'greco://registry_native';",
+            ),
+        )
+        .expect("module failed");
+        let _ = rt.eval_module_sync(
+            None,
+            Script::new("registry_invalid.mes", "import {foo} from 'invalid.mes';"),
+        );
+
+        rt.loop_realm_sync(None, |_rt, realm| {
+            let entry = realm
+                .get_module_info("registry_entry.mes")
+                .expect("entry not found");
+            assert_eq!(entry.loader, None);
+            assert_eq!(entry.status, ModuleStatus::Evaluated);
+            assert_eq!(
+                entry.dependencies,
+                vec![
+                    "registry_dep.mes".to_string(),
+                    "greco://registry_native".to_string()
+                ]
+            );
+
+            let dep = realm
+                .get_module_info("registry_dep.mes")
+                .expect("dep not found");
+            assert!(dep.loader.unwrap().contains("TestScriptModuleLoader"));
+            assert_eq!(dep.status, ModuleStatus::Evaluated);
+
+            let native = realm
+                .get_module_info("greco://registry_native")
+                .expect("native not found");
+            assert!(native.loader.unwrap().contains("TestNativeModuleLoader"));
+            assert_eq!(native.status, ModuleStatus::Evaluated);
+
+            let invalid = realm
+                .get_module_info("invalid.mes")
+                .expect("invalid not found");
+            assert_eq!(invalid.status, ModuleStatus::Errored);
+
+            assert!(realm
+                .get_loaded_modules()
+                .iter()
+                .any(|m| m.name == "registry_dep.mes"));
+
+            realm
+                .import_module("registry_dynamic.mes")
+                .expect("import failed");
+        });
+
+        // the status of an imported module is settled from the promise of the import
+        rt.loop_realm_sync(None, |_rt, realm| {
+            let dynamic = realm
+                .get_module_info("registry_dynamic.mes")
+                .expect("dynamic not found");
+            assert_eq!(dynamic.status, ModuleStatus::Evaluated);
+        });
+
+        // the code of modules is not changed to track their evaluation
+        let res = rt
+            .eval_module_sync(
+                None,
+                Script::new(
+                    "registry_meta.mes",
+                    "globalThis.metaKeys = Object.getOwnPropertyNames(import.meta).join(',');",
+                ),
+            )
+            .and_then(|_| rt.eval_sync(None, Script::new("registry_meta.js", "metaKeys")))
+            .expect("script failed");
+        assert_eq!(res.get_str(), "url,resolve,hot");
+    }
+
+    #[test]
+    fn test_import_meta() {
+        use crate::builder::QuickJsRuntimeBuilder;
+        use crate::jsutils::modules::ScriptModuleLoader;
+        use crate::jsutils::JsError;
+        use crate::quickjs_utils::modules::module_url;
+        use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+        use crate::quickjsvalueadapter::QuickJsValueAdapter;
+
+        struct MetaLoader {}
+        impl ScriptModuleLoader for MetaLoader {
+            fn normalize_path(
+                &self,
+                _realm: &QuickJsRealmAdapter,
+                ref_path: &str,
+                path: &str,
+            ) -> Option<String> {
+                match path.strip_prefix("./") {
+                    Some(sibling) => {
+                        let dir = &ref_path[..ref_path.rfind('/')? + 1];
+                        Some(format!("{dir}{sibling}"))
+                    }
+                    None if path.starts_with('/') => Some(path.to_string()),
+                    None => None,
+                }
+            }
+
+            fn load_module(&self, _realm: &QuickJsRealmAdapter, _absolute_path: &str) -> String {
+                "export const meta = {url: import.meta.url, sibling: import.meta.resolve('./data.json'), tenant: import.meta.tenant};".to_string()
+            }
+
+            fn init_import_meta(
+                &self,
+                realm: &QuickJsRealmAdapter,
+                _absolute_path: &str,
+                import_meta: &QuickJsValueAdapter,
+            ) -> Result<(), JsError> {
+                realm.set_object_property(import_meta, "tenant", &realm.create_string("acme")?)
+            }
+        }
+
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_module_loader(MetaLoader {})
+            .build();
+        rt.eval_module_sync(
+            None,
+            Script::new(
+                "/srv/app/entry.mjs",
+                "import {meta} from './lib/util.mjs';\nglobalThis.meta = meta;\nglobalThis.entryUrl = import.meta.url;\nglobalThis.entrySibling = import.meta.resolve('./other.mjs');",
+            ),
+        )
+        .expect("module failed");
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test_import_meta.js",
+                    "[meta.url, meta.sibling, meta.tenant, entryUrl, entrySibling].join(',');",
+                ),
+            )
+            .expect("script failed");
+        assert_eq!(
+            res.get_str(),
+            "file:///srv/app/lib/util.mjs,file:///srv/app/lib/data.json,acme,file:///srv/app/entry.mjs,file:///srv/app/other.mjs"
+        );
+        assert_eq!(
+            module_url("/srv/my app/#1%.mjs"),
+            "file:///srv/my%20app/%231%25.mjs"
+        );
+        assert_eq!(
+            module_url("C:\\my app\\main.mjs"),
+            "file:///C:/my%20app/main.mjs"
+        );
+
+        rt.eval_module_sync(
+            None,
+            Script::new(
+                "/srv/app/entry2.mjs",
+                "try { import.meta.resolve('unknown'); } catch(e) { globalThis.resolveError = e.message; }",
+            ),
+        )
+        .expect("module failed");
+        let res = rt
+            .eval_sync(None, Script::new("test_import_meta2.js", "resolveError;"))
+            .expect("script failed");
+        assert!(res.get_str().contains("Cannot resolve unknown"));
+    }
+
+    #[test]
+    fn test_import_map() {
+        use crate::builder::QuickJsRuntimeBuilder;
+        use crate::facades::tests::{TestNativeModuleLoader, TestScriptModuleLoader};
+        use crate::jsutils::importmaps::ImportMap;
+
+        let import_map = ImportMap::from_json(
+            r#"{"imports": {"mapped_lib": "greco://mapped_lib"}, "scopes": {"scoped/": {"mapped_lib": "scoped_lib.mes"}}}"#,
+        )
+        .expect("invalid import map");
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_module_loader(TestScriptModuleLoader {})
+            .native_module_loader(TestNativeModuleLoader {})
+            .import_map(import_map)
+            .build();
+
+        rt.eval_module_sync(
+            None,
+            Script::new("import_map_entry.mes", "import {a} from 'mapped_lib';"),
+        )
+        .expect("module failed");
+        rt.eval_module_sync(
+            None,
+            Script::new(
+                "scoped/import_map_entry.mes",
+                "import {foo} from 'mapped_lib';",
+            ),
+        )
+        .expect("scoped module failed");
+
+        rt.loop_realm_sync(None, |_rt, realm| {
+            let entry = realm
+                .get_module_info("import_map_entry.mes")
+                .expect("entry not found");
+            assert_eq!(entry.dependencies, vec!["greco://mapped_lib".to_string()]);
+            let scoped = realm
+                .get_module_info("scoped/import_map_entry.mes")
+                .expect("scoped entry not found");
+            assert_eq!(scoped.dependencies, vec!["scoped_lib.mes".to_string()]);
+
+            // a realm import map overrides the one of the runtime
+            let mut realm_map = ImportMap::new();
+            realm_map.add_import("mapped_lib", "realm_lib.mes");
+            realm.set_import_map(Some(realm_map));
+            realm
+                .eval_module(Script::new(
+                    "realm_entry.mes",
+                    "import {foo} from 'mapped_lib';",
+                ))
+                .expect("realm module failed");
+            let entry = realm
+                .get_module_info("realm_entry.mes")
+                .expect("realm entry not found");
+            assert_eq!(entry.dependencies, vec!["realm_lib.mes".to_string()]);
+            realm.set_import_map(None);
+        });
+    }
+
+    #[test]
+    fn test_scan_imports() {
+        use crate::quickjs_utils::modules::scan_imports;
+
+        let code = "import a from './a.json' with { type: 'json' };\n// import b from './b.json' with { type: 'json' }\nexport {c} from \"./c.txt\" assert {type: \"text\"};\nimport './d.js';\nlet s = 'with {}';\nimport('./e.js').then(obj.import(1));";
+        let scan = scan_imports(code);
+        assert_eq!(
+            scan.imports,
+            vec![
+                ("./a.json".to_string(), Some("json".to_string())),
+                ("./c.txt".to_string(), Some("text".to_string())),
+                ("./d.js".to_string(), None)
+            ]
+        );
+        let stripped = scan.code.expect("code not changed");
+        assert_eq!(stripped.len(), code.len());
+        // the type is added to the specifier
+        assert!(stripped.starts_with(&format!(
+            "import a from './a.json#type=json'{};",
+            " ".repeat(12)
+        )));
+        assert!(stripped.contains(&format!(
+            "export {{c}} from \"./c.txt#type=text\"{};",
+            " ".repeat(12)
+        )));
+        assert!(stripped.contains("// import b from './b.json' with { type: 'json' }"));
+        assert!(stripped.contains("let s = 'with {}';"));
+        assert!(scan_imports("import a from './a.js';").code.is_none());
+        let call = code.find("import('./e.js')").expect("no import call");
+        assert_eq!(scan.dynamic_imports, vec![(call, call + 7)]);
+
+        // imports in regular expressions and templates are not imports, a division is not a regular expression
+        let code = "const re = /from './re.js' with { type: 'json' }/g;\nconst q = /'/; import a from './a.js';\nlet x = y / 2; import b from './b.json' with { type: 'json' }; let z = y / 3;\nconst t = `import c from './c.js' ${`nested ${'import(\\'./d.js\\')'}`} from './e.js'`;\nimport('./f.js');";
+        let scan = scan_imports(code);
+        assert_eq!(
+            scan.imports,
+            vec![
+                ("./a.js".to_string(), None),
+                ("./b.json".to_string(), Some("json".to_string()))
+            ]
+        );
+        let stripped = scan.code.expect("code not changed");
+        assert!(stripped.contains("/from './re.js' with { type: 'json' }/g"));
+        assert!(stripped.contains(&format!(
+            "import b from './b.json#type=json'{}; let z",
+            " ".repeat(12)
+        )));
+        let call = code.find("import('./f.js')").expect("no import call");
+        assert_eq!(scan.dynamic_imports, vec![(call, call + 7)]);
+    }
+
+    #[test]
+    fn test_json_and_text_modules() {
+        use crate::builder::QuickJsRuntimeBuilder;
+        use crate::jsutils::modules::{ModuleSource, ScriptModuleLoader};
+        use crate::jsutils::JsError;
+        use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+
+        struct DataLoader {}
+        impl ScriptModuleLoader for DataLoader {
+            fn normalize_path(
+                &self,
+                _realm: &QuickJsRealmAdapter,
+                _ref_path: &str,
+                path: &str,
+            ) -> Option<String> {
+                Some(path.to_string())
+            }
+
+            fn load_module(&self, _realm: &QuickJsRealmAdapter, _absolute_path: &str) -> String {
+                r#"{"greeting": "hello", "count": 2}"#.to_string()
+            }
+
+            fn load_module_source(
+                &self,
+                realm: &QuickJsRealmAdapter,
+                absolute_path: &str,
+            ) -> Result<ModuleSource, JsError> {
+                Ok(match absolute_path {
+                    "typed.json" => ModuleSource::Json(r#"{"typed": true}"#.to_string()),
+                    "text.txt" => ModuleSource::Text("some text".to_string()),
+                    p if p.ends_with(".bin") => ModuleSource::Bytes(vec![1, 2, 3]),
+                    _ => ModuleSource::Script(self.load_module(realm, absolute_path)),
+                })
+            }
+        }
+
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_module_loader(DataLoader {})
+            .build();
+
+        rt.eval_module_sync(
+            None,
+            Script::new(
+                "data_entry.mes",
+                "import data from 'data.json' with { type: 'json' };\n\
+                 import typed from 'typed.json';\n\
+                 import text from 'text.txt';\n\
+                 import blob from 'blob.bin';\n\
+                 globalThis.data_result = `${data.greeting}_${data.count}_${typed.typed}_${text}_${blob instanceof Uint8Array}_${blob[2]}`;",
+            ),
+        )
+        .expect("module failed");
+        let res = rt
+            .eval_sync(None, Script::new("data_res.js", "data_result"))
+            .expect("script failed");
+        assert_eq!(res.get_str(), "hello_2_true_some text_true_3");
+
+        let res = rt.eval_module_sync(
+            None,
+            Script::new(
+                "data_entry2.mes",
+                "import text from 'text2.txt' with { type: 'json' };",
+            ),
+        );
+        // the script source of text2.txt is interpreted as json which works
+        assert!(res.is_ok());
+        let res = rt.eval_module_sync(
+            None,
+            Script::new(
+                "data_entry3.mes",
+                "import blob from 'blob2.bin' with { type: 'json' };",
+            ),
+        );
+        // bytes are not json
+        assert!(res.is_err());
+
+        // the type is part of the import, the same module can be imported as json and as text
+        rt.eval_module_sync(
+            None,
+            Script::new(
+                "data_entry4.mes",
+                "import obj from 'both.json' with { type: 'json' };\n\
+                 import str from 'both.json' with { type: 'text' };\n\
+                 globalThis.both_result = `${typeof obj}_${typeof str}_${obj.count}`;",
+            ),
+        )
+        .expect("module failed");
+        let res = rt
+            .eval_sync(None, Script::new("both_res.js", "both_result"))
+            .expect("script failed");
+        assert_eq!(res.get_str(), "object_string_2");
+    }
+
+    #[test]
+    fn test_async_module_loader() {
+        use crate::builder::QuickJsRuntimeBuilder;
+        use crate::jsutils::ifdef::Defines;
+        use crate::jsutils::modules::{AsyncModuleLoader, ModuleSource, ModuleSourceFuture};
+        use crate::jsutils::JsError;
+        use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+
+        struct SlowLoader {}
+        impl AsyncModuleLoader for SlowLoader {
+            fn normalize_path(
+                &self,
+                _realm: &QuickJsRealmAdapter,
+                _ref_path: &str,
+                path: &str,
+            ) -> Option<String> {
+                if path.starts_with("slow_") {
+                    Some(path.to_string())
+                } else {
+                    None
+                }
+            }
+
+            fn load_module(
+                &self,
+                _realm: &QuickJsRealmAdapter,
+                absolute_path: &str,
+            ) -> ModuleSourceFuture {
+                let path = absolute_path.to_string();
+                Box::pin(async move {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                    match path.as_str() {
+                        "slow_a.mes" => Ok(ModuleSource::Script(
+                            "import {b} from 'slow_b.mes';\nexport const a = 'a' + b;".to_string(),
+                        )),
+                        "slow_b.mes" => {
+                            Ok(ModuleSource::Script("export const b = 'b';".to_string()))
+                        }
+                        "slow_c.mes" => {
+                            Ok(ModuleSource::Script("export const c = 'c';".to_string()))
+                        }
+                        "slow_data.json" => Ok(ModuleSource::Json("{\"c\": 3}".to_string())),
+                        _ => Err(JsError::new_string(format!("no such module {path}"))),
+                    }
+                })
+            }
+        }
+
+        let rt = QuickJsRuntimeBuilder::new()
+            .async_module_loader(SlowLoader {})
+            .defines(Defines::new())
+            .build();
+
+        block_on(rt.eval_module(
+            None,
+            Script::new(
+                "slow_entry.mes",
+                "import {a} from 'slow_a.mes';\nimport data from 'slow_data.json' with { type: 'json' };\nglobalThis.slow_result = a + data.c;",
+            ),
+        ))
+        .expect("module failed");
+        let res = rt
+            .eval_sync(None, Script::new("slow_res.js", "slow_result"))
+            .expect("script failed");
+        assert_eq!(res.get_str(), "ab3");
+
+        // dynamic import of a module which was not loaded yet, not in regexes or templates
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "slow_dynamic.js",
+                    "const dyn_re = /import\\(/;\nconst dyn_t = `${dyn_re.source}import(`;\nimport('slow_c.mes').then((ns) => ns.c + '_dynamic_' + dyn_t);",
+                ),
+            )
+            .expect("script failed");
+        let res = match res {
+            JsValueFacade::JsPromise { cached_promise } => cached_promise
+                .get_promise_result_sync()
+                .expect("promise failed")
+                .expect("promise rejected"),
+            _ => panic!("not a promise"),
+        };
+        assert_eq!(res.get_str(), "c_dynamic_import\\(import(");
+
+        #[cfg(feature = "typescript")]
+        {
+            // import() is found by the parser after pre-processing and transpiling, so not in regexes, templates or types
+            // and static imports which were removed by the pre-processors are not loaded
+            let res = rt
+                .eval_sync(
+                    None,
+                    Script::new(
+                        "slow_dynamic.ts",
+                        "type B = typeof import('slow_b.mes');\nconst re = /import\\(/;\nconst t = `${re.source}import(`;\nimport('slow_b.mes').then((ns: B) => ns.b + '_' + t);",
+                    ),
+                )
+                .expect("script failed");
+            let res = match res {
+                JsValueFacade::JsPromise { cached_promise } => cached_promise
+                    .get_promise_result_sync()
+                    .expect("promise failed")
+                    .expect("promise rejected"),
+                _ => panic!("not a promise"),
+            };
+            assert_eq!(res.get_str(), "b_import\\(import(");
+        }
+        block_on(rt.eval_module(
+            None,
+            Script::new(
+                "slow_ifdef.mes",
+                "//#ifdef MISSING\nimport {x} from 'slow_missing.mes';\n//#endif\nimport {b} from 'slow_b.mes';\nglobalThis.slow_ifdef = b;",
+            ),
+        ))
+        .expect("module failed");
+
+        let res = block_on(rt.eval_module(
+            None,
+            Script::new("slow_fail.mes", "import {x} from 'slow_missing.mes';"),
+        ));
+        let err = res.expect_err("module should fail");
+        assert!(err
+            .get_message()
+            .contains("no such module slow_missing.mes"));
+    }
 }
@@ -1,5 +1,6 @@
 use crate::jsutils::JsError;
 use crate::quickjs_utils;
+use crate::quickjs_utils::errors;
 use crate::quickjs_utils::errors::get_stack;
 use crate::quickjs_utils::functions;
 use crate::quickjs_utils::objects::is_instance_of_by_name;
@@ -19,6 +20,37 @@ pub unsafe fn is_promise(context: *mut q::JSContext, obj_ref: &QuickJsValueAdapt
     is_instance_of_by_name(context, obj_ref, "Promise").expect("could not check instance_of")
 }
 
+/// the state of a Promise
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromiseState {
+    Pending,
+    Fulfilled,
+    Rejected,
+}
+
+/// get the state of a Promise
+pub fn get_promise_state_q(
+    context: &QuickJsRealmAdapter,
+    promise_ref: &QuickJsValueAdapter,
+) -> PromiseState {
+    unsafe { get_promise_state(context.context, promise_ref) }
+}
+
+/// get the state of a Promise
+/// # Safety
+/// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
+pub unsafe fn get_promise_state(
+    context: *mut q::JSContext,
+    promise_ref: &QuickJsValueAdapter,
+) -> PromiseState {
+    // JS_PROMISE_PENDING = 0, JS_PROMISE_FULFILLED = 1, JS_PROMISE_REJECTED = 2
+    match q::JS_PromiseState(context, *promise_ref.borrow_value()) as i32 {
+        1 => PromiseState::Fulfilled,
+        2 => PromiseState::Rejected,
+        _ => PromiseState::Pending,
+    }
+}
+
 pub struct QuickJsPromiseAdapter {
     promise_obj_ref: QuickJsValueAdapter,
     reject_function_obj_ref: QuickJsValueAdapter,
@@ -227,49 +259,128 @@ pub unsafe fn add_promise_reactions(
 
 unsafe extern "C" fn promise_rejection_tracker(
     ctx: *mut q::JSContext,
-    _promise: q::JSValue,
+    promise: q::JSValue,
     reason: q::JSValue,
     is_handled: ::std::os::raw::c_int,
     _opaque: *mut ::std::os::raw::c_void,
 ) {
-    if is_handled == 0 {
-        let reason_ref = QuickJsValueAdapter::new(
-            ctx,
-            reason,
-            false,
-            false,
-            "promises::promise_rejection_tracker reason",
-        );
-        let reason_str_res = functions::call_to_string(ctx, &reason_ref);
+    let promise_ref = QuickJsValueAdapter::new(
+        ctx,
+        promise,
+        true,
+        true,
+        "promises::promise_rejection_tracker promise",
+    );
+    let reason_ref = QuickJsValueAdapter::new(
+        ctx,
+        reason,
+        true,
+        true,
+        "promises::promise_rejection_tracker reason",
+    );
+    // the handlers are user code, a panic may not unwind into the engine
+    let res = errors::catch_native_panic(ctx, "promise rejection tracker", || {
         QuickJsRuntimeAdapter::do_with(|rt| {
             let realm = rt.get_quickjs_context(ctx);
-            let realm_id = realm.get_realm_id();
-            let stack = match get_stack(realm) {
-                Ok(s) => match s.to_string() {
-                    Ok(s) => s,
-                    Err(_) => "".to_string(),
-                },
-                Err(_) => "".to_string(),
-            };
-            match reason_str_res {
-                Ok(reason_str) => {
-                    log::error!(
-                        "[{}] unhandled promise rejection, reason: {}{}",
-                        realm_id,
-                        reason_str,
-                        stack
-                    );
+            if is_handled == 0 {
+                if rt.strict_unhandled_rejections && realm.rejection_tracking_depth.get() > 0 {
+                    // the eval which is running will fail with this reason if it stays unhandled
+                    realm
+                        .tracked_rejections
+                        .borrow_mut()
+                        .push((promise_ref, reason_ref));
+                } else {
+                    report_unhandled_rejection(rt, realm, &promise_ref, &reason_ref);
                 }
-                Err(e) => {
-                    log::error!(
-                        "[{}] unhandled promise rejection, could not get reason: {}{}",
-                        realm_id,
-                        e,
-                        stack
-                    );
+            } else {
+                let was_tracked = {
+                    let tracked = &mut *realm.tracked_rejections.borrow_mut();
+                    match tracked.iter().position(|(p, _r)| p == &promise_ref) {
+                        Some(index) => {
+                            tracked.remove(index);
+                            true
+                        }
+                        None => false,
+                    }
+                };
+                // tracked rejections were never reported so there is no need to report them as handled
+                if !was_tracked {
+                    if let Some(handler) = &rt.rejection_handled_handler {
+                        handler(realm, &promise_ref);
+                    }
                 }
             }
         });
+    });
+    if res.is_err() {
+        // the tracker can not throw, drop the InternalError which was thrown for the panic
+        let _ = errors::get_exception(ctx);
+    }
+}
+
+/// pass an unhandled rejection to the unhandled rejection handler of the runtime, or log it if there is no handler
+pub(crate) fn report_unhandled_rejection(
+    rt: &QuickJsRuntimeAdapter,
+    realm: &QuickJsRealmAdapter,
+    promise_ref: &QuickJsValueAdapter,
+    reason_ref: &QuickJsValueAdapter,
+) {
+    let realm_id = realm.get_realm_id();
+    if let Some(handler) = &rt.unhandled_rejection_handler {
+        match realm.to_js_value_facade(reason_ref) {
+            Ok(reason) => handler(realm, reason, promise_ref),
+            Err(e) => {
+                log::error!(
+                    "[{}] unhandled promise rejection, could not convert reason: {}",
+                    realm_id,
+                    e
+                );
+            }
+        }
+        return;
+    }
+
+    let reason_str_res = functions::call_to_string_q(realm, reason_ref);
+    let stack = match get_stack(realm) {
+        Ok(s) => match s.to_string() {
+            Ok(s) => s,
+            Err(_) => "".to_string(),
+        },
+        Err(_) => "".to_string(),
+    };
+    match reason_str_res {
+        Ok(reason_str) => {
+            log::error!(
+                "[{}] unhandled promise rejection, reason: {}{}",
+                realm_id,
+                reason_str,
+                stack
+            );
+        }
+        Err(e) => {
+            log::error!(
+                "[{}] unhandled promise rejection, could not get reason: {}{}",
+                realm_id,
+                e,
+                stack
+            );
+        }
+    }
+}
+
+/// convert the reason of a rejection to a JsError, Errors are converted as is, other values are wrapped in an Error
+/// # Safety
+/// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
+pub(crate) unsafe fn rejection_reason_to_js_error(
+    context: *mut q::JSContext,
+    reason_ref: &QuickJsValueAdapter,
+) -> JsError {
+    if errors::is_error(context, reason_ref) {
+        errors::error_to_js_error(context, reason_ref)
+    } else {
+        let reason_str = functions::call_to_string(context, reason_ref)
+            .unwrap_or_else(|_| "unknown reason".to_string());
+        JsError::new_string(format!("unhandled promise rejection: {reason_str}"))
     }
 }
 
@@ -5,7 +5,9 @@ use crate::quickjs_utils::typedarrays::{
     detach_array_buffer_buffer_q, get_array_buffer_buffer_copy_q, get_array_buffer_q,
     new_uint8_array_copy_q, new_uint8_array_q,
 };
-use crate::quickjs_utils::{arrays, errors, functions, get_global_q, json, new_null_ref, objects};
+use crate::quickjs_utils::{
+    arrays, compile, errors, functions, get_global_q, json, modules, new_null_ref, objects,
+};
 use crate::quickjsruntimeadapter::{make_cstring, QuickJsRuntimeAdapter};
 use crate::quickjsvalueadapter::{QuickJsValueAdapter, TAG_EXCEPTION};
 use crate::reflection::eventtarget::dispatch_event;
@@ -13,8 +15,12 @@ use crate::reflection::eventtarget::dispatch_static_event;
 use crate::reflection::{new_instance, new_instance3, Proxy};
 use hirofa_utils::auto_id_map::AutoIdMap;
 
+use crate::jsutils::ifdef::Defines;
+use crate::jsutils::importmaps::ImportMap;
 use crate::jsutils::jsproxies::{JsProxy, JsProxyInstanceId};
 use crate::jsutils::{JsError, JsValueType, Script};
+use crate::quickjs_utils::modules::{ModuleInfo, ModuleRegistry, ModuleStatus};
+use crate::quickjs_utils::promises;
 use crate::quickjs_utils::promises::QuickJsPromiseAdapter;
 use crate::values::{
     CachedJsArrayRef, CachedJsFunctionRef, CachedJsObjectRef, CachedJsPromiseRef, JsValueFacade,
@@ -22,7 +28,7 @@ use crate::values::{
 };
 use libquickjs_sys as q;
 use serde_json::Value;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::ffi::CString;
 use std::future::Future;
@@ -67,6 +73,12 @@ pub struct QuickJsRealmAdapter {
     pub(crate) proxy_constructor_refs: RefCell<HashMap<String, QuickJsValueAdapter>>,
     pub(crate) proxy_event_listeners: RefCell<ProxyEventListenerMaps>,
     pub(crate) proxy_static_event_listeners: RefCell<ProxyStaticEventListenerMaps>,
+    // (promise, reason) of unhandled rejections during eval in strict mode
+    pub(crate) tracked_rejections: RefCell<Vec<(QuickJsValueAdapter, QuickJsValueAdapter)>>,
+    pub(crate) rejection_tracking_depth: Cell<u32>,
+    pub(crate) module_registry: RefCell<ModuleRegistry>,
+    pub(crate) import_map: RefCell<Option<ImportMap>>,
+    pub(crate) defines: RefCell<Option<Defines>>,
     pub id: String,
     pub context: *mut q::JSContext,
 }
@@ -164,6 +176,8 @@ impl QuickJsRealmAdapter {
         };
         all_constructor_refs.clear();
 
+        self.module_registry.borrow_mut().clear_values();
+
         unsafe { q::JS_FreeContext(self.context) };
 
         log::trace!("after QuickJsContext:free {}", self.id);
@@ -196,6 +210,11 @@ impl QuickJsRealmAdapter {
             proxy_constructor_refs: RefCell::new(Default::default()),
             proxy_event_listeners: RefCell::new(Default::default()),
             proxy_static_event_listeners: RefCell::new(Default::default()),
+            tracked_rejections: RefCell::new(vec![]),
+            rejection_tracking_depth: Cell::new(0),
+            module_registry: RefCell::new(Default::default()),
+            import_map: RefCell::new(None),
+            defines: RefCell::new(None),
         }
     }
     /// get the id of a QuickJsContext from a JSContext
@@ -230,6 +249,56 @@ impl QuickJsRealmAdapter {
         unsafe { Self::eval_ctx(self.context, script, Some(this)) }
     }
 
+    /// run an eval of the QuickJsRuntimeFacade, if the runtime is in strict unhandled rejections mode the eval fails
+    /// with the reason of the first promise rejection which is not handled by the time the eval and its pending jobs are done
+    ///
+    /// in strict mode the pending jobs are run before this returns (instead of after the task of the event loop),
+    /// this is only used by the facade so the evals of a realm never run jobs or re-enter the realm themselves
+    pub(crate) fn track_unhandled_rejections<F>(
+        &self,
+        eval: F,
+    ) -> Result<QuickJsValueAdapter, JsError>
+    where
+        F: FnOnce() -> Result<QuickJsValueAdapter, JsError>,
+    {
+        let strict = QuickJsRuntimeAdapter::do_with(|rt| rt.strict_unhandled_rejections);
+        if !strict {
+            return eval();
+        }
+
+        let start = self.tracked_rejections.borrow().len();
+        self.rejection_tracking_depth
+            .set(self.rejection_tracking_depth.get() + 1);
+        let res = eval();
+        // run jobs so rejections in reactions and async functions are seen as well
+        QuickJsRuntimeAdapter::do_with(|rt| rt.run_pending_jobs_if_any());
+        self.rejection_tracking_depth
+            .set(self.rejection_tracking_depth.get() - 1);
+
+        let mut rejections: Vec<(QuickJsValueAdapter, QuickJsValueAdapter)> = {
+            let tracked = &mut *self.tracked_rejections.borrow_mut();
+            let start = start.min(tracked.len());
+            tracked.drain(start..).collect()
+        };
+
+        let res = match res {
+            Ok(_) if !rejections.is_empty() => {
+                let (_promise, reason) = rejections.remove(0);
+                Err(unsafe { promises::rejection_reason_to_js_error(self.context, &reason) })
+            }
+            res => res,
+        };
+
+        // these did not fail the eval but should not go unnoticed
+        QuickJsRuntimeAdapter::do_with(|rt| {
+            for (promise, reason) in rejections {
+                promises::report_unhandled_rejection(rt, self, &promise, &reason);
+            }
+        });
+
+        res
+    }
+
     /// # Safety
     /// when passing a context ptr please be sure that the corresponding QuickJsContext is still active
     pub unsafe fn eval_ctx(
@@ -239,8 +308,26 @@ impl QuickJsRealmAdapter {
     ) -> Result<QuickJsValueAdapter, JsError> {
         log::debug!("q_js_rt.eval file {}", script.get_path());
 
-        script = QuickJsRuntimeAdapter::pre_process(script)?;
+        // with a bytecode cache the script is compiled or read from the cache first
+        if this_opt.is_none() && QuickJsRuntimeAdapter::do_with(|rt| rt.bytecode_store.is_some()) {
+            let compiled = compile::compile_cached(context, script, false, |script| {
+                Self::pre_process_ctx(context, script)
+            })?;
+            return compile::run_compiled_function(context, &compiled);
+        }
+
+        script = Self::pre_process_ctx(context, script)?;
+        Self::eval_internal_ctx(context, &script, this_opt)
+    }
 
+    /// evaluate a script as is, it is not pre-processed, e.g. for the scripts of the runtime itself
+    /// # Safety
+    /// when passing a context ptr please be sure that the corresponding QuickJsContext is still active
+    pub(crate) unsafe fn eval_internal_ctx(
+        context: *mut q::JSContext,
+        script: &Script,
+        this_opt: Option<QuickJsValueAdapter>,
+    ) -> Result<QuickJsValueAdapter, JsError> {
         let code_str = script.get_runnable_code();
 
         let filename_c = make_cstring(script.get_path())?;
@@ -289,7 +376,183 @@ impl QuickJsRealmAdapter {
 
     /// evaluate a Module
     pub fn eval_module(&self, script: Script) -> Result<QuickJsValueAdapter, JsError> {
-        unsafe { Self::eval_module_ctx(self.context, script) }
+        self.eval_module_def(script).map(|(_module, res)| res)
+    }
+
+    /// evaluate a module and get its namespace (the exported values)
+    ///
+    /// returns a promise which resolves with the namespace when the module (including top level await) was evaluated
+    pub fn eval_module_namespace(&self, script: Script) -> Result<QuickJsValueAdapter, JsError> {
+        let name = script.get_path().to_string();
+        let (module, res) = self.eval_module_def(script)?;
+        let namespace = modules::import_module_namespace(self, name.as_str(), module)?;
+        self.resolve_after(&res, namespace)
+    }
+
+    fn eval_module_def(
+        &self,
+        mut script: Script,
+    ) -> Result<(*mut q::JSModuleDef, QuickJsValueAdapter), JsError> {
+        let path = script.get_path().to_string();
+        modules::strip_import_attributes(&mut script);
+        self.module_registry.borrow_mut().register_module(
+            path.as_str(),
+            None,
+            ModuleStatus::Pending,
+        );
+        let mut module = std::ptr::null_mut();
+        let res = unsafe {
+            log::debug!("q_js_rt.eval_module file {}", script.get_path());
+            compile::compile_cached(self.context, script, true, |script| {
+                modules::pre_process_module(self, script)
+            })
+            .and_then(|compiled| {
+                module = modules::get_module_def(&compiled);
+                modules::init_import_meta(self, module, path.as_str())?;
+                compile::run_compiled_function(self.context, &compiled)
+            })
+        };
+        modules::forget_pre_processed(self, path.as_str());
+        modules::track_module_evaluation(self, path.as_str(), &res);
+        let res = res?;
+        self.module_registry
+            .borrow_mut()
+            .module_defs
+            .insert(path, module);
+        Ok((module, res))
+    }
+
+    /// get the namespace of a module which was loaded in this realm by its name or normalized path
+    ///
+    /// only quickjs-ng can get the namespace of a module synchronously, use import_module with bellard
+    #[cfg(feature = "quickjs-ng")]
+    pub fn get_module_namespace(&self, name: &str) -> Result<Option<QuickJsValueAdapter>, JsError> {
+        modules::get_loaded_module_namespace(self, name)
+    }
+
+    /// import a module like import() does, a module which was already loaded or evaluated is not loaded again
+    ///
+    /// returns a promise which resolves with the namespace of the module
+    pub fn import_module(&self, path: &str) -> Result<QuickJsValueAdapter, JsError> {
+        match modules::get_loaded_module(self, path) {
+            Some((name, module)) => modules::import_module_namespace(self, name.as_str(), module),
+            None => modules::import_internal(self, path),
+        }
+    }
+
+    /// create a promise which resolves with value when res is fulfilled (if res is a promise)
+    fn resolve_after(
+        &self,
+        res: &QuickJsValueAdapter,
+        value: QuickJsValueAdapter,
+    ) -> Result<QuickJsValueAdapter, JsError> {
+        if res.is_promise() {
+            let then = self.create_function(
+                "resolve_after",
+                move |_realm, _this, _args| Ok(value.clone()),
+                0,
+            )?;
+            functions::invoke_member_function_q(self, res, "then", &[then])
+        } else {
+            let promise = self.create_promise()?;
+            promise.js_promise_resolve(self, &value)?;
+            Ok(promise.js_promise_get_value(self))
+        }
+    }
+
+    /// evaluate a module after the modules of async module loaders it (transitively) imports are loaded
+    /// the event loop keeps running other tasks while those modules load
+    ///
+    /// returns a promise which resolves with the result of eval_module
+    pub fn eval_module_async(&self, script: Script) -> Result<QuickJsValueAdapter, JsError> {
+        self.preload_module_imports(script, |realm, script| realm.eval_module(script))
+    }
+
+    /// evaluate a module after the modules of async module loaders it (transitively) imports are loaded
+    ///
+    /// returns a promise which resolves with the namespace of the module, see eval_module_namespace
+    pub fn eval_module_namespace_async(
+        &self,
+        script: Script,
+    ) -> Result<QuickJsValueAdapter, JsError> {
+        self.preload_module_imports(script, |realm, script| realm.eval_module_namespace(script))
+    }
+
+    fn preload_module_imports<E>(
+        &self,
+        mut script: Script,
+        eval: E,
+    ) -> Result<QuickJsValueAdapter, JsError>
+    where
+        E: Fn(&QuickJsRealmAdapter, Script) -> Result<QuickJsValueAdapter, JsError> + 'static,
+    {
+        let importer = script.get_path().to_string();
+        modules::strip_import_attributes(&mut script);
+        let imports = modules::find_module_imports(self, script.clone())?
+            .into_iter()
+            .map(|specifier| (importer.clone(), specifier))
+            .collect();
+        let preloaded = modules::preload_imports(self, imports)?;
+        let eval = self.create_function(
+            "eval_module_async",
+            move |realm, _this, _args| eval(realm, script.clone()),
+            0,
+        )?;
+        functions::invoke_member_function_q(self, &preloaded, "then", &[eval])
+    }
+
+    /// get info about all modules loaded in this realm, sorted by name
+    ///
+    /// the status of a module is settled from the evaluation promise of the eval_module or import which loaded it,
+    /// it is Evaluated when that completed (this includes top level await) and Errored when it failed to load or compile
+    /// or when that evaluation failed
+    ///
+    /// modules which are only imported by an import() in script code stay Pending, unless the runtime has async module
+    /// loaders (then import() is replaced by a function of the runtime)
+    pub fn get_loaded_modules(&self) -> Vec<ModuleInfo> {
+        modules::get_loaded_modules(self)
+    }
+
+    /// set the import map of this realm, this overrides the import map set on the QuickJsRuntimeBuilder
+    /// pass None to use the import map of the runtime again
+    pub fn set_import_map(&self, import_map: Option<ImportMap>) {
+        *self.import_map.borrow_mut() = import_map;
+    }
+
+    /// set the flags and constants for the IfDefPreProcessor of the runtime (see QuickJsRuntimeBuilder::defines) in this realm,
+    /// these are added to (or override) the defines of the runtime, pass None to only use the defines of the runtime
+    pub fn set_defines(&self, defines: Option<Defines>) {
+        *self.defines.borrow_mut() = defines;
+    }
+
+    /// resolve a specifier with the import map of this realm or else the import map of the runtime
+    pub fn resolve_import_map(&self, specifier: &str, referrer_path: &str) -> Option<String> {
+        if let Some(import_map) = &*self.import_map.borrow() {
+            return import_map.resolve(specifier, referrer_path);
+        }
+        QuickJsRuntimeAdapter::do_with(|rt| {
+            rt.import_map
+                .as_ref()
+                .and_then(|import_map| import_map.resolve(specifier, referrer_path))
+        })
+    }
+
+    /// get info about a loaded module by its name or normalized path
+    pub fn get_module_info(&self, name: &str) -> Option<ModuleInfo> {
+        modules::get_module_info(self, name)
+    }
+
+    /// invalidate a loaded module and all modules which (transitively) import it
+    /// the next import of one of those modules loads and evaluates fresh code from the module loaders
+    ///
+    /// modules may use import.meta.hot to hand over state
+    /// * `import.meta.hot.dispose(data => {})` is called on invalidation, the data object is passed to the new version as `import.meta.hot.data`
+    /// * `import.meta.hot.accept(newModule => {})` marks the module as self accepting, it is re-imported right away and the callback is called with the new module namespace
+    ///
+    /// returns the paths of all invalidated modules, when a dispose callback fails all modules are still invalidated and the first error is returned
+    /// please note that QuickJS can not unload modules, the old versions stay in memory until the realm is dropped
+    pub fn invalidate_module(&self, path: &str) -> Result<Vec<String>, JsError> {
+        modules::invalidate_module(self, path)
     }
 
     /// # Safety
@@ -300,7 +563,15 @@ impl QuickJsRealmAdapter {
     ) -> Result<QuickJsValueAdapter, JsError> {
         log::debug!("q_js_rt.eval_module file {}", script.get_path());
 
-        script = QuickJsRuntimeAdapter::pre_process(script)?;
+        // with a bytecode cache the module is compiled or read from the cache first
+        if QuickJsRuntimeAdapter::do_with(|rt| rt.bytecode_store.is_some()) {
+            let compiled = compile::compile_cached(context, script, true, |script| {
+                Self::pre_process_ctx(context, script)
+            })?;
+            return compile::run_compiled_function(context, &compiled);
+        }
+
+        script = Self::pre_process_ctx(context, script)?;
 
         let code_str = script.get_runnable_code();
 
@@ -409,6 +680,17 @@ impl QuickJsRealmAdapter {
 
         consumer(clone_ref)
     }
+    /// run the ScriptPreProcessors of the runtime for the realm of a context
+    /// # Safety
+    /// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
+    unsafe fn pre_process_ctx(
+        context: *mut q::JSContext,
+        script: Script,
+    ) -> Result<Script, JsError> {
+        Self::with_context(context, |realm| {
+            QuickJsRuntimeAdapter::pre_process(realm, script)
+        })
+    }
     /// # Safety
     /// When passing a context pointer please make sure the corresponding QuickJsContext is still valid
     pub unsafe fn with_context<C, R>(context: *mut q::JSContext, consumer: C) -> R
@@ -697,6 +979,14 @@ impl QuickJsRealmAdapter {
         unsafe { errors::new_error(self.context, name, message, stack) }
     }
 
+    /// create an Error from a JsError, including its cause and custom properties
+    pub fn create_error_from_js_error(
+        &self,
+        error: JsError,
+    ) -> Result<QuickJsValueAdapter, JsError> {
+        unsafe { errors::js_error_to_error(self.context, error) }
+    }
+
     pub fn delete_object_property(
         &self,
         object: &QuickJsValueAdapter,
@@ -1033,18 +1323,9 @@ impl QuickJsRealmAdapter {
                     cached_object: CachedJsObjectRef::new(self, js_value.clone()),
                 },
             },
-            JsValueType::Error => {
-                let name = self.get_object_property(js_value, "name")?.to_string()?;
-                let message = self.get_object_property(js_value, "message")?.to_string()?;
-                let stack = self.get_object_property(js_value, "stack")?.to_string()?;
-
-                #[cfg(feature = "typescript")]
-                let stack = crate::typescript::unmap_stack_trace(stack.as_str());
-
-                JsValueFacade::JsError {
-                    val: JsError::new(name, message, stack),
-                }
-            }
+            JsValueType::Error => JsValueFacade::JsError {
+                val: unsafe { errors::error_to_js_error(self.context, js_value) },
+            },
         };
         Ok(res)
     }
@@ -1133,9 +1414,7 @@ impl QuickJsRealmAdapter {
             }
             JsValueFacade::Null => self.create_null(),
             JsValueFacade::Undefined => self.create_undefined(),
-            JsValueFacade::JsError { val } => {
-                self.create_error(val.get_name(), val.get_message(), val.get_stack())
-            }
+            JsValueFacade::JsError { val } => self.create_error_from_js_error(val),
             JsValueFacade::ProxyInstance {
                 instance_id,
                 namespace,
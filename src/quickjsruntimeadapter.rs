@@ -1,16 +1,22 @@
 // store in thread_local
 
 use crate::facades::QuickjsRuntimeFacadeInner;
-use crate::jsutils::modules::{CompiledModuleLoader, NativeModuleLoader, ScriptModuleLoader};
+use crate::jsutils::bytecodecache::BytecodeStore;
+use crate::jsutils::importmaps::ImportMap;
+use crate::jsutils::modules::{
+    AsyncModuleLoader, CompiledModuleLoader, ModuleSource, ModuleSourceFuture, NativeModuleLoader,
+    ScriptModuleLoader,
+};
 use crate::jsutils::{JsError, Script, ScriptPreProcessor};
-use crate::quickjs_utils::compile::from_bytecode;
+use crate::quickjs_utils::compile::{self, from_bytecode};
 use crate::quickjs_utils::modules::{
-    add_module_export, compile_module, get_module_def, get_module_name, new_module,
-    set_module_export,
+    add_module_export, get_module_def, get_module_name, new_module, set_module_export, ModuleStatus,
 };
 use crate::quickjs_utils::runtime::new_class_id;
-use crate::quickjs_utils::{gc, interrupthandler, modules, promises};
+use crate::quickjs_utils::{errors, gc, interrupthandler, json, modules, promises, typedarrays};
 use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use crate::values::JsValueFacade;
 use libquickjs_sys as q;
 use serde::Serialize;
 use std::cell::RefCell;
@@ -47,6 +53,41 @@ pub trait ModuleLoader {
         q_ctx: &QuickJsRealmAdapter,
         module: *mut q::JSModuleDef,
     ) -> Result<(), JsError>;
+    /// the name of the loader, used in the module registry of a realm
+    fn get_name(&self) -> &str;
+    /// start loading a module asynchronously, only async loaders return a future
+    /// the loaded source is passed to load_module when the module is imported
+    fn load_module_async(
+        &self,
+        _q_ctx: &QuickJsRealmAdapter,
+        _absolute_path: &str,
+    ) -> Option<ModuleSourceFuture> {
+        None
+    }
+    /// true for loaders which load their modules with load_module_async, unlike load_module_async this does not start a load
+    fn is_async(&self) -> bool {
+        false
+    }
+    /// let the loader add properties to import.meta of a module it loaded
+    /// # Safety
+    /// be safe with the moduledef ptr
+    unsafe fn init_import_meta(
+        &self,
+        _q_ctx: &QuickJsRealmAdapter,
+        _module: *mut q::JSModuleDef,
+        _absolute_path: &str,
+    ) -> Result<(), JsError> {
+        Ok(())
+    }
+    /// get the source of a module without loading it, this is used to bundle modules
+    /// only loaders which load modules from source return Some
+    fn get_module_source(
+        &self,
+        _q_ctx: &QuickJsRealmAdapter,
+        _absolute_path: &str,
+    ) -> Option<Result<ModuleSource, JsError>> {
+        None
+    }
 }
 
 // these are the external (util) loaders (todo move these to esruntime?)
@@ -69,6 +110,48 @@ impl ScriptModuleLoaderAdapter {
     pub fn new(loader: Box<dyn ScriptModuleLoader>) -> Self {
         Self { inner: loader }
     }
+
+    /// compile a script module
+    fn load_script_module(
+        realm: &QuickJsRealmAdapter,
+        absolute_path: &str,
+        code: &str,
+    ) -> Result<*mut q::JSModuleDef, JsError> {
+        let mut script = Script::new(absolute_path, code);
+        modules::strip_import_attributes(&mut script);
+        log::trace!("load_module / 2");
+        let compiled_module = unsafe {
+            compile::compile_cached(realm.context, script, true, |script| {
+                Self::process_script_module(realm, absolute_path, script)
+            })
+        };
+        modules::forget_pre_processed(realm, modules::strip_module_version(absolute_path));
+        let compiled_module = compiled_module?;
+        log::trace!("load_module / 3");
+        let module = get_module_def(&compiled_module);
+        unsafe { modules::init_import_meta(realm, module, absolute_path)? };
+        Ok(module)
+    }
+
+    /// pre-process the code of a script module (after strip_import_attributes) as it is compiled
+    pub(crate) fn process_script_module(
+        realm: &QuickJsRealmAdapter,
+        absolute_path: &str,
+        script: Script,
+    ) -> Result<Script, JsError> {
+        let path = modules::strip_module_version(absolute_path);
+        let script = Script::new(path, script.get_code());
+        let script = modules::pre_process_module(realm, script)?;
+        // an invalidated module is compiled under its versioned name
+        let mut compiled_script = Script::new(absolute_path, script.get_runnable_code());
+        if let Some(map) = script.get_map() {
+            compiled_script.set_transpiled_code(
+                script.get_runnable_code().to_string(),
+                Some(map.to_string()),
+            );
+        }
+        Ok(compiled_script)
+    }
 }
 
 impl ModuleLoader for CompiledModuleLoaderAdapter {
@@ -86,10 +169,14 @@ impl ModuleLoader for CompiledModuleLoaderAdapter {
         q_ctx: &QuickJsRealmAdapter,
         absolute_path: &str,
     ) -> Result<*mut q::JSModuleDef, JsError> {
-        let bytes = self.inner.load_module(q_ctx, absolute_path);
+        let bytes = self
+            .inner
+            .load_module(q_ctx, modules::strip_module_version(absolute_path))?;
 
         let compiled_module = unsafe { from_bytecode(q_ctx.context, &bytes)? };
-        Ok(get_module_def(&compiled_module))
+        let module = get_module_def(&compiled_module);
+        unsafe { modules::init_import_meta(q_ctx, module, absolute_path)? };
+        Ok(module)
     }
 
     fn has_module(&self, q_ctx: &QuickJsRealmAdapter, absolute_path: &str) -> bool {
@@ -104,6 +191,24 @@ impl ModuleLoader for CompiledModuleLoaderAdapter {
     ) -> Result<(), JsError> {
         Ok(())
     }
+
+    fn get_name(&self) -> &str {
+        self.inner.get_name()
+    }
+
+    unsafe fn init_import_meta(
+        &self,
+        q_ctx: &QuickJsRealmAdapter,
+        module: *mut q::JSModuleDef,
+        absolute_path: &str,
+    ) -> Result<(), JsError> {
+        let import_meta = modules::get_import_meta(q_ctx, module)?;
+        self.inner.init_import_meta(
+            q_ctx,
+            modules::strip_module_version(absolute_path),
+            &import_meta,
+        )
+    }
 }
 
 impl ModuleLoader for ScriptModuleLoaderAdapter {
@@ -122,14 +227,91 @@ impl ModuleLoader for ScriptModuleLoaderAdapter {
         absolute_path: &str,
     ) -> Result<*mut q::JSModuleDef, JsError> {
         log::trace!("load_module");
-        let code = self.inner.load_module(realm, absolute_path);
+        let path = modules::strip_module_version(absolute_path);
+        let source = self.inner.load_module_source(realm, path)?;
+        load_module_source(realm, absolute_path, source)
+    }
 
-        let mut script = Script::new(absolute_path, code.as_str());
-        script = QuickJsRuntimeAdapter::pre_process(script)?;
-        log::trace!("load_module / 2");
-        let compiled_module = unsafe { compile_module(realm.context, script)? };
-        log::trace!("load_module / 3");
-        Ok(get_module_def(&compiled_module))
+    fn get_module_source(
+        &self,
+        realm: &QuickJsRealmAdapter,
+        absolute_path: &str,
+    ) -> Option<Result<ModuleSource, JsError>> {
+        let path = modules::strip_module_version(absolute_path);
+        Some(self.inner.load_module_source(realm, path))
+    }
+
+    unsafe fn init_import_meta(
+        &self,
+        q_ctx: &QuickJsRealmAdapter,
+        module: *mut q::JSModuleDef,
+        absolute_path: &str,
+    ) -> Result<(), JsError> {
+        let import_meta = modules::get_import_meta(q_ctx, module)?;
+        self.inner.init_import_meta(
+            q_ctx,
+            modules::strip_module_version(absolute_path),
+            &import_meta,
+        )
+    }
+
+    fn has_module(&self, q_ctx: &QuickJsRealmAdapter, absolute_path: &str) -> bool {
+        self.normalize_path(q_ctx, absolute_path, absolute_path)
+            .is_some()
+    }
+
+    unsafe fn init_module(
+        &self,
+        _q_ctx: &QuickJsRealmAdapter,
+        _module: *mut q::JSModuleDef,
+    ) -> Result<(), JsError> {
+        // synthetic (json/text/bytes) modules are initialized in native_module_init
+        Ok(())
+    }
+
+    fn get_name(&self) -> &str {
+        self.inner.get_name()
+    }
+}
+
+pub struct AsyncModuleLoaderAdapter {
+    inner: Box<dyn AsyncModuleLoader>,
+}
+
+impl AsyncModuleLoaderAdapter {
+    pub fn new(loader: Box<dyn AsyncModuleLoader>) -> Self {
+        Self { inner: loader }
+    }
+}
+
+impl ModuleLoader for AsyncModuleLoaderAdapter {
+    fn normalize_path(
+        &self,
+        q_ctx: &QuickJsRealmAdapter,
+        ref_path: &str,
+        path: &str,
+    ) -> Option<String> {
+        self.inner.normalize_path(q_ctx, ref_path, path)
+    }
+
+    fn load_module(
+        &self,
+        q_ctx: &QuickJsRealmAdapter,
+        absolute_path: &str,
+    ) -> Result<*mut q::JSModuleDef, JsError> {
+        let path = modules::strip_module_version(absolute_path);
+        let source = q_ctx
+            .module_registry
+            .borrow_mut()
+            .preloaded
+            .remove(modules::strip_version(absolute_path))
+            .ok_or_else(|| {
+                JsError::new_string(format!(
+                    "module {path} of async module loader {} was not loaded, it can only be imported from QuickJsRuntimeFacade::eval_module, QuickJsRealmAdapter::eval_module_async or import()",
+                    self.get_name()
+                ))
+            })?;
+        load_module_source(q_ctx, absolute_path, source)
     }
 
     fn has_module(&self, q_ctx: &QuickJsRealmAdapter, absolute_path: &str) -> bool {
@@ -144,6 +326,36 @@ impl ModuleLoader for ScriptModuleLoaderAdapter {
     ) -> Result<(), JsError> {
         Ok(())
     }
+
+    fn get_name(&self) -> &str {
+        self.inner.get_name()
+    }
+
+    fn load_module_async(
+        &self,
+        q_ctx: &QuickJsRealmAdapter,
+        absolute_path: &str,
+    ) -> Option<ModuleSourceFuture> {
+        Some(self.inner.load_module(q_ctx, absolute_path))
+    }
+
+    fn is_async(&self) -> bool {
+        true
+    }
+
+    unsafe fn init_import_meta(
+        &self,
+        q_ctx: &QuickJsRealmAdapter,
+        module: *mut q::JSModuleDef,
+        absolute_path: &str,
+    ) -> Result<(), JsError> {
+        let import_meta = modules::get_import_meta(q_ctx, module)?;
+        self.inner.init_import_meta(
+            q_ctx,
+            modules::strip_module_version(absolute_path),
+            &import_meta,
+        )
+    }
 }
 
 pub struct NativeModuleLoaderAdapter {
@@ -178,7 +390,8 @@ impl ModuleLoader for NativeModuleLoaderAdapter {
         // create module
         let module = unsafe { new_module(q_ctx.context, absolute_path, Some(native_module_init))? };
 
-        for name in self.inner.get_module_export_names(q_ctx, absolute_path) {
+        let path = modules::strip_module_version(absolute_path);
+        for name in self.inner.get_module_export_names(q_ctx, path) {
             unsafe { add_module_export(q_ctx.context, module, name)? }
         }
 
@@ -197,11 +410,90 @@ impl ModuleLoader for NativeModuleLoaderAdapter {
     ) -> Result<(), JsError> {
         let module_name = get_module_name(q_ctx.context, module)?;
 
-        for (name, val) in self.inner.get_module_exports(q_ctx, module_name.as_str()) {
+        let path = modules::strip_module_version(module_name.as_str());
+        for (name, val) in self.inner.get_module_exports(q_ctx, path) {
             set_module_export(q_ctx.context, module, name, val)?;
         }
         Ok(())
     }
+
+    fn get_name(&self) -> &str {
+        self.inner.get_name()
+    }
+}
+
+/// create a module from a ModuleSource, json/text/bytes sources become a synthetic module with just a default export
+fn load_module_source(
+    realm: &QuickJsRealmAdapter,
+    absolute_path: &str,
+    source: ModuleSource,
+) -> Result<*mut q::JSModuleDef, JsError> {
+    let path = modules::strip_module_version(absolute_path);
+    let source = apply_declared_type(path, modules::get_module_type(absolute_path), source)?;
+
+    let default_export = match source {
+        // module.exports of a CommonJS module is the default export
+        #[cfg(feature = "commonjs")]
+        ModuleSource::Script(code) if crate::features::commonjs::is_commonjs(path) => {
+            crate::features::commonjs::get_module_exports(realm, path, code)?
+        }
+        #[cfg(feature = "commonjs")]
+        ModuleSource::CommonJs(code) => {
+            crate::features::commonjs::get_module_exports(realm, path, code)?
+        }
+        #[cfg(not(feature = "commonjs"))]
+        ModuleSource::CommonJs(_) => {
+            return Err(JsError::new_string(format!(
+                "module {path} is a CommonJS module, which needs the commonjs feature"
+            )))
+        }
+        ModuleSource::Script(code) => {
+            return ScriptModuleLoaderAdapter::load_script_module(
+                realm,
+                absolute_path,
+                code.as_str(),
+            )
+        }
+        ModuleSource::Json(json) => json::parse_q(realm, json.as_str())?,
+        ModuleSource::Text(text) => realm.create_string(text.as_str())?,
+        ModuleSource::Bytes(bytes) => typedarrays::new_uint8_array_q(realm, bytes)?,
+        ModuleSource::Bytecode(bytes) => {
+            let compiled_module = unsafe { from_bytecode(realm.context, &bytes)? };
+            return Ok(get_module_def(&compiled_module));
+        }
+    };
+
+    // a synthetic module with just a default export, it is set in native_module_init
+    let module = unsafe { new_module(realm.context, absolute_path, Some(native_module_init))? };
+    unsafe { add_module_export(realm.context, module, "default")? };
+    realm
+        .module_registry
+        .borrow_mut()
+        .synthetic_exports
+        .insert(absolute_path.to_string(), default_export);
+    Ok(module)
+}
+
+/// the ModuleSource of a module with the type it was imported with
+pub(crate) fn apply_declared_type(
+    path: &str,
+    declared_type: Option<&str>,
+    source: ModuleSource,
+) -> Result<ModuleSource, JsError> {
+    // a script imported with a type attribute is interpreted as that type
+    let source = match (declared_type, source) {
+        (None, source) => source,
+        (Some("json"), ModuleSource::Script(code)) => ModuleSource::Json(code),
+        (Some("text"), ModuleSource::Script(code)) => ModuleSource::Text(code),
+        (Some(declared_type), source) if declared_type == source.get_type() => source,
+        (Some(declared_type), source) => {
+            return Err(JsError::new_string(format!(
+                "module {path} was imported with type '{declared_type}' but is a {} module",
+                source.get_type()
+            )));
+        }
+    };
+    Ok(source)
 }
 
 unsafe extern "C" fn native_module_init(
@@ -211,15 +503,58 @@ unsafe extern "C" fn native_module_init(
     let module_name = get_module_name(ctx, module).expect("could not get name");
     log::trace!("native_module_init: {}", module_name);
 
+    let native_name = format!("native_module_init {module_name}");
+    errors::catch_native_panic(ctx, native_name.as_str(), || {
+        native_module_init_inner(ctx, module, module_name.as_str())
+    })
+    .unwrap_or(-1)
+}
+
+unsafe fn native_module_init_inner(
+    ctx: *mut q::JSContext,
+    module: *mut q::JSModuleDef,
+    module_name: &str,
+) -> c_int {
     QuickJsRuntimeAdapter::do_with(|q_js_rt| {
         QuickJsRealmAdapter::with_context(ctx, |q_ctx| {
+            let default_export = q_ctx
+                .module_registry
+                .borrow_mut()
+                .synthetic_exports
+                .remove(module_name);
+            if let Some(default_export) = default_export {
+                return match set_module_export(ctx, module, "default", default_export) {
+                    Ok(_) => {
+                        q_ctx
+                            .module_registry
+                            .borrow_mut()
+                            .set_status(module_name, ModuleStatus::Evaluated);
+                        0
+                    }
+                    Err(e) => {
+                        q_ctx.report_ex(
+                            format!("Failed to init module: {module_name} caused by {e}").as_str(),
+                        );
+                        1
+                    }
+                };
+            }
+
             if let Some(res) = q_js_rt.with_all_module_loaders(|module_loader| {
-                if module_loader.has_module(q_ctx, module_name.as_str()) {
+                if module_loader.has_module(q_ctx, modules::strip_module_version(module_name)) {
                     match module_loader.init_module(q_ctx, module) {
                         Ok(_) => {
+                            q_ctx
+                                .module_registry
+                                .borrow_mut()
+                                .set_status(module_name, ModuleStatus::Evaluated);
                             Some(0) // ok
                         }
                         Err(e) => {
+                            q_ctx
+                                .module_registry
+                                .borrow_mut()
+                                .set_status(module_name, ModuleStatus::Errored);
                             q_ctx.report_ex(
                                 format!(
                                     "Failed to init native module: {module_name} caused by {e}"
@@ -262,12 +597,26 @@ pub struct QuickJsRuntimeAdapter {
     id: String,
     pub(crate) context_init_hooks: RefCell<ContextInitHooks>,
     script_module_loaders: Vec<ScriptModuleLoaderAdapter>,
+    async_module_loaders: Vec<AsyncModuleLoaderAdapter>,
     native_module_loaders: Vec<NativeModuleLoaderAdapter>,
     compiled_module_loaders: Vec<CompiledModuleLoaderAdapter>,
     // script preprocs just preproc the input code, typescript transpiler will be special option which is run as last preproc
     pub(crate) script_pre_processors: Vec<Box<dyn ScriptPreProcessor + Send>>,
     #[allow(clippy::type_complexity)]
     pub(crate) interrupt_handler: Option<Box<dyn Fn(&QuickJsRuntimeAdapter) -> bool>>,
+    #[allow(clippy::type_complexity)]
+    pub(crate) panic_handler:
+        Option<Box<dyn Fn(&QuickJsRuntimeAdapter, &QuickJsRealmAdapter, &str, &str)>>,
+    #[allow(clippy::type_complexity)]
+    pub(crate) unhandled_rejection_handler:
+        Option<Box<dyn Fn(&QuickJsRealmAdapter, JsValueFacade, &QuickJsValueAdapter)>>,
+    #[allow(clippy::type_complexity)]
+    pub(crate) rejection_handled_handler:
+        Option<Box<dyn Fn(&QuickJsRealmAdapter, &QuickJsValueAdapter)>>,
+    pub(crate) strict_unhandled_rejections: bool,
+    /// import map for all realms which do not have their own
+    pub(crate) import_map: Option<ImportMap>,
+    pub(crate) bytecode_store: Option<Box<dyn BytecodeStore + Send>>,
 }
 
 thread_local! {
@@ -411,14 +760,37 @@ impl QuickJsRuntimeAdapter {
         }
     }
 
-    pub(crate) fn pre_process(mut script: Script) -> Result<Script, JsError> {
+    /// the cache keys of the pre-processors, part of the key of the bytecode cache
+    pub(crate) fn get_pre_processors_cache_key(&self, realm: &QuickJsRealmAdapter) -> String {
+        let mut keys: Vec<String> = self
+            .script_pre_processors
+            .iter()
+            .map(|pp| format!("{}{}", pp.get_cache_key(), pp.get_realm_cache_key(realm)))
+            .collect();
+        // import() calls are replaced when there are async module loaders
+        if self.has_async_module_loaders() {
+            keys.push("importAsync".to_string());
+        }
+        #[cfg(feature = "typescript")]
+        keys.push(crate::typescript::get_serverside_cache_key());
+        keys.join(";")
+    }
+
+    pub(crate) fn pre_process(
+        realm: &QuickJsRealmAdapter,
+        mut script: Script,
+    ) -> Result<Script, JsError> {
+        crate::quickjs_utils::errors::remember_source(script.get_path(), script.get_code());
         Self::do_with(|q_js_rt| {
             for pp in &q_js_rt.script_pre_processors {
-                pp.process(&mut script)?;
+                pp.process_for_realm(realm, &mut script)?;
             }
             #[cfg(feature = "typescript")]
             crate::typescript::transpile_serverside(q_js_rt, &mut script)?;
 
+            if q_js_rt.has_async_module_loaders() {
+                modules::replace_dynamic_imports(realm, &mut script)?;
+            }
             Ok(script)
         })
     }
@@ -543,10 +915,17 @@ impl QuickJsRuntimeAdapter {
             id,
             context_init_hooks: RefCell::new(vec![]),
             script_module_loaders: vec![],
+            async_module_loaders: vec![],
             native_module_loaders: vec![],
             compiled_module_loaders: vec![],
             script_pre_processors: vec![],
             interrupt_handler: None,
+            panic_handler: None,
+            unhandled_rejection_handler: None,
+            rejection_handled_handler: None,
+            strict_unhandled_rejections: false,
+            import_map: None,
+            bytecode_store: None,
         };
 
         modules::set_module_loader(&q_rt);
@@ -567,10 +946,61 @@ impl QuickJsRuntimeAdapter {
         self
     }
 
+    /// set a handler which is called when a rust callback (function, Proxy method or module loader) panics
+    /// the handler receives the name of the native function and the panic message
+    pub fn set_panic_handler<
+        H: Fn(&QuickJsRuntimeAdapter, &QuickJsRealmAdapter, &str, &str) + 'static,
+    >(
+        &mut self,
+        panic_handler: H,
+    ) -> &mut Self {
+        self.panic_handler = Some(Box::new(panic_handler));
+        self
+    }
+
+    /// set a handler which is called when a promise is rejected and no rejection handler was added to it
+    /// if no handler is set the rejection is logged as an error
+    pub fn set_unhandled_rejection_handler<
+        H: Fn(&QuickJsRealmAdapter, JsValueFacade, &QuickJsValueAdapter) + 'static,
+    >(
+        &mut self,
+        handler: H,
+    ) -> &mut Self {
+        self.unhandled_rejection_handler = Some(Box::new(handler));
+        self
+    }
+
+    /// set a handler which is called when a rejection handler is added to a promise which was previously reported as unhandled
+    pub fn set_rejection_handled_handler<
+        H: Fn(&QuickJsRealmAdapter, &QuickJsValueAdapter) + 'static,
+    >(
+        &mut self,
+        handler: H,
+    ) -> &mut Self {
+        self.rejection_handled_handler = Some(Box::new(handler));
+        self
+    }
+
+    /// when strict, a rejection which is still unhandled when an eval or eval_module of the QuickJsRuntimeFacade (and its
+    /// pending jobs) is done fails that eval with the reason, the evals of a QuickJsRealmAdapter report their unhandled
+    /// rejections to the handler, see QuickJsRuntimeBuilder::strict_unhandled_rejections
+    pub fn set_strict_unhandled_rejections(&mut self, strict: bool) -> &mut Self {
+        self.strict_unhandled_rejections = strict;
+        self
+    }
+
     pub fn add_script_module_loader(&mut self, sml: ScriptModuleLoaderAdapter) {
         self.script_module_loaders.push(sml);
     }
 
+    pub fn add_async_module_loader(&mut self, aml: AsyncModuleLoaderAdapter) {
+        self.async_module_loaders.push(aml);
+    }
+
+    pub fn has_async_module_loaders(&self) -> bool {
+        !self.async_module_loaders.is_empty()
+    }
+
     pub fn add_compiled_module_loader(&mut self, cml: CompiledModuleLoaderAdapter) {
         self.compiled_module_loaders.push(cml);
     }
@@ -606,6 +1036,12 @@ impl QuickJsRuntimeAdapter {
                 return res;
             }
         }
+        for loader in &self.async_module_loaders {
+            let res = consumer(loader);
+            if res.is_some() {
+                return res;
+            }
+        }
         None
     }
 
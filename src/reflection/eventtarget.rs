@@ -5,10 +5,12 @@ use crate::jsutils::JsError;
 use crate::quickjs_utils;
 use crate::quickjs_utils::objects::{create_object_q, set_property_q};
 use crate::quickjs_utils::primitives::from_bool;
-use crate::quickjs_utils::{functions, objects, parse_args, primitives};
+use crate::quickjs_utils::{errors, functions, objects, parse_args, primitives};
 use crate::quickjsrealmadapter::QuickJsRealmAdapter;
 use crate::quickjsvalueadapter::QuickJsValueAdapter;
-use crate::reflection::{get_proxy, get_proxy_instance_info, Proxy};
+use crate::reflection::{
+    get_instance_class_name, get_proxy, get_proxy_instance_info, get_static_class_name, Proxy,
+};
 use libquickjs_sys as q;
 use std::collections::HashMap;
 
@@ -311,47 +313,54 @@ unsafe extern "C" fn ext_add_event_listener(
     // events_obj will be structured like this
     // ___eventListeners___: {eventId<String>: Map<Function, Object>} // the key of the map is the function, the value are the options
 
-    let res = QuickJsRealmAdapter::with_context(ctx, |q_ctx| {
-        let args = parse_args(ctx, argc, argv);
-
-        let this_ref =
-            QuickJsValueAdapter::new(ctx, this_val, true, true, "add_event_listener_this");
+    let native_name = format!(
+        "Proxy {} instance method [addEventListener]",
+        get_instance_class_name(&this_val)
+    );
+    errors::catch_native_panic(ctx, native_name.as_str(), || {
+        let res = QuickJsRealmAdapter::with_context(ctx, |q_ctx| {
+            let args = parse_args(ctx, argc, argv);
 
-        let proxy_info = get_proxy_instance_info(this_ref.borrow_value());
+            let this_ref =
+                QuickJsValueAdapter::new(ctx, this_val, true, true, "add_event_listener_this");
 
-        if args.len() < 2 || !args[0].is_string() || !functions::is_function_q(q_ctx, &args[1]) {
-            Err(JsError::new_str("addEventListener requires at least 2 arguments (eventId: String and Listener: Function"))
-        } else {
-            let event_id = primitives::to_string_q(q_ctx, &args[0])?;
-            let listener_func = args[1].clone();
+            let proxy_info = get_proxy_instance_info(this_ref.borrow_value());
 
-            // use the passed options arg or create a new obj
-            let options_obj = if args.len() == 3 && args[2].is_object() {
-                args[2].clone()
+            if args.len() < 2 || !args[0].is_string() || !functions::is_function_q(q_ctx, &args[1]) {
+                Err(JsError::new_str("addEventListener requires at least 2 arguments (eventId: String and Listener: Function"))
             } else {
-                create_object_q(q_ctx)?
-            };
-            // if the third args was a boolean then set that bool as the capture option
-            if args.len() == 3 && args[2].is_bool() {
-                set_property_q(q_ctx, &options_obj, "capture", &args[2])?;
-            }
+                let event_id = primitives::to_string_q(q_ctx, &args[0])?;
+                let listener_func = args[1].clone();
+
+                // use the passed options arg or create a new obj
+                let options_obj = if args.len() == 3 && args[2].is_object() {
+                    args[2].clone()
+                } else {
+                    create_object_q(q_ctx)?
+                };
+                // if the third args was a boolean then set that bool as the capture option
+                if args.len() == 3 && args[2].is_bool() {
+                    set_property_q(q_ctx, &options_obj, "capture", &args[2])?;
+                }
 
-            add_event_listener(
-                q_ctx,
-                proxy_info.class_name.as_str(),
-                event_id.as_str(),
-                proxy_info.id,
-                listener_func,
-                options_obj,
-            );
+                add_event_listener(
+                    q_ctx,
+                    proxy_info.class_name.as_str(),
+                    event_id.as_str(),
+                    proxy_info.id,
+                    listener_func,
+                    options_obj,
+                );
 
-            Ok(())
+                Ok(())
+            }
+        });
+        match res {
+            Ok(_) => quickjs_utils::new_null(),
+            Err(e) => QuickJsRealmAdapter::report_ex_ctx(ctx, format!("{e}").as_str()),
         }
-    });
-    match res {
-        Ok(_) => quickjs_utils::new_null(),
-        Err(e) => QuickJsRealmAdapter::report_ex_ctx(ctx, format!("{e}").as_str()),
-    }
+    })
+    .unwrap_or_else(|ex| ex)
 }
 
 unsafe extern "C" fn ext_remove_event_listener(
@@ -360,35 +369,42 @@ unsafe extern "C" fn ext_remove_event_listener(
     argc: ::std::os::raw::c_int,
     argv: *mut q::JSValue,
 ) -> q::JSValue {
-    let res = QuickJsRealmAdapter::with_context(ctx, |q_ctx| {
-        let args = parse_args(ctx, argc, argv);
-
-        let this_ref =
-            QuickJsValueAdapter::new(ctx, this_val, true, true, "remove_event_listener_this");
-
-        let proxy_info = get_proxy_instance_info(this_ref.borrow_value());
+    let native_name = format!(
+        "Proxy {} instance method [removeEventListener]",
+        get_instance_class_name(&this_val)
+    );
+    errors::catch_native_panic(ctx, native_name.as_str(), || {
+        let res = QuickJsRealmAdapter::with_context(ctx, |q_ctx| {
+            let args = parse_args(ctx, argc, argv);
 
-        if args.len() != 2 || !args[0].is_string() || !functions::is_function_q(q_ctx, &args[1]) {
-            Err(JsError::new_str("removeEventListener requires at least 2 arguments (eventId: String and Listener: Function"))
-        } else {
-            let event_id = primitives::to_string_q(q_ctx, &args[0])?;
-            let listener_func = args[1].clone();
+            let this_ref =
+                QuickJsValueAdapter::new(ctx, this_val, true, true, "remove_event_listener_this");
 
-            remove_event_listener(
-                q_ctx,
-                proxy_info.class_name.as_str(),
-                event_id.as_str(),
-                proxy_info.id,
-                &listener_func,
-            );
+            let proxy_info = get_proxy_instance_info(this_ref.borrow_value());
 
-            Ok(())
+            if args.len() != 2 || !args[0].is_string() || !functions::is_function_q(q_ctx, &args[1]) {
+                Err(JsError::new_str("removeEventListener requires at least 2 arguments (eventId: String and Listener: Function"))
+            } else {
+                let event_id = primitives::to_string_q(q_ctx, &args[0])?;
+                let listener_func = args[1].clone();
+
+                remove_event_listener(
+                    q_ctx,
+                    proxy_info.class_name.as_str(),
+                    event_id.as_str(),
+                    proxy_info.id,
+                    &listener_func,
+                );
+
+                Ok(())
+            }
+        });
+        match res {
+            Ok(_) => quickjs_utils::new_null(),
+            Err(e) => QuickJsRealmAdapter::report_ex_ctx(ctx, format!("{e}").as_str()),
         }
-    });
-    match res {
-        Ok(_) => quickjs_utils::new_null(),
-        Err(e) => QuickJsRealmAdapter::report_ex_ctx(ctx, format!("{e}").as_str()),
-    }
+    })
+    .unwrap_or_else(|ex| ex)
 }
 
 unsafe extern "C" fn ext_dispatch_event(
@@ -397,36 +413,43 @@ unsafe extern "C" fn ext_dispatch_event(
     argc: ::std::os::raw::c_int,
     argv: *mut q::JSValue,
 ) -> q::JSValue {
-    let res = QuickJsRealmAdapter::with_context(ctx, |q_ctx| {
-        let args = parse_args(ctx, argc, argv);
+    let native_name = format!(
+        "Proxy {} instance method [dispatchEvent]",
+        get_instance_class_name(&this_val)
+    );
+    errors::catch_native_panic(ctx, native_name.as_str(), || {
+        let res = QuickJsRealmAdapter::with_context(ctx, |q_ctx| {
+            let args = parse_args(ctx, argc, argv);
 
-        let this_ref =
-            QuickJsValueAdapter::new(ctx, this_val, true, true, "remove_event_listener_this");
+            let this_ref =
+                QuickJsValueAdapter::new(ctx, this_val, true, true, "remove_event_listener_this");
 
-        let proxy_info = get_proxy_instance_info(this_ref.borrow_value());
+            let proxy_info = get_proxy_instance_info(this_ref.borrow_value());
 
-        if args.len() != 2 || !args[0].is_string() {
-            Err(JsError::new_str(
-                "dispatchEvent requires at least 2 arguments (eventId: String and eventObj: Object)",
-            ))
-        } else {
-            let event_id = primitives::to_string_q(q_ctx, &args[0])?;
-            let evt_obj = args[1].clone();
+            if args.len() != 2 || !args[0].is_string() {
+                Err(JsError::new_str(
+                    "dispatchEvent requires at least 2 arguments (eventId: String and eventObj: Object)",
+                ))
+            } else {
+                let event_id = primitives::to_string_q(q_ctx, &args[0])?;
+                let evt_obj = args[1].clone();
 
-            let proxy = get_proxy(q_ctx, proxy_info.class_name.as_str()).unwrap();
+                let proxy = get_proxy(q_ctx, proxy_info.class_name.as_str()).unwrap();
 
-            let res = dispatch_event(q_ctx, &proxy, proxy_info.id, event_id.as_str(), evt_obj)?;
+                let res = dispatch_event(q_ctx, &proxy, proxy_info.id, event_id.as_str(), evt_obj)?;
 
-            Ok(res)
-        }
-    });
-    match res {
-        Ok(res) => {
-            let b_ref = from_bool(res);
-            b_ref.clone_value_incr_rc()
+                Ok(res)
+            }
+        });
+        match res {
+            Ok(res) => {
+                let b_ref = from_bool(res);
+                b_ref.clone_value_incr_rc()
+            }
+            Err(e) => QuickJsRealmAdapter::report_ex_ctx(ctx, format!("{e}").as_str()),
         }
-        Err(e) => QuickJsRealmAdapter::report_ex_ctx(ctx, format!("{e}").as_str()),
-    }
+    })
+    .unwrap_or_else(|ex| ex)
 }
 
 unsafe fn get_static_proxy_class_name(
@@ -447,46 +470,57 @@ unsafe extern "C" fn ext_add_static_event_listener(
     argc: ::std::os::raw::c_int,
     argv: *mut q::JSValue,
 ) -> q::JSValue {
-    let res = QuickJsRealmAdapter::with_context(ctx, |q_ctx| {
-        let args = parse_args(ctx, argc, argv);
-
+    let native_name = {
         let this_ref =
-            QuickJsValueAdapter::new(ctx, this_val, true, true, "add_event_listener_this");
-
-        let proxy_name = get_static_proxy_class_name(q_ctx, &this_ref);
-
-        if args.len() < 2 || !args[0].is_string() || !functions::is_function_q(q_ctx, &args[1]) {
-            Err(JsError::new_str("addEventListener requires at least 2 arguments (eventId: String and Listener: Function"))
-        } else {
-            let event_id = primitives::to_string_q(q_ctx, &args[0])?;
-            let listener_func = args[1].clone();
-
-            // use the passed options arg or create a new obj
-            let options_obj = if args.len() == 3 && args[2].is_object() {
-                args[2].clone()
+            QuickJsValueAdapter::new(ctx, this_val, false, false, "addEventListener_static_this");
+        format!(
+            "Proxy {} static method [addEventListener]",
+            get_static_class_name(ctx, &this_ref)
+        )
+    };
+    errors::catch_native_panic(ctx, native_name.as_str(), || {
+        let res = QuickJsRealmAdapter::with_context(ctx, |q_ctx| {
+            let args = parse_args(ctx, argc, argv);
+
+            let this_ref =
+                QuickJsValueAdapter::new(ctx, this_val, true, true, "add_event_listener_this");
+
+            let proxy_name = get_static_proxy_class_name(q_ctx, &this_ref);
+
+            if args.len() < 2 || !args[0].is_string() || !functions::is_function_q(q_ctx, &args[1]) {
+                Err(JsError::new_str("addEventListener requires at least 2 arguments (eventId: String and Listener: Function"))
             } else {
-                create_object_q(q_ctx)?
-            };
-            // if the third args was a boolean then set that bool as the capture option
-            if args.len() == 3 && args[2].is_bool() {
-                set_property_q(q_ctx, &options_obj, "capture", &args[2])?;
-            }
+                let event_id = primitives::to_string_q(q_ctx, &args[0])?;
+                let listener_func = args[1].clone();
+
+                // use the passed options arg or create a new obj
+                let options_obj = if args.len() == 3 && args[2].is_object() {
+                    args[2].clone()
+                } else {
+                    create_object_q(q_ctx)?
+                };
+                // if the third args was a boolean then set that bool as the capture option
+                if args.len() == 3 && args[2].is_bool() {
+                    set_property_q(q_ctx, &options_obj, "capture", &args[2])?;
+                }
 
-            add_static_event_listener(
-                q_ctx,
-                proxy_name.as_str(),
-                event_id.as_str(),
-                listener_func,
-                options_obj,
-            );
+                add_static_event_listener(
+                    q_ctx,
+                    proxy_name.as_str(),
+                    event_id.as_str(),
+                    listener_func,
+                    options_obj,
+                );
 
-            Ok(())
+                Ok(())
+            }
+        });
+        match res {
+            Ok(_) => quickjs_utils::new_null(),
+            Err(e) => QuickJsRealmAdapter::report_ex_ctx(ctx, format!("{e}").as_str()),
         }
-    });
-    match res {
-        Ok(_) => quickjs_utils::new_null(),
-        Err(e) => QuickJsRealmAdapter::report_ex_ctx(ctx, format!("{e}").as_str()),
-    }
+    })
+    .unwrap_or_else(|ex| ex)
 }
 
 unsafe extern "C" fn ext_remove_static_event_listener(
@@ -495,34 +529,50 @@ unsafe extern "C" fn ext_remove_static_event_listener(
     argc: ::std::os::raw::c_int,
     argv: *mut q::JSValue,
 ) -> q::JSValue {
-    let res = QuickJsRealmAdapter::with_context(ctx, |q_ctx| {
-        let args = parse_args(ctx, argc, argv);
-
-        let this_ref =
-            QuickJsValueAdapter::new(ctx, this_val, true, true, "remove_event_listener_this");
-
-        let proxy_name = get_static_proxy_class_name(q_ctx, &this_ref);
-
-        if args.len() != 2 || !args[0].is_string() || !functions::is_function_q(q_ctx, &args[1]) {
-            Err(JsError::new_str("removeEventListener requires at least 2 arguments (eventId: String and Listener: Function"))
-        } else {
-            let event_id = primitives::to_string_q(q_ctx, &args[0])?;
-            let listener_func = args[1].clone();
+    let native_name = {
+        let this_ref = QuickJsValueAdapter::new(
+            ctx,
+            this_val,
+            false,
+            false,
+            "removeEventListener_static_this",
+        );
+        format!(
+            "Proxy {} static method [removeEventListener]",
+            get_static_class_name(ctx, &this_ref)
+        )
+    };
+    errors::catch_native_panic(ctx, native_name.as_str(), || {
+        let res = QuickJsRealmAdapter::with_context(ctx, |q_ctx| {
+            let args = parse_args(ctx, argc, argv);
+
+            let this_ref =
+                QuickJsValueAdapter::new(ctx, this_val, true, true, "remove_event_listener_this");
+
+            let proxy_name = get_static_proxy_class_name(q_ctx, &this_ref);
+
+            if args.len() != 2 || !args[0].is_string() || !functions::is_function_q(q_ctx, &args[1]) {
+                Err(JsError::new_str("removeEventListener requires at least 2 arguments (eventId: String and Listener: Function"))
+            } else {
+                let event_id = primitives::to_string_q(q_ctx, &args[0])?;
+                let listener_func = args[1].clone();
 
-            remove_static_event_listener(
-                q_ctx,
-                proxy_name.as_str(),
-                event_id.as_str(),
-                &listener_func,
-            );
+                remove_static_event_listener(
+                    q_ctx,
+                    proxy_name.as_str(),
+                    event_id.as_str(),
+                    &listener_func,
+                );
 
-            Ok(())
+                Ok(())
+            }
+        });
+        match res {
+            Ok(_) => quickjs_utils::new_null(),
+            Err(e) => QuickJsRealmAdapter::report_ex_ctx(ctx, format!("{e}").as_str()),
         }
-    });
-    match res {
-        Ok(_) => quickjs_utils::new_null(),
-        Err(e) => QuickJsRealmAdapter::report_ex_ctx(ctx, format!("{e}").as_str()),
-    }
+    })
+    .unwrap_or_else(|ex| ex)
 }
 
 unsafe extern "C" fn ext_dispatch_static_event(
@@ -531,35 +581,46 @@ unsafe extern "C" fn ext_dispatch_static_event(
     argc: ::std::os::raw::c_int,
     argv: *mut q::JSValue,
 ) -> q::JSValue {
-    let res = QuickJsRealmAdapter::with_context(ctx, |q_ctx| {
-        let args = parse_args(ctx, argc, argv);
-
+    let native_name = {
         let this_ref =
-            QuickJsValueAdapter::new(ctx, this_val, true, true, "remove_event_listener_this");
-
-        let proxy_name = get_static_proxy_class_name(q_ctx, &this_ref);
-
-        if args.len() != 2 || !args[0].is_string() {
-            Err(JsError::new_str(
-                "dispatchEvent requires at least 2 arguments (eventId: String and eventObj: Object)",
-            ))
-        } else {
-            let event_id = primitives::to_string_q(q_ctx, &args[0])?;
-            let evt_obj = args[1].clone();
+            QuickJsValueAdapter::new(ctx, this_val, false, false, "dispatchEvent_static_this");
+        format!(
+            "Proxy {} static method [dispatchEvent]",
+            get_static_class_name(ctx, &this_ref)
+        )
+    };
+    errors::catch_native_panic(ctx, native_name.as_str(), || {
+        let res = QuickJsRealmAdapter::with_context(ctx, |q_ctx| {
+            let args = parse_args(ctx, argc, argv);
+
+            let this_ref =
+                QuickJsValueAdapter::new(ctx, this_val, true, true, "remove_event_listener_this");
+
+            let proxy_name = get_static_proxy_class_name(q_ctx, &this_ref);
+
+            if args.len() != 2 || !args[0].is_string() {
+                Err(JsError::new_str(
+                    "dispatchEvent requires at least 2 arguments (eventId: String and eventObj: Object)",
+                ))
+            } else {
+                let event_id = primitives::to_string_q(q_ctx, &args[0])?;
+                let evt_obj = args[1].clone();
 
-            let res =
-                dispatch_static_event(q_ctx, proxy_name.as_str(), event_id.as_str(), evt_obj)?;
+                let res =
+                    dispatch_static_event(q_ctx, proxy_name.as_str(), event_id.as_str(), evt_obj)?;
 
-            Ok(res)
-        }
-    });
-    match res {
-        Ok(res) => {
-            let b_ref = from_bool(res);
-            b_ref.clone_value_incr_rc()
+                Ok(res)
+            }
+        });
+        match res {
+            Ok(res) => {
+                let b_ref = from_bool(res);
+                b_ref.clone_value_incr_rc()
+            }
+            Err(e) => QuickJsRealmAdapter::report_ex_ctx(ctx, format!("{e}").as_str()),
         }
-        Err(e) => QuickJsRealmAdapter::report_ex_ctx(ctx, format!("{e}").as_str()),
-    }
+    })
+    .unwrap_or_else(|ex| ex)
 }
 
 #[cfg(test)]
@@ -633,6 +694,64 @@ pub mod tests {
         assert_eq!(ct, 1);
     }
 
+    #[test]
+    fn test_event_target_panic() {
+        use crate::builder::QuickJsRuntimeBuilder;
+        use crate::quickjs_utils::functions::new_function_q;
+        use crate::quickjs_utils::objects::set_property_q;
+
+        let reported = Arc::new(Mutex::new(vec![]));
+        let reported2 = reported.clone();
+        let rt = QuickJsRuntimeBuilder::new()
+            .set_panic_handler(move |_rt, _realm, native_name, _msg| {
+                reported2.lock().unwrap().push(native_name.to_string());
+            })
+            .build();
+        rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_main_realm();
+            Proxy::new()
+                .constructor(|_rt, _q_ctx, _id, _args| Ok(()))
+                .name("Alarm")
+                .event_target()
+                .static_event_target()
+                .install(q_ctx, true)
+                .expect("proxy failed");
+            let listener = new_function_q(
+                q_ctx,
+                "panickingListener",
+                |_q_ctx, _this, _args| panic!("poof"),
+                1,
+            )
+            .expect("could not create function");
+            set_property_q(q_ctx, &get_global_q(q_ctx), "panickingListener", &listener)
+                .expect("could not set listener");
+
+            let err = q_ctx
+                .eval(Script::new(
+                    "test_event_target_panic.es",
+                    "let alarm = new Alarm();\nalarm.addEventListener('ring', panickingListener);\nalarm.dispatchEvent('ring', {});",
+                ))
+                .expect_err("script passed");
+            assert_eq!(err.get_name(), "InternalError");
+
+            // the name of a static event target is read in the trampoline, a throwing getter made it panic
+            let err = q_ctx
+                .eval(Script::new(
+                    "test_event_target_panic2.es",
+                    "Alarm.addEventListener.call({get name() { throw new Error('no name'); }}, 'ring', () => {});",
+                ))
+                .expect_err("script passed");
+            assert_eq!(err.get_name(), "InternalError");
+        });
+        assert_eq!(
+            *reported.lock().unwrap(),
+            vec![
+                "panickingListener".to_string(),
+                "Proxy unknown static method [addEventListener]".to_string()
+            ]
+        );
+    }
+
     #[test]
     fn test_proxy_eh_rcs() {
         let rt = init_test_rt();
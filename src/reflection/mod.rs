@@ -15,6 +15,8 @@ use rand::{thread_rng, Rng};
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::os::raw::{c_char, c_void};
+use std::panic;
+use std::panic::AssertUnwindSafe;
 use std::rc::Rc;
 
 pub type JsProxyInstanceId = usize;
@@ -92,7 +94,7 @@ thread_local! {
         get_own_property_names: None,
         delete_property: None,
         define_own_property: None,
-        has_property: Some(proxy_static_has_prop),
+        has_property: None,
         get_property: Some(proxy_static_get_prop),
         set_property: Some(proxy_static_set_prop),
     });
@@ -102,7 +104,7 @@ thread_local! {
         get_own_property_names: None,
         delete_property: None,
         define_own_property: None,
-        has_property: Some(proxy_instance_has_prop),
+        has_property: None,
         get_property: Some(proxy_instance_get_prop),
         set_property: Some(proxy_instance_set_prop),
     });
@@ -897,48 +899,55 @@ unsafe extern "C" fn constructor(
         false,
         "reflection::constructor this_val",
     );
-    QuickJsRuntimeAdapter::do_with(|q_js_rt| {
-        let name_ref = objects::get_property(context, &this_ref, "name").expect("name get failed");
-        let class_name =
-            functions::call_to_string(context, &name_ref).expect("name.toString failed");
-
-        let q_ctx = q_js_rt.get_quickjs_context(context);
-
-        let registry = &*q_ctx.proxy_registry.borrow();
-        if let Some(proxy) = registry.get(&class_name) {
-            if let Some(constructor) = &proxy.constructor {
-                // construct
-
-                let args_vec = parse_args(context, argc, argv);
-                let instance_id = next_id(proxy);
-                let constructor_res = constructor(q_js_rt, q_ctx, instance_id, &args_vec);
-
-                match constructor_res {
-                    Ok(()) => {
-                        let instance_ref_res = new_instance3(proxy, instance_id, q_ctx);
-
-                        match instance_ref_res {
-                            Ok(instance_ref) => instance_ref.clone_value_incr_rc(),
-
-                            Err(e) => q_ctx.report_ex(
-                                format!(
-                                    "could not create proxy instance for {class_name} due to {e}"
-                                )
-                                .as_str(),
-                            ),
+    let native_name = format!(
+        "Proxy {} constructor",
+        get_static_class_name(context, &this_ref)
+    );
+    errors::catch_native_panic(context, native_name.as_str(), || {
+        QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+            let name_ref = objects::get_property(context, &this_ref, "name").expect("name get failed");
+            let class_name =
+                functions::call_to_string(context, &name_ref).expect("name.toString failed");
+
+            let q_ctx = q_js_rt.get_quickjs_context(context);
+
+            let registry = &*q_ctx.proxy_registry.borrow();
+            if let Some(proxy) = registry.get(&class_name) {
+                if let Some(constructor) = &proxy.constructor {
+                    // construct
+
+                    let args_vec = parse_args(context, argc, argv);
+                    let instance_id = next_id(proxy);
+                    let constructor_res = constructor(q_js_rt, q_ctx, instance_id, &args_vec);
+
+                    match constructor_res {
+                        Ok(()) => {
+                            let instance_ref_res = new_instance3(proxy, instance_id, q_ctx);
+
+                            match instance_ref_res {
+                                Ok(instance_ref) => instance_ref.clone_value_incr_rc(),
+
+                                Err(e) => q_ctx.report_ex(
+                                    format!(
+                                        "could not create proxy instance for {class_name} due to {e}"
+                                    )
+                                    .as_str(),
+                                ),
+                            }
                         }
+                        Err(es_err) => q_ctx.report_ex(
+                            format!("constructor for {class_name} failed with {es_err}").as_str(),
+                        ),
                     }
-                    Err(es_err) => q_ctx.report_ex(
-                        format!("constructor for {class_name} failed with {es_err}").as_str(),
-                    ),
+                } else {
+                    q_ctx.report_ex("not a constructor")
                 }
             } else {
-                q_ctx.report_ex("not a constructor")
+                q_ctx.report_ex("no such proxy")
             }
-        } else {
-            q_ctx.report_ex("no such proxy")
-        }
+        })
     })
+    .unwrap_or_else(|ex| ex)
 }
 
 pub(crate) struct ProxyInstanceInfo {
@@ -954,6 +963,27 @@ fn get_proxy_instance_info(val: &q::JSValue) -> &ProxyInstanceInfo {
     info
 }
 
+/// the class name of a proxy instance for reporting panics, unlike get_proxy_instance_info this does not fail on other objects
+fn get_instance_class_name(val: &q::JSValue) -> String {
+    let class_id = PROXY_INSTANCE_CLASS_ID.with(|rc| *rc.borrow());
+    let info_ptr = unsafe { q::JS_GetOpaque(*val, class_id) } as *const ProxyInstanceInfo;
+    if info_ptr.is_null() {
+        "unknown".to_string()
+    } else {
+        unsafe { (*info_ptr).class_name.clone() }
+    }
+}
+
+/// the class name of a static proxy (the name of the constructor function) for reporting panics
+unsafe fn get_static_class_name(context: *mut q::JSContext, proxy: &QuickJsValueAdapter) -> String {
+    // the name may be a getter which throws, to_string only accepts strings
+    objects::get_property(context, proxy, "name")
+        .ok()
+        .filter(|name| name.is_string())
+        .and_then(|name| primitives::to_string(context, &name).ok())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
 #[allow(dead_code)]
 unsafe extern "C" fn finalizer(_rt: *mut q::JSRuntime, val: q::JSValue) {
     log::trace!("finalizer called");
@@ -974,7 +1004,17 @@ unsafe extern "C" fn finalizer(_rt: *mut q::JSRuntime, val: q::JSValue) {
 
         for finalizer in &proxy.finalizers {
             log::trace!("calling Proxy's finalizer");
-            finalizer(q_js_rt, q_ctx, info.id);
+            // a finalizer can not throw so a panic is only reported
+            if let Err(payload) =
+                panic::catch_unwind(AssertUnwindSafe(|| finalizer(q_js_rt, q_ctx, info.id)))
+            {
+                let msg = errors::panic_message(payload.as_ref());
+                log::error!("finalizer of {} panicked: {}", info.class_name, msg);
+                if let Some(panic_handler) = &q_js_rt.panic_handler {
+                    let native_name = format!("Proxy {} finalizer", info.class_name);
+                    panic_handler(q_js_rt, q_ctx, native_name.as_str(), msg.as_str());
+                }
+            }
             log::trace!("after calling Proxy's finalizer");
         }
 
@@ -1015,86 +1055,96 @@ unsafe extern "C" fn proxy_static_get_prop(
         "reflection::proxy_static_get_prop receiver",
     );
 
-    QuickJsRuntimeAdapter::do_with(|q_js_rt| {
-        let q_ctx = q_js_rt.get_quickjs_context(context);
-
-        let proxy_name_ref = objects::get_property(context, &receiver_ref, "name")
-            .ok()
-            .unwrap();
-        let proxy_name = primitives::to_string(context, &proxy_name_ref)
-            .ok()
-            .unwrap();
-        trace!("proxy_static_get_prop: {}", proxy_name);
-
-        let prop_name = atoms::to_str(context, &atom).expect("could not get name");
-        trace!("proxy_static_get_prop: prop: {}", prop_name);
-
-        let registry = &*q_ctx.proxy_registry.borrow();
-        if let Some(proxy) = registry.get(proxy_name.as_str()) {
-            if proxy.static_methods.contains_key(prop_name) {
-                trace!("found method for {}", prop_name);
-
-                let function_data_ref =
-                    from_string(context, prop_name).expect("could not create function_data_ref");
-
-                let func_ref = functions::new_native_function_data(
-                    context,
-                    Some(proxy_static_method),
-                    prop_name,
-                    1,
-                    function_data_ref,
-                )
-                .expect("could not create func");
-
-                objects::set_property(context, &receiver_ref, prop_name, &func_ref)
-                    .expect("set_property 9656738 failed");
-
-                func_ref.clone_value_incr_rc()
-            } else if let Some(native_static_method) = proxy.static_native_methods.get(prop_name) {
-                trace!("found static native method for {}", prop_name);
-
-                let func_ref = functions::new_native_function(
-                    context,
-                    prop_name,
-                    *native_static_method,
-                    1,
-                    false,
-                )
-                .expect("could not create func");
-
-                objects::set_property(context, &receiver_ref, prop_name, &func_ref)
-                    .expect("set_property 36099 failed");
-
-                func_ref.clone_value_incr_rc()
-            } else if let Some(getter_setter) = proxy.static_getters_setters.get(prop_name) {
-                // call the getter
-                let getter = &getter_setter.0;
-                let res: Result<QuickJsValueAdapter, JsError> = getter(q_js_rt, q_ctx);
-                match res {
-                    Ok(g_val) => g_val.clone_value_incr_rc(),
-                    Err(e) => {
-                        let es = format!("proxy_static_get_prop failed: {e}");
-                        q_ctx.report_ex(es.as_str())
+    let native_name = format!(
+        "Proxy {} static getter",
+        get_static_class_name(context, &receiver_ref)
+    );
+    errors::catch_native_panic(context, native_name.as_str(), || {
+        QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+            let q_ctx = q_js_rt.get_quickjs_context(context);
+
+            let proxy_name_ref = objects::get_property(context, &receiver_ref, "name")
+                .ok()
+                .unwrap();
+            let proxy_name = primitives::to_string(context, &proxy_name_ref)
+                .ok()
+                .unwrap();
+            trace!("proxy_static_get_prop: {}", proxy_name);
+
+            let prop_name = atoms::to_str(context, &atom).expect("could not get name");
+            trace!("proxy_static_get_prop: prop: {}", prop_name);
+
+            let registry = &*q_ctx.proxy_registry.borrow();
+            if let Some(proxy) = registry.get(proxy_name.as_str()) {
+                if proxy.static_methods.contains_key(prop_name) {
+                    trace!("found method for {}", prop_name);
+
+                    let function_data_ref = from_string(context, prop_name)
+                        .expect("could not create function_data_ref");
+
+                    let func_ref = functions::new_native_function_data(
+                        context,
+                        Some(proxy_static_method),
+                        prop_name,
+                        1,
+                        function_data_ref,
+                    )
+                    .expect("could not create func");
+
+                    objects::set_property(context, &receiver_ref, prop_name, &func_ref)
+                        .expect("set_property 9656738 failed");
+
+                    func_ref.clone_value_incr_rc()
+                } else if let Some(native_static_method) =
+                    proxy.static_native_methods.get(prop_name)
+                {
+                    trace!("found static native method for {}", prop_name);
+
+                    let func_ref = functions::new_native_function(
+                        context,
+                        prop_name,
+                        *native_static_method,
+                        1,
+                        false,
+                    )
+                    .expect("could not create func");
+
+                    objects::set_property(context, &receiver_ref, prop_name, &func_ref)
+                        .expect("set_property 36099 failed");
+
+                    func_ref.clone_value_incr_rc()
+                } else if let Some(getter_setter) = proxy.static_getters_setters.get(prop_name) {
+                    // call the getter
+                    let getter = &getter_setter.0;
+                    let res: Result<QuickJsValueAdapter, JsError> = getter(q_js_rt, q_ctx);
+                    match res {
+                        Ok(g_val) => g_val.clone_value_incr_rc(),
+                        Err(e) => {
+                            let es = format!("proxy_static_get_prop failed: {e}");
+                            q_ctx.report_ex(es.as_str())
+                        }
                     }
-                }
-            } else if let Some(catch_all_getter_setter) = &proxy.static_catch_all {
-                // call the getter
-                let getter = &catch_all_getter_setter.0;
-                let res: Result<QuickJsValueAdapter, JsError> = getter(q_js_rt, q_ctx, prop_name);
-                match res {
-                    Ok(g_val) => g_val.clone_value_incr_rc(),
-                    Err(e) => {
-                        let es = format!("proxy_static_get_prop failed: {e}");
-                        q_ctx.report_ex(es.as_str())
+                } else if let Some(catch_all_getter_setter) = &proxy.static_catch_all {
+                    // call the getter
+                    let getter = &catch_all_getter_setter.0;
+                    let res: Result<QuickJsValueAdapter, JsError> =
+                        getter(q_js_rt, q_ctx, prop_name);
+                    match res {
+                        Ok(g_val) => g_val.clone_value_incr_rc(),
+                        Err(e) => {
+                            let es = format!("proxy_static_get_prop failed: {e}");
+                            q_ctx.report_ex(es.as_str())
+                        }
                     }
+                } else {
+                    quickjs_utils::new_undefined()
                 }
             } else {
-                quickjs_utils::new_undefined()
+                q_ctx.report_ex("proxy class not found")
             }
-        } else {
-            q_ctx.report_ex("proxy class not found")
-        }
+        })
     })
+    .unwrap_or_else(|ex| ex)
 }
 
 #[allow(dead_code)]
@@ -1121,117 +1171,113 @@ unsafe extern "C" fn proxy_instance_get_prop(
         "reflection::proxy_instance_get_prop receiver",
     );
 
-    QuickJsRuntimeAdapter::do_with(|q_js_rt| {
-        let q_ctx = q_js_rt.get_quickjs_context(context);
+    let native_name = format!("Proxy {} instance getter", get_instance_class_name(&obj));
+    errors::catch_native_panic(context, native_name.as_str(), || {
+        QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+            let q_ctx = q_js_rt.get_quickjs_context(context);
 
-        let prop_name = atoms::to_str(context, &atom).expect("could not get name");
-        trace!("proxy_instance_get_prop: {}", prop_name);
+            let prop_name = atoms::to_str(context, &atom).expect("could not get name");
+            trace!("proxy_instance_get_prop: {}", prop_name);
 
-        let info = get_proxy_instance_info(&obj);
+            let info = get_proxy_instance_info(&obj);
 
-        trace!("obj_ref.classname = {}", info.class_name);
+            trace!("obj_ref.classname = {}", info.class_name);
 
-        // see if we have a matching method
+            // see if we have a matching method
 
-        let registry = &*q_ctx.proxy_registry.borrow();
-        let proxy = registry.get(&info.class_name).unwrap();
-        if proxy.methods.contains_key(prop_name) {
-            trace!("found method for {}", prop_name);
+            let registry = &*q_ctx.proxy_registry.borrow();
+            let proxy = registry.get(&info.class_name).unwrap();
+            if proxy.methods.contains_key(prop_name) {
+                trace!("found method for {}", prop_name);
 
-            let function_data_ref =
-                from_string(context, prop_name).expect("could not create function_data_ref");
+                let function_data_ref =
+                    from_string(context, prop_name).expect("could not create function_data_ref");
 
-            let func_ref = functions::new_native_function_data(
-                context,
-                Some(proxy_instance_method),
-                prop_name,
-                1,
-                function_data_ref,
-            )
-            .expect("could not create func");
+                let func_ref = functions::new_native_function_data(
+                    context,
+                    Some(proxy_instance_method),
+                    prop_name,
+                    1,
+                    function_data_ref,
+                )
+                .expect("could not create func");
 
-            objects::set_property(context, &receiver_ref, prop_name, &func_ref)
-                .expect("set_property 96385 failed"); // todo report ex
+                objects::set_property(context, &receiver_ref, prop_name, &func_ref)
+                    .expect("set_property 96385 failed"); // todo report ex
 
-            func_ref.clone_value_incr_rc()
-        } else if let Some(native_method) = proxy.native_methods.get(prop_name) {
-            trace!("found native method for {}", prop_name);
+                func_ref.clone_value_incr_rc()
+            } else if let Some(native_method) = proxy.native_methods.get(prop_name) {
+                trace!("found native method for {}", prop_name);
 
-            let func_ref =
-                functions::new_native_function(context, prop_name, *native_method, 1, false)
-                    .expect("could not create func"); // tyodo report ex
+                let func_ref =
+                    functions::new_native_function(context, prop_name, *native_method, 1, false)
+                        .expect("could not create func"); // tyodo report ex
 
-            objects::set_property(context, &receiver_ref, prop_name, &func_ref)
-                .expect("set_property 49671 failed"); // todo report ex
+                objects::set_property(context, &receiver_ref, prop_name, &func_ref)
+                    .expect("set_property 49671 failed"); // todo report ex
 
-            func_ref.clone_value_incr_rc()
-        } else if let Some(getter_setter) = proxy.getters_setters.get(prop_name) {
-            // call the getter
-            let getter = &getter_setter.0;
-            let res: Result<QuickJsValueAdapter, JsError> = getter(q_js_rt, q_ctx, &info.id);
-            match res {
-                Ok(g_val) => g_val.clone_value_incr_rc(),
-                Err(e) => {
-                    let msg = format!("proxy_instance_get failed: {}", e.get_message());
-                    let nat_stack = format!(
-                        "    at Proxy instance getter [{}]\n{}",
-                        prop_name,
-                        e.get_stack()
-                    );
-                    let err =
-                        errors::new_error(context, e.get_name(), msg.as_str(), nat_stack.as_str())
-                            .expect("create error failed");
-                    errors::throw(context, err)
+                func_ref.clone_value_incr_rc()
+            } else if let Some(getter_setter) = proxy.getters_setters.get(prop_name) {
+                // call the getter
+                let getter = &getter_setter.0;
+                let res: Result<QuickJsValueAdapter, JsError> = getter(q_js_rt, q_ctx, &info.id);
+                match res {
+                    Ok(g_val) => g_val.clone_value_incr_rc(),
+                    Err(e) => {
+                        let msg = format!("proxy_instance_get failed: {}", e.get_message());
+                        let nat_stack = format!(
+                            "    at Proxy instance getter [{}]\n{}",
+                            prop_name,
+                            e.get_stack()
+                        );
+                        let err = errors::new_error(
+                            context,
+                            e.get_name(),
+                            msg.as_str(),
+                            nat_stack.as_str(),
+                        )
+                        .expect("create error failed");
+                        errors::throw(context, err)
+                    }
                 }
-            }
-        } else if let Some(catch_all_getter_setter) = &proxy.catch_all {
-            // call the getter
-            let getter = &catch_all_getter_setter.0;
-            let res: Result<QuickJsValueAdapter, JsError> =
-                getter(q_js_rt, q_ctx, &info.id, prop_name);
-            match res {
-                Ok(g_val) => g_val.clone_value_incr_rc(),
-                Err(e) => {
-                    let msg = format!("proxy_instance_catch_all_get failed: {}", e.get_message());
-                    let nat_stack = format!(
-                        "    at Proxy instance getter [{}]\n{}",
-                        prop_name,
-                        e.get_stack()
-                    );
-                    let err =
-                        errors::new_error(context, e.get_name(), msg.as_str(), nat_stack.as_str())
-                            .expect("create error failed");
-                    errors::throw(context, err)
+            } else if let Some(catch_all_getter_setter) = &proxy.catch_all {
+                // call the getter
+                let getter = &catch_all_getter_setter.0;
+                let res: Result<QuickJsValueAdapter, JsError> =
+                    getter(q_js_rt, q_ctx, &info.id, prop_name);
+                match res {
+                    Ok(g_val) => g_val.clone_value_incr_rc(),
+                    Err(e) => {
+                        let msg =
+                            format!("proxy_instance_catch_all_get failed: {}", e.get_message());
+                        let nat_stack = format!(
+                            "    at Proxy instance getter [{}]\n{}",
+                            prop_name,
+                            e.get_stack()
+                        );
+                        let err = errors::new_error(
+                            context,
+                            e.get_name(),
+                            msg.as_str(),
+                            nat_stack.as_str(),
+                        )
+                        .expect("create error failed");
+                        errors::throw(context, err)
+                    }
                 }
+            } else {
+                // return null if nothing was returned
+                quickjs_utils::new_undefined()
             }
-        } else {
-            // return null if nothing was returned
-            quickjs_utils::new_undefined()
-        }
+        })
     })
+    .unwrap_or_else(|ex| ex)
 
     // get constructor name
     // get proxy
     // get method or getter or setter
     // return native func (cache those?)
 }
-#[allow(dead_code)]
-unsafe extern "C" fn proxy_instance_has_prop(
-    _context: *mut q::JSContext,
-    _obj: q::JSValue,
-    _atom: q::JSAtom,
-) -> ::std::os::raw::c_int {
-    todo!()
-}
-#[allow(dead_code)]
-unsafe extern "C" fn proxy_static_has_prop(
-    _context: *mut q::JSContext,
-    _obj: q::JSValue,
-    _atom: q::JSAtom,
-) -> ::std::os::raw::c_int {
-    todo!()
-}
-
 unsafe extern "C" fn proxy_instance_method(
     context: *mut q::JSContext,
     this_val: q::JSValue,
@@ -1241,54 +1287,65 @@ unsafe extern "C" fn proxy_instance_method(
     func_data: *mut q::JSValue,
 ) -> q::JSValue {
     trace!("proxy_instance_method");
-    QuickJsRuntimeAdapter::do_with(|q_js_rt| {
-        let q_ctx = q_js_rt.get_quickjs_context(context);
-
-        let proxy_instance_info: &ProxyInstanceInfo = get_proxy_instance_info(&this_val);
+    let native_name = format!(
+        "Proxy {} instance method",
+        get_instance_class_name(&this_val)
+    );
+    errors::catch_native_panic(context, native_name.as_str(), || {
+        QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+            let q_ctx = q_js_rt.get_quickjs_context(context);
 
-        let args_vec = parse_args(context, argc, argv);
+            let proxy_instance_info: &ProxyInstanceInfo = get_proxy_instance_info(&this_val);
 
-        let func_name_ref = QuickJsValueAdapter::new(
-            context,
-            *func_data,
-            false,
-            false,
-            "reflection::proxy_instance_method func_data",
-        );
-        let func_name = primitives::to_string(context, &func_name_ref)
-            .expect("could not to_string func_name_ref");
+            let args_vec = parse_args(context, argc, argv);
 
-        trace!("proxy_instance_method: {}", func_name);
-
-        let registry = &*q_ctx.proxy_registry.borrow();
-        let proxy = registry
-            .get(proxy_instance_info.class_name.as_str())
-            .unwrap();
-        if let Some(method) = proxy.methods.get(func_name.as_str()) {
-            // todo report ex
-            let m_res: Result<QuickJsValueAdapter, JsError> =
-                method(q_js_rt, q_ctx, &proxy_instance_info.id, &args_vec);
-
-            match m_res {
-                Ok(m_res_ref) => m_res_ref.clone_value_incr_rc(),
-                Err(e) => {
-                    let msg = format!("proxy_instance_method failed: {}", e.get_message());
-                    let nat_stack = format!(
-                        "    at Proxy instance method [{}]\n{}",
-                        func_name,
-                        e.get_stack()
-                    );
-                    let err =
-                        errors::new_error(context, e.get_name(), msg.as_str(), nat_stack.as_str())
-                            .expect("create error failed");
-                    errors::throw(context, err)
+            let func_name_ref = QuickJsValueAdapter::new(
+                context,
+                *func_data,
+                false,
+                false,
+                "reflection::proxy_instance_method func_data",
+            );
+            let func_name = primitives::to_string(context, &func_name_ref)
+                .expect("could not to_string func_name_ref");
+
+            trace!("proxy_instance_method: {}", func_name);
+
+            let registry = &*q_ctx.proxy_registry.borrow();
+            let proxy = registry
+                .get(proxy_instance_info.class_name.as_str())
+                .unwrap();
+            if let Some(method) = proxy.methods.get(func_name.as_str()) {
+                // todo report ex
+                let m_res: Result<QuickJsValueAdapter, JsError> =
+                    method(q_js_rt, q_ctx, &proxy_instance_info.id, &args_vec);
+
+                match m_res {
+                    Ok(m_res_ref) => m_res_ref.clone_value_incr_rc(),
+                    Err(e) => {
+                        let msg = format!("proxy_instance_method failed: {}", e.get_message());
+                        let nat_stack = format!(
+                            "    at Proxy instance method [{}]\n{}",
+                            func_name,
+                            e.get_stack()
+                        );
+                        let err = errors::new_error(
+                            context,
+                            e.get_name(),
+                            msg.as_str(),
+                            nat_stack.as_str(),
+                        )
+                        .expect("create error failed");
+                        errors::throw(context, err)
+                    }
                 }
+            } else {
+                // return null if nothing was returned
+                quickjs_utils::new_undefined()
             }
-        } else {
-            // return null if nothing was returned
-            quickjs_utils::new_undefined()
-        }
+        })
     })
+    .unwrap_or_else(|ex| ex)
 }
 
 #[allow(dead_code)]
@@ -1301,60 +1358,71 @@ unsafe extern "C" fn proxy_static_method(
     func_data: *mut q::JSValue,
 ) -> q::JSValue {
     trace!("proxy_static_method");
-    QuickJsRuntimeAdapter::do_with(|q_js_rt| {
-        let q_ctx = q_js_rt.get_quickjs_context(context);
-        let this_ref = QuickJsValueAdapter::new(
-            context,
-            this_val,
-            false,
-            false,
-            "reflection::proxy_static_method this_val",
-        );
+    let this_ref = QuickJsValueAdapter::new(
+        context,
+        this_val,
+        false,
+        false,
+        "reflection::proxy_static_method this_val",
+    );
+    let native_name = format!(
+        "Proxy {} static method",
+        get_static_class_name(context, &this_ref)
+    );
+    errors::catch_native_panic(context, native_name.as_str(), || {
+        QuickJsRuntimeAdapter::do_with(|q_js_rt| {
+            let q_ctx = q_js_rt.get_quickjs_context(context);
 
-        let proxy_name_ref = objects::get_property(context, &this_ref, "name")
-            .ok()
-            .unwrap();
-        let proxy_name =
-            primitives::to_string(context, &proxy_name_ref).expect("could not to_string classname");
+            let proxy_name_ref = objects::get_property(context, &this_ref, "name")
+                .ok()
+                .unwrap();
+            let proxy_name = primitives::to_string(context, &proxy_name_ref)
+                .expect("could not to_string classname");
 
-        let args_vec = parse_args(context, argc, argv);
+            let args_vec = parse_args(context, argc, argv);
 
-        let func_name_ref = QuickJsValueAdapter::new(
-            context,
-            *func_data,
-            false,
-            false,
-            "reflection::proxy_static_method func_data",
-        );
-        let func_name = primitives::to_string(context, &func_name_ref)
-            .expect("could not to_string func_name_ref");
+            let func_name_ref = QuickJsValueAdapter::new(
+                context,
+                *func_data,
+                false,
+                false,
+                "reflection::proxy_static_method func_data",
+            );
+            let func_name = primitives::to_string(context, &func_name_ref)
+                .expect("could not to_string func_name_ref");
 
-        trace!("proxy_static_method: {}", func_name);
+            trace!("proxy_static_method: {}", func_name);
 
-        let registry = &*q_ctx.proxy_registry.borrow();
-        let proxy = registry.get(proxy_name.as_str()).unwrap();
-        if let Some(method) = proxy.static_methods.get(func_name.as_str()) {
-            let m_res: Result<QuickJsValueAdapter, JsError> = method(q_js_rt, q_ctx, &args_vec);
-            match m_res {
-                Ok(m_res_ref) => m_res_ref.clone_value_incr_rc(),
-                Err(e) => {
-                    let msg = format!("proxy_static_method failed: {}", e.get_message());
-                    let nat_stack = format!(
-                        "    at Proxy static method [{}]\n{}",
-                        func_name,
-                        e.get_stack()
-                    );
-                    let err =
-                        errors::new_error(context, e.get_name(), msg.as_str(), nat_stack.as_str())
-                            .expect("create error failed");
-                    errors::throw(context, err)
+            let registry = &*q_ctx.proxy_registry.borrow();
+            let proxy = registry.get(proxy_name.as_str()).unwrap();
+            if let Some(method) = proxy.static_methods.get(func_name.as_str()) {
+                let m_res: Result<QuickJsValueAdapter, JsError> = method(q_js_rt, q_ctx, &args_vec);
+                match m_res {
+                    Ok(m_res_ref) => m_res_ref.clone_value_incr_rc(),
+                    Err(e) => {
+                        let msg = format!("proxy_static_method failed: {}", e.get_message());
+                        let nat_stack = format!(
+                            "    at Proxy static method [{}]\n{}",
+                            func_name,
+                            e.get_stack()
+                        );
+                        let err = errors::new_error(
+                            context,
+                            e.get_name(),
+                            msg.as_str(),
+                            nat_stack.as_str(),
+                        )
+                        .expect("create error failed");
+                        errors::throw(context, err)
+                    }
                 }
+            } else {
+                // return null if nothing was returned
+                quickjs_utils::new_undefined()
             }
-        } else {
-            // return null if nothing was returned
-            quickjs_utils::new_undefined()
-        }
+        })
     })
+    .unwrap_or_else(|ex| ex)
 }
 
 unsafe extern "C" fn proxy_static_set_prop(
@@ -1382,180 +1450,191 @@ unsafe extern "C" fn proxy_static_set_prop(
         "reflection::proxy_static_set_prop value",
     );
 
-    QuickJsRuntimeAdapter::do_with(|rt| {
-        let realm = rt.get_quickjs_context(context);
+    let native_name = format!(
+        "Proxy {} static setter",
+        get_static_class_name(context, &receiver_ref)
+    );
+    errors::catch_native_panic(context, native_name.as_str(), || {
+        QuickJsRuntimeAdapter::do_with(|rt| {
+            let realm = rt.get_quickjs_context(context);
+
+            let prop_name = atoms::to_str(context, &atom).expect("could not get name");
+            trace!("proxy_static_set_prop: {}", prop_name);
+
+            // see if we have a matching gettersetter
+
+            let proxy_name_ref = objects::get_property(context, &receiver_ref, "name")
+                .ok()
+                .unwrap();
+            let proxy_name = primitives::to_string(context, &proxy_name_ref)
+                .ok()
+                .unwrap();
+            trace!("proxy_static_set_prop: {}", proxy_name);
+
+            let registry = &*realm.proxy_registry.borrow();
+            if let Some(proxy) = registry.get(proxy_name.as_str()) {
+                if let Some(getter_setter) = proxy.static_getters_setters.get(prop_name) {
+                    // call the setter
+                    let setter = &getter_setter.1;
+                    let res: Result<(), JsError> = setter(rt, realm, value_ref);
+                    match res {
+                        Ok(_) => 0,
+                        Err(e) => {
+                            // fail, todo do i need ex?
+                            let err = format!("proxy_static_set_prop failed: {e}");
+                            log::error!("{}", err);
+                            let _ = realm.report_ex(err.as_str());
+                            -1
+                        }
+                    }
+                } else if let Some(catch_all_getter_setter) = &proxy.static_catch_all {
+                    // call the setter
+                    let setter = &catch_all_getter_setter.1;
+                    let res: Result<(), JsError> = setter(rt, realm, prop_name, value_ref);
+                    match res {
+                        Ok(_) => 0,
+                        Err(e) => {
+                            // fail, todo do i need ex?
+                            let err = format!("proxy_static_set_prop failed: {e}");
+                            log::error!("{}", err);
+                            let _ = realm.report_ex(err.as_str());
+                            -1
+                        }
+                    }
+                } else {
+                    let receiver_ref = QuickJsValueAdapter::new(
+                        context,
+                        receiver,
+                        false,
+                        false,
+                        "reflection::proxy_static_set_prop receiver",
+                    );
+
+                    match realm.set_object_property(&receiver_ref, prop_name, &value_ref) {
+                        Ok(()) => 0,
+                        Err(e) => {
+                            let err = format!("proxy_static_set_prop failed, {}", e);
+                            log::error!("{}", err);
+                            let _ = realm.report_ex(err.as_str());
+                            -1
+                        }
+                    }
+                    /*
+                    let err = format!("proxy_static_set_prop failed, no handler found for proxy_static_set_prop: {}", prop_name);
+                    log::error!("{}", err);
+                    let _ = q_ctx.report_ex(err.as_str());
+                    -1
 
-        let prop_name = atoms::to_str(context, &atom).expect("could not get name");
-        trace!("proxy_static_set_prop: {}", prop_name);
+                     */
+                }
+            } else {
+                let err = "proxy_static_set_prop failed, no proxy found";
+                log::error!("{}", err);
+                let _ = realm.report_ex(err);
+                -1
+            }
+        })
+    })
+    .unwrap_or(-1)
+}
 
-        // see if we have a matching gettersetter
+unsafe extern "C" fn proxy_instance_set_prop(
+    context: *mut q::JSContext,
+    obj: q::JSValue,
+    atom: q::JSAtom,
+    value: q::JSValue,
+    receiver: q::JSValue,
+    _flags: ::std::os::raw::c_int,
+) -> ::std::os::raw::c_int {
+    trace!("proxy_instance_set_prop");
 
-        let proxy_name_ref = objects::get_property(context, &receiver_ref, "name")
-            .ok()
-            .unwrap();
-        let proxy_name = primitives::to_string(context, &proxy_name_ref)
-            .ok()
-            .unwrap();
-        trace!("proxy_static_set_prop: {}", proxy_name);
+    let value_ref = QuickJsValueAdapter::new(
+        context,
+        value,
+        false,
+        false,
+        "reflection::proxy_instance_set_prop value",
+    );
+
+    let native_name = format!("Proxy {} instance setter", get_instance_class_name(&obj));
+    errors::catch_native_panic(context, native_name.as_str(), || {
+        QuickJsRuntimeAdapter::do_with(|rt| {
+            let realm = rt.get_quickjs_context(context);
+
+            let prop_name = atoms::to_str(context, &atom).expect("could not get name");
+            trace!("proxy_instance_set_prop: {}", prop_name);
+
+            let info = get_proxy_instance_info(&obj);
 
-        let registry = &*realm.proxy_registry.borrow();
-        if let Some(proxy) = registry.get(proxy_name.as_str()) {
-            if let Some(getter_setter) = proxy.static_getters_setters.get(prop_name) {
+            trace!("obj_ref.classname = {}", info.class_name);
+
+            // see if we have a matching gettersetter
+
+            let registry = &*realm.proxy_registry.borrow();
+            let proxy = registry.get(&info.class_name).unwrap();
+
+            if let Some(getter_setter) = proxy.getters_setters.get(prop_name) {
                 // call the setter
                 let setter = &getter_setter.1;
-                let res: Result<(), JsError> = setter(rt, realm, value_ref);
+                let res: Result<(), JsError> = setter(rt, realm, &info.id, value_ref);
                 match res {
                     Ok(_) => 0,
                     Err(e) => {
                         // fail, todo do i need ex?
-                        let err = format!("proxy_static_set_prop failed: {e}");
+                        let err = format!("proxy_instance_set_prop failed: {e}");
                         log::error!("{}", err);
                         let _ = realm.report_ex(err.as_str());
                         -1
                     }
                 }
-            } else if let Some(catch_all_getter_setter) = &proxy.static_catch_all {
+            } else if let Some(catch_all_getter_setter) = &proxy.catch_all {
                 // call the setter
                 let setter = &catch_all_getter_setter.1;
-                let res: Result<(), JsError> = setter(rt, realm, prop_name, value_ref);
+                let res: Result<(), JsError> = setter(rt, realm, &info.id, prop_name, value_ref);
                 match res {
                     Ok(_) => 0,
                     Err(e) => {
                         // fail, todo do i need ex?
-                        let err = format!("proxy_static_set_prop failed: {e}");
+                        let err = format!("proxy_instance_set_prop failed: {e}");
                         log::error!("{}", err);
                         let _ = realm.report_ex(err.as_str());
                         -1
                     }
                 }
             } else {
+                // if not handler just add to receiver
+
                 let receiver_ref = QuickJsValueAdapter::new(
                     context,
                     receiver,
                     false,
                     false,
-                    "reflection::proxy_static_set_prop receiver",
+                    "reflection::proxy_instance_set_prop receiver",
                 );
 
                 match realm.set_object_property(&receiver_ref, prop_name, &value_ref) {
                     Ok(()) => 0,
                     Err(e) => {
-                        let err = format!("proxy_static_set_prop failed, {}", e);
+                        let err = format!("proxy_instance_set_prop failed, {}", e);
                         log::error!("{}", err);
                         let _ = realm.report_ex(err.as_str());
                         -1
                     }
                 }
                 /*
-                let err = format!("proxy_static_set_prop failed, no handler found for proxy_static_set_prop: {}", prop_name);
+                let err = format!(
+                    "proxy_instance_set_prop failed, no handler found for proxy_instance_set_prop: {}",
+                    prop_name
+                );
                 log::error!("{}", err);
-                let _ = q_ctx.report_ex(err.as_str());
+                let _ = realm.report_ex(err.as_str());
                 -1
 
                  */
             }
-        } else {
-            let err = "proxy_static_set_prop failed, no proxy found";
-            log::error!("{}", err);
-            let _ = realm.report_ex(err);
-            -1
-        }
-    })
-}
-
-unsafe extern "C" fn proxy_instance_set_prop(
-    context: *mut q::JSContext,
-    obj: q::JSValue,
-    atom: q::JSAtom,
-    value: q::JSValue,
-    receiver: q::JSValue,
-    _flags: ::std::os::raw::c_int,
-) -> ::std::os::raw::c_int {
-    trace!("proxy_instance_set_prop");
-
-    let value_ref = QuickJsValueAdapter::new(
-        context,
-        value,
-        false,
-        false,
-        "reflection::proxy_instance_set_prop value",
-    );
-
-    QuickJsRuntimeAdapter::do_with(|rt| {
-        let realm = rt.get_quickjs_context(context);
-
-        let prop_name = atoms::to_str(context, &atom).expect("could not get name");
-        trace!("proxy_instance_set_prop: {}", prop_name);
-
-        let info = get_proxy_instance_info(&obj);
-
-        trace!("obj_ref.classname = {}", info.class_name);
-
-        // see if we have a matching gettersetter
-
-        let registry = &*realm.proxy_registry.borrow();
-        let proxy = registry.get(&info.class_name).unwrap();
-
-        if let Some(getter_setter) = proxy.getters_setters.get(prop_name) {
-            // call the setter
-            let setter = &getter_setter.1;
-            let res: Result<(), JsError> = setter(rt, realm, &info.id, value_ref);
-            match res {
-                Ok(_) => 0,
-                Err(e) => {
-                    // fail, todo do i need ex?
-                    let err = format!("proxy_instance_set_prop failed: {e}");
-                    log::error!("{}", err);
-                    let _ = realm.report_ex(err.as_str());
-                    -1
-                }
-            }
-        } else if let Some(catch_all_getter_setter) = &proxy.catch_all {
-            // call the setter
-            let setter = &catch_all_getter_setter.1;
-            let res: Result<(), JsError> = setter(rt, realm, &info.id, prop_name, value_ref);
-            match res {
-                Ok(_) => 0,
-                Err(e) => {
-                    // fail, todo do i need ex?
-                    let err = format!("proxy_instance_set_prop failed: {e}");
-                    log::error!("{}", err);
-                    let _ = realm.report_ex(err.as_str());
-                    -1
-                }
-            }
-        } else {
-            // if not handler just add to receiver
-
-            let receiver_ref = QuickJsValueAdapter::new(
-                context,
-                receiver,
-                false,
-                false,
-                "reflection::proxy_instance_set_prop receiver",
-            );
-
-            match realm.set_object_property(&receiver_ref, prop_name, &value_ref) {
-                Ok(()) => 0,
-                Err(e) => {
-                    let err = format!("proxy_instance_set_prop failed, {}", e);
-                    log::error!("{}", err);
-                    let _ = realm.report_ex(err.as_str());
-                    -1
-                }
-            }
-            /*
-            let err = format!(
-                "proxy_instance_set_prop failed, no handler found for proxy_instance_set_prop: {}",
-                prop_name
-            );
-            log::error!("{}", err);
-            let _ = realm.report_ex(err.as_str());
-            -1
-
-             */
-        }
+        })
     })
+    .unwrap_or(-1)
 }
 
 #[cfg(test)]
@@ -1625,6 +1704,43 @@ pub mod tests {
         assert!(err.contains("cant run"));
     }
 
+    #[test]
+    pub fn test_proxy_panic() {
+        use crate::builder::QuickJsRuntimeBuilder;
+        use std::sync::{Arc, Mutex};
+
+        let reported = Arc::new(Mutex::new(vec![]));
+        let reported2 = reported.clone();
+        let rt = QuickJsRuntimeBuilder::new()
+            .set_panic_handler(move |_rt, _realm, native_name, _msg| {
+                reported2.lock().unwrap().push(native_name.to_string());
+            })
+            .build();
+        let res = rt.exe_rt_task_in_event_loop(|q_js_rt| {
+            let q_ctx = q_js_rt.get_main_realm();
+            Proxy::new()
+                .constructor(|_q_js_rt, _q_ctx, _id, _args| Ok(()))
+                .method("run", |_rt, _realm, _instance_id, _args| panic!("poof"))
+                .name("Runner")
+                .install(q_ctx, true)
+                .expect("could not install proxy");
+            let err = q_ctx
+                .eval(Script::new("test_proxy_panic.es", "new Runner().run();"))
+                .expect_err("script passed");
+            assert_eq!(err.get_name(), "InternalError");
+            q_ctx
+                .eval(Script::new("test_proxy_in.es", "'run' in new Runner();"))
+                .expect("in failed")
+                .to_bool()
+        });
+        // in has no exotic handler
+        assert!(!res);
+        assert_eq!(
+            *reported.lock().unwrap(),
+            vec!["Proxy Runner instance method".to_string()]
+        );
+    }
+
     #[test]
     pub fn test_proxy_instanceof() {
         log::info!("> test_proxy_instanceof");
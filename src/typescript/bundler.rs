@@ -0,0 +1,770 @@
+//! bundle a module and all modules it imports to a single script for use outside of this runtime (e.g. in a browser)
+//!
+//! [bundle] loads an entry module and every module it (transitively) imports statically with the module loaders of the runtime
+//! (and the import map of the realm), transpiles them with the TypeScript transpiler and links them in a single ES module or IIFE
+//! which may be minified and mangled, a single source map maps the bundle to the original sources
+//!
+//! ScriptPreProcessors are not applied and import() calls are left as they are, modules without source
+//! (native modules and compiled modules) can not be bundled
+//!
+//! the modules are linked like CommonJS modules, so the exports of an ES module bundle are a snapshot of the exports of
+//! the entry module which is taken when the bundle runs (they are not live bindings) and modules with top level await
+//! can not be bundled
+//!
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::modules::ScriptModuleLoader;
+//! use quickjs_runtime::jsutils::Script;
+//! use quickjs_runtime::quickjsrealmadapter::QuickJsRealmAdapter;
+//! use quickjs_runtime::typescript::bundler::{bundle, BundleFormat, BundleOptions};
+//!
+//! struct SourceLoader {}
+//! impl ScriptModuleLoader for SourceLoader {
+//!     fn normalize_path(&self, _realm: &QuickJsRealmAdapter, _ref_path: &str, path: &str) -> Option<String> {
+//!         Some(path.trim_start_matches("./").to_string())
+//!     }
+//!     fn load_module(&self, _realm: &QuickJsRealmAdapter, absolute_path: &str) -> String {
+//!         match absolute_path {
+//!             "main.ts" => "import {greet} from './greet.ts'; export const msg: string = greet('world');".to_string(),
+//!             _ => "export function greet(name: string): string { return `hello ${name}`; }".to_string(),
+//!         }
+//!     }
+//! }
+//!
+//! let rt = QuickJsRuntimeBuilder::new().script_module_loader(SourceLoader {}).build();
+//! let js_bundle = rt.loop_realm_sync(None, |_rt, realm| {
+//!     let options = BundleOptions::new().format(BundleFormat::Iife).global_name("app").minify(true);
+//!     bundle(realm, "main.ts", options).expect("bundle failed")
+//! });
+//! let res = rt.eval_sync(None, Script::new("client.js", format!("{}\napp.msg;", js_bundle.get_code()).as_str())).expect("script failed");
+//! assert_eq!(res.get_str(), "hello world");
+//! ```
+
+use crate::jsutils::modules::ModuleSource;
+use crate::jsutils::JsError;
+use crate::quickjs_utils::bundle::{get_module_source, normalize_import};
+use crate::quickjs_utils::modules;
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsruntimeadapter::apply_declared_type;
+use crate::typescript::{
+    compose_source_maps, SourceSyntax, TypeScriptOptions, TypeScriptTranspiler,
+};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::sync::Arc;
+use swc_common::{FileName, SourceMap};
+use swc_ecma_ast::{
+    ArrowExpr, AwaitExpr, Constructor, Decl, ExportSpecifier, ForOfStmt, Function, GetterProp,
+    Module, ModuleDecl, ModuleExportName, ModuleItem, Pat, SetterProp, TsModuleName,
+};
+use swc_ecma_parser::{Parser, StringInput};
+use swc_ecma_visit::{Visit, VisitWith};
+
+/// the name of the variable which holds the exports of the entry module in an ES module bundle
+const ENTRY_VAR: &str = "__bundle_entry";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BundleFormat {
+    /// an ES module with the exports of the entry module
+    Esm,
+    /// a script with an immediately invoked function expression which returns the exports of the entry module
+    Iife,
+}
+
+pub struct BundleOptions {
+    format: BundleFormat,
+    global_name: Option<String>,
+    minify: bool,
+    mangle: bool,
+    source_map: bool,
+    typescript_options: TypeScriptOptions,
+}
+
+impl BundleOptions {
+    /// create the default options, an ES module with a source map which is not minified
+    pub fn new() -> Self {
+        Self {
+            format: BundleFormat::Esm,
+            global_name: None,
+            minify: false,
+            mangle: false,
+            source_map: true,
+            typescript_options: TypeScriptOptions::new(),
+        }
+    }
+
+    pub fn format(mut self, format: BundleFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// the name of the global var which is assigned the exports of the entry module in an IIFE bundle
+    pub fn global_name(mut self, global_name: &str) -> Self {
+        self.global_name = Some(global_name.to_string());
+        self
+    }
+
+    /// minify the bundle (with swc_ecma_minifier)
+    pub fn minify(mut self, minify: bool) -> Self {
+        self.minify = minify;
+        self
+    }
+
+    /// mangle names when minifying
+    pub fn mangle(mut self, mangle: bool) -> Self {
+        self.mangle = mangle;
+        self
+    }
+
+    /// create a source map for the bundle
+    pub fn source_map(mut self, source_map: bool) -> Self {
+        self.source_map = source_map;
+        self
+    }
+
+    /// the options for transpiling the modules (e.g. target and jsx), minify, mangle and external helpers of these options are ignored
+    pub fn typescript_options(mut self, typescript_options: TypeScriptOptions) -> Self {
+        self.typescript_options = typescript_options;
+        self
+    }
+}
+
+impl Default for BundleOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// the result of bundle
+pub struct JsBundle {
+    code: String,
+    map: Option<String>,
+}
+
+impl JsBundle {
+    pub fn get_code(&self) -> &str {
+        self.code.as_str()
+    }
+    /// the source map of the bundle (if BundleOptions::source_map was set), this includes the original sources
+    pub fn get_map(&self) -> Option<&str> {
+        self.map.as_deref()
+    }
+}
+
+struct BundledModule {
+    path: String,
+    /// the source after import attributes were stripped, None for generated modules (json, text, bytes)
+    source: Option<(String, SourceSyntax)>,
+    /// the transpiled CommonJS code and its map
+    code: String,
+    map: Option<String>,
+    /// specifier to module id
+    deps: Vec<(String, usize)>,
+}
+
+/// bundle a module and all modules it imports, see the module docs
+pub fn bundle(
+    realm: &QuickJsRealmAdapter,
+    entry: &str,
+    mut options: BundleOptions,
+) -> Result<JsBundle, JsError> {
+    let typescript_options = std::mem::take(&mut options.typescript_options);
+    let target = typescript_options.target;
+    let transpiler = TypeScriptTranspiler::with_options(
+        typescript_options
+            .minify(false)
+            .mangle(false)
+            .external_helpers(false),
+    );
+
+    let entry_path = normalize_import(realm, entry, entry)
+        .map(|(_, path)| path)
+        .ok_or_else(|| JsError::new_string(format!("module {entry} was not found")))?;
+
+    // (normalized path, type attribute it was imported with) -> module id,
+    // a module which is imported with different types is bundled once per type
+    let mut ids: HashMap<(String, Option<String>), usize> = HashMap::new();
+    ids.insert((entry_path.clone(), None), 0);
+    let mut todo = vec![(entry_path, None)];
+    let mut bundled: Vec<BundledModule> = vec![];
+    // modules are processed in the order of their ids
+    let mut next = 0;
+    while next < todo.len() {
+        let (path, declared_type): (String, Option<String>) = todo[next].clone();
+        next += 1;
+
+        let source = get_module_source(realm, path.as_str())?.ok_or_else(|| {
+            JsError::new_string(format!(
+                "module {path} has no source and can not be bundled"
+            ))
+        })?;
+        let module = match apply_declared_type(path.as_str(), declared_type.as_deref(), source)? {
+            ModuleSource::Script(code) => {
+                let code = modules::find_imports(path.as_str(), code.as_str())
+                    .code
+                    .unwrap_or(code);
+                let syntax = transpiler
+                    .get_options()
+                    .get_source_kind(path.as_str())
+                    .map(|(syntax, _)| syntax)
+                    .unwrap_or(SourceSyntax::JavaScript);
+                if has_top_level_await(path.as_str(), code.as_str(), syntax)? {
+                    return Err(JsError::new_string(format!(
+                        "module {path} uses top level await and can not be bundled"
+                    )));
+                }
+                let mut deps = vec![];
+                for specifier in modules::find_static_imports(path.as_str(), code.as_str()) {
+                    // the type of an import was added to its specifier when the attributes were stripped
+                    let (plain_specifier, module_type) = modules::split_module_type(&specifier);
+                    let (_, import_path) = normalize_import(realm, path.as_str(), plain_specifier)
+                        .ok_or_else(|| {
+                            JsError::new_string(format!(
+                                "module {specifier} imported by {path} was not found"
+                            ))
+                        })?;
+                    let key = (import_path, module_type.map(|t| t.to_string()));
+                    let id = match ids.get(&key) {
+                        Some(id) => *id,
+                        None => {
+                            ids.insert(key.clone(), todo.len());
+                            todo.push(key);
+                            todo.len() - 1
+                        }
+                    };
+                    deps.push((specifier, id));
+                }
+                let (transpiled, map, _) = transpiler.transpile_as(
+                    code.as_str(),
+                    path.as_str(),
+                    syntax,
+                    Some("commonjs"),
+                )?;
+                BundledModule {
+                    path: path.clone(),
+                    source: Some((code, syntax)),
+                    code: transpiled,
+                    map,
+                    deps,
+                }
+            }
+            ModuleSource::Json(json) => {
+                serde_json::from_str::<serde_json::Value>(json.as_str()).map_err(|e| {
+                    JsError::new_string(format!("module {path} is not valid json: {e}"))
+                })?;
+                generated_module(
+                    path.as_str(),
+                    format!("JSON.parse({})", to_js_string(&json)).as_str(),
+                )
+            }
+            ModuleSource::Text(text) => generated_module(path.as_str(), &to_js_string(&text)),
+            ModuleSource::Bytes(bytes) => generated_module(
+                path.as_str(),
+                format!(
+                    "new Uint8Array({})",
+                    serde_json::to_string(&bytes).unwrap_or_default()
+                )
+                .as_str(),
+            ),
+            ModuleSource::Bytecode(_) => {
+                return Err(JsError::new_string(format!(
+                    "module {path} is bytecode and can not be bundled"
+                )));
+            }
+            ModuleSource::CommonJs(_) => {
+                return Err(JsError::new_string(format!(
+                    "module {path} is a CommonJS module and can not be bundled"
+                )));
+            }
+        };
+        bundled.push(module);
+    }
+
+    let entry_exports = if options.format == BundleFormat::Esm {
+        let mut seen = HashSet::new();
+        get_export_names(&bundled, 0, &mut seen)?
+    } else {
+        vec![]
+    };
+
+    let (code, map) = link(&bundled, &options, entry_exports.as_slice())?;
+
+    if !options.minify {
+        return Ok(JsBundle { code, map });
+    }
+
+    let minifier = TypeScriptTranspiler::with_options(
+        TypeScriptOptions::new()
+            .target(target)
+            .minify(true)
+            .mangle(options.mangle)
+            .external_helpers(false),
+    );
+    let module_type = (options.format == BundleFormat::Esm).then_some("es6");
+    let (minified, minified_map, _) = minifier.transpile_as(
+        code.as_str(),
+        "bundle.js",
+        SourceSyntax::JavaScript,
+        module_type,
+    )?;
+    let map = match (map, minified_map) {
+        (Some(map), Some(minified_map)) => {
+            Some(compose_source_maps(&[map.as_str(), minified_map.as_str()])?)
+        }
+        _ => None,
+    };
+    Ok(JsBundle {
+        code: minified,
+        map,
+    })
+}
+
+/// a module with a default export, which looks like a transpiled ES module
+fn generated_module(path: &str, default_export: &str) -> BundledModule {
+    BundledModule {
+        path: path.to_string(),
+        source: None,
+        code: format!(
+            "Object.defineProperty(exports, \"__esModule\", {{ value: true }});\nexports.default = {default_export};"
+        ),
+        map: None,
+        deps: vec![],
+    }
+}
+
+fn to_js_string(val: &str) -> String {
+    serde_json::to_string(val).unwrap_or_default()
+}
+
+/// link the modules into a single script or module
+fn link(
+    bundled: &[BundledModule],
+    options: &BundleOptions,
+    entry_exports: &[String],
+) -> Result<(String, Option<String>), JsError> {
+    let mut out = String::new();
+    let mut line: u32 = 0;
+    // push a line (or lines) of code and return the (0-based) line where it starts
+    let mut push = |out: &mut String, text: &str| {
+        let start = line;
+        out.push_str(text);
+        out.push('\n');
+        line += text.matches('\n').count() as u32 + 1;
+        start
+    };
+
+    let mut map_builder = options
+        .source_map
+        .then(|| swc::sourcemap::SourceMapBuilder::new(None));
+
+    match (options.format, options.global_name.as_ref()) {
+        (BundleFormat::Esm, _) => push(
+            &mut out,
+            format!("const {ENTRY_VAR} = (function () {{").as_str(),
+        ),
+        (BundleFormat::Iife, Some(global_name)) => push(
+            &mut out,
+            format!("var {global_name} = (function () {{").as_str(),
+        ),
+        (BundleFormat::Iife, None) => push(&mut out, "(function () {"),
+    };
+    push(&mut out, "const __modules = [");
+
+    for module in bundled {
+        push(&mut out, "[function (module, exports, require) {");
+        let offset = push(&mut out, module.code.trim_end());
+        let deps: serde_json::Map<String, serde_json::Value> = module
+            .deps
+            .iter()
+            .map(|(specifier, id)| (specifier.clone(), serde_json::Value::from(*id)))
+            .collect();
+        push(
+            &mut out,
+            format!("}}, {}],", serde_json::Value::Object(deps)).as_str(),
+        );
+
+        if let (Some(builder), Some(map)) = (map_builder.as_mut(), module.map.as_ref()) {
+            let module_map =
+                swc::sourcemap::SourceMap::from_reader(io::Cursor::new(map)).map_err(|e| {
+                    JsError::new_string(format!("invalid source map for {}: {e}", module.path))
+                })?;
+            let src_id = builder.add_source(module.path.as_str());
+            if let Some((source, _)) = &module.source {
+                builder.set_source_contents(src_id, Some(source.as_str()));
+            }
+            for token in module_map.tokens() {
+                builder.add(
+                    token.get_dst_line() + offset,
+                    token.get_dst_col(),
+                    token.get_src_line(),
+                    token.get_src_col(),
+                    Some(module.path.as_str()),
+                    token.get_name(),
+                );
+            }
+        }
+    }
+
+    push(&mut out, "];");
+    push(&mut out, RUNTIME);
+    push(&mut out, "return __require(0);");
+    push(&mut out, "})();");
+
+    if options.format == BundleFormat::Esm {
+        let names: Vec<&str> = entry_exports
+            .iter()
+            .map(|n| n.as_str())
+            .filter(|n| *n != "default")
+            .collect();
+        // the exports are a snapshot of the exports of the entry module, not live bindings
+        if !names.is_empty() {
+            push(
+                &mut out,
+                format!("export const {{ {} }} = {ENTRY_VAR};", names.join(", ")).as_str(),
+            );
+        }
+        if entry_exports.iter().any(|n| n == "default") {
+            push(
+                &mut out,
+                format!("export default {ENTRY_VAR}.default;").as_str(),
+            );
+        }
+    }
+
+    let map = match map_builder {
+        None => None,
+        Some(builder) => {
+            let mut map_out: Vec<u8> = vec![];
+            builder
+                .into_sourcemap()
+                .to_writer(&mut map_out)
+                .map_err(|e| JsError::new_string(format!("could not write source map: {e}")))?;
+            Some(String::from_utf8(map_out).map_err(|e| JsError::new_string(format!("{e}")))?)
+        }
+    };
+    Ok((out, map))
+}
+
+/// the module registry of a bundle, modules are only run when they are first required
+const RUNTIME: &str = r#"const __cache = [];
+function __require(id) {
+    let module = __cache[id];
+    if (!module) {
+        module = __cache[id] = { exports: {} };
+        const [factory, deps] = __modules[id];
+        factory.call(module.exports, module, module.exports, (specifier) => {
+            if (!(specifier in deps)) {
+                throw new Error("module " + specifier + " is not in the bundle");
+            }
+            return __require(deps[specifier]);
+        });
+    }
+    return module.exports;
+}"#;
+
+/// the names exported by a module, including the names of export * from
+fn get_export_names(
+    bundled: &[BundledModule],
+    id: usize,
+    seen: &mut HashSet<usize>,
+) -> Result<Vec<String>, JsError> {
+    if !seen.insert(id) {
+        return Ok(vec![]);
+    }
+    let module = &bundled[id];
+    let (source, syntax) = match &module.source {
+        Some(source) => source,
+        None => return Ok(vec!["default".to_string()]),
+    };
+    let (mut names, star_exports) = scan_exports(module.path.as_str(), source, *syntax)?;
+    for specifier in star_exports {
+        if let Some((_, star_id)) = module.deps.iter().find(|(s, _)| *s == specifier) {
+            // export * does not re-export the default
+            names.extend(
+                get_export_names(bundled, *star_id, seen)?
+                    .into_iter()
+                    .filter(|n| n != "default"),
+            );
+        }
+    }
+    // names which are not identifiers can not be exported by the bundle
+    names.retain(|n| {
+        n.chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+            && !n.starts_with(|c: char| c.is_ascii_digit())
+    });
+    names.sort();
+    names.dedup();
+    Ok(names)
+}
+
+fn parse_module(path: &str, source: &str, syntax: SourceSyntax) -> Result<Module, JsError> {
+    let source_map = Arc::<SourceMap>::default();
+    let fm = source_map.new_source_file(FileName::Custom(path.to_string()), source.to_string());
+    let mut parser = Parser::new(syntax.parser_syntax(), StringInput::from(&*fm), None);
+    parser
+        .parse_module()
+        .map_err(|e| JsError::new_string(format!("could not parse {path}: {:?}", e.kind())))
+}
+
+/// see if a module uses await (or for await) outside of functions, the bundle runs modules synchronously
+fn has_top_level_await(path: &str, source: &str, syntax: SourceSyntax) -> Result<bool, JsError> {
+    if !source.contains("await") {
+        return Ok(false);
+    }
+    let module = parse_module(path, source, syntax)?;
+    let mut finder = TopLevelAwaitFinder {
+        depth: 0,
+        found: false,
+    };
+    module.visit_with(&mut finder);
+    Ok(finder.found)
+}
+
+struct TopLevelAwaitFinder {
+    /// the number of functions the visitor is in
+    depth: usize,
+    found: bool,
+}
+
+impl TopLevelAwaitFinder {
+    fn visit_function_body<N: VisitWith<Self>>(&mut self, node: &N) {
+        self.depth += 1;
+        node.visit_children_with(self);
+        self.depth -= 1;
+    }
+}
+
+impl Visit for TopLevelAwaitFinder {
+    fn visit_function(&mut self, function: &Function) {
+        self.visit_function_body(function);
+    }
+
+    fn visit_arrow_expr(&mut self, arrow: &ArrowExpr) {
+        self.visit_function_body(arrow);
+    }
+
+    fn visit_constructor(&mut self, constructor: &Constructor) {
+        self.visit_function_body(constructor);
+    }
+
+    fn visit_getter_prop(&mut self, getter: &GetterProp) {
+        self.visit_function_body(getter);
+    }
+
+    fn visit_setter_prop(&mut self, setter: &SetterProp) {
+        self.visit_function_body(setter);
+    }
+
+    fn visit_await_expr(&mut self, await_expr: &AwaitExpr) {
+        self.found |= self.depth == 0;
+        await_expr.visit_children_with(self);
+    }
+
+    fn visit_for_of_stmt(&mut self, for_of: &ForOfStmt) {
+        self.found |= self.depth == 0 && for_of.await_token.is_some();
+        for_of.visit_children_with(self);
+    }
+}
+
+/// parse a module and return the names it exports and the specifiers of export * from
+fn scan_exports(
+    path: &str,
+    source: &str,
+    syntax: SourceSyntax,
+) -> Result<(Vec<String>, Vec<String>), JsError> {
+    let module = parse_module(path, source, syntax)?;
+
+    let export_name = |name: &ModuleExportName| match name {
+        ModuleExportName::Ident(ident) => ident.sym.to_string(),
+        ModuleExportName::Str(s) => s.value.to_string(),
+    };
+
+    let mut names = vec![];
+    let mut star_exports = vec![];
+    for item in &module.body {
+        let decl = match item {
+            ModuleItem::ModuleDecl(decl) => decl,
+            ModuleItem::Stmt(_) => continue,
+        };
+        match decl {
+            ModuleDecl::ExportDecl(export) => match &export.decl {
+                Decl::Class(class) => names.push(class.ident.sym.to_string()),
+                Decl::Fn(func) => names.push(func.ident.sym.to_string()),
+                Decl::Var(var) => {
+                    for declarator in &var.decls {
+                        if let Pat::Ident(ident) = &declarator.name {
+                            names.push(ident.id.sym.to_string());
+                        }
+                    }
+                }
+                Decl::TsEnum(ts_enum) => names.push(ts_enum.id.sym.to_string()),
+                Decl::TsModule(ts_module) => {
+                    if let TsModuleName::Ident(ident) = &ts_module.id {
+                        names.push(ident.sym.to_string());
+                    }
+                }
+                // types are removed by the transpiler
+                _ => {}
+            },
+            ModuleDecl::ExportNamed(export) if !export.type_only => {
+                for specifier in &export.specifiers {
+                    match specifier {
+                        ExportSpecifier::Named(named) if !named.is_type_only => {
+                            names.push(export_name(named.exported.as_ref().unwrap_or(&named.orig)))
+                        }
+                        ExportSpecifier::Named(_) => {}
+                        ExportSpecifier::Namespace(namespace) => {
+                            names.push(export_name(&namespace.name))
+                        }
+                        ExportSpecifier::Default(default) => {
+                            names.push(default.exported.sym.to_string())
+                        }
+                    }
+                }
+            }
+            ModuleDecl::ExportDefaultDecl(_) | ModuleDecl::ExportDefaultExpr(_) => {
+                names.push("default".to_string())
+            }
+            ModuleDecl::ExportAll(export_all) => {
+                star_exports.push(export_all.src.value.to_string())
+            }
+            _ => {}
+        }
+    }
+    Ok((names, star_exports))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::modules::ScriptModuleLoader;
+    use crate::jsutils::Script;
+    use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+    use crate::typescript::bundler::{bundle, BundleFormat, BundleOptions};
+
+    struct SourceLoader {}
+    impl ScriptModuleLoader for SourceLoader {
+        fn normalize_path(
+            &self,
+            _realm: &QuickJsRealmAdapter,
+            ref_path: &str,
+            path: &str,
+        ) -> Option<String> {
+            match path {
+                "./util.ts" if ref_path == "app/main.ts" => Some("app/util.ts".to_string()),
+                "./data.json" => Some("app/data.json".to_string()),
+                "./consts.js" => Some("app/consts.js".to_string()),
+                p if p.starts_with("app/") => Some(p.to_string()),
+                _ => None,
+            }
+        }
+
+        fn load_module(&self, _realm: &QuickJsRealmAdapter, absolute_path: &str) -> String {
+            match absolute_path {
+                "app/main.ts" => "import {mul} from './util.ts';\nimport data from './data.json' with { type: 'json' };\nexport * from './consts.js';\nexport const answer: number = mul(data.a, data.b);\nexport default 'main';".to_string(),
+                "app/util.ts" => "export function mul(a: number, b: number): number {\n    return a * b;\n}".to_string(),
+                "app/data.json" => "{\"a\": 6, \"b\": 7}".to_string(),
+                "app/consts.js" => "export const version = '1.0';".to_string(),
+                "app/async.ts" => "export async function first(items: AsyncIterable<number>): Promise<number> {\n    for await (const item of items) {\n        return item;\n    }\n    return await Promise.resolve(0);\n}".to_string(),
+                "app/tla.ts" => "export const config = await Promise.resolve({a: 1});".to_string(),
+                _ => panic!("unexpected module {absolute_path}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_bundle() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_module_loader(SourceLoader {})
+            .build();
+        let (iife, esm, minified) = rt.loop_realm_sync(None, |_rt, realm| {
+            let iife = bundle(
+                realm,
+                "app/main.ts",
+                BundleOptions::new()
+                    .format(BundleFormat::Iife)
+                    .global_name("app"),
+            )
+            .expect("bundle failed");
+            let esm = bundle(realm, "app/main.ts", BundleOptions::new()).expect("bundle failed");
+            let minified = bundle(
+                realm,
+                "app/main.ts",
+                BundleOptions::new()
+                    .format(BundleFormat::Iife)
+                    .global_name("app_min")
+                    .minify(true)
+                    .mangle(true),
+            )
+            .expect("bundle failed");
+            (iife, esm, minified)
+        });
+
+        let map = iife.get_map().expect("no source map");
+        assert!(map.contains("app/util.ts"));
+        assert!(map.contains("return a * b;"));
+
+        // the bundles run without the module loaders
+        let rt = QuickJsRuntimeBuilder::new().build();
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "iife.js",
+                    format!(
+                        "{}\n[app.answer, app.default, app.version].join();",
+                        iife.get_code()
+                    )
+                    .as_str(),
+                ),
+            )
+            .expect("iife failed");
+        assert_eq!(res.get_str(), "42,main,1.0");
+
+        rt.eval_module_sync(
+            None,
+            Script::new(
+                "esm.mjs",
+                format!(
+                    "{}\nglobalThis.esmResult = [answer, version].join();",
+                    esm.get_code()
+                )
+                .as_str(),
+            ),
+        )
+        .expect("esm failed");
+        let res = rt
+            .eval_sync(None, Script::new("esm_result.js", "esmResult;"))
+            .expect("script failed");
+        assert_eq!(res.get_str(), "42,1.0");
+
+        assert!(minified.get_code().len() < iife.get_code().len());
+        assert!(minified.get_map().is_some());
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "min.js",
+                    format!("{}\napp_min.answer;", minified.get_code()).as_str(),
+                ),
+            )
+            .expect("minified failed");
+        assert_eq!(res.get_i32(), 42);
+    }
+
+    #[test]
+    fn test_bundle_checks() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_module_loader(SourceLoader {})
+            .build();
+        rt.loop_realm_sync(None, |_rt, realm| {
+            bundle(realm, "app/async.ts", BundleOptions::new()).expect("bundle failed");
+            let err = bundle(realm, "app/tla.ts", BundleOptions::new())
+                .err()
+                .expect("top level await was bundled");
+            assert!(err.get_message().contains("top level await"));
+        });
+    }
+}
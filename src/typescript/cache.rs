@@ -0,0 +1,299 @@
+//! a cache for transpiled TypeScript so the same file is not transpiled again in every realm or runtime
+//!
+//! entries are keyed by the path and code of the script and the TypeScriptOptions, the most recently used entries are kept in memory
+//! and a TranspileStore (e.g. a DirectoryTranspileStore) may be added to persist the entries, the warnings of the transpiler are
+//! stored with the code so they are reported again when an entry is used
+//!
+//! # Example
+//! ```rust
+//! use std::sync::Arc;
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::typescript::TypeScriptOptions;
+//! use quickjs_runtime::typescript::cache::{DirectoryTranspileStore, TranspileCache};
+//! // the cache may be shared by multiple runtimes
+//! let store = DirectoryTranspileStore::new(std::env::temp_dir().join("my_app_transpiled")).expect("could not create cache dir");
+//! let cache = Arc::new(TranspileCache::new(256).with_store(store));
+//! let rt1 = QuickJsRuntimeBuilder::new().typescript_options(TypeScriptOptions::new().cache(cache.clone())).build();
+//! let rt2 = QuickJsRuntimeBuilder::new().typescript_options(TypeScriptOptions::new().cache(cache)).build();
+//! ```
+
+use crate::jsutils::bytecodecache::StableHasher;
+use crate::jsutils::diagnostics::Diagnostic;
+use crate::jsutils::JsError;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// a store for transpiled code, an entry is the json of the transpiled code, source map and warnings
+pub trait TranspileStore {
+    fn get(&self, key: &str) -> Option<String>;
+    fn put(&self, key: &str, entry: &str);
+}
+
+/// a TranspileStore which keeps the entries in memory
+#[derive(Default)]
+pub struct MemoryTranspileStore {
+    entries: RefCell<HashMap<String, String>>,
+}
+
+impl MemoryTranspileStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.borrow().is_empty()
+    }
+}
+
+impl TranspileStore for MemoryTranspileStore {
+    fn get(&self, key: &str) -> Option<String> {
+        self.entries.borrow().get(key).cloned()
+    }
+
+    fn put(&self, key: &str, entry: &str) {
+        self.entries
+            .borrow_mut()
+            .insert(key.to_string(), entry.to_string());
+    }
+}
+
+/// a TranspileStore which persists the entries as json files in a directory
+pub struct DirectoryTranspileStore {
+    dir: PathBuf,
+}
+
+impl DirectoryTranspileStore {
+    /// create a new store, the directory is created if it does not exist
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Result<Self, JsError> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir).map_err(|e| {
+            JsError::new_string(format!(
+                "could not create transpile cache dir {}: {e}",
+                dir.display()
+            ))
+        })?;
+        Ok(Self { dir })
+    }
+
+    fn get_file(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.json"))
+    }
+}
+
+impl TranspileStore for DirectoryTranspileStore {
+    fn get(&self, key: &str) -> Option<String> {
+        std::fs::read_to_string(self.get_file(key)).ok()
+    }
+
+    fn put(&self, key: &str, entry: &str) {
+        // write to a temp file first so a concurrent reader never sees half a file
+        let file = self.get_file(key);
+        let tmp_file = self.dir.join(format!("{key}.{}.tmp", std::process::id()));
+        let res = std::fs::write(&tmp_file, entry).and_then(|_| std::fs::rename(&tmp_file, &file));
+        if let Err(e) = res {
+            log::error!("could not write transpile cache {}: {e}", file.display());
+            let _ = std::fs::remove_file(&tmp_file);
+        }
+    }
+}
+
+/// the transpiled code, source map and warnings of a script
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct CachedTranspile {
+    pub(crate) code: String,
+    pub(crate) map: Option<String>,
+    pub(crate) diagnostics: Vec<Diagnostic>,
+}
+
+struct Entries {
+    entries: HashMap<String, (CachedTranspile, u64)>,
+    tick: u64,
+}
+
+/// an in memory LRU cache of transpiled code and source maps, with an optional persistent store
+pub struct TranspileCache {
+    capacity: usize,
+    entries: Mutex<Entries>,
+    store: Option<Mutex<Box<dyn TranspileStore + Send>>>,
+}
+
+impl TranspileCache {
+    /// create a new cache which keeps (at most) capacity entries in memory
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(Entries {
+                entries: HashMap::new(),
+                tick: 0,
+            }),
+            store: None,
+        }
+    }
+
+    /// also persist the entries in a store, entries which are not in memory are looked up in the store
+    pub fn with_store<S: TranspileStore + Send + 'static>(mut self, store: S) -> Self {
+        self.store = Some(Mutex::new(Box::new(store)));
+        self
+    }
+
+    /// the number of entries in memory
+    pub fn len(&self) -> usize {
+        self.entries.lock().map(|e| e.entries.len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// remove all entries from memory (the store is not cleared)
+    pub fn clear(&self) {
+        if let Ok(entries) = &mut self.entries.lock() {
+            entries.entries.clear();
+        }
+    }
+
+    /// the key of an entry, this is a hex string of 128 bits
+    pub(crate) fn key(path: &str, code: &str, options_key: &str) -> String {
+        let mut hasher = StableHasher::new();
+        for field in [env!("CARGO_PKG_VERSION"), path, code, options_key] {
+            hasher.write_str(field);
+        }
+        format!("{:032x}", hasher.finish())
+    }
+
+    /// get the transpiled code, source map and warnings
+    pub(crate) fn get(&self, key: &str) -> Option<CachedTranspile> {
+        if let Ok(entries) = &mut self.entries.lock() {
+            entries.tick += 1;
+            let tick = entries.tick;
+            if let Some((cached, last_used)) = entries.entries.get_mut(key) {
+                *last_used = tick;
+                return Some(cached.clone());
+            }
+        }
+        let store = self.store.as_ref()?.lock().ok()?;
+        let entry = store.get(key)?;
+        drop(store);
+        let cached: CachedTranspile = serde_json::from_str(entry.as_str()).ok()?;
+        self.put_in_memory(key, cached.clone());
+        Some(cached)
+    }
+
+    pub(crate) fn put(&self, key: &str, cached: CachedTranspile) {
+        if let Some(store) = &self.store {
+            match (store.lock(), serde_json::to_string(&cached)) {
+                (Ok(store), Ok(entry)) => store.put(key, entry.as_str()),
+                (_, Err(e)) => log::error!("could not serialize transpile cache entry: {e}"),
+                _ => {}
+            }
+        }
+        self.put_in_memory(key, cached);
+    }
+
+    fn put_in_memory(&self, key: &str, cached: CachedTranspile) {
+        if self.capacity == 0 {
+            return;
+        }
+        if let Ok(entries) = &mut self.entries.lock() {
+            if entries.entries.len() >= self.capacity && !entries.entries.contains_key(key) {
+                // evict the least recently used entry
+                let lru = entries
+                    .entries
+                    .iter()
+                    .min_by_key(|(_, (_, last_used))| *last_used)
+                    .map(|(k, _)| k.clone());
+                if let Some(lru) = lru {
+                    entries.entries.remove(&lru);
+                }
+            }
+            entries.tick += 1;
+            let tick = entries.tick;
+            entries.entries.insert(key.to_string(), (cached, tick));
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::diagnostics::{Diagnostic, DiagnosticSeverity};
+    use crate::jsutils::Script;
+    use crate::typescript::cache::{
+        CachedTranspile, DirectoryTranspileStore, MemoryTranspileStore, TranspileCache,
+    };
+    use crate::typescript::TypeScriptOptions;
+    use std::sync::Arc;
+
+    fn entry(code: &str, map: Option<&str>) -> CachedTranspile {
+        CachedTranspile {
+            code: code.to_string(),
+            map: map.map(|m| m.to_string()),
+            diagnostics: vec![],
+        }
+    }
+
+    #[test]
+    fn test_transpile_cache() {
+        let cache = TranspileCache::new(2);
+        cache.put("a", entry("a;", None));
+        cache.put("b", entry("b;", Some("{}")));
+        assert!(cache.get("a").is_some());
+        // b is the least recently used entry
+        cache.put("c", entry("c;", None));
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get("b").is_none());
+        assert_eq!(cache.get("a"), Some(entry("a;", None)));
+
+        // warnings are kept in the store
+        let cache = TranspileCache::new(2).with_store(MemoryTranspileStore::new());
+        let mut warned = entry("d;", None);
+        warned.diagnostics = vec![Diagnostic::new(DiagnosticSeverity::Warning, "careful")];
+        cache.put("d", warned.clone());
+        cache.clear();
+        assert_eq!(cache.get("d"), Some(warned.clone()));
+
+        // entries are json files
+        let dir = std::env::temp_dir().join(format!("transpile_cache_{}", std::process::id()));
+        let store = DirectoryTranspileStore::new(&dir).expect("could not create dir");
+        let cache = TranspileCache::new(2).with_store(store);
+        cache.put("e", warned.clone());
+        assert!(dir.join("e.json").is_file());
+        let cache = TranspileCache::new(2)
+            .with_store(DirectoryTranspileStore::new(&dir).expect("could not open dir"));
+        assert_eq!(cache.get("e"), Some(warned));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let cache = Arc::new(TranspileCache::new(16));
+        let options = TypeScriptOptions::new().cache(cache.clone());
+        let options_key = options.get_cache_key();
+        let rt = QuickJsRuntimeBuilder::new()
+            .typescript_options(options)
+            .build();
+        let res = rt
+            .eval_sync(None, Script::new("sdk.ts", "let a: number = 6; a * 7;"))
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 42);
+        assert_eq!(cache.len(), 1);
+
+        // a hit does not transpile
+        let code = "let b: number = 1; b;";
+        cache.put(
+            TranspileCache::key("cached.ts", code, options_key.as_str()).as_str(),
+            entry("43;", None),
+        );
+        let rt2 = QuickJsRuntimeBuilder::new()
+            .typescript_options(TypeScriptOptions::new().cache(cache.clone()))
+            .build();
+        let res = rt2
+            .eval_sync(None, Script::new("cached.ts", code))
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 43);
+    }
+}
@@ -0,0 +1,952 @@
+//! code coverage for scripts and modules
+//!
+//! the CoveragePreProcessor inserts counters for functions, statements and branches (the paths of if statements and
+//! conditional expressions) in the code of a script before it is transpiled, counters are stored per realm in
+//! globalThis.__qjsrt_coverage__ and read with [collect_coverage]
+//!
+//! the counters are inserted in the AST and the code is generated again with a source map so stack traces are not
+//! affected, locations in the report are those of the original (TypeScript) source, also when another
+//! ScriptPreProcessor changed the code with Script::set_code_with_map
+//!
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::Script;
+//! use quickjs_runtime::typescript::coverage::{collect_coverage, CoveragePreProcessor};
+//! let rt = QuickJsRuntimeBuilder::new()
+//!     .script_pre_processor(CoveragePreProcessor::new().filter(|path| path.starts_with("rules/")))
+//!     .build();
+//! rt.eval_sync(None, Script::new("rules/discount.ts", "function discount(amount: number): number {\n  if (amount > 100) {\n    return 10;\n  }\n  return 0;\n}\ndiscount(50);")).expect("script failed");
+//! let report = rt.loop_realm_sync(None, |_rt, realm| collect_coverage(realm)).expect("collect failed");
+//! let lcov = report.to_lcov();
+//! assert!(lcov.contains("SF:rules/discount.ts"));
+//! assert!(lcov.contains("DA:3,0"));
+//! ```
+
+use crate::jsutils::bytecodecache::StableHasher;
+use crate::jsutils::{JsError, Script, ScriptPreProcessor};
+use crate::quickjs_utils::{arrays, get_global_q, json, objects, primitives};
+use crate::quickjsrealmadapter::QuickJsRealmAdapter;
+use crate::quickjsvalueadapter::QuickJsValueAdapter;
+use crate::typescript::{compose_source_maps, SourceSyntax, TypeScriptOptions};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::sync::Arc;
+use swc_common::comments::SingleThreadedComments;
+use swc_common::util::take::Take;
+use swc_common::{BytePos, FileName, SourceMap, Span, Spanned, DUMMY_SP};
+use swc_ecma_ast::{
+    ArrowExpr, BlockStmt, BlockStmtOrExpr, ClassMethod, CondExpr, Constructor, Decl, DoWhileStmt,
+    EsVersion, Expr, ExprStmt, FnDecl, FnExpr, ForInStmt, ForOfStmt, ForStmt, Function, GetterProp,
+    Ident, IfStmt, Lit, MethodProp, ModuleDecl, ModuleItem, Number, ParenExpr, Pat, Program,
+    PropName, SeqExpr, SetterProp, Stmt, Str, UpdateExpr, UpdateOp, VarDeclarator, WhileStmt,
+};
+use swc_ecma_codegen::text_writer::JsWriter;
+use swc_ecma_codegen::Emitter;
+use swc_ecma_parser::{Parser, StringInput};
+use swc_ecma_utils::{DropSpan, ExprFactory};
+use swc_ecma_visit::{VisitMut, VisitMutWith};
+
+/// the global which holds the counters of all instrumented files of a realm
+const COVERAGE_GLOBAL: &str = "__qjsrt_coverage__";
+
+type PathFilter = Box<dyn Fn(&str) -> bool + Send>;
+
+pub struct CoveragePreProcessor {
+    filter: Option<PathFilter>,
+}
+
+impl CoveragePreProcessor {
+    /// create a CoveragePreProcessor which instruments all scripts
+    pub fn new() -> Self {
+        Self { filter: None }
+    }
+
+    /// only instrument a script if the filter returns true for its path
+    pub fn filter<F: Fn(&str) -> bool + Send + 'static>(mut self, filter: F) -> Self {
+        self.filter = Some(Box::new(filter));
+        self
+    }
+
+    /// instrument the code of a script, returns the code and a source map which maps it to the code of the script
+    /// or None if the script could not be parsed (evaluating it will report the syntax error)
+    pub fn instrument(&self, script: &Script) -> Option<(String, String)> {
+        let path = script.get_path();
+        let code = script.get_code();
+        let syntax = match TypeScriptOptions::new().get_source_kind(path) {
+            Some((syntax, _)) => syntax,
+            None => SourceSyntax::JavaScript,
+        };
+
+        let source_map = Arc::<SourceMap>::default();
+        let fm = source_map.new_source_file(FileName::Custom(path.to_string()), code.to_string());
+        let comments = SingleThreadedComments::default();
+        let mut parser = Parser::new(
+            syntax.parser_syntax(),
+            StringInput::from(&*fm),
+            Some(&comments),
+        );
+        let mut program = match parser.parse_program() {
+            Ok(program) if parser.take_errors().is_empty() => program,
+            _ => {
+                log::debug!("could not parse {path}, not instrumenting it");
+                return None;
+            }
+        };
+
+        // locations are reported for the code before the other pre-processors
+        let previous_map = if script.get_code_maps().is_empty() {
+            None
+        } else {
+            let maps: Vec<&str> = script.get_code_maps().iter().map(|m| m.as_str()).collect();
+            compose_source_maps(maps.as_slice())
+                .ok()
+                .and_then(|m| swc::sourcemap::SourceMap::from_reader(io::Cursor::new(m)).ok())
+        };
+
+        let mut hasher = StableHasher::new();
+        hasher.write_str(path);
+        hasher.write_str(code);
+        let hash = format!("{:032x}", hasher.finish());
+
+        // counters are referenced as globalThis.__qjsrt_coverage__["path"] so a classic script does not get an
+        // extra global
+        let counters = Expr::Ident(Ident::new("globalThis".into(), DUMMY_SP))
+            .make_member(Ident::new(COVERAGE_GLOBAL.into(), DUMMY_SP))
+            .computed_member(Expr::Lit(Lit::Str(Str {
+                span: DUMMY_SP,
+                value: path.into(),
+                raw: None,
+            })));
+        let mut instrumenter = Instrumenter {
+            source_map: source_map.clone(),
+            previous_map,
+            counters,
+            statements: vec![],
+            functions: vec![],
+            branches: vec![],
+            pending_name: None,
+        };
+        program.visit_mut_with(&mut instrumenter);
+
+        let data = serde_json::json!({
+            "path": path,
+            "hash": hash,
+            "s": vec![0; instrumenter.statements.len()],
+            "f": vec![0; instrumenter.functions.len()],
+            "b": instrumenter.branches.iter().map(|b| vec![0; b.3]).collect::<Vec<_>>(),
+            "statements": instrumenter.statements,
+            "functions": instrumenter.functions,
+            "branches": instrumenter.branches.iter().map(|b| (b.0, b.1, b.2)).collect::<Vec<_>>(),
+        });
+        let preamble = preamble(
+            format!(
+                "(function (g) {{ var c = g[{0}]; if (!c || c.hash !== \"{hash}\") {{ g[{0}] = {data}; }} }})(globalThis.{COVERAGE_GLOBAL} || (globalThis.{COVERAGE_GLOBAL} = {{}}));",
+                serde_json::Value::from(path),
+            )
+            .as_str(),
+        )?;
+        match &mut program {
+            Program::Script(script) => {
+                // after the directives so "use strict" still works
+                let directives = directive_count(&script.body);
+                script.body.insert(directives, preamble);
+            }
+            Program::Module(module) => {
+                let directives = module_directive_count(&module.body);
+                module.body.insert(directives, ModuleItem::Stmt(preamble));
+            }
+        }
+
+        let mut buf = vec![];
+        let mut mappings = vec![];
+        {
+            let mut emitter = Emitter {
+                cfg: swc_ecma_codegen::Config {
+                    target: EsVersion::latest(),
+                    ..Default::default()
+                },
+                cm: source_map.clone(),
+                comments: Some(&comments),
+                wr: JsWriter::new(source_map.clone(), "\n", &mut buf, Some(&mut mappings)),
+            };
+            if let Err(e) = emitter.emit_program(&program) {
+                log::error!("could not emit the instrumented code of {path}: {e}");
+                return None;
+            }
+        }
+        let mut map = vec![];
+        source_map
+            .build_source_map(&mappings)
+            .to_writer(&mut map)
+            .ok()?;
+        Some((String::from_utf8(buf).ok()?, String::from_utf8(map).ok()?))
+    }
+}
+
+/// parse the statement which creates the counters of a file, it has no spans so it is not mapped to the original code
+fn preamble(code: &str) -> Option<Stmt> {
+    let source_map = Arc::<SourceMap>::default();
+    let fm = source_map.new_source_file(FileName::Anon, code.to_string());
+    let mut parser = Parser::new(Default::default(), StringInput::from(&*fm), None);
+    let mut script = parser.parse_script().ok()?;
+    script.visit_mut_with(&mut DropSpan {
+        preserve_ctxt: false,
+    });
+    script.body.pop()
+}
+
+impl Default for CoveragePreProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ScriptPreProcessor for CoveragePreProcessor {
+    fn process(&self, script: &mut Script) -> Result<(), JsError> {
+        if let Some(filter) = &self.filter {
+            if !filter(script.get_path()) {
+                return Ok(());
+            }
+        }
+        if let Some((code, map)) = self.instrument(script) {
+            script.set_code_with_map(code, map);
+        }
+        Ok(())
+    }
+
+    fn get_cache_key(&self) -> String {
+        "CoveragePreProcessor".to_string()
+    }
+}
+
+/// inserts counters in the AST, counters are registered before the children of a node are visited and inserted
+/// after, so inserted statements are never counted themselves
+struct Instrumenter {
+    source_map: Arc<SourceMap>,
+    previous_map: Option<swc::sourcemap::SourceMap>,
+    /// the counters of the file: globalThis.__qjsrt_coverage__["path"]
+    counters: Expr,
+    /// [line, column, end line, end column]
+    statements: Vec<[u32; 4]>,
+    /// (name, line, column, end line, end column)
+    functions: Vec<(String, u32, u32, u32, u32)>,
+    /// (kind, line, column, number of paths)
+    branches: Vec<(&'static str, u32, u32, usize)>,
+    /// the name for the next function, set by the declaration, method or variable of the function
+    pending_name: Option<String>,
+}
+
+impl Instrumenter {
+    /// the 1-based line and column in the original code
+    fn location(&self, pos: BytePos) -> (u32, u32) {
+        let loc = self.source_map.lookup_char_pos(pos);
+        let line = loc.line.saturating_sub(1) as u32;
+        let column = loc.col.0 as u32;
+        let (line, column) = match self
+            .previous_map
+            .as_ref()
+            .and_then(|map| map.lookup_token(line, column))
+        {
+            Some(token) if token.get_dst_line() == line => (
+                token.get_src_line(),
+                token.get_src_col() + (column - token.get_dst_col()),
+            ),
+            Some(token) => (token.get_src_line(), token.get_src_col()),
+            None => (line, column),
+        };
+        (line + 1, column + 1)
+    }
+
+    fn range(&self, span: Span) -> [u32; 4] {
+        let (line, column) = self.location(span.lo);
+        let (end_line, end_column) = self.location(span.hi);
+        [line, column, end_line, end_column]
+    }
+
+    /// e.g. globalThis.__qjsrt_coverage__["path"].b[0][1]++
+    fn counter(&self, kind: &str, index: usize, path: Option<usize>) -> Box<Expr> {
+        let mut counter = self
+            .counters
+            .clone()
+            .make_member(Ident::new(kind.into(), DUMMY_SP))
+            .computed_member(number(index));
+        if let Some(path) = path {
+            counter = counter.computed_member(number(path));
+        }
+        Box::new(Expr::Update(UpdateExpr {
+            span: DUMMY_SP,
+            op: UpdateOp::PlusPlus,
+            prefix: false,
+            arg: Box::new(counter),
+        }))
+    }
+
+    fn counter_stmt(&self, kind: &str, index: usize, path: Option<usize>) -> Stmt {
+        Stmt::Expr(ExprStmt {
+            span: DUMMY_SP,
+            expr: self.counter(kind, index, path),
+        })
+    }
+
+    fn add_statement(&mut self, span: Span) -> Stmt {
+        let range = self.range(span);
+        self.statements.push(range);
+        self.counter_stmt("s", self.statements.len() - 1, None)
+    }
+
+    fn add_function(&mut self, span: Span) -> usize {
+        let name = self.pending_name.take();
+        let [line, column, end_line, end_column] = self.range(span);
+        let name = name.unwrap_or_else(|| format!("(anonymous_{})", self.functions.len()));
+        self.functions
+            .push((name, line, column, end_line, end_column));
+        self.functions.len() - 1
+    }
+
+    /// count the calls of a function with a body, e.g. not an overload signature
+    fn instrument_function<N: VisitMutWith<Self>>(
+        &mut self,
+        span: Span,
+        node: &mut N,
+        body: fn(&mut N) -> Option<&mut BlockStmt>,
+    ) {
+        let function = if body(node).is_some() {
+            Some(self.add_function(span))
+        } else {
+            self.pending_name = None;
+            None
+        };
+        node.visit_mut_children_with(self);
+        if let (Some(function), Some(body)) = (function, body(node)) {
+            // after the directives so "use strict" still works
+            let directives = directive_count(&body.stmts);
+            body.stmts
+                .insert(directives, self.counter_stmt("f", function, None));
+        }
+    }
+
+    fn add_branch(&mut self, kind: &'static str, span: Span, paths: usize) -> usize {
+        let (line, column) = self.location(span.lo);
+        self.branches.push((kind, line, column, paths));
+        self.branches.len() - 1
+    }
+
+    fn visit_stmt_list<T: VisitMutWith<Self> + Spanned>(
+        &mut self,
+        items: &mut Vec<T>,
+        directives: usize,
+        is_counted: fn(&T) -> bool,
+        wrap: fn(Stmt) -> T,
+    ) {
+        let mut instrumented = Vec::with_capacity(items.len() * 2);
+        for (index, mut item) in items.drain(..).enumerate() {
+            if index >= directives && is_counted(&item) {
+                instrumented.push(wrap(self.add_statement(item.span())));
+            }
+            item.visit_mut_with(self);
+            instrumented.push(item);
+        }
+        *items = instrumented;
+    }
+}
+
+impl VisitMut for Instrumenter {
+    fn visit_mut_stmts(&mut self, stmts: &mut Vec<Stmt>) {
+        let directives = directive_count(stmts);
+        self.visit_stmt_list(stmts, directives, is_counted, |stmt| stmt);
+    }
+
+    fn visit_mut_module_items(&mut self, items: &mut Vec<ModuleItem>) {
+        let directives = module_directive_count(items);
+        self.visit_stmt_list(
+            items,
+            directives,
+            |item| match item {
+                ModuleItem::Stmt(stmt) => is_counted(stmt),
+                ModuleItem::ModuleDecl(ModuleDecl::ExportDecl(export)) => {
+                    is_counted_decl(&export.decl)
+                }
+                ModuleItem::ModuleDecl(ModuleDecl::ExportDefaultExpr(_)) => true,
+                ModuleItem::ModuleDecl(_) => false,
+            },
+            ModuleItem::Stmt,
+        );
+    }
+
+    fn visit_mut_fn_decl(&mut self, n: &mut FnDecl) {
+        self.pending_name = Some(n.ident.sym.to_string());
+        n.visit_mut_children_with(self);
+    }
+
+    fn visit_mut_fn_expr(&mut self, n: &mut FnExpr) {
+        if let Some(ident) = &n.ident {
+            self.pending_name = Some(ident.sym.to_string());
+        }
+        n.visit_mut_children_with(self);
+    }
+
+    fn visit_mut_var_declarator(&mut self, n: &mut VarDeclarator) {
+        if let (Pat::Ident(ident), Some(init)) = (&n.name, &n.init) {
+            if matches!(&**init, Expr::Arrow(_) | Expr::Fn(_)) {
+                self.pending_name = Some(ident.id.sym.to_string());
+            }
+        }
+        n.visit_mut_children_with(self);
+    }
+
+    fn visit_mut_class_method(&mut self, n: &mut ClassMethod) {
+        self.pending_name = prop_name(&n.key);
+        n.visit_mut_children_with(self);
+    }
+
+    fn visit_mut_method_prop(&mut self, n: &mut MethodProp) {
+        self.pending_name = prop_name(&n.key);
+        n.visit_mut_children_with(self);
+    }
+
+    fn visit_mut_function(&mut self, n: &mut Function) {
+        self.instrument_function(n.span, n, |n| n.body.as_mut());
+    }
+
+    fn visit_mut_constructor(&mut self, n: &mut Constructor) {
+        self.pending_name = Some("constructor".to_string());
+        self.instrument_function(n.span, n, |n| n.body.as_mut());
+    }
+
+    fn visit_mut_getter_prop(&mut self, n: &mut GetterProp) {
+        self.pending_name = prop_name(&n.key).map(|name| format!("get {name}"));
+        self.instrument_function(n.span, n, |n| n.body.as_mut());
+    }
+
+    fn visit_mut_setter_prop(&mut self, n: &mut SetterProp) {
+        self.pending_name = prop_name(&n.key).map(|name| format!("set {name}"));
+        self.instrument_function(n.span, n, |n| n.body.as_mut());
+    }
+
+    fn visit_mut_arrow_expr(&mut self, n: &mut ArrowExpr) {
+        let function = self.add_function(n.span);
+        n.visit_mut_children_with(self);
+        let counter = self.counter("f", function, None);
+        match &mut n.body {
+            BlockStmtOrExpr::BlockStmt(body) => {
+                let directives = directive_count(&body.stmts);
+                body.stmts.insert(
+                    directives,
+                    Stmt::Expr(ExprStmt {
+                        span: DUMMY_SP,
+                        expr: counter,
+                    }),
+                );
+            }
+            BlockStmtOrExpr::Expr(body) => *body = counted(counter, body.take()),
+        }
+    }
+
+    fn visit_mut_if_stmt(&mut self, n: &mut IfStmt) {
+        let branch = self.add_branch("if", n.span, 2);
+        let mut alt = n
+            .alt
+            .take()
+            .unwrap_or_else(|| Box::new(Stmt::Block(BlockStmt::dummy())));
+        into_block(&mut n.cons);
+        into_block(&mut alt);
+        n.alt = Some(alt);
+        n.visit_mut_children_with(self);
+        prepend(&mut n.cons, self.counter_stmt("b", branch, Some(0)));
+        if let Some(alt) = &mut n.alt {
+            prepend(alt, self.counter_stmt("b", branch, Some(1)));
+        }
+    }
+
+    fn visit_mut_cond_expr(&mut self, n: &mut CondExpr) {
+        let branch = self.add_branch("cond", n.span, 2);
+        n.visit_mut_children_with(self);
+        n.cons = counted(self.counter("b", branch, Some(0)), n.cons.take());
+        n.alt = counted(self.counter("b", branch, Some(1)), n.alt.take());
+    }
+
+    fn visit_mut_for_stmt(&mut self, n: &mut ForStmt) {
+        into_loop_block(&mut n.body);
+        n.visit_mut_children_with(self);
+    }
+
+    fn visit_mut_for_in_stmt(&mut self, n: &mut ForInStmt) {
+        into_loop_block(&mut n.body);
+        n.visit_mut_children_with(self);
+    }
+
+    fn visit_mut_for_of_stmt(&mut self, n: &mut ForOfStmt) {
+        into_loop_block(&mut n.body);
+        n.visit_mut_children_with(self);
+    }
+
+    fn visit_mut_while_stmt(&mut self, n: &mut WhileStmt) {
+        into_loop_block(&mut n.body);
+        n.visit_mut_children_with(self);
+    }
+
+    fn visit_mut_do_while_stmt(&mut self, n: &mut DoWhileStmt) {
+        into_loop_block(&mut n.body);
+        n.visit_mut_children_with(self);
+    }
+}
+
+fn number(value: usize) -> Expr {
+    Expr::Lit(Lit::Num(Number {
+        span: DUMMY_SP,
+        value: value as f64,
+        raw: None,
+    }))
+}
+
+/// (counter, expr)
+fn counted(counter: Box<Expr>, expr: Box<Expr>) -> Box<Expr> {
+    Box::new(Expr::Paren(ParenExpr {
+        span: DUMMY_SP,
+        expr: Box::new(Expr::Seq(SeqExpr {
+            span: DUMMY_SP,
+            exprs: vec![counter, expr],
+        })),
+    }))
+}
+
+/// a body which is not a block is wrapped in a block so its statement is counted and a counter can be prepended
+fn into_block(body: &mut Box<Stmt>) {
+    match &**body {
+        Stmt::Block(_) => {}
+        Stmt::Empty(_) => **body = Stmt::Block(BlockStmt::dummy()),
+        _ => {
+            let stmt = (**body).take();
+            **body = Stmt::Block(BlockStmt {
+                span: stmt.span(),
+                stmts: vec![stmt],
+            });
+        }
+    }
+}
+
+/// the statement of a loop body which is not a block is counted, an empty body is kept
+fn into_loop_block(body: &mut Box<Stmt>) {
+    if !matches!(&**body, Stmt::Empty(_)) {
+        into_block(body);
+    }
+}
+
+fn prepend(body: &mut Stmt, counter: Stmt) {
+    if let Stmt::Block(block) = body {
+        block.stmts.insert(0, counter);
+    }
+}
+
+fn prop_name(key: &PropName) -> Option<String> {
+    match key {
+        PropName::Ident(ident) => Some(ident.sym.to_string()),
+        PropName::Str(s) => Some(s.value.to_string()),
+        _ => None,
+    }
+}
+
+/// the number of directives (e.g. "use strict") at the start of a body
+fn directive_count(stmts: &[Stmt]) -> usize {
+    stmts.iter().take_while(|stmt| is_directive(stmt)).count()
+}
+
+/// the number of directives at the start of a module
+fn module_directive_count(items: &[ModuleItem]) -> usize {
+    items
+        .iter()
+        .take_while(|item| matches!(item, ModuleItem::Stmt(stmt) if is_directive(stmt)))
+        .count()
+}
+
+fn is_directive(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::Expr(expr_stmt) => matches!(&*expr_stmt.expr, Expr::Lit(Lit::Str(_))),
+        _ => false,
+    }
+}
+
+/// function declarations are hoisted and declarations of types do not run, they are not counted
+fn is_counted(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::Empty(_) => false,
+        Stmt::Decl(decl) => is_counted_decl(decl),
+        _ => true,
+    }
+}
+
+fn is_counted_decl(decl: &Decl) -> bool {
+    match decl {
+        Decl::Fn(_) | Decl::TsInterface(_) | Decl::TsTypeAlias(_) => false,
+        Decl::Class(class) => !class.declare,
+        Decl::Var(var) => !var.declare,
+        Decl::TsEnum(ts_enum) => !ts_enum.declare,
+        Decl::TsModule(ts_module) => !ts_module.declare,
+        #[allow(unreachable_patterns)]
+        _ => true,
+    }
+}
+
+/// a range in the original source, lines and columns are 1-based
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceRange {
+    pub line_number: u32,
+    pub column_number: u32,
+    pub end_line_number: u32,
+    pub end_column_number: u32,
+}
+
+impl SourceRange {
+    fn new(range: [u32; 4]) -> Self {
+        Self {
+            line_number: range[0],
+            column_number: range[1],
+            end_line_number: range[2],
+            end_column_number: range[3],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatementCoverage {
+    pub range: SourceRange,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FunctionCoverage {
+    pub name: String,
+    pub range: SourceRange,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BranchCoverage {
+    /// "if" or "cond" (a conditional expression)
+    pub kind: String,
+    pub line_number: u32,
+    pub column_number: u32,
+    /// the number of times every path was taken, the first is the consequent and the second the alternate
+    pub counts: Vec<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileCoverage {
+    pub path: String,
+    pub statements: Vec<StatementCoverage>,
+    pub functions: Vec<FunctionCoverage>,
+    pub branches: Vec<BranchCoverage>,
+}
+
+impl FileCoverage {
+    /// the hit count per line, the highest count of the statements which start on that line
+    pub fn get_line_counts(&self) -> BTreeMap<u32, u64> {
+        let mut lines = BTreeMap::new();
+        for statement in &self.statements {
+            let count = lines.entry(statement.range.line_number).or_insert(0);
+            *count = statement.count.max(*count);
+        }
+        lines
+    }
+
+    fn merge(&mut self, other: FileCoverage) {
+        if self.statements.len() != other.statements.len()
+            || self.functions.len() != other.functions.len()
+            || self.branches.len() != other.branches.len()
+        {
+            // a different version of the file, keep the last
+            *self = other;
+            return;
+        }
+        for (s, o) in self.statements.iter_mut().zip(other.statements) {
+            s.count += o.count;
+        }
+        for (f, o) in self.functions.iter_mut().zip(other.functions) {
+            f.count += o.count;
+        }
+        for (b, o) in self.branches.iter_mut().zip(other.branches) {
+            for (c, o) in b.counts.iter_mut().zip(o.counts) {
+                *c += o;
+            }
+        }
+    }
+}
+
+/// the coverage of all instrumented files of one or more realms
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoverageReport {
+    pub files: BTreeMap<String, FileCoverage>,
+}
+
+impl CoverageReport {
+    /// add the counts of another report, e.g. of another realm
+    pub fn merge(&mut self, other: CoverageReport) {
+        for (path, file) in other.files {
+            match self.files.get_mut(&path) {
+                Some(existing) => existing.merge(file),
+                None => {
+                    self.files.insert(path, file);
+                }
+            }
+        }
+    }
+
+    /// the report as JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    /// the report in the LCOV tracefile format (e.g. for genhtml or a CI coverage service)
+    pub fn to_lcov(&self) -> String {
+        let mut lcov = String::new();
+        for file in self.files.values() {
+            lcov.push_str("TN:\n");
+            lcov.push_str(format!("SF:{}\n", file.path).as_str());
+            for function in &file.functions {
+                lcov.push_str(
+                    format!("FN:{},{}\n", function.range.line_number, function.name).as_str(),
+                );
+            }
+            for function in &file.functions {
+                lcov.push_str(format!("FNDA:{},{}\n", function.count, function.name).as_str());
+            }
+            lcov.push_str(format!("FNF:{}\n", file.functions.len()).as_str());
+            lcov.push_str(
+                format!(
+                    "FNH:{}\n",
+                    file.functions.iter().filter(|f| f.count > 0).count()
+                )
+                .as_str(),
+            );
+            let mut branches_found = 0;
+            let mut branches_hit = 0;
+            for (block, branch) in file.branches.iter().enumerate() {
+                let evaluated = branch.counts.iter().any(|c| *c > 0);
+                for (path, count) in branch.counts.iter().enumerate() {
+                    branches_found += 1;
+                    if *count > 0 {
+                        branches_hit += 1;
+                    }
+                    let taken = if evaluated {
+                        count.to_string()
+                    } else {
+                        "-".to_string()
+                    };
+                    lcov.push_str(
+                        format!("BRDA:{},{block},{path},{taken}\n", branch.line_number).as_str(),
+                    );
+                }
+            }
+            lcov.push_str(format!("BRF:{branches_found}\nBRH:{branches_hit}\n").as_str());
+            let lines = file.get_line_counts();
+            for (line, count) in &lines {
+                lcov.push_str(format!("DA:{line},{count}\n").as_str());
+            }
+            lcov.push_str(format!("LF:{}\n", lines.len()).as_str());
+            lcov.push_str(format!("LH:{}\n", lines.values().filter(|c| **c > 0).count()).as_str());
+            lcov.push_str("end_of_record\n");
+        }
+        lcov
+    }
+}
+
+/// the counters of a file as stored by the instrumented code
+#[derive(Deserialize)]
+struct RawFileCoverage {
+    path: String,
+    s: Vec<u64>,
+    f: Vec<u64>,
+    b: Vec<Vec<u64>>,
+    statements: Vec<[u32; 4]>,
+    functions: Vec<(String, u32, u32, u32, u32)>,
+    branches: Vec<(String, u32, u32)>,
+}
+
+impl From<RawFileCoverage> for FileCoverage {
+    fn from(raw: RawFileCoverage) -> Self {
+        Self {
+            path: raw.path,
+            statements: raw
+                .statements
+                .into_iter()
+                .zip(raw.s)
+                .map(|(range, count)| StatementCoverage {
+                    range: SourceRange::new(range),
+                    count,
+                })
+                .collect(),
+            functions: raw
+                .functions
+                .into_iter()
+                .zip(raw.f)
+                .map(|(f, count)| FunctionCoverage {
+                    name: f.0,
+                    range: SourceRange::new([f.1, f.2, f.3, f.4]),
+                    count,
+                })
+                .collect(),
+            branches: raw
+                .branches
+                .into_iter()
+                .zip(raw.b)
+                .map(|(b, counts)| BranchCoverage {
+                    kind: b.0,
+                    line_number: b.1,
+                    column_number: b.2,
+                    counts,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// collect the coverage of the scripts which were instrumented by a CoveragePreProcessor and ran in a realm
+pub fn collect_coverage(realm: &QuickJsRealmAdapter) -> Result<CoverageReport, JsError> {
+    let global = get_global_q(realm);
+    let coverage = objects::get_property_q(realm, &global, COVERAGE_GLOBAL)?;
+    if !coverage.is_object() {
+        return Ok(CoverageReport::default());
+    }
+    let json_str = json::stringify_q(realm, &coverage, None)?.to_string()?;
+    let raw: HashMap<String, RawFileCoverage> = serde_json::from_str(json_str.as_str())
+        .map_err(|e| JsError::new_string(format!("invalid coverage data: {e}")))?;
+    Ok(CoverageReport {
+        files: raw
+            .into_iter()
+            .map(|(path, file)| (path, file.into()))
+            .collect(),
+    })
+}
+
+/// set all counters of a realm to 0, e.g. before running the next test suite
+pub fn reset_coverage(realm: &QuickJsRealmAdapter) -> Result<(), JsError> {
+    let global = get_global_q(realm);
+    let coverage = objects::get_property_q(realm, &global, COVERAGE_GLOBAL)?;
+    if !coverage.is_object() {
+        return Ok(());
+    }
+    objects::traverse_properties_q(realm, &coverage, |_path, file| {
+        for counters in ["s", "f", "b"] {
+            let counters = objects::get_property_q(realm, file, counters)?;
+            reset_counters(realm, &counters)?;
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+fn reset_counters(
+    realm: &QuickJsRealmAdapter,
+    counters: &QuickJsValueAdapter,
+) -> Result<(), JsError> {
+    let zero = primitives::from_i32(0);
+    for index in 0..arrays::get_length_q(realm, counters)? {
+        let counter = arrays::get_element_q(realm, counters, index)?;
+        if arrays::is_array_q(realm, &counter) {
+            reset_counters(realm, &counter)?;
+        } else {
+            arrays::set_element_q(realm, counters, index, &zero)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::Script;
+    use crate::typescript::coverage::{collect_coverage, reset_coverage, CoveragePreProcessor};
+
+    #[test]
+    fn test_coverage() {
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_pre_processor(
+                CoveragePreProcessor::new().filter(|path| path.starts_with("cov/")),
+            )
+            .build();
+
+        let code = r#"'use strict';
+function grade(score: number): string {
+    if (score > 90) {
+        return "A";
+    } else if (score > 50) return "B";
+    return score > 10 ? "C" : "D";
+}
+const twice = (a: number) => a * 2;
+let grades = [grade(95), grade(60), grade(60), grade(twice(2))];
+grades.join(",");
+"#;
+        let res = rt
+            .eval_sync(None, Script::new("cov/grade.ts", code))
+            .expect("script failed");
+        assert_eq!(res.get_str(), "A,B,B,D");
+
+        let report = rt
+            .loop_realm_sync(None, |_rt, realm| collect_coverage(realm))
+            .expect("collect failed");
+        let file = report.files.get("cov/grade.ts").expect("no coverage");
+        let grade = file
+            .functions
+            .iter()
+            .find(|f| f.name == "grade")
+            .expect("no grade");
+        assert_eq!(grade.count, 4);
+        assert_eq!(grade.range.line_number, 2);
+        let twice = file
+            .functions
+            .iter()
+            .find(|f| f.name == "twice")
+            .expect("no twice");
+        assert_eq!(twice.count, 1);
+        let lines = file.get_line_counts();
+        assert_eq!(lines.get(&4), Some(&1));
+        assert_eq!(lines.get(&6), Some(&1));
+        assert_eq!(lines.get(&9), Some(&1));
+        assert_eq!(file.branches[0].counts, vec![1, 3]);
+        assert_eq!(file.branches[1].counts, vec![2, 1]);
+        assert_eq!(file.branches[2].kind, "cond");
+        assert_eq!(file.branches[2].counts, vec![0, 1]);
+
+        let lcov = report.to_lcov();
+        assert!(lcov.starts_with("TN:\nSF:cov/grade.ts\n"));
+        assert!(lcov.contains("FNDA:4,grade\n"));
+        assert!(lcov.contains("BRDA:6,2,0,0\n"));
+        assert!(report.to_json().contains("\"cov/grade.ts\""));
+
+        // line numbers of errors are not changed by the counters
+        let err = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "cov/throws.ts",
+                    "let a: number = 1;\nif (a > 0) { a++; }\nthrow new Error('boom');\n",
+                ),
+            )
+            .expect_err("script passed");
+        assert_eq!(err.get_line_number(), Some(3));
+
+        // the counters of a classic script are not declared as globals
+        let globals = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "globals.js",
+                    "Object.keys(globalThis).filter((k) => k.startsWith('__qjsrt_cov')).join(',')",
+                ),
+            )
+            .expect("script failed");
+        assert_eq!(globals.get_str(), "__qjsrt_coverage__");
+
+        rt.loop_realm_sync(None, |_rt, realm| reset_coverage(realm))
+            .expect("reset failed");
+        let report = rt
+            .loop_realm_sync(None, |_rt, realm| collect_coverage(realm))
+            .expect("collect failed");
+        assert!(report.files["cov/grade.ts"]
+            .statements
+            .iter()
+            .all(|s| s.count == 0));
+    }
+}
@@ -0,0 +1,303 @@
+//! a ScriptPreProcessor which lints scripts with the rules of swc_ecma_lints
+//!
+//! the rules duplicate-bindings, duplicate-exports, no-dupe-args and const-assign are always enabled and report errors,
+//! other rules are enabled with a LintLevel, rule names are the eslint names (e.g. "no-debugger" or "eqeqeq")
+//!
+//! # Example
+//! ```rust
+//! use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+//! use quickjs_runtime::jsutils::Script;
+//! use quickjs_runtime::typescript::lint::{LintLevel, LintPreProcessor};
+//! let linter = LintPreProcessor::new(&[("no-debugger", LintLevel::Error), ("eqeqeq", LintLevel::Warning)])
+//!     .expect("invalid rules")
+//!     .on_diagnostic(|path, diagnostic| println!("{path}: {diagnostic}"));
+//! // lint a script without running it, e.g. when it is uploaded
+//! let findings = linter.lint(&Script::new("upload.js", "if (a == 1) { debugger; }")).expect("parse failed");
+//! assert_eq!(findings.len(), 2);
+//! // or fail evaluation of scripts with errors
+//! let rt = QuickJsRuntimeBuilder::new().script_pre_processor(linter).build();
+//! assert!(rt.eval_sync(None, Script::new("tenant.js", "debugger;")).is_err());
+//! ```
+
+use crate::jsutils::diagnostics::{Diagnostic, DiagnosticSeverity};
+use crate::jsutils::{JsError, Script, ScriptPreProcessor};
+use crate::typescript::{collect_diagnostics, CollectingEmitter, SourceSyntax, TypeScriptOptions};
+use std::sync::{Arc, Mutex};
+use swc_common::errors::{Handler, HANDLER};
+use swc_common::{FileName, Mark, SourceMap, SyntaxContext};
+use swc_ecma_ast::{EsVersion, Program};
+use swc_ecma_lints::config::LintConfig;
+use swc_ecma_lints::rule::Rule;
+use swc_ecma_lints::rules::LintParams;
+use swc_ecma_parser::{Parser, StringInput};
+use swc_ecma_transforms_base::resolver;
+use swc_ecma_visit::VisitMutWith;
+
+/// the rules which are always enabled by swc_ecma_lints
+const CRITICAL_RULES: &[&str] = &[
+    "duplicate-bindings",
+    "duplicate-exports",
+    "no-dupe-args",
+    "const-assign",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LintLevel {
+    Off,
+    Warning,
+    Error,
+}
+
+impl LintLevel {
+    fn as_str(&self) -> &str {
+        match self {
+            LintLevel::Off => "off",
+            LintLevel::Warning => "warning",
+            LintLevel::Error => "error",
+        }
+    }
+}
+
+type DiagnosticReporter = Box<dyn Fn(&str, &Diagnostic) + Send>;
+
+pub struct LintPreProcessor {
+    config: LintConfig,
+    config_json: String,
+    fail_on: Option<DiagnosticSeverity>,
+    reporter: Option<DiagnosticReporter>,
+}
+
+impl LintPreProcessor {
+    /// create a new LintPreProcessor with (rule, level) pairs, by default evaluation fails when a rule reports an error
+    pub fn new(rules: &[(&str, LintLevel)]) -> Result<Self, JsError> {
+        // the default config tells us which rules exist and how their config looks
+        let mut config_json = serde_json::to_value(LintConfig::default())
+            .map_err(|e| JsError::new_string(format!("{e}")))?;
+        for (name, level) in rules {
+            if CRITICAL_RULES.contains(name) {
+                if *level != LintLevel::Error {
+                    return Err(JsError::new_string(format!(
+                        "lint rule {name} is always enabled as error"
+                    )));
+                }
+                continue;
+            }
+            let rule = config_json
+                .get_mut(*name)
+                .ok_or_else(|| JsError::new_string(format!("unknown lint rule: {name}")))?;
+            let reaction = serde_json::Value::from(level.as_str());
+            match rule {
+                serde_json::Value::Array(parts) if !parts.is_empty() => parts[0] = reaction,
+                _ => *rule = reaction,
+            }
+        }
+        let config: LintConfig = serde_json::from_value(config_json.clone())
+            .map_err(|e| JsError::new_string(format!("invalid lint config: {e}")))?;
+        Ok(Self {
+            config,
+            config_json: config_json.to_string(),
+            fail_on: Some(DiagnosticSeverity::Error),
+            reporter: None,
+        })
+    }
+
+    /// fail evaluation when a rule reports a finding with this severity (or worse), None to never fail
+    pub fn fail_on(mut self, severity: Option<DiagnosticSeverity>) -> Self {
+        self.fail_on = severity;
+        self
+    }
+
+    /// called with the path of the script for every finding, when not set findings are logged
+    pub fn on_diagnostic<R: Fn(&str, &Diagnostic) + Send + 'static>(mut self, reporter: R) -> Self {
+        self.reporter = Some(Box::new(reporter));
+        self
+    }
+
+    /// lint a script, this returns an Err if the script could not be parsed
+    pub fn lint(&self, script: &Script) -> Result<Vec<Diagnostic>, JsError> {
+        let path = script.get_path();
+        let syntax = match TypeScriptOptions::new().get_source_kind(path) {
+            Some((syntax, _)) => syntax,
+            None => SourceSyntax::JavaScript,
+        };
+
+        let source_map = Arc::<SourceMap>::default();
+        let collected = Arc::new(Mutex::new(vec![]));
+        let handler = Handler::with_emitter(
+            true,
+            false,
+            Box::new(CollectingEmitter {
+                diagnostics: collected.clone(),
+            }),
+        );
+
+        let globals = swc_common::Globals::new();
+        let parsed = swc_common::GLOBALS.set(&globals, || {
+            let fm = source_map.new_source_file(
+                FileName::Custom(path.to_string()),
+                script.get_code().to_string(),
+            );
+            let mut parser = Parser::new(syntax.parser_syntax(), StringInput::from(&*fm), None);
+            let program = parser.parse_program();
+            let mut parsed = true;
+            for e in parser.take_errors() {
+                e.into_diagnostic(&handler).emit();
+                parsed = false;
+            }
+            let mut program = match program {
+                Ok(program) => program,
+                Err(e) => {
+                    e.into_diagnostic(&handler).emit();
+                    return false;
+                }
+            };
+            if !parsed {
+                return false;
+            }
+
+            let unresolved_mark = Mark::new();
+            let top_level_mark = Mark::new();
+            program.visit_mut_with(&mut resolver(
+                unresolved_mark,
+                top_level_mark,
+                syntax == SourceSyntax::TypeScript || syntax == SourceSyntax::Tsx,
+            ));
+
+            let mut rules = swc_ecma_lints::rules::all(LintParams {
+                program: &program,
+                lint_config: &self.config,
+                unresolved_ctxt: SyntaxContext::empty().apply_mark(unresolved_mark),
+                top_level_ctxt: SyntaxContext::empty().apply_mark(top_level_mark),
+                // the newest version of the pinned swc_ecma_ast
+                es_version: EsVersion::Es2022,
+                source_map: source_map.clone(),
+            });
+            HANDLER.set(&handler, || match &program {
+                Program::Module(module) => rules.lint_module(module),
+                Program::Script(s) => rules.lint_script(s),
+            });
+            true
+        });
+
+        let diagnostics = collect_diagnostics(&source_map, &collected, path);
+        if parsed {
+            Ok(diagnostics)
+        } else {
+            let message = diagnostics
+                .first()
+                .map(|d| d.message.clone())
+                .unwrap_or_else(|| format!("could not parse {path}"));
+            Err(
+                JsError::new("SyntaxError".to_string(), message, "".to_string())
+                    .with_diagnostics(diagnostics),
+            )
+        }
+    }
+}
+
+impl ScriptPreProcessor for LintPreProcessor {
+    fn process(&self, script: &mut Script) -> Result<(), JsError> {
+        let diagnostics = self.lint(script)?;
+        for diagnostic in &diagnostics {
+            match &self.reporter {
+                Some(reporter) => reporter(script.get_path(), diagnostic),
+                None => log::warn!("{}: {}", script.get_path(), diagnostic),
+            }
+        }
+        if let Some(fail_on) = self.fail_on {
+            let failed: Vec<Diagnostic> = diagnostics
+                .into_iter()
+                .filter(|d| rank(d.severity) >= rank(fail_on))
+                .collect();
+            if let Some(first) = failed.first() {
+                let stack = match (first.line_number, first.column_number) {
+                    (Some(line_number), Some(column_number)) => format!(
+                        "    at {}:{line_number}:{column_number}\n",
+                        script.get_path()
+                    ),
+                    _ => "".to_string(),
+                };
+                return Err(
+                    JsError::new("LintError".to_string(), first.message.clone(), stack)
+                        .with_diagnostics(failed),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn get_cache_key(&self) -> String {
+        format!("LintPreProcessor:{}:{:?}", self.config_json, self.fail_on)
+    }
+}
+
+fn rank(severity: DiagnosticSeverity) -> u8 {
+    match severity {
+        DiagnosticSeverity::Error => 3,
+        DiagnosticSeverity::Warning => 2,
+        DiagnosticSeverity::Note | DiagnosticSeverity::Help => 1,
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::builder::QuickJsRuntimeBuilder;
+    use crate::jsutils::diagnostics::DiagnosticSeverity;
+    use crate::jsutils::Script;
+    use crate::typescript::lint::{LintLevel, LintPreProcessor};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_lint() {
+        assert!(LintPreProcessor::new(&[("no-such-rule", LintLevel::Error)]).is_err());
+
+        let linter =
+            LintPreProcessor::new(&[("no-debugger", LintLevel::Error)]).expect("invalid rules");
+        let findings = linter
+            .lint(&Script::new(
+                "dupes.js",
+                "function f(a, a) { return a; }\nlet b = 1;\nlet b = 2;",
+            ))
+            .expect("lint failed");
+        assert!(findings.len() >= 2);
+        assert!(findings
+            .iter()
+            .all(|d| d.severity == DiagnosticSeverity::Error));
+        assert!(findings.iter().any(|d| d.line_number == Some(1)));
+        assert!(findings.iter().any(|d| d.line_number == Some(3)));
+        assert!(linter
+            .lint(&Script::new("broken.ts", "let a: = 1;"))
+            .is_err());
+
+        let reported = Arc::new(Mutex::new(vec![]));
+        let reported2 = reported.clone();
+        let linter = LintPreProcessor::new(&[
+            ("no-debugger", LintLevel::Error),
+            ("eqeqeq", LintLevel::Warning),
+        ])
+        .expect("invalid rules")
+        .on_diagnostic(move |path, diagnostic| {
+            reported2
+                .lock()
+                .unwrap()
+                .push(format!("{path}:{:?}", diagnostic.severity));
+        });
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_pre_processor(linter)
+            .build();
+
+        let res = rt
+            .eval_sync(None, Script::new("warn.ts", "let a: number = 1; a == 1;"))
+            .expect("script failed");
+        assert!(res.get_bool());
+        let err = rt
+            .eval_sync(None, Script::new("fail.js", "let c = 1;\ndebugger;"))
+            .expect_err("script passed");
+        assert_eq!(err.get_name(), "LintError");
+        assert_eq!(err.get_line_number(), Some(2));
+        assert_eq!(
+            reported.lock().unwrap().as_slice(),
+            &["warn.ts:Warning".to_string(), "fail.js:Error".to_string()]
+        );
+    }
+}
@@ -1,18 +1,38 @@
 // public transpile function which can also be used by gcs to transpile clientside ts
 
+use crate::jsutils::diagnostics::{code_frame, Diagnostic, DiagnosticSeverity};
 use crate::jsutils::JsError;
 use crate::jsutils::Script;
-use crate::quickjs_utils::modules::detect_module;
+use crate::quickjs_utils::modules::{
+    detect_module, strip_attributes_clause, strip_module_version, ImportScan,
+};
 use crate::quickjsruntimeadapter::QuickJsRuntimeAdapter;
+use crate::typescript::cache::{CachedTranspile, TranspileCache};
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::io;
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use swc::Compiler;
-use swc_common::errors::{ColorConfig, Handler};
-use swc_common::{FileName, SourceMap};
+use swc_atoms::JsWord;
+use swc_common::errors::{DiagnosticBuilder, Emitter, Handler, Level};
+use swc_common::sync::Lrc;
+use swc_common::{BytePos, FileName, SourceFile, SourceMap, Span, Spanned};
+use swc_ecma_ast::{
+    CallExpr, Callee, Expr, Ident, ModuleDecl, ModuleItem, Pat, PatOrExpr, Program, Prop,
+    UpdateExpr,
+};
+use swc_ecma_parser::lexer::Lexer;
+use swc_ecma_parser::token::{BinOpToken, Keyword, Token, TokenAndSpan, Word};
+use swc_ecma_parser::{Parser, StringInput};
+use swc_ecma_visit::{Visit, VisitWith};
 
+pub mod bundler;
+pub mod cache;
+pub mod coverage;
+pub mod lint;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum TargetVersion {
     Es3,
     Es5,
@@ -35,50 +55,426 @@ impl TargetVersion {
     }
 }
 
-pub struct TypeScriptTranspiler {
+/// the decorators flavour to transpile
+/// the pinned swc version only implements the legacy decorators, the standard (2023) decorators need a newer swc
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decorators {
+    /// experimentalDecorators as used by TypeScript before 5.0 (with decorator metadata)
+    Legacy,
+}
+
+/// the runtime used for transpiling JSX
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JsxRuntime {
+    /// transpile to calls to the pragma function (React.createElement by default)
+    Classic,
+    /// transpile to calls to jsx() which is imported from {import_source}/jsx-runtime
+    Automatic,
+}
+
+impl JsxRuntime {
+    fn as_str(&self) -> &str {
+        match self {
+            JsxRuntime::Classic => "classic",
+            JsxRuntime::Automatic => "automatic",
+        }
+    }
+}
+
+/// the syntax a source file is parsed with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SourceSyntax {
+    /// TypeScript without JSX (.ts, .mts, .cts)
+    TypeScript,
+    /// TypeScript with JSX (.tsx)
+    Tsx,
+    /// JavaScript with JSX (.jsx)
+    Jsx,
+    /// plain JavaScript, only transpiled for extensions which are mapped with TypeScriptOptions::extension
+    JavaScript,
+}
+
+/// how the transpiler decides between module and script code
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModuleMode {
+    /// module for .mts and .mjs, script for .cts and .cjs, else detect by the presence of import/export
+    Detect,
+    /// always transpile as module
+    Module,
+    /// always transpile as script
+    Script,
+}
+
+/// the extensions which are transpiled by default
+const DEFAULT_EXTENSIONS: &[(&str, SourceSyntax, ModuleMode)] = &[
+    (".ts", SourceSyntax::TypeScript, ModuleMode::Detect),
+    (".tsx", SourceSyntax::Tsx, ModuleMode::Detect),
+    (".jsx", SourceSyntax::Jsx, ModuleMode::Detect),
+    (".mts", SourceSyntax::TypeScript, ModuleMode::Module),
+    (".cts", SourceSyntax::TypeScript, ModuleMode::Script),
+];
+
+impl SourceSyntax {
+    pub(crate) fn parser_syntax(&self) -> swc_ecma_parser::Syntax {
+        match self {
+            SourceSyntax::TypeScript | SourceSyntax::Tsx => {
+                swc_ecma_parser::Syntax::Typescript(swc_ecma_parser::TsConfig {
+                    tsx: *self == SourceSyntax::Tsx,
+                    decorators: true,
+                    ..Default::default()
+                })
+            }
+            SourceSyntax::Jsx | SourceSyntax::JavaScript => {
+                swc_ecma_parser::Syntax::Es(swc_ecma_parser::EsConfig {
+                    jsx: *self == SourceSyntax::Jsx,
+                    decorators: true,
+                    ..Default::default()
+                })
+            }
+        }
+    }
+
+    fn parser_config(&self) -> serde_json::Value {
+        let (syntax, jsx_key, jsx) = match self {
+            SourceSyntax::TypeScript => ("typescript", "tsx", false),
+            SourceSyntax::Tsx => ("typescript", "tsx", true),
+            SourceSyntax::Jsx => ("ecmascript", "jsx", true),
+            SourceSyntax::JavaScript => ("ecmascript", "jsx", false),
+        };
+        let mut parser = serde_json::json!({
+            "syntax": syntax,
+            "decorators": true,
+            "decoratorsBeforeExport": true,
+            "dynamicImport": true,
+            "preserveAllComments": false
+        });
+        parser[jsx_key] = serde_json::Value::from(jsx);
+        parser
+    }
+}
+
+type TranspileFilter = Box<dyn Fn(&str) -> bool + Send>;
+
+/// options for the TypeScript transpiler
+/// # Example
+/// ```rust
+/// use quickjs_runtime::builder::QuickJsRuntimeBuilder;
+/// use quickjs_runtime::typescript::{Decorators, TargetVersion, TypeScriptOptions};
+/// let options = TypeScriptOptions::new()
+///     .target(TargetVersion::Es2022)
+///     .decorators(Decorators::Legacy)
+///     .transpile_filter(|path| !path.starts_with("file:///vendor/"));
+/// let rt = QuickJsRuntimeBuilder::new().typescript_options(options).build();
+/// ```
+pub struct TypeScriptOptions {
+    target: TargetVersion,
+    decorators: Decorators,
+    jsx_runtime: JsxRuntime,
+    jsx_pragma: Option<String>,
+    jsx_pragma_frag: Option<String>,
+    jsx_import_source: Option<String>,
+    jsx_refresh: bool,
+    use_define_for_class_fields: bool,
     minify: bool,
     mangle: bool,
     external_helpers: bool,
-    target: TargetVersion,
+    transpile_filter: Option<TranspileFilter>,
+    extensions: Vec<(String, SourceSyntax, ModuleMode)>,
+    collect_warnings: bool,
+    cache: Option<Arc<TranspileCache>>,
+}
+
+impl TypeScriptOptions {
+    /// create the default options (es2020, legacy decorators, classic jsx runtime)
+    pub fn new() -> Self {
+        Self {
+            target: TargetVersion::Es2020,
+            decorators: Decorators::Legacy,
+            jsx_runtime: JsxRuntime::Classic,
+            jsx_pragma: None,
+            jsx_pragma_frag: None,
+            jsx_import_source: None,
+            jsx_refresh: true,
+            use_define_for_class_fields: true,
+            minify: false,
+            mangle: false,
+            external_helpers: true,
+            transpile_filter: None,
+            extensions: vec![],
+            collect_warnings: false,
+            cache: None,
+        }
+    }
+
+    /// the ecmascript version to transpile to
+    pub fn target(mut self, target: TargetVersion) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// the decorators flavour to transpile
+    /// decorators use the _ts_decorate helper, scripts need external_helpers(false) unless @swc/helpers can be imported
+    pub fn decorators(mut self, decorators: Decorators) -> Self {
+        self.decorators = decorators;
+        self
+    }
+
+    /// the runtime used for transpiling JSX
+    pub fn jsx_runtime(mut self, runtime: JsxRuntime) -> Self {
+        self.jsx_runtime = runtime;
+        self
+    }
+
+    /// the function used to create elements with the classic runtime, e.g. "h"
+    pub fn jsx_pragma(mut self, pragma: &str) -> Self {
+        self.jsx_pragma = Some(pragma.to_string());
+        self
+    }
+
+    /// the function used to create fragments with the classic runtime, e.g. "Fragment"
+    pub fn jsx_pragma_frag(mut self, pragma_frag: &str) -> Self {
+        self.jsx_pragma_frag = Some(pragma_frag.to_string());
+        self
+    }
+
+    /// the module which provides jsx-runtime for the automatic runtime, e.g. "preact"
+    pub fn jsx_import_source(mut self, import_source: &str) -> Self {
+        self.jsx_import_source = Some(import_source.to_string());
+        self
+    }
+
+    /// enable react refresh transforms
+    pub fn jsx_refresh(mut self, refresh: bool) -> Self {
+        self.jsx_refresh = refresh;
+        self
+    }
+
+    /// see useDefineForClassFields in tsconfig
+    pub fn use_define_for_class_fields(mut self, use_define: bool) -> Self {
+        self.use_define_for_class_fields = use_define;
+        self
+    }
+
+    /// minify the transpiled code
+    pub fn minify(mut self, minify: bool) -> Self {
+        self.minify = minify;
+        self
+    }
+
+    /// mangle names when minifying
+    pub fn mangle(mut self, mangle: bool) -> Self {
+        self.mangle = mangle;
+        self
+    }
+
+    /// import helpers from @swc/helpers instead of inlining them
+    pub fn external_helpers(mut self, external_helpers: bool) -> Self {
+        self.external_helpers = external_helpers;
+        self
+    }
+
+    /// only transpile a script if the filter returns true for its path
+    pub fn transpile_filter<F: Fn(&str) -> bool + Send + 'static>(mut self, filter: F) -> Self {
+        self.transpile_filter = Some(Box::new(filter));
+        self
+    }
+
+    /// also collect warnings (errors are always collected), see TypeScriptTranspiler::transpile_with_diagnostics
+    pub fn collect_warnings(mut self, collect_warnings: bool) -> Self {
+        self.collect_warnings = collect_warnings;
+        self
+    }
+
+    /// cache the transpiled code (and source maps) in a TranspileCache, the cache may be shared by multiple runtimes
+    pub fn cache(mut self, cache: Arc<TranspileCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// transpile files with a custom extension (e.g. ".es6" or ".mjs"), these mappings are checked before the defaults
+    /// # Example
+    /// ```rust
+    /// use quickjs_runtime::typescript::{ModuleMode, SourceSyntax, TypeScriptOptions};
+    /// let options = TypeScriptOptions::new()
+    ///     .extension(".es6", SourceSyntax::JavaScript, ModuleMode::Module);
+    /// ```
+    pub fn extension(mut self, extension: &str, syntax: SourceSyntax, mode: ModuleMode) -> Self {
+        self.extensions.push((extension.to_string(), syntax, mode));
+        self
+    }
+
+    /// get the syntax and module mode for a path, None if files with this path are not transpiled
+    pub fn get_source_kind(&self, path: &str) -> Option<(SourceSyntax, ModuleMode)> {
+        if let Some((_, syntax, mode)) = self
+            .extensions
+            .iter()
+            .find(|(ext, _, _)| path.ends_with(ext.as_str()))
+        {
+            return Some((*syntax, *mode));
+        }
+        DEFAULT_EXTENSIONS
+            .iter()
+            .find(|(ext, _, _)| path.ends_with(ext))
+            .map(|(_, syntax, mode)| (*syntax, *mode))
+    }
+
+    /// check if a script with this path should be transpiled
+    pub fn should_transpile(&self, path: &str) -> bool {
+        if self.get_source_kind(path).is_none() {
+            return false;
+        }
+        match &self.transpile_filter {
+            None => true,
+            Some(filter) => filter(path),
+        }
+    }
+
+    /// a key which identifies these options, used as part of the key of the bytecode and transpile cache
+    /// note that the transpile_filter is not part of the key
+    pub fn get_cache_key(&self) -> String {
+        format!(
+            "typescript:{}:{:?}:{}:{}:{}:{}:{}:{}:{}:{}:{}:{}:{:?}",
+            self.target.as_str(),
+            self.decorators,
+            self.jsx_runtime.as_str(),
+            self.jsx_pragma.as_deref().unwrap_or(""),
+            self.jsx_pragma_frag.as_deref().unwrap_or(""),
+            self.jsx_import_source.as_deref().unwrap_or(""),
+            self.jsx_refresh,
+            self.use_define_for_class_fields,
+            self.minify,
+            self.mangle,
+            self.external_helpers,
+            self.collect_warnings,
+            self.extensions
+        )
+    }
+
+    fn transform_config(&self) -> serde_json::Value {
+        let mut react = serde_json::json!({
+            "runtime": self.jsx_runtime.as_str(),
+            "useBuiltins": true,
+            "refresh": self.jsx_refresh
+        });
+        if let Some(pragma) = &self.jsx_pragma {
+            react["pragma"] = serde_json::Value::from(pragma.as_str());
+        }
+        if let Some(pragma_frag) = &self.jsx_pragma_frag {
+            react["pragmaFrag"] = serde_json::Value::from(pragma_frag.as_str());
+        }
+        if let Some(import_source) = &self.jsx_import_source {
+            react["importSource"] = serde_json::Value::from(import_source.as_str());
+        }
+        let mut transform = serde_json::json!({
+            "useDefineForClassFields": self.use_define_for_class_fields,
+            "react": react
+        });
+        match self.decorators {
+            Decorators::Legacy => {
+                transform["legacyDecorator"] = serde_json::Value::from(true);
+                transform["decoratorMetadata"] = serde_json::Value::from(true);
+            }
+        }
+        transform
+    }
+}
+
+impl Default for TypeScriptOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct TypeScriptTranspiler {
+    options: TypeScriptOptions,
     compiler: Compiler,
     source_map: Arc<SourceMap>,
 }
 
 impl TypeScriptTranspiler {
     pub fn new(target: TargetVersion, minify: bool, external_helpers: bool, mangle: bool) -> Self {
+        Self::with_options(
+            TypeScriptOptions::new()
+                .target(target)
+                .minify(minify)
+                .external_helpers(external_helpers)
+                .mangle(mangle),
+        )
+    }
+
+    /// create a transpiler based on TypeScriptOptions
+    pub fn with_options(options: TypeScriptOptions) -> Self {
         let source_map = Arc::<SourceMap>::default();
         let compiler = swc::Compiler::new(source_map.clone());
 
         Self {
-            minify,
-            mangle,
-            external_helpers,
-            target,
+            options,
             source_map,
             compiler,
         }
     }
-    // todo custom target
+
+    pub fn get_options(&self) -> &TypeScriptOptions {
+        &self.options
+    }
+
+    /// transpile code, the syntax is based on the extension of file_name (TSX for unknown extensions)
+    /// warnings are logged, see transpile_with_diagnostics
     pub fn transpile(
         &self,
         code: &str,
         file_name: &str,
         is_module: bool,
     ) -> Result<(String, Option<String>), JsError> {
+        let (code, map, diagnostics) =
+            self.transpile_with_diagnostics(code, file_name, is_module)?;
+        for diagnostic in diagnostics {
+            log::warn!("{diagnostic}");
+        }
+        Ok((code, map))
+    }
+
+    /// transpile code and return the warnings (if TypeScriptOptions::collect_warnings is set) with the result
+    /// when transpilation fails the JsError is a SyntaxError at the location of the first error
+    /// and JsError::get_diagnostics returns all errors (and warnings)
+    pub fn transpile_with_diagnostics(
+        &self,
+        code: &str,
+        file_name: &str,
+        is_module: bool,
+    ) -> Result<(String, Option<String>, Vec<Diagnostic>), JsError> {
+        let syntax = self
+            .options
+            .get_source_kind(file_name)
+            .map(|(syntax, _)| syntax)
+            .unwrap_or(SourceSyntax::Tsx);
+        self.transpile_as(code, file_name, syntax, is_module.then_some("es6"))
+    }
+
+    /// transpile code with an explicit syntax, module_type is the swc module type (e.g. "es6" or "commonjs") for modules and None for scripts
+    pub(crate) fn transpile_as(
+        &self,
+        code: &str,
+        file_name: &str,
+        syntax: SourceSyntax,
+        module_type: Option<&str>,
+    ) -> Result<(String, Option<String>, Vec<Diagnostic>), JsError> {
         let globals = swc_common::Globals::new();
         swc_common::GLOBALS.set(&globals, || {
-            let handler = Handler::with_tty_emitter(
-                ColorConfig::Auto,
-                true,
+            let collected = Arc::new(Mutex::new(vec![]));
+            let handler = Handler::with_emitter(
+                self.options.collect_warnings,
                 false,
-                Some(self.source_map.clone()),
+                Box::new(CollectingEmitter {
+                    diagnostics: collected.clone(),
+                }),
             );
 
             let fm = self
                 .source_map
                 .new_source_file(FileName::Custom(file_name.into()), code.into());
 
-            let mangle_config = if self.mangle {
+            let mangle_config = if self.options.mangle {
                 r#"
                     {
                         "topLevel": false,
@@ -89,7 +485,7 @@ impl TypeScriptTranspiler {
                 "false"
             };
 
-            let minify_options = if self.minify {
+            let minify_options = if self.options.minify {
                 format!(
                     r#"
                 "minify": {{
@@ -114,19 +510,29 @@ impl TypeScriptTranspiler {
                 .to_string()
             };
 
-            let module = if is_module {
-                r#"
-                "module": {
-                    "type": "es6",
+            let module = if let Some(module_type) = module_type {
+                format!(
+                    r#"
+                "module": {{
+                    "type": "{module_type}",
                     "strict": true,
                     "strictMode": true,
                     "lazy": false,
                     "noInterop": false,
                     "ignoreDynamic": true
-                },
+                }},
                 "#
+                )
+            } else {
+                "".to_string()
+            };
+
+            // without a module type (and external helpers, which are imported) the code is only parsed as a module
+            // if it has imports or exports, top level declarations of a script are globals which the minifier may not remove
+            let is_module = if module_type.is_some() || self.options.external_helpers {
+                "true"
             } else {
-                ""
+                "\"unknown\""
             };
 
             let cfg_json = format!(
@@ -134,40 +540,28 @@ impl TypeScriptTranspiler {
 
             {{
               "minify": {},
+              "isModule": {},
               "sourceMaps": true,
               {}
               "jsc": {{
                 {}
                 "externalHelpers": {},
-                "parser": {{
-                  "syntax": "typescript",
-                  "jsx": true,
-                  "tsx": true,
-                  "decorators": true,
-                  "decoratorsBeforeExport": true,
-                  "dynamicImport": true,
-                  "preserveAllComments": false
-                }},
-                "transform": {{
-                  "legacyDecorator": true,
-                  "decoratorMetadata": true,
-                  "react": {{
-                      "runtime": "classic",
-                      "useBuiltins": true,
-                      "refresh": true
-                  }}
-                }},
+                "parser": {},
+                "transform": {},
                 "target": "{}",
                 "keepClassNames": true
               }}
             }}
 
         "#,
-                self.minify,
+                self.options.minify,
+                is_module,
                 module,
                 minify_options,
-                self.external_helpers,
-                self.target.as_str()
+                self.options.external_helpers,
+                syntax.parser_config(),
+                self.options.transform_config(),
+                self.options.target.as_str()
             );
 
             log::trace!("using config {}", cfg_json);
@@ -185,22 +579,75 @@ impl TypeScriptTranspiler {
 
             let res = self.compiler.process_js_file(fm, &handler, &ops);
 
+            let diagnostics = collect_diagnostics(&self.source_map, &collected, file_name);
+
             match res {
-                Ok(to) => Ok((to.code, to.map)),
-                Err(e) => Err(JsError::new_string(format!("transpile failed: {e}"))),
+                Ok(to) => Ok((to.code, to.map, diagnostics)),
+                Err(e) => {
+                    let first_error = diagnostics
+                        .iter()
+                        .find(|d| d.severity == DiagnosticSeverity::Error);
+                    let message = match first_error {
+                        Some(d) => d.message.clone(),
+                        None => format!("transpile failed: {e}"),
+                    };
+                    let stack = match first_error {
+                        Some(Diagnostic {
+                            line_number: Some(line_number),
+                            column_number: Some(column_number),
+                            ..
+                        }) => format!("    at {file_name}:{line_number}:{column_number}\n"),
+                        _ => "".to_string(),
+                    };
+                    Err(JsError::new("SyntaxError".to_string(), message, stack)
+                        .with_diagnostics(diagnostics))
+                }
             }
         })
     }
 
     pub fn transpile_script(&self, script: &mut Script) -> Result<(), JsError> {
-        if script.get_path().ends_with(".ts") {
+        if self.options.should_transpile(script.get_path()) {
             let code = script.get_code();
 
-            let is_module = detect_module(code);
+            let is_module = match self.options.get_source_kind(script.get_path()) {
+                Some((_, ModuleMode::Module)) => true,
+                Some((_, ModuleMode::Script)) => false,
+                _ => is_module_path(script.get_path()).unwrap_or_else(|| detect_module(code)),
+            };
 
-            let js = self.transpile(code, script.get_path(), is_module)?;
-            log::debug!("map: {:?}", js.1);
-            script.set_transpiled_code(js.0, js.1);
+            let transpile = || {
+                self.transpile_with_diagnostics(code, script.get_path(), is_module)
+                    .map(|(code, map, diagnostics)| CachedTranspile {
+                        code,
+                        map,
+                        diagnostics,
+                    })
+            };
+            let js = match &self.options.cache {
+                None => transpile()?,
+                Some(cache) => {
+                    let key = TranspileCache::key(
+                        script.get_path(),
+                        code,
+                        self.options.get_cache_key().as_str(),
+                    );
+                    match cache.get(key.as_str()) {
+                        Some(js) => js,
+                        None => {
+                            let js = transpile()?;
+                            cache.put(key.as_str(), js.clone());
+                            js
+                        }
+                    }
+                }
+            };
+            // the warnings of cached entries are logged again
+            for diagnostic in &js.diagnostics {
+                log::warn!("{diagnostic}");
+            }
+            log::debug!("map: {:?}", js.map);
+            script.set_transpiled_code(js.code, js.map);
         }
         log::debug!(
             "TypeScriptPreProcessor:process file={} result = {}",
@@ -214,14 +661,469 @@ impl TypeScriptTranspiler {
 
 impl Default for TypeScriptTranspiler {
     fn default() -> Self {
-        Self::new(TargetVersion::Es2020, false, true, false)
+        Self::with_options(TypeScriptOptions::new())
+    }
+}
+
+/// convert the diagnostics collected by a CollectingEmitter
+pub(crate) fn collect_diagnostics(
+    source_map: &SourceMap,
+    collected: &Mutex<Vec<swc_common::errors::Diagnostic>>,
+    file_name: &str,
+) -> Vec<Diagnostic> {
+    collected
+        .lock()
+        .map(|collected| {
+            collected
+                .iter()
+                .filter_map(|d| to_diagnostic(source_map, d, file_name))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn to_diagnostic(
+    source_map: &SourceMap,
+    diagnostic: &swc_common::errors::Diagnostic,
+    file_name: &str,
+) -> Option<Diagnostic> {
+    let severity = match diagnostic.level {
+        Level::Bug | Level::Fatal | Level::PhaseFatal | Level::Error => DiagnosticSeverity::Error,
+        Level::Warning => DiagnosticSeverity::Warning,
+        Level::Note => DiagnosticSeverity::Note,
+        Level::Help => DiagnosticSeverity::Help,
+        Level::Cancelled | Level::FailureNote => return None,
+    };
+    let mut ret = Diagnostic::new(severity, diagnostic.message().as_str());
+    ret.code = diagnostic.code.as_ref().map(|code| match code {
+        swc_common::errors::DiagnosticId::Error(s) => s.clone(),
+        swc_common::errors::DiagnosticId::Lint(s) => s.clone(),
+    });
+    if let Some(span) = diagnostic.span.primary_span() {
+        locate(source_map, &mut ret, span, file_name);
+    }
+    Some(ret)
+}
+
+fn locate(source_map: &SourceMap, diagnostic: &mut Diagnostic, span: Span, file_name: &str) {
+    if span.is_dummy() {
+        return;
+    }
+    let lo = source_map.lookup_char_pos(span.lo);
+    let line_number = lo.line as u32;
+    let column_number = lo.col.0 as u32 + 1;
+    let width = if span.hi > span.lo {
+        let hi = source_map.lookup_char_pos(span.hi);
+        if hi.line == lo.line {
+            hi.col.0 - lo.col.0
+        } else {
+            1
+        }
+    } else {
+        1
+    };
+    diagnostic.file_name = Some(file_name.to_string());
+    diagnostic.line_number = Some(line_number);
+    diagnostic.column_number = Some(column_number);
+    diagnostic.code_frame = lo
+        .file
+        .get_line(lo.line - 1)
+        .map(|line| code_frame(line.as_ref(), line_number, column_number, width));
+}
+
+/// collects the diagnostics of swc instead of printing them to stderr
+pub(crate) struct CollectingEmitter {
+    pub(crate) diagnostics: Arc<Mutex<Vec<swc_common::errors::Diagnostic>>>,
+}
+
+impl Emitter for CollectingEmitter {
+    fn emit(&mut self, db: &DiagnosticBuilder<'_>) {
+        if let Ok(diagnostics) = &mut self.diagnostics.lock() {
+            diagnostics.push((**db).clone());
+        }
+    }
+}
+
+/// module for .mts and .mjs, script for .cts and .cjs
+fn is_module_path(path: &str) -> Option<bool> {
+    if path.ends_with(".mts") || path.ends_with(".mjs") {
+        Some(true)
+    } else if path.ends_with(".cts") || path.ends_with(".cjs") {
+        Some(false)
+    } else {
+        None
     }
 }
 
 thread_local! {
     // we store this in a thread local inb the worker thread so they are dropped when the runtimefacade is dropped
     static SOURCE_MAPS: RefCell<HashMap<String, String>> = RefCell::new(HashMap::new());
-    static TRANSPILER: RefCell<TypeScriptTranspiler> = RefCell::new(TypeScriptTranspiler::new(TargetVersion::Es2020, false, true, false));
+    static TRANSPILER: RefCell<TypeScriptTranspiler> = RefCell::new(TypeScriptTranspiler::default());
+}
+
+/// replace the options of the server side transpiler of the current (worker) thread
+pub(crate) fn set_serverside_options(options: TypeScriptOptions) {
+    TRANSPILER.with(|rc| {
+        rc.replace(TypeScriptTranspiler::with_options(options));
+    })
+}
+
+/// the cache key of the options of the server side transpiler
+pub(crate) fn get_serverside_cache_key() -> String {
+    TRANSPILER.with(|rc| rc.borrow().get_options().get_cache_key())
+}
+
+/// an identifier which is read in the code of a script, see [find_identifier_references]
+pub(crate) struct IdentifierReference {
+    /// byte offset of the identifier in the code
+    pub start: usize,
+    pub end: usize,
+    pub name: String,
+    /// the identifier is a shorthand property ({name}) and should be replaced by name: value
+    pub shorthand: bool,
+}
+
+/// parse the code of a script with the syntax of its path and find the identifiers which are read, so not those which
+/// are declared, assigned to or used as property names or keys
+pub(crate) fn find_identifier_references(
+    path: &str,
+    code: &str,
+) -> Result<Vec<IdentifierReference>, JsError> {
+    let syntax = TRANSPILER
+        .with(|rc| rc.borrow().get_options().get_source_kind(path))
+        .map(|(syntax, _)| syntax)
+        .unwrap_or(SourceSyntax::JavaScript);
+    let source_map = Arc::<SourceMap>::default();
+    let fm = source_map.new_source_file(FileName::Custom(path.to_string()), code.to_string());
+    let mut parser = Parser::new(syntax.parser_syntax(), StringInput::from(&*fm), None);
+    let program = parser.parse_program().map_err(|e| {
+        JsError::new(
+            "SyntaxError".to_string(),
+            format!("could not parse {path}: {}", e.kind().msg()),
+            "".to_string(),
+        )
+    })?;
+    let mut collector = ReferenceCollector {
+        start_pos: fm.start_pos,
+        references: vec![],
+    };
+    program.visit_with(&mut collector);
+    collector.references.sort_by_key(|r| r.start);
+    Ok(collector.references)
+}
+
+struct ReferenceCollector {
+    start_pos: BytePos,
+    references: Vec<IdentifierReference>,
+}
+
+impl ReferenceCollector {
+    fn add(&mut self, ident: &Ident, shorthand: bool) {
+        self.references.push(IdentifierReference {
+            start: (ident.span.lo.0 - self.start_pos.0) as usize,
+            end: (ident.span.hi.0 - self.start_pos.0) as usize,
+            name: ident.sym.to_string(),
+            shorthand,
+        });
+    }
+}
+
+impl Visit for ReferenceCollector {
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Ident(ident) => self.add(ident, false),
+            _ => expr.visit_children_with(self),
+        }
+    }
+
+    fn visit_prop(&mut self, prop: &Prop) {
+        match prop {
+            Prop::Shorthand(ident) => self.add(ident, true),
+            _ => prop.visit_children_with(self),
+        }
+    }
+
+    // assignment targets are not read
+    fn visit_pat(&mut self, pat: &Pat) {
+        match pat {
+            Pat::Expr(expr) if expr.is_ident() => {}
+            _ => pat.visit_children_with(self),
+        }
+    }
+
+    fn visit_pat_or_expr(&mut self, target: &PatOrExpr) {
+        match target {
+            PatOrExpr::Expr(expr) if expr.is_ident() => {}
+            _ => target.visit_children_with(self),
+        }
+    }
+
+    fn visit_update_expr(&mut self, update: &UpdateExpr) {
+        if !update.arg.is_ident() {
+            update.arg.visit_with(self);
+        }
+    }
+}
+
+/// the syntax of a path with the options of the transpiler of this thread
+fn get_source_syntax(path: &str) -> SourceSyntax {
+    TRANSPILER
+        .with(|rc| rc.borrow().get_options().get_source_kind(path))
+        .map(|(syntax, _)| syntax)
+        .unwrap_or(SourceSyntax::JavaScript)
+}
+
+/// parse the code of a script with the syntax of its path, returns None if it could not be parsed
+fn parse_program(path: &str, code: &str) -> Option<(Program, Lrc<SourceFile>)> {
+    let syntax = get_source_syntax(path);
+    let source_map = Arc::<SourceMap>::default();
+    let fm = source_map.new_source_file(FileName::Custom(path.to_string()), code.to_string());
+    let mut parser = Parser::new(syntax.parser_syntax(), StringInput::from(&*fm), None);
+    match parser.parse_program() {
+        Ok(program) if parser.take_errors().is_empty() => Some((program, fm)),
+        _ => None,
+    }
+}
+
+/// find the specifiers of the static imports and exports (`export * from`) of a module, type only imports are skipped
+///
+/// returns None if the code could not be parsed
+pub(crate) fn find_static_imports(path: &str, code: &str) -> Option<Vec<String>> {
+    let (program, _) = parse_program(path, code)?;
+    let mut specifiers = vec![];
+    if let Program::Module(module) = program {
+        for item in &module.body {
+            let src = match item {
+                ModuleItem::ModuleDecl(ModuleDecl::Import(import)) if !import.type_only => {
+                    Some(&import.src)
+                }
+                ModuleItem::ModuleDecl(ModuleDecl::ExportAll(export)) => Some(&export.src),
+                ModuleItem::ModuleDecl(ModuleDecl::ExportNamed(export)) if !export.type_only => {
+                    export.src.as_ref()
+                }
+                _ => None,
+            };
+            if let Some(src) = src {
+                specifiers.push(src.value.to_string());
+            }
+        }
+    }
+    Some(specifiers)
+}
+
+/// find the static imports of a module and strip their attributes (`with { type: 'json' }` or `assert { type: 'json' }`)
+/// with the lexer of swc, the parser of the pinned swc does not know the `with` keyword
+///
+/// returns None if the code could not be tokenized, the import() calls are not searched
+pub(crate) fn scan_imports(path: &str, code: &str) -> Option<ImportScan> {
+    let source_map = Arc::<SourceMap>::default();
+    let fm = source_map.new_source_file(FileName::Custom(path.to_string()), code.to_string());
+    let mut lexer = Lexer::new(
+        get_source_syntax(path).parser_syntax(),
+        Default::default(),
+        StringInput::from(&*fm),
+        None,
+    );
+    let mut tokens: Vec<TokenAndSpan> = vec![];
+    while let Some(mut token) = lexer.next() {
+        // the lexer leaves regular expressions to the parser, a / where an expression may start is a regular expression
+        if matches!(
+            token.token,
+            Token::BinOp(BinOpToken::Div) | Token::AssignOp(swc_ecma_ast::AssignOp::DivAssign)
+        ) && tokens.last().map(|t| t.token.before_expr()) != Some(false)
+        {
+            lexer.set_next_regexp(Some(token.span.lo));
+            token = lexer.next()?;
+            lexer.set_next_regexp(None);
+        }
+        if let Token::Error(_) = token.token {
+            return None;
+        }
+        tokens.push(token);
+    }
+    let offset = |pos: BytePos| (pos.0 - fm.start_pos.0) as usize;
+
+    let mut imports = vec![];
+    // byte ranges of the attribute clauses with their type
+    let mut clauses = vec![];
+    let mut depth = 0;
+    for (index, token) in tokens.iter().enumerate() {
+        match &token.token {
+            Token::LBrace | Token::DollarLBrace => depth += 1,
+            Token::RBrace => depth -= 1,
+            // a static import or export is a string after import or from outside of blocks
+            Token::Str { value, .. }
+                if depth == 0
+                    && index > 0
+                    && (matches!(
+                        &tokens[index - 1].token,
+                        Token::Word(Word::Keyword(Keyword::Import))
+                    ) || is_word(&tokens[index - 1], "from")) =>
+            {
+                let mut module_type = None;
+                let keyword = tokens.get(index + 1);
+                if keyword.map(|t| is_word(t, "with") || is_word(t, "assert")) == Some(true)
+                    && matches!(tokens.get(index + 2).map(|t| &t.token), Some(Token::LBrace))
+                {
+                    let close = tokens[index + 3..]
+                        .iter()
+                        .position(|t| matches!(t.token, Token::RBrace))
+                        .map(|pos| index + 3 + pos);
+                    if let Some(close) = close {
+                        module_type = find_type_attribute(&tokens[index + 3..close]);
+                        // from the closing quote of the specifier
+                        clauses.push((
+                            offset(token.span.hi) - 1..offset(tokens[close].span.hi),
+                            module_type.clone(),
+                        ));
+                    }
+                }
+                imports.push((value.to_string(), module_type));
+            }
+            _ => {}
+        }
+    }
+
+    let code = if clauses.is_empty() {
+        None
+    } else {
+        let mut out = String::with_capacity(code.len());
+        let mut copied = 0;
+        for (clause, module_type) in clauses {
+            out.push_str(&code[copied..clause.start]);
+            out.push_str(
+                strip_attributes_clause(&code[clause.clone()], module_type.as_deref()).as_str(),
+            );
+            copied = clause.end;
+        }
+        out.push_str(&code[copied..]);
+        Some(out)
+    };
+    Some(ImportScan {
+        code,
+        imports,
+        dynamic_imports: vec![],
+    })
+}
+
+/// a word token (an identifier or keyword)
+fn is_word(token: &TokenAndSpan, word: &str) -> bool {
+    match &token.token {
+        Token::Word(w) => &*JsWord::from(w.clone()) == word,
+        _ => false,
+    }
+}
+
+/// get the type from the tokens of an attributes clause like `type: 'json'`
+fn find_type_attribute(tokens: &[TokenAndSpan]) -> Option<String> {
+    tokens.windows(3).find_map(|entry| {
+        let key = match &entry[0].token {
+            Token::Word(w) => JsWord::from(w.clone()),
+            Token::Str { value, .. } => value.clone(),
+            _ => return None,
+        };
+        match (&entry[1].token, &entry[2].token) {
+            (Token::Colon, Token::Str { value, .. }) if &*key == "type" => Some(value.to_string()),
+            _ => None,
+        }
+    })
+}
+
+/// replace the import() calls in the runnable code of a script by calls to another function,
+/// `import(specifier)` becomes `{callee}specifier)` so callee should end with an open call (e.g. `load("main.js", `)
+///
+/// the source map of the script is updated so stack traces still point to the original code,
+/// code which can not be parsed is not changed (the syntax error is reported when it is compiled)
+pub(crate) fn replace_dynamic_imports(script: &mut Script, callee: &str) -> Result<(), JsError> {
+    let code = script.get_runnable_code();
+    if !code.contains("import") {
+        return Ok(());
+    }
+    let (program, fm) = match parse_program(script.get_path(), code) {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
+    let mut collector = ImportCallCollector {
+        start_pos: fm.start_pos,
+        calls: vec![],
+    };
+    program.visit_with(&mut collector);
+    if collector.calls.is_empty() {
+        return Ok(());
+    }
+    collector.calls.sort_unstable();
+
+    let path = script.get_path().to_string();
+    let mut builder = swc::sourcemap::SourceMapBuilder::new(Some(path.as_str()));
+    builder.add(0, 0, 0, 0, Some(path.as_str()), None);
+    let callee_len = callee.chars().count() as u32;
+    let mut out = String::with_capacity(code.len() + collector.calls.len() * callee.len());
+    let mut calls = collector.calls.iter().peekable();
+    let (mut line, mut column, mut dst_column) = (0u32, 0u32, 0u32);
+    let mut skip_to = 0;
+    for (offset, c) in code.char_indices() {
+        if let Some((_, arg_start)) = calls.next_if(|(start, _)| *start == offset) {
+            out.push_str(callee);
+            dst_column += callee_len;
+            skip_to = *arg_start;
+        }
+        if offset < skip_to {
+            // the import keyword and the ( are replaced, line breaks are kept
+            if c == '\n' {
+                out.push('\n');
+                line += 1;
+                column = 0;
+                dst_column = 0;
+                builder.add(line, 0, line, 0, Some(path.as_str()), None);
+            } else {
+                column += 1;
+            }
+            if offset + c.len_utf8() == skip_to {
+                builder.add(line, dst_column, line, column, Some(path.as_str()), None);
+            }
+            continue;
+        }
+        out.push(c);
+        if c == '\n' {
+            line += 1;
+            column = 0;
+            dst_column = 0;
+            builder.add(line, 0, line, 0, Some(path.as_str()), None);
+        } else {
+            column += 1;
+            dst_column += 1;
+        }
+    }
+    let mut map: Vec<u8> = vec![];
+    // writing to a Vec does not fail
+    let _ = builder.into_sourcemap().to_writer(&mut map);
+    let map = String::from_utf8(map).unwrap_or_default();
+
+    let map = match script.get_map() {
+        Some(transpiled_map) => compose_source_maps(&[transpiled_map, map.as_str()])?,
+        None => map,
+    };
+    register_source_map(path.as_str(), map.as_str());
+    script.set_transpiled_code(out, Some(map));
+    Ok(())
+}
+
+/// collects the (byte offset of the import keyword, byte offset of the first argument) of import() calls
+struct ImportCallCollector {
+    start_pos: BytePos,
+    calls: Vec<(usize, usize)>,
+}
+
+impl Visit for ImportCallCollector {
+    fn visit_call_expr(&mut self, call: &CallExpr) {
+        if let (Callee::Import(import), Some(arg)) = (&call.callee, call.args.first()) {
+            self.calls.push((
+                (import.span.lo.0 - self.start_pos.0) as usize,
+                (arg.span().lo.0 - self.start_pos.0) as usize,
+            ));
+        }
+        call.visit_children_with(self);
+    }
 }
 
 // fix stacktrace method
@@ -237,16 +1139,114 @@ pub(crate) fn transpile_serverside(
         transpiler.transpile_script(script)
     })?;
 
+    compose_script_maps(script);
+
     // register in source_maps so fix_stack can use it later
     if let Some(map_str) = script.get_map() {
-        SOURCE_MAPS.with(|rc| {
-            let maps = &mut *rc.borrow_mut();
-            maps.insert(script.get_path().to_string(), map_str.to_string());
-        })
+        register_source_map(script.get_path(), map_str);
     }
     Ok(())
 }
 
+/// compose the maps of the pre-processors (Script::set_code_with_map) and the map of the transpiler
+fn compose_script_maps(script: &mut Script) {
+    if script.get_code_maps().is_empty() {
+        return;
+    }
+    let mut maps: Vec<&str> = script.get_code_maps().iter().map(|m| m.as_str()).collect();
+    if let Some(map) = script.get_map() {
+        maps.push(map);
+    }
+    match compose_source_maps(maps.as_slice()) {
+        Ok(composed) => script.set_map(Some(composed)),
+        Err(e) => log::error!(
+            "could not compose source maps of {}: {}",
+            script.get_path(),
+            e
+        ),
+    }
+}
+
+/// compose a chain of source maps into a single map from the last generated code to the original code
+/// maps\[0\] maps the first generated code to the original code and every next map maps its code to the code generated by the previous map
+pub fn compose_source_maps(maps: &[&str]) -> Result<String, JsError> {
+    let parsed = maps
+        .iter()
+        .map(|m| swc::sourcemap::SourceMap::from_reader(io::Cursor::new(m)))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| JsError::new_string(format!("invalid source map: {e}")))?;
+    let (last, previous) = parsed
+        .split_last()
+        .ok_or_else(|| JsError::new_str("no source maps to compose"))?;
+
+    let mut builder = swc::sourcemap::SourceMapBuilder::new(last.get_file());
+    for token in last.tokens() {
+        let mut line = token.get_src_line();
+        let mut col = token.get_src_col();
+        let mut source = token.get_source();
+        let mut name = token.get_name();
+        let mut mapped = true;
+        for map in previous.iter().rev() {
+            match map.lookup_token(line, col) {
+                Some(prev) => {
+                    // tokens may only mark the start of a line (or statement), keep the offset from that start
+                    col = if prev.get_dst_line() == line {
+                        prev.get_src_col() + (col - prev.get_dst_col())
+                    } else {
+                        prev.get_src_col()
+                    };
+                    line = prev.get_src_line();
+                    source = prev.get_source();
+                    name = prev.get_name().or(name);
+                }
+                None => {
+                    mapped = false;
+                    break;
+                }
+            }
+        }
+        if mapped {
+            builder.add(
+                token.get_dst_line(),
+                token.get_dst_col(),
+                line,
+                col,
+                source,
+                name,
+            );
+        }
+    }
+    let mut out: Vec<u8> = vec![];
+    builder
+        .into_sourcemap()
+        .to_writer(&mut out)
+        .map_err(|e| JsError::new_string(format!("could not write source map: {e}")))?;
+    String::from_utf8(out).map_err(|e| JsError::new_string(format!("{e}")))
+}
+
+/// create a source map for code of which lines were moved, inserted or removed
+/// lines\[n\] is the (0-based) line in the original code of line n of the generated code, None for inserted lines
+pub fn line_source_map(source: &str, lines: &[Option<u32>]) -> String {
+    let mut builder = swc::sourcemap::SourceMapBuilder::new(Some(source));
+    for (dst_line, src_line) in lines.iter().enumerate() {
+        if let Some(src_line) = src_line {
+            builder.add(dst_line as u32, 0, *src_line, 0, Some(source), None);
+        }
+    }
+    let mut out: Vec<u8> = vec![];
+    // writing to a Vec does not fail
+    let _ = builder.into_sourcemap().to_writer(&mut out);
+    String::from_utf8(out).unwrap_or_default()
+}
+
+/// register the source map of a transpiled script so fix_stack can use it, e.g. for a script loaded from the bytecode cache
+pub(crate) fn register_source_map(path: &str, map: &str) {
+    SOURCE_MAPS.with(|rc| {
+        let maps = &mut *rc.borrow_mut();
+        maps.insert(path.to_string(), map.to_string());
+    })
+}
+
 #[derive(Debug)]
 struct StackEntry {
     function_name: String,
@@ -333,7 +1333,9 @@ pub fn fix_stack_trace(stack_trace: &str, maps: &HashMap<String, String>) -> Str
     match parse_stack_trace(stack_trace) {
         Ok(mut parsed_stack) => {
             for stack_trace_entry in parsed_stack.iter_mut() {
-                if let Some(map_str) = maps.get(stack_trace_entry.file_name.as_str()) {
+                // invalidated modules run under a versioned name, their map is registered under the path
+                let map_key = strip_module_version(stack_trace_entry.file_name.as_str());
+                if let Some(map_str) = maps.get(map_key) {
                     log::trace!(
                         "fix_stack_trace:found map for file {}:\n{map_str}",
                         stack_trace_entry.file_name.as_str()
@@ -342,15 +1344,28 @@ pub fn fix_stack_trace(stack_trace: &str, maps: &HashMap<String, String>) -> Str
                         log::trace!("lookup line number:{line_number}");
                         match swc::sourcemap::SourceMap::from_reader(io::Cursor::new(map_str)) {
                             Ok(source_map) => {
-                                if let Some(original_location) = source_map.lookup_token(
-                                    line_number,
-                                    stack_trace_entry.column_number.unwrap_or(1),
-                                ) {
-                                    let original_line = original_location.get_src_line();
-                                    let original_column = original_location.get_src_col();
+                                // stack positions are 1-based, source map positions are 0-based
+                                let line = line_number.saturating_sub(1);
+                                let column = stack_trace_entry
+                                    .column_number
+                                    .unwrap_or(1)
+                                    .saturating_sub(1);
+                                if let Some(original_location) =
+                                    source_map.lookup_token(line, column)
+                                {
+                                    let original_line = original_location.get_src_line() + 1;
+                                    let original_column =
+                                        if original_location.get_dst_line() == line {
+                                            original_location.get_src_col()
+                                                + (column - original_location.get_dst_col())
+                                        } else {
+                                            original_location.get_src_col()
+                                        } + 1;
                                     log::trace!("lookup original_line:{original_line}");
                                     stack_trace_entry.line_number = Some(original_line);
-                                    stack_trace_entry.column_number = Some(original_column);
+                                    if stack_trace_entry.column_number.is_some() {
+                                        stack_trace_entry.column_number = Some(original_column);
+                                    }
                                 }
                             }
                             Err(_) => {
@@ -405,6 +1420,260 @@ pub mod tests {
         assert!(res.get_value_type() == JsValueType::Boolean);
     }
     #[test]
+    fn test_options() {
+        use crate::builder::QuickJsRuntimeBuilder;
+        use crate::typescript::{TypeScriptOptions, TypeScriptTranspiler};
+
+        let transpiler =
+            TypeScriptTranspiler::with_options(TypeScriptOptions::new().jsx_pragma("h"));
+        let (code, _map) = transpiler
+            .transpile(
+                "const a: number = 1; const d = <div>{a}</div>;",
+                "test.tsx",
+                false,
+            )
+            .expect("transpile failed");
+        assert!(code.contains("h(\"div\""));
+
+        let rt = QuickJsRuntimeBuilder::new()
+            .typescript_options(
+                TypeScriptOptions::new().transpile_filter(|path| !path.starts_with("plain/")),
+            )
+            .build();
+        let res = rt
+            .eval_sync(None, Script::new("typed.ts", "let a: number = 2; a * 3;"))
+            .expect("script failed");
+        assert_eq!(res.get_i32(), 6);
+        rt.eval_sync(
+            None,
+            Script::new("plain/typed.ts", "let b: number = 2; b * 3;"),
+        )
+        .expect_err("script was transpiled");
+    }
+    #[test]
+    fn test_decorators() {
+        use crate::builder::QuickJsRuntimeBuilder;
+        use crate::typescript::{Decorators, TypeScriptOptions};
+
+        let rt = QuickJsRuntimeBuilder::new()
+            .typescript_options(
+                TypeScriptOptions::new()
+                    .decorators(Decorators::Legacy)
+                    .external_helpers(false),
+            )
+            .build();
+        let code = r#"
+            function tagged(tag: string) {
+                return function (target: any) {
+                    target.tag = tag;
+                };
+            }
+            function twice(target: any, key: string, descriptor: PropertyDescriptor) {
+                const method = descriptor.value;
+                descriptor.value = function (...args: any[]) {
+                    return method.apply(this, args) * 2;
+                };
+            }
+            @tagged("service")
+            class Service {
+                @twice
+                value(a: number): number {
+                    return a + 1;
+                }
+            }
+            (Service as any).tag + ":" + new Service().value(2);
+        "#;
+        let res = rt
+            .eval_sync(None, Script::new("decorated.ts", code))
+            .expect("script failed");
+        assert_eq!(res.get_str(), "service:6");
+    }
+    #[test]
+    fn test_extensions() {
+        use crate::typescript::{ModuleMode, SourceSyntax, TypeScriptOptions};
+        let options = TypeScriptOptions::new().extension(
+            ".es6",
+            SourceSyntax::JavaScript,
+            ModuleMode::Module,
+        );
+        assert_eq!(
+            options.get_source_kind("a/b.tsx"),
+            Some((SourceSyntax::Tsx, ModuleMode::Detect))
+        );
+        assert_eq!(
+            options.get_source_kind("a/b.cts"),
+            Some((SourceSyntax::TypeScript, ModuleMode::Script))
+        );
+        assert_eq!(
+            options.get_source_kind("a/b.es6"),
+            Some((SourceSyntax::JavaScript, ModuleMode::Module))
+        );
+        assert!(!options.should_transpile("a/b.js"));
+        assert!(!options.should_transpile("a/b.mjs"));
+
+        let rt = init_test_rt();
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test.tsx",
+                    "globalThis.React = {createElement: (t: string, p: any, ...c: Array<any>) => t + c.length};\n<div><b/><i/></div>;",
+                ),
+            )
+            .expect("tsx failed");
+        assert_eq!(res.get_str(), "div2");
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "test.jsx",
+                    "globalThis.React = {createElement: (t, p, ...c) => t + c.length};\n<span>hi</span>;",
+                ),
+            )
+            .expect("jsx failed");
+        assert_eq!(res.get_str(), "span1");
+        // a type assertion is not jsx in a .cts file
+        let res = rt
+            .eval_sync(
+                None,
+                Script::new("test.cts", "let a: any = 3; (<number>a) * 2;"),
+            )
+            .expect("cts failed");
+        assert_eq!(res.get_i32(), 6);
+    }
+    #[test]
+    fn test_diagnostics() {
+        use crate::jsutils::diagnostics::DiagnosticSeverity;
+        use crate::jsutils::JsErrorKind;
+        use crate::typescript::TypeScriptTranspiler;
+
+        let transpiler = TypeScriptTranspiler::default();
+        let err = transpiler
+            .transpile("let a = 1;\nlet b: = 2;\n", "bad.ts", false)
+            .expect_err("transpile passed");
+        assert_eq!(err.get_kind(), JsErrorKind::SyntaxError);
+        assert_eq!(err.get_file_name(), Some("bad.ts"));
+        assert_eq!(err.get_line_number(), Some(2));
+
+        let diagnostic = err.get_diagnostics().first().expect("no diagnostics");
+        assert_eq!(diagnostic.severity, DiagnosticSeverity::Error);
+        assert_eq!(diagnostic.file_name.as_deref(), Some("bad.ts"));
+        assert_eq!(diagnostic.line_number, Some(2));
+        assert_eq!(diagnostic.column_number, Some(8));
+        assert!(diagnostic
+            .code_frame
+            .as_deref()
+            .expect("no code frame")
+            .starts_with("2 | let b: = 2;"));
+
+        // errors of server side transpilation are returned by eval
+        let rt = init_test_rt();
+        let err = rt
+            .eval_sync(None, Script::new("bad2.ts", "let c: = 3;"))
+            .expect_err("script passed");
+        assert!(!err.get_diagnostics().is_empty());
+    }
+    #[test]
+    fn test_replace_dynamic_imports() {
+        use crate::typescript::replace_dynamic_imports;
+        use std::io;
+
+        let mut script = Script::new(
+            "dyn.js",
+            "let a = 1; import(\n'./a.js').then(() => obj.import(1));\nlet b = import('./b.js'); // import('./c.js')",
+        );
+        replace_dynamic_imports(&mut script, "load(\"dyn.js\", ").expect("replace failed");
+        assert_eq!(
+            script.get_runnable_code(),
+            "let a = 1; load(\"dyn.js\", \n'./a.js').then(() => obj.import(1));\nlet b = load(\"dyn.js\", './b.js'); // import('./c.js')"
+        );
+        // the specifier is mapped to its position in the original code
+        let map = swc::sourcemap::SourceMap::from_reader(io::Cursor::new(
+            script.get_map().expect("no map"),
+        ))
+        .expect("invalid map");
+        let token = map.lookup_token(2, 23).expect("no token");
+        assert_eq!((token.get_src_line(), token.get_src_col()), (2, 15));
+    }
+    #[test]
+    fn test_scan_imports() {
+        use crate::typescript::scan_imports;
+
+        // imports in regular expressions, templates and comments are not imports
+        let code = "const re = /from './re.js' with { type: 'json' }/g;\nconst q = /'/; import a from './a.js';\nlet x = y / 2; import b from './b.json' with { type: 'json' }; let z = y / 3;\nconst t = `import c from './c.js' ${`nested ${'import(\\'./d.js\\')'}`} from './e.js'`;\n// import f from './f.txt' with { type: 'text' }\nexport { g } from \"./g.txt\" assert {\ntype: \"text\" };";
+        let scan = scan_imports("scan.js", code).expect("scan failed");
+        assert_eq!(
+            scan.imports,
+            vec![
+                ("./a.js".to_string(), None),
+                ("./b.json".to_string(), Some("json".to_string())),
+                ("./g.txt".to_string(), Some("text".to_string()))
+            ]
+        );
+        let stripped = scan.code.expect("code not changed");
+        assert_eq!(stripped.len(), code.len());
+        assert!(stripped.contains("/from './re.js' with { type: 'json' }/g"));
+        assert!(stripped.contains(&format!(
+            "import b from './b.json#type=json'{}; let z",
+            " ".repeat(12)
+        )));
+        // line breaks are kept
+        assert!(stripped.ends_with(&format!(
+            "export {{ g }} from \"./g.txt#type=text\"{}\n{};",
+            " ".repeat(9),
+            " ".repeat(4)
+        )));
+    }
+    #[test]
+    fn test_pre_processor_maps() {
+        use crate::builder::QuickJsRuntimeBuilder;
+        use crate::jsutils::{JsError, ScriptPreProcessor};
+        use crate::typescript::line_source_map;
+
+        // inserts two lines at the top of a script
+        struct HeaderPreProcessor {}
+        impl ScriptPreProcessor for HeaderPreProcessor {
+            fn process(&self, script: &mut Script) -> Result<(), JsError> {
+                let line_count = script.get_code().lines().count() as u32;
+                let mut lines = vec![None, None];
+                lines.extend((0..line_count).map(Some));
+                let map = line_source_map(script.get_path(), lines.as_slice());
+                let code = format!(
+                    "// header\nglobalThis.header = true;\n{}",
+                    script.get_code()
+                );
+                script.set_code_with_map(code, map);
+                Ok(())
+            }
+        }
+
+        let rt = QuickJsRuntimeBuilder::new()
+            .script_pre_processor(HeaderPreProcessor {})
+            .build();
+
+        let err = rt
+            .eval_sync(
+                None,
+                Script::new("pp_test.js", "let a = 1;\nthrow new Error('boom');\n"),
+            )
+            .expect_err("script passed");
+        assert_eq!(err.get_file_name(), Some("pp_test.js"));
+        assert_eq!(err.get_line_number(), Some(2));
+
+        // composed with the map of the transpiler
+        let err = rt
+            .eval_sync(
+                None,
+                Script::new(
+                    "pp_test.ts",
+                    "let b: number = 1;\n\nthrow new Error('boom');\n",
+                ),
+            )
+            .expect_err("script passed");
+        assert_eq!(err.get_file_name(), Some("pp_test.ts"));
+        assert_eq!(err.get_line_number(), Some(3));
+    }
+    #[test]
     fn test_stack_map() {
         let rt = init_test_rt();
         println!("testing ts");